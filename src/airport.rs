@@ -0,0 +1,204 @@
+//! Parses the output of the macOS `airport` tool. The wifiscanner crate keeps the channel exactly as airport prints
+//! it and drops the noise floor, this module reads both.
+
+use std::error::Error;
+use std::fmt;
+use std::process::Command;
+use std::time::Instant;
+
+use crate::logging;
+
+/// Location of airport, which is not on the PATH.
+const AIRPORT_PATH: &str = "/System/Library/PrivateFrameworks/Apple80211.framework/Versions/Current/Resources/airport";
+
+/// Error which occurs when running airport.
+#[derive(Debug)]
+pub struct AirportError {
+    /// Reason airport failed.
+    reason: String,
+}
+
+impl fmt::Display for AirportError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "airport: {}", self.reason)
+    }
+}
+
+impl Error for AirportError {}
+
+/// An access point from `airport -s` output.
+#[derive(Default, Debug)]
+pub struct AccessPoint {
+    /// Hardware address, lowercase.
+    pub mac: String,
+
+    /// Network name, empty for hidden networks.
+    pub ssid: String,
+
+    /// Signal strength in dBm.
+    pub rssi: Option<f32>,
+
+    /// Primary channel number.
+    pub channel: String,
+
+    /// Channel width in MHz, from the suffix of the channel, e.g. "36,+1" is 40 MHz and "149,80" is 80 MHz.
+    pub width: u32,
+
+    /// True if the access point supports 802.11n.
+    pub ht: bool,
+
+    /// Security description, e.g. "WPA2(PSK/AES/AES)", "NONE".
+    pub security: String,
+}
+
+/// Noise floor measured on the channel of the associated network, from `airport -I`.
+pub struct Noise {
+    /// Primary channel number.
+    pub channel: String,
+
+    /// Noise in dBm.
+    pub noise: f32,
+}
+
+/// Run airport with args.
+fn run_airport(args: &[&str]) -> Result<String, Box<dyn Error>> {
+    let started = Instant::now();
+    let output = Command::new(AIRPORT_PATH)
+        .args(args)
+        .output()?;
+    logging::command(AIRPORT_PATH, args, &output, started.elapsed());
+
+    if !output.status.success() {
+        return Err(Box::new(AirportError{
+            reason: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        }));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// True if s looks like a hardware address, e.g. 84:d4:7e:69:2f:e2.
+fn is_mac(s: &str) -> bool {
+    s.len() == 17 && s.split(':').count() == 6 && s.split(':').all(|b| b.len() == 2 && b.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+/// Split a channel like "36,+1" into the primary channel and the channel width in MHz.
+fn parse_channel(channel: &str) -> (String, u32) {
+    let mut parts = channel.split(',');
+    let primary = parts.next().unwrap_or("").to_string();
+
+    let width = match parts.next() {
+        Some("+1") | Some("-1") => 40,
+        Some("80") => 80,
+        Some("160") => 160,
+        _ => 20,
+    };
+
+    (primary, width)
+}
+
+/// Scan for networks.
+pub fn scan() -> Result<Vec<AccessPoint>, Box<dyn Error>> {
+    let output = run_airport(&["-s"])?;
+
+    Ok(parse_scan(&output))
+}
+
+/// Noise floor of the channel the computer is associated on. None if it is not associated.
+pub fn noise() -> Result<Option<Noise>, Box<dyn Error>> {
+    let output = run_airport(&["-I"])?;
+
+    Ok(parse_info(&output))
+}
+
+/// Parse the output of `airport -s`. SSIDs are right aligned and may contain spaces, so each
+/// line is split at its BSSID.
+pub fn parse_scan(output: &str) -> Vec<AccessPoint> {
+    let mut results = Vec::<AccessPoint>::new();
+
+    for line in output.lines() {
+        let mac = match line.split_whitespace().find(|t| is_mac(t)) {
+            Some(m) => m,
+            None => continue,
+        };
+        let idx = line.find(mac).expect("mac is part of the line");
+        let mut fields = line[idx + mac.len()..].split_whitespace();
+
+        let rssi = fields.next().and_then(|r| r.parse::<f32>().ok());
+        let (channel, width) = parse_channel(fields.next().unwrap_or(""));
+        let ht = fields.next() == Some("Y");
+        // Country code
+        fields.next();
+
+        results.push(AccessPoint{
+            mac: mac.to_lowercase(),
+            ssid: line[..idx].trim().to_string(),
+            rssi,
+            channel,
+            width,
+            ht,
+            security: fields.collect::<Vec<&str>>().join(" "),
+        });
+    }
+
+    results
+}
+
+/// Parse the output of `airport -I`.
+pub fn parse_info(output: &str) -> Option<Noise> {
+    let field = |name: &str| output.lines()
+        .filter_map(|l| l.trim().strip_prefix(name))
+        .find_map(|v| v.strip_prefix(':'))
+        .map(|v| v.trim());
+
+    let noise = field("agrCtlNoise")?.parse::<f32>().ok()?;
+    let (channel, _) = parse_channel(field("channel")?);
+
+    // airport reports a noise of 0 when it is not associated
+    match noise < 0.0 {
+        true => Some(Noise{ channel, noise }),
+        false => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_scan_splits_lines_at_the_bssid() {
+        let aps = parse_scan(include_str!("../tests/fixtures/airport-scan.txt"));
+        assert_eq!(aps.len(), 3);
+
+        assert_eq!(aps[0].mac, "84:d4:7e:69:2f:e2");
+        assert_eq!(aps[0].ssid, "CorpWiFi");
+        assert_eq!(aps[0].rssi, Some(-58.0));
+        assert_eq!(aps[0].channel, "36");
+        assert_eq!(aps[0].width, 40);
+        assert!(aps[0].ht);
+        assert_eq!(aps[0].security, "WPA2(802.1x/AES/AES)");
+
+        assert_eq!(aps[1].mac, "84:d4:7e:69:2f:f2");
+        assert_eq!(aps[1].ssid, "Guest Lounge");
+        assert_eq!(aps[1].width, 80);
+        assert_eq!(aps[1].security, "WPA2(PSK/AES/AES) WPA3(SAE/AES/AES)");
+
+        assert_eq!(aps[2].ssid, "");
+        assert_eq!(aps[2].width, 20);
+        assert!(!aps[2].ht);
+        assert_eq!(aps[2].security, "NONE");
+    }
+
+    #[test]
+    fn parse_info_reads_the_noise_of_the_channel() {
+        let noise = parse_info(include_str!("../tests/fixtures/airport-info.txt")).expect("associated");
+        assert_eq!(noise.channel, "36");
+        assert_eq!(noise.noise, -92.0);
+    }
+
+    #[test]
+    fn parse_info_is_none_when_not_associated() {
+        assert!(parse_info("     agrCtlRSSI: 0\n    agrCtlNoise: 0\n          state: init\n        channel: 1,1\n").is_none());
+        assert!(parse_info("AirPort: Off\n").is_none());
+    }
+}
@@ -0,0 +1,55 @@
+//! Warns right after a node is recorded if a network is weak there, so weak spots can be
+//! investigated while still on site.
+
+use std::io::{stderr, IsTerminal, Write};
+
+use crate::Node;
+
+/// Networks to watch and the strength below which they are weak.
+pub struct Alert {
+    /// SSIDs which are checked at each node.
+    pub ssids: Vec<String>,
+
+    /// Weakest strength in dBm which does not raise an alert.
+    pub below: f64,
+
+    /// Ring the terminal bell with each alert.
+    pub beep: bool,
+}
+
+impl Alert {
+    /// Print a warning for each watched SSID which node heard weaker than the threshold or did not hear at all.
+    /// Returns the number of warnings.
+    pub fn check(&self, node: &Node) -> usize {
+        let mut warnings = Vec::<String>::new();
+        for ssid in &self.ssids {
+            let strongest = node.networks.iter()
+                .filter(|n| n.ssid == *ssid)
+                .filter_map(|n| n.strength)
+                .map(|s| s as f64)
+                .fold(None, |best: Option<f64>, s| Some(best.map_or(s, |b| b.max(s))));
+
+            match strongest {
+                Some(s) if s < self.below => warnings.push(format!("{} is {:.0} dBm here, below {} dBm", ssid, s, self.below)),
+                None => warnings.push(format!("{} was not heard here", ssid)),
+                _ => (),
+            };
+        }
+
+        // Bold red stands out from the scan output when a person is watching
+        let (start, end) = match stderr().is_terminal() {
+            true => ("\x1b[1;31m", "\x1b[0m"),
+            false => ("", ""),
+        };
+        let bell = match self.beep {
+            true => "\x07",
+            false => "",
+        };
+        for warning in &warnings {
+            eprintln!("{}{}!!! ALERT: {} !!!{}", bell, start, warning, end);
+        }
+        let _ = stderr().flush();
+
+        warnings.len()
+    }
+}
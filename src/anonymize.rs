@@ -0,0 +1,123 @@
+//! Removes identifying information from a scan map so it can be shared publicly.
+//!
+//! BSSIDs are replaced with salted hashes. The same salt always produces the same
+//! replacement for a BSSID, so anonymized maps made with one salt can still be correlated.
+
+use sha2::{Digest, Sha256};
+
+use crate::ScanMap;
+
+/// Salted SHA-256 digest of a value.
+fn salted_hash(salt: &str, value: &str) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(salt.as_bytes());
+    hasher.update([0u8]);
+    hasher.update(value.as_bytes());
+
+    hasher.finalize().into()
+}
+
+/// Replacement for a mac address. The result is formatted like a mac address with the locally
+/// administered bit set, so it can never collide with or be looked up as a real vendor address.
+pub fn anonymize_mac(salt: &str, mac: &str) -> String {
+    let digest = salted_hash(salt, &mac.to_lowercase());
+
+    let mut octets = [0u8; 6];
+    octets.copy_from_slice(&digest[..6]);
+    octets[0] = (octets[0] | 0x02) & 0xfe;
+
+    octets.iter()
+        .map(|o| format!("{:02x}", o))
+        .collect::<Vec<String>>()
+        .join(":")
+}
+
+/// Replacement for an SSID, which keeps networks distinguishable without revealing their name.
+pub fn anonymize_ssid(salt: &str, ssid: &str) -> String {
+    let digest = salted_hash(salt, ssid);
+
+    format!("ssid-{:02x}{:02x}{:02x}{:02x}", digest[0], digest[1], digest[2], digest[3])
+}
+
+/// Replace the BSSIDs in a scan map with salted hashes. If strip_ssids is true SSIDs of broadcasting networks are replaced as well.
+pub fn anonymize(scan_map: &mut ScanMap, salt: &str, strip_ssids: bool) {
+    scan_map.annotations = std::mem::take(&mut scan_map.annotations).into_iter()
+        .map(|(mac, note)| (anonymize_mac(salt, &mac), note))
+        .collect();
+    scan_map.access_points = std::mem::take(&mut scan_map.access_points).into_iter()
+        .map(|(mac, mut location)| {
+            if strip_ssids && !location.ssid.is_empty() {
+                location.ssid = anonymize_ssid(salt, &location.ssid);
+            }
+            (anonymize_mac(salt, &mac), location)
+        })
+        .collect();
+
+    for node in &mut scan_map.nodes {
+        for network in &mut node.networks {
+            network.mac = anonymize_mac(salt, &network.mac);
+
+            if strip_ssids && !network.is_hidden {
+                network.ssid = anonymize_ssid(salt, &network.ssid);
+            }
+        }
+
+        for device in &mut node.ble_devices {
+            device.address = anonymize_mac(salt, &device.address);
+        }
+
+        for client in &mut node.clients {
+            client.mac = anonymize_mac(salt, &client.mac);
+
+            if strip_ssids {
+                client.probed_ssids = client.probed_ssids.iter()
+                    .map(|s| anonymize_ssid(salt, s))
+                    .collect();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::scan_map;
+    use serde_json::json;
+
+    #[test]
+    fn anonymize_mac_is_a_stable_local_address() {
+        let mac = anonymize_mac("salt", "84:D4:7E:69:2F:E2");
+        assert_eq!(mac, anonymize_mac("salt", "84:d4:7e:69:2f:e2"));
+        assert_ne!(mac, anonymize_mac("pepper", "84:d4:7e:69:2f:e2"));
+
+        let first = u8::from_str_radix(&mac[..2], 16).unwrap();
+        assert_eq!(first & 0x03, 0x02, "{} is not a locally administered unicast address", mac);
+        assert_eq!(mac.len(), 17);
+    }
+
+    #[test]
+    fn anonymize_replaces_bssids_and_optionally_ssids() {
+        let map = || scan_map(json!({
+            "nodes": [{
+                "position": {"x": 0.0, "y": 0.0, "z": 0.0},
+                "networks": [
+                    {"mac": "84:d4:7e:69:2f:e2", "ssid": "CorpWiFi", "strength": -60.0},
+                    {"mac": "aa:bb:cc:00:11:22", "ssid": "", "strength": -80.0},
+                ],
+            }],
+        }));
+
+        let mut kept = map();
+        anonymize(&mut kept, "salt", false);
+        let networks = &kept.nodes[0].networks;
+        assert_eq!(networks[0].mac, anonymize_mac("salt", "84:d4:7e:69:2f:e2"));
+        assert_eq!(networks[0].ssid, "CorpWiFi");
+
+        let mut stripped = map();
+        anonymize(&mut stripped, "salt", true);
+        let networks = &stripped.nodes[0].networks;
+        assert_eq!(networks[0].ssid, anonymize_ssid("salt", "CorpWiFi"));
+        assert!(networks[0].ssid.starts_with("ssid-"));
+        assert_eq!(networks[1].ssid, "");
+    }
+}
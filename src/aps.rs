@@ -0,0 +1,321 @@
+//! Summarizes each access point across the nodes which heard it, e.g. to find where an access
+//! point is strong without reading through every node. BSSIDs are compared ignoring case, and an
+//! access point heard several times at one node, e.g. by two interfaces or through a merge, counts
+//! once with its strongest measurement.
+
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::error::Error;
+
+use serde::Serialize;
+
+use crate::query::glob_match;
+use crate::radio::Band;
+use crate::stream;
+use crate::table;
+use crate::timestamp;
+use crate::{Coordinate, Network, ScanMap};
+
+/// Which networks are summarized. Filters which are None match everything.
+pub struct ApOptions {
+    /// Only include nodes on this floor.
+    pub floor: Option<String>,
+
+    /// Only include nodes with all of these tags.
+    pub tags: Vec<String>,
+
+    /// Glob which the SSID must match, * matches any characters and ? one character.
+    pub ssid: Option<String>,
+
+    /// Prefix of the BSSID, case insensitive.
+    pub bssid: Option<String>,
+}
+
+/// Strength of an access point measured at a node.
+#[derive(Serialize)]
+pub struct Reading {
+    /// Index of the node in the map.
+    pub node: usize,
+
+    pub floor: Option<String>,
+    pub position: Coordinate,
+
+    /// Strength in dBm.
+    pub strength: f32,
+}
+
+/// Channel an access point was seen on.
+#[derive(Serialize)]
+pub struct ChannelUse {
+    pub channel: String,
+    pub band: Option<Band>,
+
+    /// Number of nodes which saw the access point on the channel.
+    pub nodes: usize,
+
+    /// First and last time the access point was seen on the channel, in milliseconds since the unix epoch. Serialized as
+    /// RFC 3339 timestamps.
+    #[serde(serialize_with = "timestamp::serialize")]
+    pub first_seen: u128,
+    #[serde(serialize_with = "timestamp::serialize")]
+    pub last_seen: u128,
+}
+
+/// Summary of a BSSID across the nodes of a map.
+#[derive(Serialize)]
+pub struct AccessPoint {
+    /// Lower case BSSID.
+    pub bssid: String,
+
+    /// SSIDs broadcast by the BSSID, usually one.
+    pub ssids: Vec<String>,
+
+    pub vendor: Option<String>,
+
+    /// Note about the access point, see the annotate sub-command.
+    pub note: Option<String>,
+
+    /// Number of nodes which saw the access point.
+    pub nodes: usize,
+
+    /// Extra measurements at nodes which already saw the access point, which were merged into one.
+    pub duplicates: usize,
+
+    /// Nodes which measured the strongest and weakest signals, None if no strength was parsed.
+    pub best: Option<Reading>,
+    pub worst: Option<Reading>,
+
+    /// Channels the access point used, in the order it was first seen on them.
+    pub channels: Vec<ChannelUse>,
+}
+
+impl AccessPoint {
+    fn new(scan_map: &ScanMap, bssid: String) -> AccessPoint {
+        AccessPoint{
+            note: scan_map.annotations.get(&bssid).cloned(),
+            bssid,
+            ssids: Vec::new(),
+            vendor: None,
+            nodes: 0,
+            duplicates: 0,
+            best: None,
+            worst: None,
+            channels: Vec::new(),
+        }
+    }
+
+    /// Add a measurement of the access point to the channel it was on, returns the index of the channel in channels.
+    fn add_channel(&mut self, network: &Network) -> usize {
+        let index = match self.channels.iter().position(|c| c.channel == network.channel && c.band == network.band) {
+            Some(index) => index,
+            None => {
+                self.channels.push(ChannelUse{
+                    channel: network.channel.clone(),
+                    band: network.band,
+                    nodes: 0,
+                    first_seen: network.time_scanned,
+                    last_seen: network.time_scanned,
+                });
+                self.channels.len() - 1
+            },
+        };
+
+        let channel = &mut self.channels[index];
+        channel.first_seen = channel.first_seen.min(network.time_scanned);
+        channel.last_seen = channel.last_seen.max(network.time_scanned);
+
+        index
+    }
+}
+
+/// Summaries of the access points heard at the nodes opts selects, those heard at the most nodes first.
+pub fn access_points(scan_map: &ScanMap, opts: &ApOptions) -> Result<Vec<AccessPoint>, Box<dyn Error>> {
+    let mut aps = BTreeMap::<String, AccessPoint>::new();
+
+    stream::visit_nodes(scan_map, |i, node| {
+        if (opts.floor.is_some() && node.floor != opts.floor) || !node.has_tags(&opts.tags) {
+            return Ok(());
+        }
+
+        let mut heard = BTreeMap::<String, Vec<&Network>>::new();
+        for network in &node.networks {
+            let bssid = network.mac.to_lowercase();
+            if opts.ssid.as_ref().is_some_and(|pattern| !glob_match(pattern, network.display_ssid()))
+                || opts.bssid.as_ref().is_some_and(|prefix| !bssid.starts_with(&prefix.to_lowercase())) {
+                continue;
+            }
+            heard.entry(bssid).or_default().push(network);
+        }
+
+        for (bssid, networks) in heard {
+            let ap = aps.entry(bssid.clone()).or_insert_with(|| AccessPoint::new(scan_map, bssid));
+            ap.nodes += 1;
+            ap.duplicates += networks.len() - 1;
+
+            let mut channels = Vec::<usize>::new();
+            for network in &networks {
+                if !ap.ssids.iter().any(|s| s == network.display_ssid()) {
+                    ap.ssids.push(network.display_ssid().to_string());
+                }
+                if ap.vendor.is_none() {
+                    ap.vendor = network.vendor.clone();
+                }
+
+                let channel = ap.add_channel(network);
+                if !channels.contains(&channel) {
+                    channels.push(channel);
+                    ap.channels[channel].nodes += 1;
+                }
+            }
+
+            let strength = match networks.iter().filter_map(|n| n.strength).reduce(f32::max) {
+                Some(s) => s,
+                None => continue,
+            };
+            let reading = || Reading{
+                node: i,
+                floor: node.floor.clone(),
+                position: Coordinate{ x: node.position.x, y: node.position.y, z: node.position.z },
+                strength,
+            };
+            if ap.best.as_ref().is_none_or(|b| strength > b.strength) {
+                ap.best = Some(reading());
+            }
+            if ap.worst.as_ref().is_none_or(|w| strength < w.strength) {
+                ap.worst = Some(reading());
+            }
+        }
+
+        Ok(())
+    })?;
+
+    for ap in aps.values_mut() {
+        ap.channels.sort_by_key(|c| c.first_seen);
+    }
+
+    // Most widely heard first, then strongest
+    let mut aps: Vec<AccessPoint> = aps.into_values().collect();
+    aps.sort_by(|a, b| b.nodes.cmp(&a.nodes)
+        .then(b.best.as_ref().map(|r| r.strength).partial_cmp(&a.best.as_ref().map(|r| r.strength)).unwrap_or(Ordering::Equal))
+        .then(a.bssid.cmp(&b.bssid)));
+
+    Ok(aps)
+}
+
+/// Strength and where it was measured, for display.
+fn reading_columns(scan_map: &ScanMap, reading: &Option<Reading>) -> [String; 2] {
+    match reading {
+        Some(r) => {
+            let position = r.position.format(scan_map.coordinate_system, scan_map.units, scan_map.units);
+            [
+                format!("{:.1}", r.strength),
+                match &r.floor {
+                    Some(floor) => format!("node {} ({}, {})", r.node, floor, position),
+                    None => format!("node {} ({})", r.node, position),
+                },
+            ]
+        },
+        None => [String::from("-"), String::from("-")],
+    }
+}
+
+/// Print a table of access points.
+pub fn print_access_points(scan_map: &ScanMap, aps: &[AccessPoint]) {
+    if aps.is_empty() {
+        println!("No access points");
+        return;
+    }
+
+    let rows: Vec<Vec<String>> = aps.iter()
+        .map(|ap| {
+            let [best, best_at] = reading_columns(scan_map, &ap.best);
+            let [worst, worst_at] = reading_columns(scan_map, &ap.worst);
+            vec![
+                ap.bssid.clone(),
+                ap.ssids.join(", "),
+                ap.vendor.clone().unwrap_or_default(),
+                match ap.duplicates {
+                    0 => ap.nodes.to_string(),
+                    d => format!("{} (+{} duplicate{})", ap.nodes, d, match d != 1 {
+                        true => "s",
+                        false => "",
+                    }),
+                },
+                best,
+                best_at,
+                worst,
+                worst_at,
+                ap.channels.iter().map(|c| c.channel.clone()).collect::<Vec<String>>().join(", "),
+                ap.note.clone().unwrap_or_default(),
+            ]
+        })
+        .collect();
+
+    table::print_table(&["BSSID", "SSID", "Vendor", "Nodes", "Best dBm", "Best at", "Worst dBm", "Worst at", "Channels", "Note"], &rows);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::scan_map;
+
+    fn opts() -> ApOptions {
+        ApOptions{
+            floor: None,
+            tags: Vec::new(),
+            ssid: None,
+            bssid: None,
+        }
+    }
+
+    fn survey() -> ScanMap {
+        scan_map(serde_json::json!({
+            "nodes": [
+                {"position": {"x": 0.0, "y": 0.0, "z": 0.0}, "floor": "F1", "networks": [
+                    {"mac": "AA:BB:CC:00:11:22", "ssid": "Corp", "channel": "1", "strength": -50.0, "time_scanned": 1000},
+                    {"mac": "aa:bb:cc:00:11:22", "ssid": "Corp", "channel": "1", "strength": -45.0, "time_scanned": 1000},
+                    {"mac": "dd:ee:ff:00:11:22", "ssid": "Guest", "channel": "6", "strength": -80.0, "time_scanned": 1000},
+                ]},
+                {"position": {"x": 5.0, "y": 0.0, "z": 0.0}, "floor": "F2", "networks": [
+                    {"mac": "aa:bb:cc:00:11:22", "ssid": "Corp", "channel": "11", "strength": -70.0, "time_scanned": 2000},
+                ]},
+            ],
+        }))
+    }
+
+    #[test]
+    fn access_points_merge_measurements_of_a_bssid() {
+        let aps = access_points(&survey(), &opts()).unwrap();
+        assert_eq!(aps.len(), 2);
+
+        let corp = &aps[0];
+        assert_eq!(corp.bssid, "aa:bb:cc:00:11:22");
+        assert_eq!(corp.ssids, vec!["Corp"]);
+        assert_eq!(corp.nodes, 2);
+        assert_eq!(corp.duplicates, 1);
+
+        let best = corp.best.as_ref().unwrap();
+        assert_eq!((best.node, best.strength), (0, -45.0));
+        let worst = corp.worst.as_ref().unwrap();
+        assert_eq!((worst.node, worst.strength, worst.position.x), (1, -70.0, 5.0));
+
+        let channels: Vec<(&str, usize)> = corp.channels.iter().map(|c| (c.channel.as_str(), c.nodes)).collect();
+        assert_eq!(channels, vec![("1", 1), ("11", 1)]);
+    }
+
+    #[test]
+    fn access_points_apply_filters() {
+        let ssid = access_points(&survey(), &ApOptions{ ssid: Some(String::from("G*")), ..opts() }).unwrap();
+        assert_eq!(ssid.len(), 1);
+        assert_eq!(ssid[0].bssid, "dd:ee:ff:00:11:22");
+
+        let bssid = access_points(&survey(), &ApOptions{ bssid: Some(String::from("AA:BB")), ..opts() }).unwrap();
+        assert_eq!(bssid.len(), 1);
+        assert_eq!(bssid[0].bssid, "aa:bb:cc:00:11:22");
+
+        let floor = access_points(&survey(), &ApOptions{ floor: Some(String::from("F2")), ..opts() }).unwrap();
+        assert_eq!(floor.len(), 1);
+        assert_eq!(floor[0].nodes, 1);
+        assert_eq!(floor[0].best.as_ref().unwrap().strength, -70.0);
+    }
+}
@@ -0,0 +1,191 @@
+//! Scans for Bluetooth LE advertisements, e.g. to map the coverage of beacons. Needs the ble feature.
+
+use std::error::Error;
+use std::fmt;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+/// Error which occurs during a Bluetooth scan.
+#[derive(Debug)]
+pub struct BleError {
+    /// Reason the scan failed.
+    reason: String,
+}
+
+impl fmt::Display for BleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ble: {}", self.reason)
+    }
+}
+
+impl Error for BleError {}
+
+/// Kind of device, determined from its advertisement.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum DeviceKind {
+    /// Apple iBeacon.
+    IBeacon,
+
+    /// Google Eddystone beacon.
+    Eddystone,
+
+    /// Any other advertising device, e.g. a phone or a sensor.
+    Device,
+}
+
+impl fmt::Display for DeviceKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DeviceKind::IBeacon => write!(f, "iBeacon"),
+            DeviceKind::Eddystone => write!(f, "Eddystone"),
+            DeviceKind::Device => write!(f, "device"),
+        }
+    }
+}
+
+/// A Bluetooth LE device which was heard advertising.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct BleDevice {
+    /// Bluetooth address, lowercase.
+    pub address: String,
+
+    /// Name the device advertises. None if it did not send one.
+    pub name: Option<String>,
+
+    pub kind: DeviceKind,
+
+    /// Beacon identifier: the UUID, major, and minor of iBeacons, e.g. "f7826da6-4fa2-4e98-8024-bc5b71e0893e/1/2".
+    /// None for other devices.
+    pub beacon_id: Option<String>,
+
+    /// Strength of the most recent advertisement in dBm.
+    pub strength: Option<f32>,
+
+    /// Transmit power the device advertises in dBm.
+    pub tx_power: Option<i16>,
+
+    /// Time the device was scanned, unix time in milliseconds.
+    pub time_scanned: u128,
+}
+
+impl fmt::Display for BleDevice {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let strength_str = match self.strength {
+            Some(s) => format!("{:.2} dBm", s),
+            None => String::from("unknown strength"),
+        };
+        let beacon_str = match &self.beacon_id {
+            Some(id) => format!(", {}", id),
+            None => String::new(),
+        };
+
+        write!(f, "{name:<width$} ({address}, {kind}{beacon_str}, {strength_str})", name=self.name.as_deref().unwrap_or("<unnamed>"),
+               width=f.width().unwrap_or(0), address=self.address, kind=self.kind, beacon_str=beacon_str, strength_str=strength_str)
+    }
+}
+
+/// Apple's Bluetooth company identifier, under which iBeacons advertise.
+#[cfg(feature = "ble")]
+const APPLE_COMPANY_ID: u16 = 0x004c;
+
+/// Service UUID Eddystone beacons advertise.
+#[cfg(feature = "ble")]
+const EDDYSTONE_SERVICE: u16 = 0xfeaa;
+
+/// Determine the kind and beacon identifier of a device from its advertisement.
+#[cfg(feature = "ble")]
+fn classify(properties: &btleplug::api::PeripheralProperties) -> (DeviceKind, Option<String>) {
+    use btleplug::api::bleuuid::uuid_from_u16;
+
+    // iBeacon data: type 0x02, length 0x15, a 16 byte UUID, then the major and minor
+    if let Some(data) = properties.manufacturer_data.get(&APPLE_COMPANY_ID) {
+        if data.len() >= 22 && data[0] == 0x02 && data[1] == 0x15 {
+            let uuid: String = data[2..18].iter().enumerate()
+                .map(|(i, b)| match i {
+                    4 | 6 | 8 | 10 => format!("-{:02x}", b),
+                    _ => format!("{:02x}", b),
+                })
+                .collect();
+            let major = u16::from_be_bytes([data[18], data[19]]);
+            let minor = u16::from_be_bytes([data[20], data[21]]);
+
+            return (DeviceKind::IBeacon, Some(format!("{}/{}/{}", uuid, major, minor)));
+        }
+    }
+
+    let eddystone = uuid_from_u16(EDDYSTONE_SERVICE);
+    if properties.services.contains(&eddystone) || properties.service_data.contains_key(&eddystone) {
+        return (DeviceKind::Eddystone, None);
+    }
+
+    (DeviceKind::Device, None)
+}
+
+/// Listen for advertisements with every Bluetooth adapter for duration.
+#[cfg(feature = "ble")]
+pub fn scan(duration: Duration) -> Result<Vec<BleDevice>, Box<dyn Error>> {
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    use btleplug::api::{Central, Manager as _, Peripheral as _, ScanFilter};
+    use btleplug::platform::Manager;
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+
+    runtime.block_on(async {
+        let manager = Manager::new().await?;
+        let adapters = manager.adapters().await?;
+        if adapters.is_empty() {
+            return Err(Box::new(BleError{
+                reason: String::from("no Bluetooth adapters found"),
+            }) as Box<dyn Error>);
+        }
+
+        for adapter in &adapters {
+            adapter.start_scan(ScanFilter::default()).await?;
+        }
+        tokio::time::sleep(duration).await;
+
+        let scan_time = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis();
+        let mut devices = Vec::<BleDevice>::new();
+        for adapter in &adapters {
+            adapter.stop_scan().await?;
+
+            for peripheral in adapter.peripherals().await? {
+                // Devices remembered from earlier scans have no strength
+                let properties = match peripheral.properties().await? {
+                    Some(p) if p.rssi.is_some() => p,
+                    _ => continue,
+                };
+                let address = properties.address.to_string().to_lowercase();
+                if devices.iter().any(|d| d.address == address) {
+                    continue;
+                }
+
+                let (kind, beacon_id) = classify(&properties);
+                devices.push(BleDevice{
+                    address,
+                    name: properties.local_name.clone(),
+                    kind,
+                    beacon_id,
+                    strength: properties.rssi.map(|r| r as f32),
+                    tx_power: properties.tx_power_level,
+                    time_scanned: scan_time,
+                });
+            }
+        }
+
+        Ok(devices)
+    })
+}
+
+/// Bluetooth scans are not available in builds without the ble feature.
+#[cfg(not(feature = "ble"))]
+pub fn scan(_duration: Duration) -> Result<Vec<BleDevice>, Box<dyn Error>> {
+    Err(Box::new(BleError{
+        reason: String::from("this build does not support Bluetooth, rebuild with --features ble"),
+    }))
+}
@@ -0,0 +1,492 @@
+//! Derives networks from the beacon frames a monitor mode interface captures. Access points send around ten beacons
+//! a second, so every beacon adds a strength sample, where an active scan yields one per network. Needs libpcap,
+//! and is only built with the capture feature. Probe requests sent by client devices can be captured the same way.
+
+use std::error::Error;
+use std::fmt;
+use std::time::Duration;
+
+use crate::iw::Bss;
+
+/// Error which occurs during a capture.
+#[derive(Debug)]
+pub struct CaptureError {
+    /// Reason the capture failed.
+    reason: String,
+}
+
+impl fmt::Display for CaptureError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "capture: {}", self.reason)
+    }
+}
+
+impl Error for CaptureError {}
+
+/// A captured beacon frame.
+pub struct Beacon {
+    /// Details of the sender, signal is the strength the beacon was received with.
+    pub bss: Bss,
+
+    /// Noise floor in dBm when the beacon was received. None if the driver did not report it.
+    pub noise: Option<f32>,
+}
+
+/// A captured probe request, sent by a client device looking for networks.
+pub struct ProbeRequest {
+    /// Hardware address of the client, lowercase.
+    pub mac: String,
+
+    /// Network the client asked for, empty if it asked for any network.
+    pub ssid: String,
+
+    /// Strength the request was received with in dBm. None if the driver did not report it.
+    pub signal: Option<f32>,
+}
+
+/// Channels which are listened on in turn: 2.4 GHz, then 5 GHz.
+#[cfg(feature = "capture")]
+const CHANNELS: [u32; 38] = [
+    1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13,
+    36, 40, 44, 48, 52, 56, 60, 64, 100, 104, 108, 112, 116, 120, 124, 128, 132, 136, 140, 144, 149, 153, 157, 161, 165,
+];
+
+/// Names of the capability field bits, in the order iw prints them.
+#[cfg(feature = "capture")]
+const CAPABILITIES: [&str; 16] = [
+    "ESS", "IBSS", "CfPollable", "CfPollReq", "Privacy", "ShortPreamble", "PBCC", "ChannelAgility",
+    "SpectrumMgmt", "QoS", "ShortSlotTime", "APSD", "RadioMeasure", "DSSS-OFDM", "DelayedBACK", "ImmediateBACK",
+];
+
+/// Fields of the radiotap header which precede the beacon.
+#[cfg(feature = "capture")]
+#[derive(Default)]
+struct Radiotap {
+    /// Length of the header in bytes.
+    len: usize,
+
+    /// True if the frame ends with a 4 byte checksum.
+    has_fcs: bool,
+
+    /// Frequency in MHz.
+    freq: Option<u32>,
+
+    /// Signal strength in dBm.
+    signal: Option<f32>,
+
+    /// Noise in dBm.
+    noise: Option<f32>,
+}
+
+/// Puts an interface in monitor mode, and back into managed mode when dropped.
+#[cfg(feature = "capture")]
+struct Monitor<'a> {
+    interface: &'a str,
+}
+
+#[cfg(feature = "capture")]
+impl<'a> Monitor<'a> {
+    fn enable(interface: &'a str) -> Result<Monitor<'a>, Box<dyn Error>> {
+        crate::iw::set_type(interface, "monitor")?;
+
+        Ok(Monitor{ interface })
+    }
+}
+
+#[cfg(feature = "capture")]
+impl Drop for Monitor<'_> {
+    fn drop(&mut self) {
+        if let Err(e) = crate::iw::set_type(self.interface, "managed") {
+            crate::logging::warn(&format!("Failed to switch {} back to managed mode: {}", self.interface, e));
+        }
+    }
+}
+
+/// Read a little endian u16 at offset.
+#[cfg(feature = "capture")]
+fn u16_at(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes([*data.get(offset)?, *data.get(offset + 1)?]))
+}
+
+/// Read a little endian u32 at offset.
+#[cfg(feature = "capture")]
+fn u32_at(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes([*data.get(offset)?, *data.get(offset + 1)?, *data.get(offset + 2)?, *data.get(offset + 3)?]))
+}
+
+/// Parse the radiotap header at the start of a captured frame. Only the fields of the first namespace up to the
+/// antenna noise are read, since the header must be walked in order and later fields are not needed.
+#[cfg(feature = "capture")]
+fn parse_radiotap(data: &[u8]) -> Option<Radiotap> {
+    if *data.first()? != 0 {
+        return None;
+    }
+
+    let mut header = Radiotap{
+        len: u16_at(data, 2)? as usize,
+        ..Radiotap::default()
+    };
+    let present = u32_at(data, 4)?;
+
+    // Further present words follow while the extension bit is set
+    let mut offset = 8;
+    let mut word = present;
+    while word & (1 << 31) != 0 {
+        word = u32_at(data, offset)?;
+        offset += 4;
+    }
+
+    // Alignment and size of TSFT, flags, rate, channel, FHSS, antenna signal, and antenna noise
+    let fields: [(usize, usize); 7] = [(8, 8), (1, 1), (1, 1), (2, 4), (1, 2), (1, 1), (1, 1)];
+    for (bit, (align, size)) in fields.iter().enumerate() {
+        if present & (1 << bit) == 0 {
+            continue;
+        }
+
+        offset = offset.next_multiple_of(*align);
+        let field = data.get(offset..offset + size)?;
+        match bit {
+            1 => header.has_fcs = field[0] & 0x10 != 0,
+            3 => header.freq = Some(u16_at(field, 0)? as u32),
+            5 => header.signal = Some(field[0] as i8 as f32),
+            6 => header.noise = Some(field[0] as i8 as f32),
+            _ => (),
+        };
+        offset += size;
+    }
+
+    Some(header)
+}
+
+/// Authentication suites of an RSN or WPA element body, starting at its version field.
+#[cfg(feature = "capture")]
+fn parse_auth_suites(data: &[u8]) -> Vec<String> {
+    // Version and group cipher, then the pairwise ciphers
+    let pairwise = match u16_at(data, 6) {
+        Some(n) => n as usize,
+        None => return Vec::new(),
+    };
+    let akm_offset = 8 + pairwise * 4;
+    let count = u16_at(data, akm_offset).unwrap_or(0) as usize;
+
+    (0..count)
+        .filter_map(|i| data.get(akm_offset + 2 + i * 4 + 3))
+        .filter_map(|suite| match suite {
+            1 | 3 | 5 => Some(String::from("802.1X")),
+            2 | 4 | 6 => Some(String::from("PSK")),
+            8 | 9 => Some(String::from("SAE")),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Parse an 802.11 beacon frame into a Bss. None if the frame is not a beacon or is truncated.
+#[cfg(feature = "capture")]
+fn parse_beacon(frame: &[u8]) -> Option<Bss> {
+    // Frame control of a management frame with the beacon subtype
+    if *frame.first()? != 0x80 {
+        return None;
+    }
+
+    let mac = mac_at(frame, 16)?;
+    // Timestamp, beacon interval, and capabilities, then the information elements
+    let body = frame.get(24..)?;
+    let capability = u16_at(body, 10)?;
+
+    let mut bss = Bss{
+        mac,
+        beacon_interval: u16_at(body, 8).map(|i| i as u32),
+        capabilities: CAPABILITIES.iter()
+            .enumerate()
+            .filter(|(bit, _)| capability & (1 << bit) != 0)
+            .map(|(_, c)| c.to_string())
+            .collect(),
+        ..Bss::default()
+    };
+
+    let mut elements = body.get(12..)?;
+    while elements.len() >= 2 {
+        let (id, len) = (elements[0], elements[1] as usize);
+        let data = match elements.get(2..2 + len) {
+            Some(d) => d,
+            None => break,
+        };
+        elements = &elements[2 + len..];
+
+        let name = match (id, data.first()) {
+            (0, _) => {
+                bss.ssid = Some(String::from_utf8_lossy(data).into_owned());
+                "SSID"
+            },
+            (1, _) => "Supported rates",
+            (3, Some(channel)) => {
+                bss.channel = Some(channel.to_string());
+                "DS Parameter set"
+            },
+            (5, _) => "TIM",
+            (7, _) => "Country",
+            (45, _) => {
+                bss.standard = bss.standard.max(Some(crate::radio::Standard::N));
+                "HT capabilities"
+            },
+            (48, _) => {
+                bss.rsn = true;
+                bss.auth_suites.extend(parse_auth_suites(data));
+                "RSN"
+            },
+            (61, _) => {
+                // Secondary channel offset is above or below for 40 MHz channels
+                bss.ht_width = match data.get(1).map(|b| b & 0x3) {
+                    Some(1) | Some(3) => Some(40),
+                    _ => Some(20),
+                };
+                "HT operation"
+            },
+            (191, _) => {
+                bss.standard = bss.standard.max(Some(crate::radio::Standard::Ac));
+                "VHT capabilities"
+            },
+            (192, Some(width)) => {
+                bss.vht_width = match width {
+                    1 => Some(80),
+                    2 | 3 => Some(160),
+                    _ => None,
+                };
+                "VHT operation"
+            },
+            (221, _) if data.starts_with(&[0x00, 0x50, 0xf2, 0x01]) => {
+                bss.wpa = true;
+                if !bss.rsn {
+                    bss.auth_suites.extend(parse_auth_suites(&data[4..]));
+                }
+                "WPA"
+            },
+            (221, _) => "Vendor specific",
+            (255, Some(35)) => {
+                bss.standard = bss.standard.max(Some(crate::radio::Standard::Ax));
+                "HE capabilities"
+            },
+            (255, Some(108)) => {
+                bss.standard = bss.standard.max(Some(crate::radio::Standard::Be));
+                "EHT capabilities"
+            },
+            _ => continue,
+        };
+
+        if !bss.elements.iter().any(|e| e == name) {
+            bss.elements.push(name.to_string());
+        }
+    }
+
+    Some(bss)
+}
+
+/// Format the hardware address at offset of a frame.
+#[cfg(feature = "capture")]
+fn mac_at(frame: &[u8], offset: usize) -> Option<String> {
+    Some(frame.get(offset..offset + 6)?.iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<Vec<String>>()
+        .join(":"))
+}
+
+/// Parse a captured probe request with its radiotap header. None if the frame is not a probe request or is truncated.
+#[cfg(feature = "capture")]
+fn parse_probe_frame(data: &[u8]) -> Option<ProbeRequest> {
+    let header = parse_radiotap(data)?;
+    let frame = data.get(header.len..)?;
+
+    // Frame control of a management frame with the probe request subtype
+    if *frame.first()? != 0x40 {
+        return None;
+    }
+
+    // The SSID element comes first, right after the header
+    let ssid = match frame.get(24..26) {
+        Some([0, len]) => frame.get(26..26 + *len as usize).map(|s| String::from_utf8_lossy(s).into_owned()),
+        _ => None,
+    };
+
+    Some(ProbeRequest{
+        mac: mac_at(frame, 10)?,
+        ssid: ssid.unwrap_or_default(),
+        signal: header.signal,
+    })
+}
+
+/// Parse a captured frame with its radiotap header.
+#[cfg(feature = "capture")]
+fn parse_frame(data: &[u8]) -> Option<Beacon> {
+    let header = parse_radiotap(data)?;
+    let end = match header.has_fcs {
+        true => data.len().checked_sub(4)?,
+        false => data.len(),
+    };
+
+    let mut bss = parse_beacon(data.get(header.len..end)?)?;
+    bss.freq = header.freq;
+    bss.signal = header.signal;
+
+    Some(Beacon{
+        bss,
+        noise: header.noise,
+    })
+}
+
+/// Switch interface to monitor mode and listen on each channel for dwell, collecting the frames which match filter
+/// and parse into a value. Channels the interface does not support are skipped. The interface is switched back to
+/// managed mode afterwards.
+#[cfg(feature = "capture")]
+fn listen<T>(interface: &str, dwell: Duration, filter: &str, parse: impl Fn(&[u8]) -> Option<T>) -> Result<Vec<T>, Box<dyn Error>> {
+    use std::time::Instant;
+
+    let _monitor = Monitor::enable(interface)?;
+
+    let mut capture = pcap::Capture::from_device(interface)?
+        .timeout(100)
+        .open()?;
+    if capture.get_datalink() != pcap::Linktype::IEEE802_11_RADIOTAP {
+        return Err(Box::new(CaptureError{
+            reason: format!("{} did not switch to monitor mode", interface),
+        }));
+    }
+    capture.filter(filter, true)?;
+
+    let mut frames = Vec::<T>::new();
+    for channel in CHANNELS {
+        if crate::iw::set_channel(interface, channel).is_err() {
+            continue;
+        }
+
+        let started = Instant::now();
+        while started.elapsed() < dwell {
+            match capture.next_packet() {
+                Ok(packet) => frames.extend(parse(packet.data)),
+                Err(pcap::Error::TimeoutExpired) => continue,
+                Err(e) => return Err(Box::new(e)),
+            };
+        }
+    }
+
+    Ok(frames)
+}
+
+/// Collect the beacons heard with interface, listening on each channel for dwell.
+#[cfg(feature = "capture")]
+pub fn capture(interface: &str, dwell: Duration) -> Result<Vec<Beacon>, Box<dyn Error>> {
+    listen(interface, dwell, "type mgt subtype beacon", parse_frame)
+}
+
+/// Collect the probe requests heard with interface, listening on each channel for dwell.
+#[cfg(feature = "capture")]
+pub fn probe_requests(interface: &str, dwell: Duration) -> Result<Vec<ProbeRequest>, Box<dyn Error>> {
+    listen(interface, dwell, "type mgt subtype probe-req", parse_probe_frame)
+}
+
+/// Error for builds without the capture feature.
+#[cfg(not(feature = "capture"))]
+fn unsupported() -> Box<dyn Error> {
+    Box::new(CaptureError{
+        reason: String::from("this build does not support packet capture, rebuild with --features capture"),
+    })
+}
+
+/// Packet capture is not available in builds without the capture feature.
+#[cfg(not(feature = "capture"))]
+pub fn capture(_interface: &str, _dwell: Duration) -> Result<Vec<Beacon>, Box<dyn Error>> {
+    Err(unsupported())
+}
+
+/// Packet capture is not available in builds without the capture feature.
+#[cfg(not(feature = "capture"))]
+pub fn probe_requests(_interface: &str, _dwell: Duration) -> Result<Vec<ProbeRequest>, Box<dyn Error>> {
+    Err(unsupported())
+}
+
+#[cfg(all(test, feature = "capture"))]
+mod tests {
+    use super::*;
+    use crate::radio::Standard;
+    use crate::security::Security;
+
+    /// Bytes of a hex dump, lines starting with # are comments.
+    fn hex(dump: &str) -> Vec<u8> {
+        dump.lines()
+            .filter(|l| !l.starts_with('#'))
+            .flat_map(|l| l.split_whitespace())
+            .map(|b| u8::from_str_radix(b, 16).expect("fixture is hex"))
+            .collect()
+    }
+
+    #[test]
+    fn parse_radiotap_reads_the_fields_before_the_frame() {
+        let data = hex(include_str!("../tests/fixtures/beacon.hex"));
+        let header = parse_radiotap(&data).expect("radiotap header");
+        assert_eq!(header.len, 16);
+        assert!(header.has_fcs);
+        assert_eq!(header.freq, Some(5180));
+        assert_eq!(header.signal, Some(-58.0));
+        assert_eq!(header.noise, Some(-92.0));
+    }
+
+    #[test]
+    fn parse_radiotap_skips_extended_present_words() {
+        // Antenna signal and the extension bit, then a second present word
+        let data = [0, 0, 13, 0, 0x20, 0, 0, 0x80, 0, 0, 0, 0, 0xb5];
+        let header = parse_radiotap(&data).expect("radiotap header");
+        assert_eq!(header.signal, Some(-75.0));
+        assert_eq!(header.freq, None);
+        assert!(!header.has_fcs);
+    }
+
+    #[test]
+    fn parse_radiotap_rejects_other_versions_and_truncated_headers() {
+        assert!(parse_radiotap(&[1, 0, 8, 0, 0, 0, 0, 0]).is_none());
+        assert!(parse_radiotap(&[0, 0, 16, 0, 0x20, 0, 0, 0]).is_none());
+        assert!(parse_radiotap(&[]).is_none());
+    }
+
+    #[test]
+    fn parse_beacon_reads_the_information_elements() {
+        let data = hex(include_str!("../tests/fixtures/beacon.hex"));
+        let bss = parse_beacon(&data[16..data.len() - 4]).expect("beacon");
+        assert_eq!(bss.mac, "84:d4:7e:69:2f:e2");
+        assert_eq!(bss.ssid.as_deref(), Some("CorpWiFi"));
+        assert_eq!(bss.channel.as_deref(), Some("36"));
+        assert_eq!(bss.beacon_interval, Some(100));
+        assert_eq!(bss.capabilities, vec!["ESS", "Privacy", "SpectrumMgmt"]);
+        assert_eq!(bss.security(), Security::Enterprise);
+        assert_eq!(bss.standard(), Standard::Ax);
+        assert_eq!(bss.ht_width, Some(40));
+        assert_eq!(bss.channel_width(), 80);
+        assert_eq!(bss.elements, vec!["SSID", "Supported rates", "DS Parameter set", "RSN", "HT capabilities", "HT operation", "VHT capabilities", "VHT operation", "HE capabilities", "Vendor specific"]);
+    }
+
+    #[test]
+    fn parse_frame_takes_signal_and_frequency_from_radiotap() {
+        let beacon = parse_frame(&hex(include_str!("../tests/fixtures/beacon.hex"))).expect("beacon");
+        assert_eq!(beacon.bss.freq, Some(5180));
+        assert_eq!(beacon.bss.signal, Some(-58.0));
+        assert_eq!(beacon.noise, Some(-92.0));
+    }
+
+    #[test]
+    fn parse_beacon_rejects_other_frames() {
+        let data = hex(include_str!("../tests/fixtures/beacon.hex"));
+        let mut probe = data[16..].to_vec();
+        probe[0] = 0x40;
+        assert!(parse_beacon(&probe).is_none());
+        assert!(parse_beacon(&data[16..30]).is_none());
+    }
+
+    #[test]
+    fn parse_auth_suites_reads_every_akm() {
+        // Version, group cipher, one pairwise cipher, then PSK, SAE, and an unknown suite
+        let rsn = [1, 0, 0, 0x0f, 0xac, 4, 1, 0, 0, 0x0f, 0xac, 4, 3, 0, 0, 0x0f, 0xac, 2, 0, 0x0f, 0xac, 8, 0, 0x0f, 0xac, 18];
+        assert_eq!(parse_auth_suites(&rsn), vec!["PSK", "SAE"]);
+
+        // Count of suites longer than the element
+        assert_eq!(parse_auth_suites(&rsn[..22]), vec!["PSK", "SAE"]);
+        assert!(parse_auth_suites(&[1, 0]).is_empty());
+    }
+}
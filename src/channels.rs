@@ -0,0 +1,533 @@
+//! Measures channel congestion across the surveyed area: in each region of the map, how many
+//! BSSIDs share or overlap each channel, band by band. Also recommends a channel for each of
+//! one's own access points from the neighbors heard around it.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::locate::{self, LocateOptions};
+use crate::radio::{self, Band};
+use crate::{CoordinateSystem, Node, ScanMap, EARTH_RADIUS};
+
+/// 2.4 GHz channels closer than this in MHz overlap, e.g. 1 overlaps 2 through 5 but not 6.
+const OVERLAP_MHZ: u32 = 25;
+
+/// Options which control how the map is divided and which networks count.
+pub struct ChannelOptions {
+    /// Width and height of each region, in the map's units, meters for wgs84 maps.
+    pub region: f64,
+
+    /// Only analyze nodes on this floor.
+    pub floor: Option<String>,
+
+    /// Networks weaker than this in dBm are too faint to congest a channel.
+    pub min_strength: f64,
+}
+
+/// Use of one channel in one region.
+#[derive(Serialize)]
+pub struct ChannelUse {
+    pub floor: Option<String>,
+
+    /// Bounds of the region, min_x, min_y, max_x, max_y.
+    pub region: (f64, f64, f64, f64),
+
+    pub band: Band,
+
+    pub channel: u32,
+
+    /// BSSIDs on the channel.
+    pub co_channel: usize,
+
+    /// BSSIDs on other channels which overlap it, only possible in the 2.4 GHz band.
+    pub overlapping: usize,
+
+    /// Number of nodes in the region.
+    pub nodes: usize,
+}
+
+impl ChannelUse {
+    /// BSSIDs which compete for the channel's airtime.
+    pub fn congestion(&self) -> usize {
+        self.co_channel + self.overlapping
+    }
+}
+
+/// Channel, center frequency, and band of a network. None if the channel cannot be parsed.
+fn channel_of(channel: &str, frequency: Option<u32>, band: Option<Band>) -> Option<(u32, u32, Band)> {
+    let number = radio::parse_channel(channel)?;
+    let frequency = frequency.or_else(|| radio::channel_to_frequency(channel))?;
+    let band = band.or_else(|| Band::from_frequency(frequency))?;
+
+    Some((number, frequency, band))
+}
+
+/// Count the BSSIDs on and overlapping each channel in each region, the most congested first.
+pub fn analyze_channels(scan_map: &ScanMap, opts: &ChannelOptions) -> Vec<ChannelUse> {
+    let nodes: Vec<_> = scan_map.nodes.iter()
+        .filter(|n| opts.floor.is_none() || n.floor == opts.floor)
+        .collect();
+    if nodes.is_empty() || opts.region <= 0.0 {
+        return Vec::new();
+    }
+
+    // Size of a region in map coordinates
+    let size = match scan_map.coordinate_system {
+        CoordinateSystem::Local => (opts.region, opts.region),
+        CoordinateSystem::Wgs84 => {
+            let lat = nodes.iter().map(|n| n.position.y).sum::<f64>() / nodes.len() as f64;
+            ((opts.region / (EARTH_RADIUS * lat.to_radians().cos())).to_degrees(), (opts.region / EARTH_RADIUS).to_degrees())
+        },
+    };
+
+    // BSSIDs heard in each region, with where they broadcast
+    let mut regions = BTreeMap::<(Option<String>, i64, i64), (usize, HashMap<&str, (u32, u32, Band)>)>::new();
+    for node in &nodes {
+        let key = (node.floor.clone(), (node.position.x / size.0).floor() as i64, (node.position.y / size.1).floor() as i64);
+        let (count, bssids) = regions.entry(key).or_default();
+        *count += 1;
+
+        for network in &node.networks {
+            if network.strength.is_none_or(|s| (s as f64) < opts.min_strength) {
+                continue;
+            }
+            if let Some(p) = channel_of(&network.channel, network.frequency, network.band) {
+                bssids.insert(&network.mac, p);
+            }
+        }
+    }
+
+    let mut uses = Vec::<ChannelUse>::new();
+    for ((floor, column, row), (count, bssids)) in regions {
+        let mut channels: Vec<(Band, u32, u32)> = bssids.values().map(|(c, f, b)| (*b, *c, *f)).collect();
+        channels.sort();
+        channels.dedup_by(|a, b| a.0 == b.0 && a.1 == b.1);
+
+        for (band, channel, frequency) in channels {
+            let co_channel = bssids.values().filter(|(c, _, b)| *b == band && *c == channel).count();
+            let overlapping = match band {
+                Band::TwoPointFour => bssids.values()
+                    .filter(|(c, f, b)| *b == band && *c != channel && f.abs_diff(frequency) < OVERLAP_MHZ)
+                    .count(),
+                _ => 0,
+            };
+
+            uses.push(ChannelUse{
+                floor: floor.clone(),
+                region: (column as f64 * size.0, row as f64 * size.1, (column + 1) as f64 * size.0, (row + 1) as f64 * size.1),
+                band,
+                channel,
+                co_channel,
+                overlapping,
+                nodes: count,
+            });
+        }
+    }
+
+    // Sorting is stable so ties stay ordered by region, band, and channel
+    uses.sort_by_key(|u| std::cmp::Reverse(u.congestion()));
+    uses
+}
+
+/// Print a table of channel uses, after the most congested channel of each band.
+pub fn print_channels(scan_map: &ScanMap, uses: &[ChannelUse]) {
+    // Degrees need more digits than lengths
+    let precision = match scan_map.coordinate_system {
+        CoordinateSystem::Local => 2,
+        CoordinateSystem::Wgs84 => 6,
+    };
+    let region = |u: &ChannelUse| {
+        let bounds = format!("{:.*},{:.*} to {:.*},{:.*}", precision, u.region.0, precision, u.region.1, precision, u.region.2, precision, u.region.3);
+        match &u.floor {
+            Some(floor) => format!("{} on floor {}", bounds, floor),
+            None => bounds,
+        }
+    };
+
+    for band in [Band::TwoPointFour, Band::Five, Band::Six] {
+        if let Some(worst) = uses.iter().find(|u| u.band == band) {
+            println!("Most congested {} channel: {} in {}, {} BSSID{}", band, worst.channel, region(worst), worst.congestion(), match worst.congestion() != 1 {
+                true => "s",
+                false => "",
+            });
+        }
+    }
+    println!();
+
+    let rows: Vec<Vec<String>> = uses.iter()
+        .map(|u| vec![
+            region(u),
+            u.band.to_string(),
+            u.channel.to_string(),
+            u.co_channel.to_string(),
+            u.overlapping.to_string(),
+            u.congestion().to_string(),
+            u.nodes.to_string(),
+        ])
+        .collect();
+
+    crate::table::print_table(&["Region", "Band", "Channel", "Co-channel", "Overlapping", "Total", "Nodes"], &rows);
+}
+
+/// Regulatory domains, which decide the channels an access point may use.
+#[derive(Clone, Copy)]
+pub enum Domain {
+    /// United States and the other countries which follow the FCC.
+    Fcc,
+
+    /// Europe and the other countries which follow ETSI.
+    Etsi,
+}
+
+impl fmt::Display for Domain {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Domain::Fcc => write!(f, "fcc"),
+            Domain::Etsi => write!(f, "etsi"),
+        }
+    }
+}
+
+impl std::str::FromStr for Domain {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Domain, String> {
+        match s.to_lowercase().as_str() {
+            "fcc" => Ok(Domain::Fcc),
+            "etsi" => Ok(Domain::Etsi),
+            _ => Err(format!("unknown regulatory domain \"{}\", must be fcc or etsi", s)),
+        }
+    }
+}
+
+impl Domain {
+    /// 20 MHz channels access points may use in band.
+    pub fn channels(&self, band: Band) -> Vec<u32> {
+        match (self, band) {
+            (Domain::Fcc, Band::TwoPointFour) => (1..=11).collect(),
+            (Domain::Etsi, Band::TwoPointFour) => (1..=13).collect(),
+            (Domain::Fcc, Band::Five) => (36..=64).step_by(4).chain((100..=144).step_by(4)).chain((149..=165).step_by(4)).collect(),
+            (Domain::Etsi, Band::Five) => (36..=64).step_by(4).chain((100..=140).step_by(4)).collect(),
+            (Domain::Fcc, Band::Six) => (1..=233).step_by(4).collect(),
+            (Domain::Etsi, Band::Six) => (1..=93).step_by(4).collect(),
+        }
+    }
+}
+
+/// True if access points on a 5 GHz channel must detect radar and give way to it.
+pub fn is_dfs(band: Band, channel: u32) -> bool {
+    band == Band::Five && (52..=144).contains(&channel)
+}
+
+/// Center frequency in MHz of a channel in band.
+fn frequency(band: Band, channel: u32) -> u32 {
+    match (band, channel) {
+        (Band::TwoPointFour, 14) => 2484,
+        (Band::TwoPointFour, c) => 2407 + 5 * c,
+        (Band::Five, c) => 5000 + 5 * c,
+        (Band::Six, c) => 5950 + 5 * c,
+    }
+}
+
+/// Options which control how channels are recommended.
+pub struct RecommendOptions {
+    /// Networks whose BSSIDs channels are recommended for.
+    pub ssids: Vec<String>,
+
+    pub domain: Domain,
+
+    /// Recommend channels which require radar detection.
+    pub dfs: bool,
+
+    /// Neighbors heard by nodes within this distance of an access point contend with it, in the map's units.
+    pub radius: f64,
+
+    /// Networks weaker than this in dBm are too faint to contend.
+    pub min_strength: f64,
+
+    /// Path loss model and which access points are located, if the map has no saved locations.
+    pub locate: LocateOptions,
+}
+
+/// Channel recommended for one access point.
+#[derive(Serialize)]
+pub struct Recommendation {
+    pub bssid: String,
+
+    pub ssid: String,
+
+    pub band: Band,
+
+    /// Channel the access point was heard on.
+    pub current: u32,
+
+    /// Neighbors on or overlapping the current channel.
+    pub current_contention: usize,
+
+    pub recommended: u32,
+
+    /// Neighbors on or overlapping the recommended channel.
+    pub contention: usize,
+
+    /// True if the recommended channel requires radar detection.
+    pub dfs: bool,
+
+    /// Number of nodes near the access point whose neighbors were counted.
+    pub nodes: usize,
+}
+
+/// Neighbors which contend with a channel: those on it and, in the 2.4 GHz band, those on overlapping channels.
+/// Ties between channels are broken by the strongest contender, in dBm.
+fn contention(neighbors: &[(u32, Band, f64)], band: Band, channel: u32) -> (usize, f64) {
+    let contenders: Vec<f64> = neighbors.iter()
+        .filter(|(c, b, _)| *b == band && match band {
+            Band::TwoPointFour => frequency(band, *c).abs_diff(frequency(band, channel)) < OVERLAP_MHZ,
+            _ => *c == channel,
+        })
+        .map(|(_, _, strength)| *strength)
+        .collect();
+
+    (contenders.len(), contenders.iter().copied().fold(f64::NEG_INFINITY, f64::max))
+}
+
+/// Recommend the least contended channel for each BSSID of opts.ssids. Access points are handled in order of BSSID,
+/// and the channels recommended for earlier ones are used when they are neighbors of later ones.
+pub fn recommend(scan_map: &ScanMap, opts: &RecommendOptions) -> Vec<Recommendation> {
+    // Saved locations are used as they are, they may have been located with a tuned model
+    let computed;
+    let located = match scan_map.access_points.is_empty() {
+        true => {
+            computed = locate::locate(scan_map, &opts.locate);
+            &computed
+        },
+        false => &scan_map.access_points,
+    };
+
+    // Channel each BSSID was heard on
+    let mut heard = HashMap::<String, (u32, Band)>::new();
+    for network in scan_map.nodes.iter().flat_map(|n| n.networks.iter()) {
+        if let Some((channel, _, band)) = channel_of(&network.channel, network.frequency, network.band) {
+            heard.insert(network.mac.to_lowercase(), (channel, band));
+        }
+    }
+
+    let mut assigned = HashMap::<String, u32>::new();
+    let mut recommendations = Vec::<Recommendation>::new();
+    for (mac, location) in located {
+        if !opts.ssids.contains(&location.ssid) || opts.locate.floor.as_ref().is_some_and(|f| location.floor.as_ref() != Some(f)) {
+            continue;
+        }
+        let (current, band) = match heard.get(mac) {
+            Some(h) => *h,
+            None => continue,
+        };
+
+        // Nodes near the access point, the nearest one if none is within the radius
+        let distances: Vec<(&Node, f64)> = scan_map.nodes.iter()
+            .filter(|n| n.floor == location.floor)
+            .map(|n| (n, n.position.distance(&location.position, scan_map.coordinate_system)))
+            .collect();
+        let mut nearby: Vec<&Node> = distances.iter().filter(|(_, d)| *d <= opts.radius).map(|(n, _)| *n).collect();
+        if nearby.is_empty() {
+            if let Some((n, _)) = distances.iter().min_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal)) {
+                nearby.push(n);
+            }
+        }
+
+        // Strongest strength of each other BSSID in the band heard near the access point
+        let mut strongest = HashMap::<String, (u32, f64)>::new();
+        for network in nearby.iter().flat_map(|n| n.networks.iter()) {
+            let other = network.mac.to_lowercase();
+            let strength = match network.strength {
+                Some(s) if s as f64 >= opts.min_strength => s as f64,
+                _ => continue,
+            };
+            if other == *mac {
+                continue;
+            }
+            let channel = match (assigned.get(&other), heard.get(&other)) {
+                (Some(c), Some((_, b))) if *b == band => *c,
+                (None, Some((c, b))) if *b == band => *c,
+                _ => continue,
+            };
+            let entry = strongest.entry(other).or_insert((channel, strength));
+            entry.1 = entry.1.max(strength);
+        }
+        let neighbors: Vec<(u32, Band, f64)> = strongest.into_values().map(|(c, s)| (c, band, s)).collect();
+
+        // Staying on the current channel wins ties, so access points are not moved for nothing
+        let current_contention = contention(&neighbors, band, current);
+        let (recommended, best) = opts.domain.channels(band).into_iter()
+            .filter(|c| opts.dfs || !is_dfs(band, *c))
+            .map(|c| (c, contention(&neighbors, band, c)))
+            .fold((current, current_contention), |best, candidate| match candidate.1.0 < best.1.0 || (candidate.1.0 == best.1.0 && candidate.1.1 < best.1.1) {
+                true => candidate,
+                false => best,
+            });
+
+        assigned.insert(mac.clone(), recommended);
+        recommendations.push(Recommendation{
+            bssid: mac.clone(),
+            ssid: location.ssid.clone(),
+            band,
+            current,
+            current_contention: current_contention.0,
+            recommended,
+            contention: best.0,
+            dfs: is_dfs(band, recommended),
+            nodes: nearby.len(),
+        });
+    }
+
+    recommendations
+}
+
+/// Print a table of recommendations.
+pub fn print_recommendations(recommendations: &[Recommendation]) {
+    let rows: Vec<Vec<String>> = recommendations.iter()
+        .map(|r| vec![
+            r.bssid.clone(),
+            r.ssid.clone(),
+            r.band.to_string(),
+            format!("{} ({})", r.current, r.current_contention),
+            match r.recommended == r.current {
+                true => String::from("keep"),
+                false => format!("{} ({}){}", r.recommended, r.contention, match r.dfs {
+                    true => " DFS",
+                    false => "",
+                }),
+            },
+            r.nodes.to_string(),
+        ])
+        .collect();
+
+    crate::table::print_table(&["BSSID", "SSID", "Band", "Current", "Recommended", "Nodes"], &rows);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::scan_map;
+
+    /// A crowded region at the origin and a quiet one to the east.
+    fn survey() -> ScanMap {
+        let network = |mac: &str, channel: &str, strength: f64| serde_json::json!({"mac": mac, "channel": channel, "strength": strength});
+        scan_map(serde_json::json!({
+            "nodes": [
+                {"position": {"x": 1.0, "y": 1.0, "z": 0.0}, "networks": [
+                    network("00:00:00:00:00:01", "1", -50.0),
+                    network("00:00:00:00:00:02", "1", -60.0),
+                    network("00:00:00:00:00:03", "3", -70.0),
+                    network("00:00:00:00:00:04", "6", -70.0),
+                    network("00:00:00:00:00:05", "36", -60.0),
+                    network("00:00:00:00:00:06", "11", -95.0),
+                ]},
+                {"position": {"x": 4.0, "y": 2.0, "z": 0.0}, "networks": [
+                    network("00:00:00:00:00:01", "1", -55.0),
+                ]},
+                {"position": {"x": 15.0, "y": 1.0, "z": 0.0}, "networks": [
+                    network("00:00:00:00:00:07", "6", -50.0),
+                ]},
+            ],
+        }))
+    }
+
+    fn opts() -> ChannelOptions {
+        ChannelOptions{
+            region: 10.0,
+            floor: None,
+            min_strength: -90.0,
+        }
+    }
+
+    #[test]
+    fn analyze_channels_counts_co_channel_and_overlapping_bssids() {
+        let uses = analyze_channels(&survey(), &opts());
+        let summary: Vec<(f64, u32, usize, usize, usize)> = uses.iter()
+            .map(|u| (u.region.0, u.channel, u.co_channel, u.overlapping, u.nodes))
+            .collect();
+
+        // Channel 6 is 25 MHz from channel 1, far enough not to overlap, and channel 11 is too faint to count
+        assert_eq!(summary, vec![
+            (0.0, 3, 1, 3, 2),
+            (0.0, 1, 2, 1, 2),
+            (0.0, 6, 1, 1, 2),
+            (0.0, 36, 1, 0, 2),
+            (10.0, 6, 1, 0, 1),
+        ]);
+        assert_eq!(uses[0].congestion(), 4);
+        assert_eq!(uses[3].band, Band::Five);
+        assert_eq!(uses[4].region, (10.0, 0.0, 20.0, 10.0));
+    }
+
+    #[test]
+    fn analyze_channels_needs_nodes_and_a_region() {
+        assert!(analyze_channels(&survey(), &ChannelOptions{ floor: Some(String::from("F2")), ..opts() }).is_empty());
+        assert!(analyze_channels(&survey(), &ChannelOptions{ region: 0.0, ..opts() }).is_empty());
+    }
+
+    #[test]
+    fn domain_channels_and_dfs() {
+        assert_eq!(Domain::Fcc.channels(Band::TwoPointFour).len(), 11);
+        assert_eq!(Domain::Etsi.channels(Band::TwoPointFour).len(), 13);
+        assert_eq!(Domain::Fcc.channels(Band::Five).len(), 25);
+        assert!(!Domain::Etsi.channels(Band::Five).contains(&149));
+        assert!(is_dfs(Band::Five, 52) && is_dfs(Band::Five, 144));
+        assert!(!is_dfs(Band::Five, 36) && !is_dfs(Band::TwoPointFour, 11));
+        assert!("ETSI".parse::<Domain>().is_ok() && "mkk".parse::<Domain>().is_err());
+    }
+
+    #[test]
+    fn recommend_moves_access_points_away_from_their_neighbors() {
+        let network = |mac: &str, ssid: &str, channel: &str, strength: f64| serde_json::json!({"mac": mac, "ssid": ssid, "channel": channel, "strength": strength});
+        let location = |x: f64| serde_json::json!({"ssid": "CorpWiFi", "position": {"x": x, "y": 0.0, "z": 0.0}, "floor": null, "radius": 1.0, "nodes": 2});
+        let scan_map = scan_map(serde_json::json!({
+            "nodes": [
+                {"position": {"x": 0.0, "y": 0.0, "z": 0.0}, "networks": [
+                    network("84:d4:7e:00:00:01", "CorpWiFi", "1", -40.0),
+                    network("84:d4:7e:00:00:02", "CorpWiFi", "6", -60.0),
+                    network("00:11:22:00:00:01", "Neighbor", "1", -50.0),
+                    network("00:11:22:00:00:02", "Neighbor", "6", -65.0),
+                ]},
+                {"position": {"x": 5.0, "y": 0.0, "z": 0.0}, "networks": [
+                    network("84:d4:7e:00:00:01", "CorpWiFi", "1", -60.0),
+                    network("84:d4:7e:00:00:02", "CorpWiFi", "6", -40.0),
+                    network("00:11:22:00:00:03", "Neighbor", "11", -55.0),
+                ]},
+            ],
+            "access_points": {
+                "84:d4:7e:00:00:01": location(0.0),
+                "84:d4:7e:00:00:02": location(5.0),
+            },
+        }));
+
+        let recommendations = recommend(&scan_map, &RecommendOptions{
+            ssids: vec![String::from("CorpWiFi")],
+            domain: Domain::Fcc,
+            dfs: false,
+            radius: 2.0,
+            min_strength: -90.0,
+            locate: LocateOptions{
+                path_loss: locate::PathLoss{
+                    reference: -40.0,
+                    exponent: 3.0,
+                    ..locate::PathLoss::default()
+                },
+                min_nodes: 1,
+                floor: None,
+            },
+        });
+        let summary: Vec<(&str, u32, usize, u32, usize)> = recommendations.iter()
+            .map(|r| (r.bssid.as_str(), r.current, r.current_contention, r.recommended, r.contention))
+            .collect();
+
+        // The first access point moves to 11, clear of both neighbors, which the second then no longer overlaps
+        assert_eq!(summary, vec![
+            ("84:d4:7e:00:00:01", 1, 1, 11, 0),
+            ("84:d4:7e:00:00:02", 6, 0, 6, 0),
+        ]);
+        assert!(recommendations.iter().all(|r| r.nodes == 1 && !r.dfs));
+    }
+}
@@ -0,0 +1,378 @@
+//! Defaults for command line flags, read from a TOML file so the same flags do not have to be
+//! typed for every survey. The file is ~/.config/wifi-mapper/config.toml unless --config names
+//! another one, and flags given on the command line replace its values:
+//!
+//! ```toml
+//! map-file = "office.json"
+//! backend = "iw"
+//! interface = ["wlan0", "wlan1"]
+//!
+//! [record]
+//! samples = 3
+//! only-ssid = ["CorpWiFi"]
+//!
+//! [path-loss]
+//! reference-power = -40
+//! exponent = 3
+//! ```
+
+use std::env;
+use std::error::Error;
+use std::ffi::OsString;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use clap::parser::ValueSource;
+use clap::{ArgMatches, Command};
+use serde::Deserialize;
+
+/// Error which occurs when the config file cannot be used.
+#[derive(Debug)]
+pub struct ConfigError {
+    /// Reason the file could not be used.
+    reason: String,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.reason)
+    }
+}
+
+impl Error for ConfigError {}
+
+/// Command line flag which a key of the config file sets.
+struct Setting {
+    /// Section of the key, empty for top level keys.
+    section: &'static str,
+
+    key: &'static str,
+
+    /// Name of the clap argument and its long flag.
+    arg: &'static str,
+    flag: &'static str,
+
+    /// Sub-commands which have the flag, by their names joined with spaces. Empty for global flags.
+    subcommands: &'static [&'static str],
+
+    /// Arguments which replace the setting when given, e.g. --all-interfaces replaces interface.
+    replaced_by: &'static [&'static str],
+}
+
+/// Sub-commands which model path loss, meta is left out since it saves the model to the map.
+const PATH_LOSS_SUBCOMMANDS: &[&str] = &["locate", "predict", "analyze placement", "analyze recommend"];
+
+/// Every key the config file may contain.
+const SETTINGS: &[Setting] = &[
+    Setting{ section: "", key: "map-file", arg: "map_file", flag: "map-file", subcommands: &[], replaced_by: &[] },
+    Setting{ section: "", key: "backend", arg: "backend", flag: "backend", subcommands: &[], replaced_by: &[] },
+    Setting{ section: "", key: "interface", arg: "interface", flag: "interface", subcommands: &[], replaced_by: &["all_interfaces"] },
+    Setting{ section: "record", key: "samples", arg: "samples", flag: "samples", subcommands: &["record"], replaced_by: &["continuous"] },
+    Setting{ section: "record", key: "only-ssid", arg: "only_ssid", flag: "only-ssid", subcommands: &["record"], replaced_by: &[] },
+    Setting{ section: "record", key: "ignore-ssid", arg: "ignore_ssid", flag: "ignore-ssid", subcommands: &["record"], replaced_by: &[] },
+    Setting{ section: "path-loss", key: "reference-power", arg: "reference_power", flag: "reference-power", subcommands: PATH_LOSS_SUBCOMMANDS, replaced_by: &[] },
+    Setting{ section: "path-loss", key: "exponent", arg: "path_loss_exponent", flag: "path-loss-exponent", subcommands: PATH_LOSS_SUBCOMMANDS, replaced_by: &[] },
+    Setting{ section: "path-loss", key: "wall-attenuation", arg: "wall_attenuation", flag: "wall-attenuation", subcommands: PATH_LOSS_SUBCOMMANDS, replaced_by: &[] },
+    Setting{ section: "path-loss", key: "wall-spacing", arg: "wall_spacing", flag: "wall-spacing", subcommands: PATH_LOSS_SUBCOMMANDS, replaced_by: &[] },
+];
+
+/// Number in the config file, TOML keeps integers and floats apart.
+#[derive(Deserialize)]
+#[serde(untagged, expecting = "expected a number")]
+enum Number {
+    Integer(i64),
+    Float(f64),
+}
+
+impl fmt::Display for Number {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Number::Integer(n) => write!(f, "{}", n),
+            Number::Float(n) => write!(f, "{}", n),
+        }
+    }
+}
+
+/// Value of a flag which may be given more than once.
+#[derive(Deserialize)]
+#[serde(untagged, expecting = "expected a string or a list of strings")]
+enum List {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl List {
+    fn values(self) -> Vec<String> {
+        match self {
+            List::One(s) => vec![s],
+            List::Many(values) => values,
+        }
+    }
+}
+
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+struct RecordSection {
+    samples: Option<Number>,
+    only_ssid: Option<List>,
+    ignore_ssid: Option<List>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+struct PathLossSection {
+    reference_power: Option<Number>,
+    exponent: Option<Number>,
+    wall_attenuation: Option<Number>,
+    wall_spacing: Option<Number>,
+}
+
+/// Contents of the config file, one field for every key of SETTINGS.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+struct File {
+    map_file: Option<String>,
+    backend: Option<String>,
+    interface: Option<List>,
+    record: Option<RecordSection>,
+    path_loss: Option<PathLossSection>,
+}
+
+/// Flags the config file sets, with their values as they would be typed.
+pub struct Config {
+    flags: Vec<(&'static Setting, Vec<String>)>,
+}
+
+impl Config {
+    /// Parse the text of a config file.
+    pub fn parse(text: &str) -> Result<Config, ConfigError> {
+        let file: File = toml::from_str(text).map_err(|e| {
+            let message = e.message().trim().replace('\n', ", ");
+            ConfigError{
+                reason: match e.span() {
+                    Some(span) => format!("line {}: {}", text[..span.start].matches('\n').count() + 1, message),
+                    None => message,
+                },
+            }
+        })?;
+
+        let mut config = Config{
+            flags: Vec::new(),
+        };
+        let record = file.record.unwrap_or_default();
+        let path_loss = file.path_loss.unwrap_or_default();
+        config.set("", "map-file", file.map_file.map(|s| vec![s]));
+        config.set("", "backend", file.backend.map(|s| vec![s]));
+        config.set("", "interface", file.interface.map(List::values));
+        config.set("record", "samples", record.samples.map(|n| vec![n.to_string()]));
+        config.set("record", "only-ssid", record.only_ssid.map(List::values));
+        config.set("record", "ignore-ssid", record.ignore_ssid.map(List::values));
+        config.set("path-loss", "reference-power", path_loss.reference_power.map(|n| vec![n.to_string()]));
+        config.set("path-loss", "exponent", path_loss.exponent.map(|n| vec![n.to_string()]));
+        config.set("path-loss", "wall-attenuation", path_loss.wall_attenuation.map(|n| vec![n.to_string()]));
+        config.set("path-loss", "wall-spacing", path_loss.wall_spacing.map(|n| vec![n.to_string()]));
+
+        Ok(config)
+    }
+
+    /// Give the flag of the key in section the values, if the file sets it.
+    fn set(&mut self, section: &str, key: &str, values: Option<Vec<String>>) {
+        let setting = SETTINGS.iter()
+            .find(|s| s.section == section && s.key == key)
+            .expect("every key of File is in SETTINGS");
+        if let Some(values) = values {
+            self.flags.push((setting, values));
+        }
+    }
+
+    /// Read the config file at p.
+    pub fn read(p: &Path) -> Result<Config, ConfigError> {
+        let text = fs::read_to_string(p).map_err(|e| ConfigError{
+            reason: e.to_string(),
+        })?;
+
+        Config::parse(&text)
+    }
+
+    /// args with the flags of the config which matches, args parsed, does not already give. Global flags are put
+    /// before the sub-command, the flags of sub-commands after everything else.
+    fn apply(&self, args: &[OsString], matches: &ArgMatches) -> Vec<OsString> {
+        // Names of the nested sub-commands and the arguments given to the innermost one
+        let mut path = Vec::<&str>::new();
+        let mut innermost = matches;
+        while let Some((name, sub)) = innermost.subcommand() {
+            path.push(name);
+            innermost = sub;
+        }
+        let path = path.join(" ");
+
+        let mut global = Vec::<OsString>::new();
+        let mut local = Vec::<OsString>::new();
+        for (setting, values) in &self.flags {
+            let (given, list) = match setting.subcommands.is_empty() {
+                true => (matches, &mut global),
+                false if setting.subcommands.contains(&path.as_str()) => (innermost, &mut local),
+                false => continue,
+            };
+            let on_command_line = |arg: &str| given.value_source(arg) == Some(ValueSource::CommandLine);
+            if on_command_line(setting.arg) || setting.replaced_by.iter().any(|a| on_command_line(a)) {
+                continue;
+            }
+
+            list.extend(values.iter().map(|v| OsString::from(format!("--{}={}", setting.flag, v))));
+        }
+
+        let mut applied = args[..1].to_vec();
+        applied.extend(global);
+        applied.extend(args[1..].iter().cloned());
+        applied.extend(local);
+        applied
+    }
+}
+
+/// Path of the config file used if --config is not given, None if there is no home directory.
+pub fn default_path() -> Option<PathBuf> {
+    let dir = env::var_os("XDG_CONFIG_HOME")
+        .filter(|d| !d.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|h| Path::new(&h).join(".config")))
+        .or_else(|| env::var_os("APPDATA").map(PathBuf::from))?;
+
+    Some(dir.join("wifi-mapper").join("config.toml"))
+}
+
+/// Parse the command line with app, using the config file for flags which are not given. Returns the matches and
+/// the path of the config file which was used, if any. Exits like clap does if the command line is invalid.
+pub fn matches(app: Command) -> Result<(ArgMatches, Option<PathBuf>), ConfigError> {
+    let args: Vec<OsString> = env::args_os().collect();
+    let matches = app.clone().try_get_matches_from(&args).unwrap_or_else(|e| e.exit());
+    if matches.get_flag("no_config") {
+        return Ok((matches, None));
+    }
+
+    // A missing config file is only an error if it was asked for
+    let path = match matches.get_one::<String>("config") {
+        Some(p) => PathBuf::from(p),
+        None => match default_path() {
+            Some(p) if p.exists() => p,
+            _ => return Ok((matches, None)),
+        },
+    };
+    let config = Config::read(&path).map_err(|e| ConfigError{
+        reason: format!("\"{}\": {}", path.display(), e),
+    })?;
+
+    let applied = config.apply(&args, &matches);
+    Ok((app.get_matches_from(applied), Some(path)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::{Arg, ArgAction};
+
+    fn flags(config: &Config) -> Vec<(&str, Vec<&str>)> {
+        config.flags.iter().map(|(s, values)| (s.flag, values.iter().map(String::as_str).collect())).collect()
+    }
+
+    fn reason(text: &str) -> String {
+        match Config::parse(text) {
+            Ok(_) => panic!("{:?} parsed", text),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    #[test]
+    fn parse_reads_every_section() {
+        let config = Config::parse(r#"
+# Defaults for the office survey
+map-file = "office.json"
+interface = ["wlan0", 'wlan1',]
+
+[record]
+samples = 3  # averaged
+only-ssid = "Corp\"WiFi\""
+
+[ path-loss ]
+reference-power = -40
+exponent = 2.5
+"#).expect("valid config");
+
+        assert_eq!(flags(&config), vec![
+            ("map-file", vec!["office.json"]),
+            ("interface", vec!["wlan0", "wlan1"]),
+            ("samples", vec!["3"]),
+            ("only-ssid", vec!["Corp\"WiFi\""]),
+            ("reference-power", vec!["-40"]),
+            ("path-loss-exponent", vec!["2.5"]),
+        ]);
+        assert!(flags(&Config::parse("").expect("empty config")).is_empty());
+    }
+
+    #[test]
+    fn parse_errors_name_the_line() {
+        assert!(reason("backend = \"iw\"\nsamples = 3\n").starts_with("line 2: unknown field `samples`"));
+        assert!(reason("[record]\nsamples = \"three\"\n").starts_with("line 2: expected a number"));
+        assert!(reason("interface = 1\n").starts_with("line 1: expected a string or a list of strings"));
+        assert!(reason("backend = \"iw\"\nbackend = \"nmcli\"\n").starts_with("line 2: duplicate key"));
+        assert!(reason("backend = \n").starts_with("line 1: "));
+    }
+
+    #[test]
+    fn parse_sets_only_known_keys() {
+        for setting in SETTINGS {
+            let value = match setting.key == "samples" || setting.section == "path-loss" {
+                true => "1",
+                false => "\"1\"",
+            };
+            let text = match setting.section {
+                "" => format!("{} = {}", setting.key, value),
+                section => format!("[{}]\n{} = {}", section, setting.key, value),
+            };
+            let config = Config::parse(&text).unwrap_or_else(|e| panic!("{}: {}", setting.key, e));
+            assert_eq!(flags(&config), vec![(setting.flag, vec!["1"])]);
+        }
+    }
+
+    fn app() -> Command {
+        Command::new("wifi-mapper")
+            .arg(Arg::new("map_file").long("map-file"))
+            .arg(Arg::new("interface").long("interface").action(ArgAction::Append))
+            .arg(Arg::new("all_interfaces").long("all-interfaces").action(ArgAction::SetTrue))
+            .subcommand(Command::new("record")
+                .arg(Arg::new("samples").long("samples").default_value("1"))
+                .arg(Arg::new("continuous").long("continuous").action(ArgAction::SetTrue)))
+            .subcommand(Command::new("list"))
+    }
+
+    fn apply(config: &str, args: &[&str]) -> Vec<String> {
+        let args: Vec<OsString> = args.iter().map(OsString::from).collect();
+        let matches = app().get_matches_from(&args);
+        Config::parse(config).expect("valid config").apply(&args, &matches).into_iter()
+            .map(|a| a.into_string().expect("utf-8 argument"))
+            .collect()
+    }
+
+    #[test]
+    fn apply_adds_flags_which_are_not_given() {
+        let config = "map-file = \"office.json\"\ninterface = \"wlan0\"\n[record]\nsamples = 3\n";
+        assert_eq!(apply(config, &["wifi-mapper", "record"]), vec![
+            "wifi-mapper", "--map-file=office.json", "--interface=wlan0", "record", "--samples=3",
+        ]);
+        assert_eq!(apply(config, &["wifi-mapper", "list"]), vec![
+            "wifi-mapper", "--map-file=office.json", "--interface=wlan0", "list",
+        ]);
+    }
+
+    #[test]
+    fn apply_keeps_flags_given_on_the_command_line() {
+        let config = "map-file = \"office.json\"\ninterface = \"wlan0\"\n[record]\nsamples = 3\n";
+        assert_eq!(apply(config, &["wifi-mapper", "--map-file", "home.json", "--all-interfaces", "record", "--continuous"]), vec![
+            "wifi-mapper", "--map-file", "home.json", "--all-interfaces", "record", "--continuous",
+        ]);
+        assert_eq!(apply(config, &["wifi-mapper", "--interface", "wlan1", "record", "--samples", "5"]), vec![
+            "wifi-mapper", "--map-file=office.json", "--interface", "wlan1", "record", "--samples", "5",
+        ]);
+    }
+}
@@ -0,0 +1,196 @@
+//! Merges nodes which were measured at the same position, e.g. when a spot is surveyed again
+//! after an access point was moved.
+
+use crate::ble::BleDevice;
+use crate::{Client, Network, Node, ScanMap};
+
+/// Average the measurements of one BSSID seen by several nodes. Other fields are taken from the most recent measurement.
+fn merge_networks(measurements: Vec<Network>) -> Network {
+    let strengths: Vec<f32> = measurements.iter().filter_map(|n| n.strength).collect();
+    let count = measurements.len();
+    let samples = measurements.iter().map(|n| n.samples).sum();
+
+    let mut network = measurements.into_iter()
+        .max_by_key(|n| n.time_scanned)
+        .expect("no measurements to merge");
+
+    if count > 1 {
+        network.strength = match strengths.is_empty() {
+            true => None,
+            false => Some(strengths.iter().sum::<f32>() / strengths.len() as f32),
+        };
+        network.strength_raw = None;
+        network.samples = samples;
+        network.strength_stddev = None;
+    }
+    network.update_snr();
+
+    network
+}
+
+/// Combine the sightings of one client device by several nodes. Strengths are weighted by the number of probe requests.
+fn merge_clients(sightings: Vec<Client>) -> Client {
+    let weighted: Vec<(f32, u32)> = sightings.iter()
+        .filter_map(|c| c.strength.map(|s| (s, c.samples.max(1))))
+        .collect();
+    let weight: u32 = weighted.iter().map(|(_, w)| w).sum();
+
+    let mut merged = sightings[0].clone();
+    merged.samples = sightings.iter().map(|c| c.samples).sum();
+    merged.strength = match weight {
+        0 => None,
+        _ => Some(weighted.iter().map(|(s, w)| s * *w as f32).sum::<f32>() / weight as f32),
+    };
+    for ssid in sightings.iter().flat_map(|c| &c.probed_ssids) {
+        if !merged.probed_ssids.contains(ssid) {
+            merged.probed_ssids.push(ssid.clone());
+        }
+    }
+
+    merged
+}
+
+/// Average the strengths of one Bluetooth device seen by several nodes. Other fields are taken from the most recent sighting.
+fn merge_ble_devices(sightings: Vec<BleDevice>) -> BleDevice {
+    let strengths: Vec<f32> = sightings.iter().filter_map(|d| d.strength).collect();
+
+    let mut device = sightings.into_iter()
+        .max_by_key(|d| d.time_scanned)
+        .expect("no sightings to merge");
+    if !strengths.is_empty() {
+        device.strength = Some(strengths.iter().sum::<f32>() / strengths.len() as f32);
+    }
+
+    device
+}
+
+/// Merge nodes into the first of them. Notes and tags are joined, networks are averaged per BSSID, and client and Bluetooth devices per address.
+fn merge_nodes(mut nodes: Vec<Node>) -> Node {
+    let mut notes = Vec::<String>::new();
+    let mut tags = Vec::<String>::new();
+    let mut by_mac = Vec::<(String, Vec<Network>)>::new();
+    let mut clients_by_mac = Vec::<(String, Vec<Client>)>::new();
+    let mut ble_by_address = Vec::<(String, Vec<BleDevice>)>::new();
+
+    for node in &mut nodes {
+        if !node.notes.is_empty() && !notes.contains(&node.notes) {
+            notes.push(node.notes.clone());
+        }
+        for tag in node.tags.drain(..) {
+            if !tags.contains(&tag) {
+                tags.push(tag);
+            }
+        }
+
+        for network in node.networks.drain(..) {
+            match by_mac.iter_mut().find(|(mac, _)| *mac == network.mac) {
+                Some((_, measurements)) => measurements.push(network),
+                None => by_mac.push((network.mac.clone(), vec![network])),
+            };
+        }
+
+        for client in node.clients.drain(..) {
+            match clients_by_mac.iter_mut().find(|(mac, _)| *mac == client.mac) {
+                Some((_, sightings)) => sightings.push(client),
+                None => clients_by_mac.push((client.mac.clone(), vec![client])),
+            };
+        }
+
+        for device in node.ble_devices.drain(..) {
+            match ble_by_address.iter_mut().find(|(address, _)| *address == device.address) {
+                Some((_, sightings)) => sightings.push(device),
+                None => ble_by_address.push((device.address.clone(), vec![device])),
+            };
+        }
+    }
+
+    let mut merged = nodes.swap_remove(0);
+    merged.notes = notes.join("; ");
+    merged.tags = tags;
+    merged.networks = by_mac.into_iter()
+        .map(|(_, measurements)| merge_networks(measurements))
+        .collect();
+    merged.clients = clients_by_mac.into_iter()
+        .map(|(_, sightings)| merge_clients(sightings))
+        .collect();
+    merged.ble_devices = ble_by_address.into_iter()
+        .map(|(_, sightings)| merge_ble_devices(sightings))
+        .collect();
+
+    merged
+}
+
+/// Merge nodes on the same floor whose positions are within tolerance of each other, in the map's units. Returns the number of nodes which were removed.
+pub fn dedupe(scan_map: &mut ScanMap, tolerance: f64) -> usize {
+    let crs = scan_map.coordinate_system;
+    let before = scan_map.nodes.len();
+
+    // Each group is anchored at the position of its first node, so a chain of close nodes is not merged into one
+    let mut groups = Vec::<Vec<Node>>::new();
+    for node in scan_map.nodes.drain(..) {
+        let group = groups.iter_mut().find(|g| {
+            g[0].floor == node.floor && g[0].position.distance(&node.position, crs) <= tolerance
+        });
+
+        match group {
+            Some(g) => g.push(node),
+            None => groups.push(vec![node]),
+        };
+    }
+
+    scan_map.nodes = groups.into_iter().map(merge_nodes).collect();
+
+    before - scan_map.nodes.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::scan_map;
+    use serde_json::json;
+
+    #[test]
+    fn dedupe_merges_close_nodes_on_the_same_floor() {
+        let mut scan_map = scan_map(json!({
+            "nodes": [
+                {"position": {"x": 0.0, "y": 0.0, "z": 0.0}, "notes": "Desk", "networks": [
+                    {"mac": "84:d4:7e:69:2f:e2", "channel": "1", "strength": -60.0, "time_scanned": 1},
+                ]},
+                {"position": {"x": 0.3, "y": 0.0, "z": 0.0}, "notes": "Desk again", "networks": [
+                    {"mac": "84:d4:7e:69:2f:e2", "channel": "6", "strength": -70.0, "time_scanned": 2},
+                    {"mac": "aa:bb:cc:00:11:22", "strength": -80.0, "time_scanned": 2},
+                ]},
+                {"position": {"x": 0.3, "y": 0.0, "z": 0.0}, "floor": "F1"},
+                {"position": {"x": 0.7, "y": 0.0, "z": 0.0}, "notes": "Door"},
+            ],
+        }));
+
+        assert_eq!(dedupe(&mut scan_map, 0.5), 1);
+        assert_eq!(scan_map.nodes.len(), 3);
+
+        let merged = &scan_map.nodes[0];
+        assert_eq!(merged.position.x, 0.0);
+        assert_eq!(merged.notes, "Desk; Desk again");
+        assert_eq!(merged.networks.len(), 2);
+        assert_eq!(merged.networks[0].strength, Some(-65.0));
+        assert_eq!(merged.networks[0].channel, "6");
+        assert_eq!(merged.networks[1].strength, Some(-80.0));
+
+        // Groups are anchored at their first node, the door is too far from the desk
+        assert_eq!(scan_map.nodes[1].floor.as_deref(), Some("F1"));
+        assert_eq!(scan_map.nodes[2].notes, "Door");
+    }
+
+    #[test]
+    fn dedupe_keeps_distinct_positions() {
+        let mut scan_map = scan_map(json!({
+            "nodes": [
+                {"position": {"x": 0.0, "y": 0.0, "z": 0.0}},
+                {"position": {"x": 0.0, "y": 0.0, "z": 2.0}},
+            ],
+        }));
+
+        assert_eq!(dedupe(&mut scan_map, 1.0), 0);
+        assert_eq!(scan_map.nodes.len(), 2);
+    }
+}
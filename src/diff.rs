@@ -0,0 +1,291 @@
+//! Compares two surveys of the same place, e.g. before and after reconfiguring an access point.
+
+use std::error::Error;
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::transform::{self, Transform};
+use crate::{Coordinate, Network, Node, ScanMap};
+
+/// Error which occurs when maps cannot be compared.
+#[derive(Debug)]
+pub struct DiffError {
+    /// Reason the maps could not be compared.
+    reason: String,
+}
+
+impl fmt::Display for DiffError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.reason)
+    }
+}
+
+impl Error for DiffError {}
+
+/// Options which control which differences are reported.
+pub struct DiffOptions {
+    /// Largest distance between nodes which are compared, in the units of the first map.
+    pub tolerance: f64,
+
+    /// Smallest change in signal strength in dB which is reported.
+    pub threshold: f32,
+}
+
+/// A network seen at a position in only one of the maps.
+#[derive(Serialize)]
+pub struct NetworkRef {
+    pub mac: String,
+    pub ssid: String,
+    pub strength: Option<f32>,
+}
+
+impl NetworkRef {
+    fn of(network: &Network) -> NetworkRef {
+        NetworkRef{
+            mac: network.mac.clone(),
+            ssid: network.display_ssid().to_string(),
+            strength: network.strength,
+        }
+    }
+}
+
+/// A network whose signal strength changed at a position.
+#[derive(Serialize)]
+pub struct StrengthChange {
+    pub mac: String,
+    pub ssid: String,
+    pub before: f32,
+    pub after: f32,
+}
+
+/// Differences between the networks seen at one position.
+#[derive(Serialize)]
+pub struct PositionDiff {
+    /// Position of the node in the first map.
+    pub position: Coordinate,
+    pub floor: Option<String>,
+    pub appeared: Vec<NetworkRef>,
+    pub disappeared: Vec<NetworkRef>,
+    pub changed: Vec<StrengthChange>,
+}
+
+/// Differences between two maps.
+#[derive(Serialize)]
+pub struct DiffReport {
+    /// Positions measured in both maps where something changed.
+    pub positions: Vec<PositionDiff>,
+
+    /// Number of positions measured in both maps.
+    pub matched: usize,
+
+    /// Number of nodes in the first map without a node at the same position in the second.
+    pub only_before: usize,
+
+    /// Number of nodes in the second map without a node at the same position in the first.
+    pub only_after: usize,
+}
+
+/// Compare the networks seen by two nodes at the same position.
+fn diff_nodes(before: &Node, after: &Node, threshold: f32) -> PositionDiff {
+    let appeared = after.networks.iter()
+        .filter(|a| !before.networks.iter().any(|b| b.mac == a.mac))
+        .map(NetworkRef::of)
+        .collect();
+    let disappeared = before.networks.iter()
+        .filter(|b| !after.networks.iter().any(|a| a.mac == b.mac))
+        .map(NetworkRef::of)
+        .collect();
+
+    let mut changed = Vec::<StrengthChange>::new();
+    for b in &before.networks {
+        let a = match after.networks.iter().find(|a| a.mac == b.mac) {
+            Some(a) => a,
+            None => continue,
+        };
+
+        if let (Some(before_dbm), Some(after_dbm)) = (b.strength, a.strength) {
+            if (after_dbm - before_dbm).abs() >= threshold {
+                changed.push(StrengthChange{
+                    mac: b.mac.clone(),
+                    ssid: a.display_ssid().to_string(),
+                    before: before_dbm,
+                    after: after_dbm,
+                });
+            }
+        }
+    }
+
+    PositionDiff{
+        position: Coordinate{ x: before.position.x, y: before.position.y, z: before.position.z },
+        floor: before.floor.clone(),
+        appeared,
+        disappeared,
+        changed,
+    }
+}
+
+/// Compare the nodes of two maps which are at the same position. The second map is converted to the units of the first.
+pub fn diff(before: &ScanMap, mut after: ScanMap, opts: &DiffOptions) -> Result<DiffReport, DiffError> {
+    let crs = before.coordinate_system;
+    if after.coordinate_system != crs {
+        return Err(DiffError{
+            reason: format!("maps use different coordinate systems, {} and {}", crs, after.coordinate_system),
+        });
+    }
+
+    if after.units != before.units {
+        let scale = after.units.convert(1.0, before.units);
+        transform::transform(&mut after, &Transform{
+            translate: (0.0, 0.0, 0.0),
+            rotate: 0.0,
+            scale,
+        });
+    }
+
+    let mut used = vec![false; after.nodes.len()];
+    let mut report = DiffReport{
+        positions: Vec::new(),
+        matched: 0,
+        only_before: 0,
+        only_after: 0,
+    };
+
+    for node in &before.nodes {
+        // Closest unused node on the same floor within the tolerance
+        let closest = after.nodes.iter().enumerate()
+            .filter(|(i, n)| !used[*i] && n.floor == node.floor)
+            .map(|(i, n)| (i, node.position.distance(&n.position, crs)))
+            .filter(|(_, d)| *d <= opts.tolerance)
+            .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal));
+
+        let i = match closest {
+            Some((i, _)) => i,
+            None => {
+                report.only_before += 1;
+                continue;
+            },
+        };
+        used[i] = true;
+        report.matched += 1;
+
+        let position = diff_nodes(node, &after.nodes[i], opts.threshold);
+        if !position.appeared.is_empty() || !position.disappeared.is_empty() || !position.changed.is_empty() {
+            report.positions.push(position);
+        }
+    }
+
+    report.only_after = used.iter().filter(|u| !**u).count();
+
+    Ok(report)
+}
+
+/// Print a report for people to read.
+pub fn print_report(report: &DiffReport, before: &ScanMap) {
+    for position in &report.positions {
+        let floor_str = match &position.floor {
+            Some(f) => format!(" on floor {}", f),
+            None => String::new(),
+        };
+        println!("At {}{}:", position.position.format(before.coordinate_system, before.units, before.units), floor_str);
+
+        for n in &position.appeared {
+            let strength_str = match n.strength {
+                Some(v) => format!(", {:.2} dBm", v),
+                None => String::new(),
+            };
+            println!("    + {} ({}{})", n.ssid, n.mac, strength_str);
+        }
+        for n in &position.disappeared {
+            println!("    - {} ({})", n.ssid, n.mac);
+        }
+        for c in &position.changed {
+            println!("    ~ {} ({}) {:.2} dBm -> {:.2} dBm ({:+.1} dB)", c.ssid, c.mac, c.before, c.after, c.after - c.before);
+        }
+    }
+
+    let plural = |n: usize| match n != 1 {
+        true => "s",
+        false => "",
+    };
+
+    if report.positions.is_empty() {
+        println!("No differences at {} matching position{}", report.matched, plural(report.matched));
+    } else {
+        println!("Differences at {} of {} matching position{}", report.positions.len(), report.matched, plural(report.matched));
+    }
+
+    if report.only_before > 0 || report.only_after > 0 {
+        println!("{} position{} only in the first map, {} only in the second", report.only_before, plural(report.only_before), report.only_after);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::scan_map;
+    use serde_json::json;
+
+    fn opts() -> DiffOptions {
+        DiffOptions{
+            tolerance: 0.5,
+            threshold: 3.0,
+        }
+    }
+
+    #[test]
+    fn diff_reports_networks_which_changed_at_matching_positions() {
+        let before = scan_map(json!({
+            "nodes": [
+                {"position": {"x": 0.0, "y": 0.0, "z": 0.0}, "networks": [
+                    {"mac": "84:d4:7e:69:2f:e2", "ssid": "CorpWiFi", "strength": -60.0},
+                    {"mac": "aa:bb:cc:00:11:22", "ssid": "Guest", "strength": -70.0},
+                    {"mac": "00:11:22:33:44:55", "ssid": "OldCafe", "strength": -80.0},
+                ]},
+                {"position": {"x": 10.0, "y": 0.0, "z": 0.0}},
+            ],
+        }));
+        let after = scan_map(json!({
+            "nodes": [
+                {"position": {"x": 0.2, "y": 0.0, "z": 0.0}, "networks": [
+                    {"mac": "84:d4:7e:69:2f:e2", "ssid": "CorpWiFi", "strength": -52.0},
+                    {"mac": "aa:bb:cc:00:11:22", "ssid": "Guest", "strength": -71.0},
+                    {"mac": "66:77:88:99:aa:bb", "ssid": "NewAP", "strength": -65.0},
+                ]},
+                {"position": {"x": 20.0, "y": 0.0, "z": 0.0}},
+            ],
+        }));
+
+        let report = diff(&before, after, &opts()).unwrap();
+        assert_eq!((report.matched, report.only_before, report.only_after), (1, 1, 1));
+        assert_eq!(report.positions.len(), 1);
+
+        let position = &report.positions[0];
+        assert_eq!(position.position.x, 0.0);
+        let changed: Vec<(&str, f32, f32)> = position.changed.iter().map(|c| (c.ssid.as_str(), c.before, c.after)).collect();
+        assert_eq!(changed, vec![("CorpWiFi", -60.0, -52.0)]);
+        assert_eq!(position.appeared.iter().map(|n| n.ssid.as_str()).collect::<Vec<&str>>(), vec!["NewAP"]);
+        assert_eq!(position.disappeared.iter().map(|n| n.ssid.as_str()).collect::<Vec<&str>>(), vec!["OldCafe"]);
+    }
+
+    #[test]
+    fn diff_converts_units_and_matches_each_node_once() {
+        let before = scan_map(json!({
+            "nodes": [
+                {"position": {"x": 3.048, "y": 0.0, "z": 0.0}},
+                {"position": {"x": 3.048, "y": 0.0, "z": 0.0}},
+            ],
+        }));
+        let after = scan_map(json!({
+            "units": "feet",
+            "nodes": [{"position": {"x": 10.0, "y": 0.0, "z": 0.0}}],
+        }));
+
+        let report = diff(&before, after, &opts()).unwrap();
+        assert_eq!((report.matched, report.only_before, report.only_after), (1, 1, 0));
+        assert!(report.positions.is_empty());
+
+        let gps = scan_map(json!({"coordinate_system": "wgs84"}));
+        assert!(diff(&before, gps, &opts()).is_err());
+    }
+}
@@ -0,0 +1,437 @@
+//! Writes the measurements of a scan map in formats other tools read, e.g. InfluxDB line
+//! protocol so surveys and monitoring runs can be graphed over time, or the projects of
+//! commercial survey tools, see the interchange module.
+
+use std::error::Error;
+use std::fmt;
+use std::io::Write;
+use std::path::Path;
+
+use crate::interchange;
+use crate::query::glob_match;
+use crate::render::strength_color;
+use crate::stream;
+use crate::timestamp::TimeRange;
+use crate::{CoordinateSystem, Network, Node, ScanMap, Units, EARTH_RADIUS};
+
+/// Formats a map can be exported in.
+#[derive(Clone, Copy)]
+pub enum ExportFormat {
+    /// InfluxDB line protocol, one point per network observation.
+    Influx,
+
+    /// Ekahau project with a passive survey per floor.
+    Esx,
+
+    /// NetSpot CSV table, one row per network observation.
+    Netspot,
+
+    /// ASCII PLY point cloud, one point per node colored by its signal strength.
+    Ply,
+
+    /// Point cloud of "x y z rssi" lines, one per node.
+    Xyz,
+}
+
+impl ExportFormat {
+    /// What the number of exported records counts.
+    pub fn counts(self) -> &'static str {
+        match self {
+            ExportFormat::Esx => "node",
+            ExportFormat::Influx | ExportFormat::Netspot | ExportFormat::Ply | ExportFormat::Xyz => "line",
+        }
+    }
+}
+
+impl fmt::Display for ExportFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExportFormat::Influx => write!(f, "influx"),
+            ExportFormat::Esx => write!(f, "esx"),
+            ExportFormat::Netspot => write!(f, "netspot"),
+            ExportFormat::Ply => write!(f, "ply"),
+            ExportFormat::Xyz => write!(f, "xyz"),
+        }
+    }
+}
+
+impl std::str::FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<ExportFormat, String> {
+        match s.to_lowercase().as_str() {
+            "influx" => Ok(ExportFormat::Influx),
+            "esx" | "ekahau" => Ok(ExportFormat::Esx),
+            "netspot" => Ok(ExportFormat::Netspot),
+            "ply" => Ok(ExportFormat::Ply),
+            "xyz" => Ok(ExportFormat::Xyz),
+            _ => Err(format!("unknown export format \"{}\", must be influx, esx, netspot, ply, or xyz", s)),
+        }
+    }
+}
+
+/// Error which occurs when points cannot be sent.
+#[derive(Debug)]
+pub struct ExportError {
+    /// Reason the points were not sent.
+    reason: String,
+}
+
+impl fmt::Display for ExportError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.reason)
+    }
+}
+
+impl Error for ExportError {}
+
+/// Options which control what is exported.
+pub struct ExportOptions {
+    pub format: ExportFormat,
+
+    /// Only export nodes on this floor.
+    pub floor: Option<String>,
+
+    /// Only export nodes with all of these tags.
+    pub tags: Vec<String>,
+
+    /// Name of the InfluxDB measurement points are written to.
+    pub measurement: String,
+
+    /// Point clouds use the strength of the strongest network whose SSID matches, or of the strongest network if None.
+    pub ssid: Option<String>,
+
+    /// Only export networks scanned within this range, nodes with none are left out.
+    pub times: TimeRange,
+}
+
+/// Escape a measurement name, commas and spaces end it.
+fn escape_measurement(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace(',', "\\,")
+        .replace(' ', "\\ ")
+}
+
+/// Escape a tag key or value, commas, equals signs, and spaces end them.
+fn escape_tag(s: &str) -> String {
+    escape_measurement(s).replace('=', "\\=")
+}
+
+/// Line protocol point of a network observed at a node. None if the strength is unknown, since a point needs a field.
+fn influx_point(measurement: &str, scan_map: &ScanMap, node: &Node, network: &Network) -> Option<String> {
+    let rssi = network.strength?;
+
+    // Tags with empty values are rejected, so they are left out
+    let mut tags = vec![
+        ("ssid", network.ssid.clone()),
+        ("bssid", network.mac.clone()),
+        ("vendor", network.vendor.clone().unwrap_or_default()),
+        ("channel", network.channel.clone()),
+        ("band", network.band.map(|b| b.to_string()).unwrap_or_default()),
+        ("floor", node.floor.clone().unwrap_or_default()),
+        ("session", node.session.map(|s| s.to_string()).unwrap_or_default()),
+        ("map", scan_map.name.clone()),
+    ];
+    tags.retain(|(_, value)| !value.is_empty());
+
+    let mut fields = vec![
+        format!("rssi={}", rssi),
+        format!("x={}", node.position.x),
+        format!("y={}", node.position.y),
+        format!("z={}", node.position.z),
+    ];
+    if let Some(snr) = network.snr {
+        fields.push(format!("snr={}", snr));
+    }
+
+    let mut line = escape_measurement(measurement);
+    for (key, value) in tags {
+        line.push(',');
+        line.push_str(key);
+        line.push('=');
+        line.push_str(&escape_tag(&value));
+    }
+
+    // Timestamps are nanoseconds, the default precision
+    Some(format!("{} {} {}", line, fields.join(","), network.time_scanned * 1_000_000))
+}
+
+/// Position of a node in meters for 3D tools. The height is above the map's origin, not the floor, so floors are stacked.
+/// wgs84 positions are meters east and north of origin, the longitude and latitude of the first node.
+fn position_3d(scan_map: &ScanMap, node: &Node, origin: (f64, f64)) -> (f64, f64, f64) {
+    let meters = |v: f64| scan_map.units.convert(v, Units::Meters);
+    let elevation = node.floor.as_deref().and_then(|f| scan_map.floor(f)).map(|f| f.elevation).unwrap_or(0.0);
+    let (p, (origin_x, origin_y)) = (&node.position, origin);
+
+    match scan_map.coordinate_system {
+        CoordinateSystem::Local => (meters(p.x), meters(p.y), meters(elevation + p.z)),
+        CoordinateSystem::Wgs84 => (
+            (p.x - origin_x).to_radians() * origin_y.to_radians().cos() * EARTH_RADIUS,
+            (p.y - origin_y).to_radians() * EARTH_RADIUS,
+            meters(elevation + p.z),
+        ),
+    }
+}
+
+/// Point of a point cloud for a node, with the strength of the strongest network opts.ssid selects. None if the node saw no such network.
+fn cloud_point(scan_map: &ScanMap, node: &Node, origin: (f64, f64), opts: &ExportOptions) -> Option<((f64, f64, f64), f32)> {
+    let strength = node.networks_in(&opts.times)
+        .filter(|n| opts.ssid.as_ref().is_none_or(|pattern| glob_match(pattern, n.display_ssid())))
+        .filter_map(|n| n.strength)
+        .fold(None, |best: Option<f32>, s| Some(best.map_or(s, |b| b.max(s))))?;
+
+    Some((position_3d(scan_map, node, origin), strength))
+}
+
+/// Lines of a PLY point cloud, whose header holds the number of points.
+fn ply_lines(scan_map: &ScanMap, points: &[((f64, f64, f64), f32)]) -> Vec<String> {
+    let mut lines = vec![
+        String::from("ply"),
+        String::from("format ascii 1.0"),
+        format!("comment {} exported by wifi-mapper, positions in meters", scan_map.name.replace('\n', " ")),
+        format!("element vertex {}", points.len()),
+        String::from("property float x"),
+        String::from("property float y"),
+        String::from("property float z"),
+        String::from("property float rssi"),
+        String::from("property uchar red"),
+        String::from("property uchar green"),
+        String::from("property uchar blue"),
+        String::from("end_header"),
+    ];
+    lines.extend(points.iter().map(|((x, y, z), rssi)| {
+        let color = strength_color(Some(*rssi as f64));
+        format!("{} {} {} {} {} {} {}", x, y, z, rssi, color.0, color.1, color.2)
+    }));
+
+    lines
+}
+
+/// True if opts exports node.
+fn selects(opts: &ExportOptions, node: &Node) -> bool {
+    (opts.floor.is_none() || node.floor == opts.floor) && node.has_tags(&opts.tags)
+        && (opts.times.is_open() || node.networks_in(&opts.times).next().is_some())
+}
+
+/// Nodes opts selects.
+fn selected<'a>(scan_map: &'a ScanMap, opts: &ExportOptions) -> Vec<&'a Node> {
+    scan_map.nodes.iter()
+        .filter(|n| selects(opts, n))
+        .collect()
+}
+
+/// Call f with each line of the nodes opts selects in opts.format. Nodes of streamed maps are read one at a time, only
+/// the points of PLY clouds are held until the end. Ekahau projects are archives, so they have no lines.
+pub fn for_each_line<F>(scan_map: &ScanMap, opts: &ExportOptions, mut f: F) -> Result<(), Box<dyn Error>>
+where F: FnMut(String) -> Result<(), Box<dyn Error>> {
+    if let ExportFormat::Netspot = opts.format {
+        f(interchange::netspot_header())?;
+    }
+
+    let mut selected = 0;
+    let mut origin = None;
+    let mut points = Vec::new();
+    stream::visit_nodes(scan_map, |_, node| {
+        if !selects(opts, node) {
+            return Ok(());
+        }
+        selected += 1;
+
+        match opts.format {
+            ExportFormat::Influx => for line in node.networks_in(&opts.times).filter_map(|network| influx_point(&opts.measurement, scan_map, node, network)) {
+                f(line)?;
+            },
+            ExportFormat::Netspot => for row in interchange::netspot_rows(scan_map, selected, node, &opts.times) {
+                f(row)?;
+            },
+            ExportFormat::Ply | ExportFormat::Xyz => {
+                let origin = *origin.get_or_insert((node.position.x, node.position.y));
+                match (cloud_point(scan_map, node, origin, opts), opts.format) {
+                    (Some(point), ExportFormat::Ply) => points.push(point),
+                    (Some(((x, y, z), rssi)), _) => f(format!("{} {} {} {}", x, y, z, rssi))?,
+                    (None, _) => (),
+                };
+            },
+            ExportFormat::Esx => (),
+        };
+
+        Ok(())
+    })?;
+
+    if let ExportFormat::Ply = opts.format {
+        for line in ply_lines(scan_map, &points) {
+            f(line)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Write the nodes opts selects to writer in opts.format. Floor plans are read relative to map_dir. Returns the
+/// number of records written, see ExportFormat::counts.
+pub fn export(scan_map: &ScanMap, opts: &ExportOptions, map_dir: &Path, mut writer: impl Write) -> Result<usize, Box<dyn Error>> {
+    if let ExportFormat::Esx = opts.format {
+        if scan_map.streamed.nodes > 0 {
+            return Err(Box::new(ExportError{
+                reason: String::from("Ekahau projects are built from the whole map, they cannot be exported with --stream"),
+            }));
+        }
+
+        let nodes = selected(scan_map, opts);
+        writer.write_all(&interchange::esx(scan_map, &nodes, &opts.times, map_dir)?)?;
+        writer.flush()?;
+        return Ok(nodes.len());
+    }
+
+    let mut count = 0;
+    for_each_line(scan_map, opts, |line| {
+        writeln!(writer, "{}", line)?;
+        count += 1;
+        Ok(())
+    })?;
+    writer.flush()?;
+
+    Ok(count)
+}
+
+/// Send the points of the nodes opts selects to an InfluxDB write endpoint, batch_size points per request, so only a
+/// batch is held at a time. Returns the number of points sent.
+pub fn send_all(scan_map: &ScanMap, opts: &ExportOptions, url: &str, token: Option<&str>, batch_size: usize) -> Result<usize, Box<dyn Error>> {
+    let batch_size = batch_size.max(1);
+    let mut batch = Vec::<String>::new();
+    let mut sent = 0;
+
+    for_each_line(scan_map, opts, |line| {
+        batch.push(line);
+        if batch.len() == batch_size {
+            send(&batch, url, token, batch_size)?;
+            sent += batch.len();
+            batch.clear();
+        }
+        Ok(())
+    })?;
+    send(&batch, url, token, batch_size)?;
+
+    Ok(sent + batch.len())
+}
+
+/// Send lines to an InfluxDB write endpoint, e.g. http://host:8086/api/v2/write?org=ORG&bucket=BUCKET, batch_size lines per request.
+fn send(lines: &[String], url: &str, token: Option<&str>, batch_size: usize) -> Result<(), Box<dyn Error>> {
+    for batch in lines.chunks(batch_size.max(1)) {
+        let mut request = ureq::post(url).set("Content-Type", "text/plain; charset=utf-8");
+        if let Some(token) = token {
+            request = request.set("Authorization", &format!("Token {}", token));
+        }
+
+        match request.send_string(&batch.join("\n")) {
+            Ok(_) => (),
+            Err(ureq::Error::Status(status, response)) => return Err(Box::new(ExportError{
+                reason: format!("{} refused points with status {}: {}", url, status, response.into_string().unwrap_or_default()),
+            })),
+            Err(e) => return Err(Box::new(e)),
+        };
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::scan_map;
+
+    fn opts(floor: Option<&str>) -> ExportOptions {
+        ExportOptions{
+            format: ExportFormat::Influx,
+            floor: floor.map(String::from),
+            tags: Vec::new(),
+            measurement: String::from("wifi scan"),
+            ssid: None,
+            times: TimeRange::default(),
+        }
+    }
+
+    fn export_lines(scan_map: &ScanMap, opts: &ExportOptions) -> Vec<String> {
+        let mut lines = Vec::new();
+        for_each_line(scan_map, opts, |line| {
+            lines.push(line);
+            Ok(())
+        }).unwrap();
+        lines
+    }
+
+    fn survey() -> ScanMap {
+        scan_map(serde_json::json!({
+            "name": "Office, East",
+            "nodes": [
+                {"position": {"x": 1.0, "y": 2.0, "z": 0.0}, "floor": "F1", "networks": [
+                    {"mac": "84:d4:7e:69:2f:e2", "ssid": "Corp=WiFi", "channel": "36", "strength": -61.0, "time_scanned": 1000},
+                    {"mac": "aa:bb:cc:00:11:22", "strength": null},
+                ]},
+                {"position": {"x": 5.0, "y": 2.0, "z": 0.0}, "networks": [
+                    {"mac": "84:d4:7e:69:2f:e2", "ssid": "Corp=WiFi", "channel": "36", "strength": -75.0, "time_scanned": 2000},
+                ]},
+            ],
+        }))
+    }
+
+    #[test]
+    fn for_each_line_writes_a_point_per_measured_network() {
+        let lines = export_lines(&survey(), &opts(None));
+        assert_eq!(lines, vec![
+            "wifi\\ scan,ssid=Corp\\=WiFi,bssid=84:d4:7e:69:2f:e2,vendor=Hewlett\\ Packard\\ Enterprise,channel=36,band=5\\ GHz,floor=F1,map=Office\\,\\ East rssi=-61,x=1,y=2,z=0 1000000000",
+            "wifi\\ scan,ssid=Corp\\=WiFi,bssid=84:d4:7e:69:2f:e2,vendor=Hewlett\\ Packard\\ Enterprise,channel=36,band=5\\ GHz,map=Office\\,\\ East rssi=-75,x=5,y=2,z=0 2000000000",
+        ]);
+        assert_eq!(export_lines(&survey(), &opts(Some("F1"))).len(), 1);
+
+        let since = ExportOptions{
+            times: TimeRange{ since: Some(1500), until: None },
+            ..opts(None)
+        };
+        assert_eq!(export_lines(&survey(), &since).len(), 1);
+    }
+
+    #[test]
+    fn export_format_parses_case_insensitively() {
+        assert!(matches!("Influx".parse::<ExportFormat>(), Ok(ExportFormat::Influx)));
+        assert!(matches!("ekahau".parse::<ExportFormat>(), Ok(ExportFormat::Esx)));
+        assert!(matches!("PLY".parse::<ExportFormat>(), Ok(ExportFormat::Ply)));
+        assert!("csv".parse::<ExportFormat>().is_err());
+    }
+
+    #[test]
+    fn point_clouds_stack_floors_and_use_the_strongest_network() {
+        let scan_map = scan_map(serde_json::json!({
+            "floors": [{"name": "F1", "elevation": 3.0, "floor_plan": null}],
+            "nodes": [
+                {"position": {"x": 10.0, "y": 0.0, "z": 0.0}, "floor": "F1", "networks": [
+                    {"mac": "84:d4:7e:69:2f:e2", "ssid": "CorpWiFi", "strength": -60.0},
+                    {"mac": "84:d4:7e:69:2f:e3", "ssid": "Guest", "strength": -50.0},
+                ]},
+                {"position": {"x": 0.0, "y": 0.0, "z": 1.0}, "networks": [
+                    {"mac": "84:d4:7e:69:2f:e3", "ssid": "Guest", "strength": -70.0},
+                ]},
+                {"position": {"x": 5.0, "y": 0.0, "z": 0.0}, "networks": [{"mac": "aa:bb:cc:00:11:22", "strength": null}]},
+            ],
+        }));
+
+        let xyz = |ssid: Option<&str>| export_lines(&scan_map, &ExportOptions{
+            format: ExportFormat::Xyz,
+            ssid: ssid.map(String::from),
+            ..opts(None)
+        });
+        assert_eq!(xyz(None), vec!["10 0 3 -50", "0 0 1 -70"]);
+        assert_eq!(xyz(Some("Corp*")), vec!["10 0 3 -60"]);
+
+        let ply = export_lines(&scan_map, &ExportOptions{
+            format: ExportFormat::Ply,
+            ..opts(None)
+        });
+        assert_eq!(ply[0], "ply");
+        assert_eq!(ply[3], "element vertex 2");
+        assert_eq!(ply[11], "end_header");
+        let color = strength_color(Some(-50.0));
+        assert_eq!(ply[12], format!("10 0 3 -50 {} {} {}", color.0, color.1, color.2));
+        assert_eq!(ply.len(), 14);
+    }
+}
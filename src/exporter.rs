@@ -0,0 +1,170 @@
+//! Scans on an interval and serves the results as Prometheus metrics, which turns a stationary
+//! computer into a long term signal monitor.
+
+use std::error::Error;
+use std::fmt::{self, Write as _};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use tiny_http::{Header, Response, Server};
+
+use crate::logging;
+use crate::{Network, ScanOptions};
+
+/// Options which control how often networks are scanned and where metrics are served.
+pub struct ExporterOptions {
+    /// Address to serve metrics on, host:port.
+    pub listen: String,
+
+    /// Time from the start of one scan to the start of the next.
+    pub interval: Duration,
+}
+
+/// Error which occurs when the exporter cannot start.
+#[derive(Debug)]
+pub struct ExporterError {
+    /// Reason the exporter failed.
+    reason: String,
+}
+
+impl fmt::Display for ExporterError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.reason)
+    }
+}
+
+impl Error for ExporterError {}
+
+/// Measurements of the most recent scan and totals since the exporter started.
+#[derive(Default)]
+struct Metrics {
+    /// Networks found by the last successful scan.
+    networks: Vec<Network>,
+
+    /// Seconds the last scan took.
+    duration: f64,
+
+    /// Unix time in seconds the last successful scan finished.
+    last_scan: f64,
+
+    scans: u64,
+
+    errors: u64,
+}
+
+/// Escape a label value as the Prometheus text format requires.
+fn label(value: &str) -> String {
+    value.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Append the help and type lines of a metric.
+fn describe(text: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(text, "# HELP {} {}", name, help);
+    let _ = writeln!(text, "# TYPE {} {}", name, kind);
+}
+
+impl Metrics {
+    /// Metrics in the Prometheus text format.
+    fn render(&self) -> String {
+        let mut text = String::new();
+
+        describe(&mut text, "wifi_mapper_signal_strength_dbm", "gauge", "Signal strength of each BSSID seen by the last scan.");
+        for network in self.networks.iter().filter(|n| n.strength.is_some()) {
+            let _ = writeln!(text, "wifi_mapper_signal_strength_dbm{{bssid=\"{}\",ssid=\"{}\",channel=\"{}\",band=\"{}\"}} {}",
+                             label(&network.mac), label(network.display_ssid()), label(&network.channel),
+                             network.band.map(|b| b.to_string()).unwrap_or_default(), network.strength.unwrap_or_default());
+        }
+
+        describe(&mut text, "wifi_mapper_networks", "gauge", "Number of BSSIDs seen by the last scan.");
+        let _ = writeln!(text, "wifi_mapper_networks {}", self.networks.len());
+
+        describe(&mut text, "wifi_mapper_ssid_networks", "gauge", "Number of BSSIDs of each SSID seen by the last scan.");
+        let mut ssids = Vec::<(&str, usize)>::new();
+        for network in &self.networks {
+            match ssids.iter_mut().find(|(ssid, _)| *ssid == network.display_ssid()) {
+                Some((_, count)) => *count += 1,
+                None => ssids.push((network.display_ssid(), 1)),
+            };
+        }
+        ssids.sort();
+        for (ssid, count) in ssids {
+            let _ = writeln!(text, "wifi_mapper_ssid_networks{{ssid=\"{}\"}} {}", label(ssid), count);
+        }
+
+        describe(&mut text, "wifi_mapper_scan_duration_seconds", "gauge", "Time the last scan took.");
+        let _ = writeln!(text, "wifi_mapper_scan_duration_seconds {}", self.duration);
+
+        describe(&mut text, "wifi_mapper_last_scan_timestamp_seconds", "gauge", "Unix time the last successful scan finished.");
+        let _ = writeln!(text, "wifi_mapper_last_scan_timestamp_seconds {}", self.last_scan);
+
+        describe(&mut text, "wifi_mapper_scans_total", "counter", "Scans made since the exporter started.");
+        let _ = writeln!(text, "wifi_mapper_scans_total {}", self.scans);
+
+        describe(&mut text, "wifi_mapper_scan_errors_total", "counter", "Scans which failed since the exporter started.");
+        let _ = writeln!(text, "wifi_mapper_scan_errors_total {}", self.errors);
+
+        text
+    }
+}
+
+/// Answer requests for /metrics with the latest metrics until the process is stopped.
+fn respond(server: Server, metrics: Arc<Mutex<Metrics>>) {
+    for request in server.incoming_requests() {
+        let result = match request.url().split('?').next().unwrap_or("") {
+            "/metrics" => {
+                let text = metrics.lock().unwrap_or_else(|e| e.into_inner()).render();
+                request.respond(Response::from_string(text)
+                                .with_header(Header::from_bytes("Content-Type", "text/plain; version=0.0.4").expect("content type is a valid header")))
+            },
+            "/" => request.respond(Response::from_string("<html><body><a href=\"/metrics\">Metrics</a></body></html>")
+                                   .with_header(Header::from_bytes("Content-Type", "text/html").expect("content type is a valid header"))),
+            _ => request.respond(Response::from_string("not found").with_status_code(404)),
+        };
+        if let Err(e) = result {
+            logging::warn(&format!("failed to respond: {}", e));
+        }
+    }
+}
+
+/// Scan every opts.interval and serve the results until the process is stopped. Failed scans are counted and retried at the next interval.
+pub fn export(scan_opts: &ScanOptions, opts: &ExporterOptions) -> Result<(), Box<dyn Error>> {
+    let server = Server::http(&opts.listen).map_err(|e| ExporterError{
+        reason: format!("failed to listen on {}: {}", opts.listen, e),
+    })?;
+    println!("Serving metrics at http://{}/metrics, scanning every {:?}", opts.listen, opts.interval);
+
+    let metrics = Arc::new(Mutex::new(Metrics::default()));
+    let served = Arc::clone(&metrics);
+    thread::spawn(move || respond(server, served));
+
+    let started = Instant::now();
+    let mut scans: u32 = 0;
+
+    loop {
+        let scan_start = Instant::now();
+        let result = Network::scan(scan_opts);
+        let duration = scan_start.elapsed().as_secs_f64();
+        scans += 1;
+
+        let mut m = metrics.lock().unwrap_or_else(|e| e.into_inner());
+        m.scans += 1;
+        m.duration = duration;
+        match result {
+            Ok(networks) => {
+                m.networks = networks;
+                m.last_scan = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs_f64()).unwrap_or(0.0);
+            },
+            Err(e) => {
+                m.errors += 1;
+                logging::warn(&format!("scan failed: {}", e));
+            },
+        };
+        drop(m);
+
+        // Scans are scheduled from the start so slow scans do not make the schedule drift
+        thread::sleep((opts.interval * scans).saturating_sub(started.elapsed()));
+    }
+}
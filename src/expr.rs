@@ -0,0 +1,130 @@
+//! Evaluates the arithmetic which can be typed instead of a number at prompts, e.g. "3.5+0.6" to
+//! add a step to a distance read off a plan.
+
+/// Value of an expression of numbers, + - * / and parentheses. Commas are decimal separators, e.g. "3,5".
+pub fn eval(s: &str) -> Result<f64, String> {
+    let chars: Vec<char> = s.chars().filter(|c| !c.is_whitespace()).map(|c| match c {
+        ',' => '.',
+        c => c,
+    }).collect();
+
+    let mut parser = Parser{
+        chars: &chars,
+        pos: 0,
+    };
+    let value = parser.sum()?;
+    if let Some(c) = parser.peek() {
+        return Err(format!("unexpected \"{}\"", c));
+    }
+    if !value.is_finite() {
+        return Err(String::from("result is not a finite number"));
+    }
+
+    Ok(value)
+}
+
+struct Parser<'a> {
+    chars: &'a [char],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    /// Terms added or subtracted.
+    fn sum(&mut self) -> Result<f64, String> {
+        let mut value = self.product()?;
+        while let Some(op @ ('+' | '-')) = self.peek() {
+            self.pos += 1;
+            let rhs = self.product()?;
+            value = match op {
+                '+' => value + rhs,
+                _ => value - rhs,
+            };
+        }
+        Ok(value)
+    }
+
+    /// Factors multiplied or divided.
+    fn product(&mut self) -> Result<f64, String> {
+        let mut value = self.factor()?;
+        while let Some(op @ ('*' | '/')) = self.peek() {
+            self.pos += 1;
+            let rhs = self.factor()?;
+            value = match op {
+                '*' => value * rhs,
+                _ => value / rhs,
+            };
+        }
+        Ok(value)
+    }
+
+    /// Number, signed factor, or expression in parentheses.
+    fn factor(&mut self) -> Result<f64, String> {
+        match self.peek() {
+            Some('-') => {
+                self.pos += 1;
+                Ok(-self.factor()?)
+            },
+            Some('+') => {
+                self.pos += 1;
+                self.factor()
+            },
+            Some('(') => {
+                self.pos += 1;
+                let value = self.sum()?;
+                match self.peek() {
+                    Some(')') => {
+                        self.pos += 1;
+                        Ok(value)
+                    },
+                    _ => Err(String::from("missing )")),
+                }
+            },
+            Some(c) if c.is_ascii_digit() || c == '.' => {
+                let start = self.pos;
+                while self.peek().is_some_and(|c| c.is_ascii_digit() || c == '.') {
+                    self.pos += 1;
+                }
+                let number: String = self.chars[start..self.pos].iter().collect();
+                number.parse::<f64>().map_err(|_| format!("\"{}\" is not a number", number))
+            },
+            Some(c) => Err(format!("unexpected \"{}\"", c)),
+            None => Err(String::from("expected a number")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numbers_and_operators() {
+        assert_eq!(eval("3.5"), Ok(3.5));
+        assert_eq!(eval("3.5+0.6"), Ok(3.5 + 0.6));
+        assert_eq!(eval(" 10 - 2 * 3 "), Ok(4.0));
+        assert_eq!(eval("(10-2)*3"), Ok(24.0));
+        assert_eq!(eval("7/2"), Ok(3.5));
+        assert_eq!(eval("-2*-(1+1)"), Ok(4.0));
+        assert_eq!(eval("+.5"), Ok(0.5));
+    }
+
+    #[test]
+    fn commas_are_decimal_separators() {
+        assert_eq!(eval("3,5+1"), Ok(4.5));
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(eval(""), Err(String::from("expected a number")));
+        assert_eq!(eval("2+"), Err(String::from("expected a number")));
+        assert_eq!(eval("(1+2"), Err(String::from("missing )")));
+        assert_eq!(eval("1+2)"), Err(String::from("unexpected \")\"")));
+        assert_eq!(eval("2x"), Err(String::from("unexpected \"x\"")));
+        assert_eq!(eval("1.2.3"), Err(String::from("\"1.2.3\" is not a number")));
+        assert_eq!(eval("1/0"), Err(String::from("result is not a finite number")));
+    }
+}
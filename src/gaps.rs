@@ -0,0 +1,168 @@
+//! Finds coverage gaps: connected regions of an interpolated grid where a network is weaker
+//! than a threshold.
+
+use std::collections::VecDeque;
+
+use serde::Serialize;
+
+use crate::interpolate::{self, Grid, InterpolateError, InterpolateOptions};
+use crate::{CoordinateSystem, ScanMap, Units};
+
+/// Connected region of weak cells.
+#[derive(Serialize)]
+pub struct Gap {
+    /// Average position of the region's cells.
+    pub center: (f64, f64),
+
+    /// Area of the region in square map units, square meters for wgs84 maps.
+    pub area: f64,
+
+    /// Bounding box of the region's cell centers, min_x, min_y, max_x, max_y.
+    pub bounds: (f64, f64, f64, f64),
+
+    /// Weakest strength in the region in dBm.
+    pub weakest: f64,
+
+    /// Average strength of the region in dBm.
+    pub average: f64,
+
+    /// Column and row of each cell in the grid.
+    #[serde(skip)]
+    pub cells: Vec<(usize, usize)>,
+}
+
+/// Regions of grid weaker than threshold, largest first. spacing is the grid's spacing in the map's units, meters for
+/// wgs84 maps, and regions smaller than min_area are left out. Empty cells are not part of any gap.
+pub fn find_gaps(grid: &Grid, spacing: f64, threshold: f64, min_area: f64) -> Vec<Gap> {
+    let weak = |column: usize, row: usize| grid.value(column, row).is_some_and(|v| v < threshold);
+    let mut visited = vec![false; grid.columns * grid.rows];
+    let mut gaps = Vec::<Gap>::new();
+
+    for row in 0..grid.rows {
+        for column in 0..grid.columns {
+            if visited[row * grid.columns + column] || !weak(column, row) {
+                continue;
+            }
+
+            // Flood fill the cells which share an edge
+            let mut cells = Vec::<(usize, usize)>::new();
+            let mut queue = VecDeque::from([(column, row)]);
+            visited[row * grid.columns + column] = true;
+            while let Some((c, r)) = queue.pop_front() {
+                cells.push((c, r));
+
+                let neighbors = [(c.wrapping_sub(1), r), (c + 1, r), (c, r.wrapping_sub(1)), (c, r + 1)];
+                for (nc, nr) in neighbors {
+                    if nc < grid.columns && nr < grid.rows && !visited[nr * grid.columns + nc] && weak(nc, nr) {
+                        visited[nr * grid.columns + nc] = true;
+                        queue.push_back((nc, nr));
+                    }
+                }
+            }
+
+            let area = cells.len() as f64 * spacing * spacing;
+            if area < min_area {
+                continue;
+            }
+
+            let positions: Vec<(f64, f64)> = cells.iter().map(|(c, r)| grid.position(*c, *r)).collect();
+            let values: Vec<f64> = cells.iter().filter_map(|(c, r)| grid.value(*c, *r)).collect();
+            let count = cells.len() as f64;
+
+            gaps.push(Gap{
+                center: (positions.iter().map(|p| p.0).sum::<f64>() / count, positions.iter().map(|p| p.1).sum::<f64>() / count),
+                area,
+                bounds: (positions.iter().map(|p| p.0).fold(f64::INFINITY, f64::min),
+                         positions.iter().map(|p| p.1).fold(f64::INFINITY, f64::min),
+                         positions.iter().map(|p| p.0).fold(f64::NEG_INFINITY, f64::max),
+                         positions.iter().map(|p| p.1).fold(f64::NEG_INFINITY, f64::max)),
+                weakest: values.iter().copied().fold(f64::INFINITY, f64::min),
+                average: values.iter().sum::<f64>() / count,
+                cells,
+            });
+        }
+    }
+
+    gaps.sort_by(|a, b| b.area.partial_cmp(&a.area).unwrap_or(std::cmp::Ordering::Equal));
+    gaps
+}
+
+/// Interpolate the target of opts and find its gaps.
+pub fn gaps(scan_map: &ScanMap, opts: &InterpolateOptions, threshold: f64, min_area: f64) -> Result<Vec<Gap>, InterpolateError> {
+    let grid = interpolate::interpolate(scan_map, opts)?;
+
+    Ok(find_gaps(&grid, opts.spacing, threshold, min_area))
+}
+
+/// Area formatted with its unit.
+pub fn format_area(area: f64, units: Units) -> String {
+    format!("{:.1} {}²", area, units.abbreviation())
+}
+
+/// Print a table of gaps.
+pub fn print_gaps(scan_map: &ScanMap, gaps: &[Gap]) {
+    let units = scan_map.units;
+    // Degrees need more digits than lengths
+    let precision = match scan_map.coordinate_system {
+        CoordinateSystem::Local => 2,
+        CoordinateSystem::Wgs84 => 6,
+    };
+    let rows: Vec<Vec<String>> = gaps.iter()
+        .enumerate()
+        .map(|(i, g)| vec![
+            (i + 1).to_string(),
+            format!("{:.*} {:.*}", precision, g.center.0, precision, g.center.1),
+            format_area(g.area, units),
+            format!("{:.*},{:.*},{:.*},{:.*}", precision, g.bounds.0, precision, g.bounds.1, precision, g.bounds.2, precision, g.bounds.3),
+            format!("{:.0} dBm", g.weakest),
+            format!("{:.0} dBm", g.average),
+        ])
+        .collect();
+
+    crate::table::print_table(&["Gap", "Center", "Area", "Bounds", "Weakest", "Average"], &rows);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Grid of 4 by 2 cells one unit apart, with a weak region of three cells on the left, a weak cell on the right,
+    /// and an empty cell.
+    fn grid() -> Grid {
+        Grid{
+            origin: (0.0, 0.0),
+            spacing: (1.0, 1.0),
+            columns: 4,
+            rows: 2,
+            values: vec![
+                Some(-80.0), Some(-75.0), Some(-60.0), Some(-90.0),
+                Some(-72.0), Some(-60.0), None, Some(-50.0),
+            ],
+        }
+    }
+
+    #[test]
+    fn find_gaps_joins_weak_cells_which_share_an_edge() {
+        let gaps = find_gaps(&grid(), 1.0, -70.0, 0.0);
+        assert_eq!(gaps.len(), 2);
+
+        let left = &gaps[0];
+        assert_eq!(left.area, 3.0);
+        assert_eq!(left.bounds, (0.0, 0.0, 1.0, 1.0));
+        assert_eq!(left.weakest, -80.0);
+        assert!((left.average + 75.666).abs() < 0.001);
+        assert!((left.center.0 - 1.0 / 3.0).abs() < 1e-9 && (left.center.1 - 1.0 / 3.0).abs() < 1e-9);
+
+        let right = &gaps[1];
+        assert_eq!(right.cells, vec![(3, 0)]);
+        assert_eq!(right.center, (3.0, 0.0));
+    }
+
+    #[test]
+    fn find_gaps_leaves_out_small_regions() {
+        let gaps = find_gaps(&grid(), 2.0, -70.0, 5.0);
+        assert_eq!(gaps.len(), 1);
+        assert_eq!(gaps[0].area, 12.0);
+        assert!(find_gaps(&grid(), 1.0, -95.0, 0.0).is_empty());
+    }
+}
@@ -0,0 +1,115 @@
+//! Reads the current position from a gpsd daemon using its JSON protocol.
+
+use std::error::Error;
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::TcpStream;
+use std::time::{Duration, Instant};
+
+use serde_json::Value;
+
+/// Options which control how a position fix is obtained.
+pub struct GpsOptions {
+    /// Address of gpsd, host:port.
+    pub address: String,
+
+    /// Largest acceptable horizontal error estimate in meters.
+    pub max_error: f64,
+
+    /// How long to wait for an acceptable fix.
+    pub timeout: Duration,
+}
+
+/// Position reported by the GPS.
+pub struct Fix {
+    /// Latitude in degrees.
+    pub lat: f64,
+
+    /// Longitude in degrees.
+    pub lon: f64,
+
+    /// Altitude in meters. None for 2D fixes.
+    pub alt: Option<f64>,
+
+    /// Estimated horizontal error in meters. None if gpsd did not report it.
+    pub error: Option<f64>,
+}
+
+/// Error which occurs when a fix cannot be obtained.
+#[derive(Debug)]
+pub struct GpsError {
+    /// Reason no fix was obtained.
+    reason: String,
+}
+
+impl fmt::Display for GpsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.reason)
+    }
+}
+
+impl Error for GpsError {}
+
+/// Parse a gpsd TPV (time-position-velocity) report. None if the report does not contain at least a 2D fix.
+fn parse_tpv(report: &Value) -> Option<Fix> {
+    if report.get("class")?.as_str()? != "TPV" || report.get("mode")?.as_u64()? < 2 {
+        return None;
+    }
+
+    let error = match (report.get("epx").and_then(|v| v.as_f64()), report.get("epy").and_then(|v| v.as_f64())) {
+        (Some(epx), Some(epy)) => Some(epx.max(epy)),
+        _ => report.get("eph").and_then(|v| v.as_f64()),
+    };
+
+    Some(Fix{
+        lat: report.get("lat")?.as_f64()?,
+        lon: report.get("lon")?.as_f64()?,
+        alt: match report.get("mode")?.as_u64()? {
+            3 => report.get("altMSL").or_else(|| report.get("alt")).and_then(|v| v.as_f64()),
+            _ => None,
+        },
+        error,
+    })
+}
+
+/// Wait for gpsd to report a fix whose error estimate is within the maximum allowed.
+pub fn read_fix(opts: &GpsOptions) -> Result<Fix, Box<dyn Error>> {
+    let mut stream = TcpStream::connect(&opts.address)?;
+    stream.set_read_timeout(Some(opts.timeout))?;
+    stream.write_all(b"?WATCH={\"enable\":true,\"json\":true};\n")?;
+
+    let started = Instant::now();
+    let mut best_error: Option<f64> = None;
+    let mut reader = BufReader::new(stream);
+
+    while started.elapsed() < opts.timeout {
+        let mut line = String::new();
+        match reader.read_line(&mut line) {
+            Ok(0) => return Err(Box::new(GpsError{
+                reason: String::from("gpsd closed the connection"),
+            })),
+            Ok(_) => (),
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut => break,
+            Err(e) => return Err(Box::new(e)),
+        };
+
+        let fix = match serde_json::from_str::<Value>(&line).ok().as_ref().and_then(parse_tpv) {
+            Some(f) => f,
+            None => continue,
+        };
+
+        match fix.error {
+            Some(e) if e > opts.max_error => {
+                best_error = Some(best_error.map_or(e, |b| b.min(e)));
+            },
+            _ => return Ok(fix),
+        };
+    }
+
+    Err(Box::new(GpsError{
+        reason: match best_error {
+            Some(e) => format!("no fix within {} m after {} seconds, best was {:.1} m", opts.max_error, opts.timeout.as_secs(), e),
+            None => format!("no fix after {} seconds", opts.timeout.as_secs()),
+        },
+    }))
+}
@@ -0,0 +1,116 @@
+//! Guides the user through a survey of regularly spaced points.
+
+use std::error::Error;
+use std::io::{stdin, stdout, Write};
+use std::path::Path;
+
+use crate::stream;
+use crate::{Coordinate, Node, RecordOptions, ScanMap, ScanOptions, WriteOptions};
+
+/// Area and spacing of a grid survey.
+pub struct GridOptions {
+    /// Area to cover: min x, min y, max x, max y.
+    pub bbox: (f64, f64, f64, f64),
+
+    /// Distance between neighboring points, in the map's units.
+    pub spacing: f64,
+
+    /// Height of the points.
+    pub z: f64,
+}
+
+/// Points of the grid, row by row. Every other row is walked backwards so the user never has to walk back across the area.
+fn points(opts: &GridOptions) -> Vec<Coordinate> {
+    let (min_x, min_y, max_x, max_y) = opts.bbox;
+    let steps = |min: f64, max: f64| ((max - min) / opts.spacing + 1e-9).floor() as usize + 1;
+
+    let mut points = Vec::<Coordinate>::new();
+    for row in 0..steps(min_y, max_y) {
+        let y = min_y + row as f64 * opts.spacing;
+        let mut xs: Vec<f64> = (0..steps(min_x, max_x)).map(|col| min_x + col as f64 * opts.spacing).collect();
+        if row % 2 == 1 {
+            xs.reverse();
+        }
+
+        points.extend(xs.into_iter().map(|x| Coordinate{ x, y, z: opts.z }));
+    }
+
+    points
+}
+
+/// For each point, true if the map has a node on floor within a quarter of the grid spacing of it, so a resumed grid
+/// skips it. Nodes of streamed maps are read one at a time.
+fn recorded(scan_map: &ScanMap, floor: &Option<String>, points: &[Coordinate], spacing: f64) -> Result<Vec<bool>, Box<dyn Error>> {
+    let mut recorded = vec![false; points.len()];
+
+    stream::visit_nodes(scan_map, |_, n| {
+        if n.floor == *floor {
+            for (point, recorded) in points.iter().zip(recorded.iter_mut()) {
+                *recorded |= ((n.position.x - point.x).powi(2) + (n.position.y - point.y).powi(2)).sqrt() <= spacing / 4.0;
+            }
+        }
+        Ok(())
+    })?;
+
+    Ok(recorded)
+}
+
+/// Walk the user through each grid point which has no node yet, recording a node at each. The map is saved after every node.
+pub fn grid(scan_map: &mut ScanMap, record_opts: &RecordOptions, scan_opts: &ScanOptions, opts: &GridOptions, map_path: &Path, write_opts: &WriteOptions) -> Result<(), Box<dyn Error>> {
+    let crs = scan_map.coordinate_system;
+    let units = scan_map.units;
+    let floor = record_opts.determine_floor(scan_map);
+
+    let points = points(opts);
+    let recorded = recorded(scan_map, &floor, &points, opts.spacing)?;
+    let remaining: Vec<Coordinate> = points.iter()
+        .zip(recorded)
+        .filter(|(_, recorded)| !recorded)
+        .map(|(p, _)| Coordinate{ x: p.x, y: p.y, z: p.z })
+        .collect();
+
+    println!("Grid of {} points, {} already recorded", points.len(), points.len() - remaining.len());
+
+    let mut skipped = 0;
+    for (i, point) in remaining.iter().enumerate() {
+        print!("Point {} of {}: go to {}, press enter to scan, s to skip, or q to quit: ", i + 1, remaining.len(), point.format(crs, units, units));
+        stdout().flush().expect("failed to flush stdout");
+        let mut answer = String::new();
+        let read = stdin().read_line(&mut answer).expect("failed to read input");
+
+        if read == 0 || answer.trim() == "q" {
+            stopped(remaining.len() - i, skipped);
+            return Ok(());
+        }
+        if answer.trim() == "s" {
+            skipped += 1;
+            continue;
+        }
+
+        let position = Coordinate{ x: point.x, y: point.y, z: point.z };
+        let node = Node::measure(scan_map, record_opts, scan_opts, floor.clone(), position, String::new())?;
+        println!("Recorded a new measurement with {} network{}", node.networks.len(), match node.networks.len() != 1 {
+            true => "s",
+            false => "",
+        });
+
+        scan_map.nodes.push(node);
+        scan_map.write(map_path, write_opts)?;
+        println!();
+    }
+
+    match skipped {
+        0 => println!("Grid complete"),
+        _ => stopped(0, skipped),
+    };
+
+    Ok(())
+}
+
+/// Tell the user how to finish the grid later.
+fn stopped(left: usize, skipped: usize) {
+    println!("{} point{} left and {} skipped, run the same command again to resume", left, match left != 1 {
+        true => "s",
+        false => "",
+    }, skipped);
+}
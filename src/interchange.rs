@@ -0,0 +1,408 @@
+//! Converts scan maps into the project formats of commercial survey tools, so a survey can be
+//! refined by people who use them. Ekahau projects (.esx) are ZIP archives of JSON files: floor
+//! plans with their images, the measured access points, and one passive survey per floor whose
+//! locations are in floor plan pixels. NetSpot imports CSV tables with one row per network heard
+//! at each survey point.
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io::{Cursor, Write};
+use std::path::Path;
+
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use zip::result::ZipResult;
+use zip::write::SimpleFileOptions;
+use zip::CompressionMethod;
+
+use crate::render::Viewport;
+use crate::timestamp::TimeRange;
+use crate::{CoordinateSystem, Node, ScanMap, Units};
+
+/// Longest side of the blank floor plan used for floors without one, in pixels.
+const BLANK_PLAN_SIZE: u32 = 2000;
+
+/// Error which occurs when a map cannot be converted.
+#[derive(Debug)]
+pub struct InterchangeError {
+    /// Reason the map could not be converted.
+    reason: String,
+}
+
+impl fmt::Display for InterchangeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.reason)
+    }
+}
+
+impl Error for InterchangeError {}
+
+/// Identifier in the UUID format Ekahau uses. Derived from kind and key so exporting a map twice gives the same ids.
+fn uuid(kind: &str, key: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(kind.as_bytes());
+    hasher.update([0u8]);
+    hasher.update(key.as_bytes());
+    let mut bytes: [u8; 32] = hasher.finalize().into();
+
+    // Version 4 and RFC 4122 variant bits, as if the id was random
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+
+    let hex: String = bytes[..16].iter().map(|b| format!("{:02x}", b)).collect();
+    format!("{}-{}-{}-{}-{}", &hex[..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..32])
+}
+
+/// Writes a ZIP archive whose files are stored without compression, which every reader supports.
+struct ZipWriter {
+    zip: zip::ZipWriter<Cursor<Vec<u8>>>,
+}
+
+impl ZipWriter {
+    fn new() -> ZipWriter {
+        ZipWriter{
+            zip: zip::ZipWriter::new(Cursor::new(Vec::new())),
+        }
+    }
+
+    /// Add a file, fails if it is too large for a ZIP archive without ZIP64 extensions, since Ekahau cannot read those.
+    fn add(&mut self, name: &str, contents: &[u8]) -> ZipResult<()> {
+        // Every file is dated 1980-01-01 so exporting a map twice gives the same archive
+        let options = SimpleFileOptions::default()
+            .compression_method(CompressionMethod::Stored)
+            .last_modified_time(zip::DateTime::default());
+        self.zip.start_file(name, options)?;
+        self.zip.write_all(contents)?;
+        Ok(())
+    }
+
+    fn add_json(&mut self, name: &str, value: &serde_json::Value) -> ZipResult<()> {
+        self.add(name, &serde_json::to_vec(value).expect("failed to serialize project file"))
+    }
+
+    fn finish(self) -> ZipResult<Vec<u8>> {
+        Ok(self.zip.finish()?.into_inner())
+    }
+}
+
+/// Nodes grouped by floor, in the order floors are first seen.
+fn by_floor<'a>(nodes: &[&'a Node]) -> Vec<(Option<String>, Vec<&'a Node>)> {
+    let mut floors = Vec::<(Option<String>, Vec<&'a Node>)>::new();
+    for node in nodes {
+        match floors.iter_mut().find(|(f, _)| *f == node.floor) {
+            Some((_, n)) => n.push(node),
+            None => floors.push((node.floor.clone(), vec![node])),
+        };
+    }
+    floors
+}
+
+/// Ekahau project of the networks of nodes scanned within times. Floor plan images are read relative to map_dir, floors
+/// without one get a blank plan which fits their nodes.
+pub fn esx(scan_map: &ScanMap, nodes: &[&Node], times: &TimeRange, map_dir: &Path) -> Result<Vec<u8>, Box<dyn Error>> {
+    if scan_map.coordinate_system != CoordinateSystem::Local {
+        return Err(Box::new(InterchangeError{
+            reason: String::from("only maps with local coordinates can be exported to Ekahau, floor plans have no geographic position"),
+        }));
+    }
+    if nodes.is_empty() {
+        return Err(Box::new(InterchangeError{
+            reason: String::from("no nodes to export"),
+        }));
+    }
+
+    let mut zip = ZipWriter::new();
+    let project_id = uuid("project", &scan_map.name);
+
+    zip.add_json("project.json", &json!({
+        "project": {
+            "name": scan_map.name,
+            "title": scan_map.name,
+            "description": scan_map.notes,
+            "id": project_id,
+            "status": "CREATED",
+        },
+    }))?;
+
+    // Every BSSID is one measured radio of its own access point, the tool can group them afterwards
+    let mut access_points = BTreeMap::<String, serde_json::Value>::new();
+    for network in nodes.iter().flat_map(|n| n.networks_in(times)) {
+        let mac = network.mac.to_lowercase();
+        access_points.entry(mac.clone()).or_insert_with(|| json!({
+            "mac": mac,
+            "ssid": network.ssid,
+            "vendor": network.vendor,
+            "channel": network.channel.parse::<u32>().ok(),
+            "frequency": network.frequency,
+            "security": network.security.as_ref().map(|s| s.to_string()),
+            "annotation": scan_map.annotations.get(&mac),
+        }));
+    }
+
+    zip.add_json("accessPoints.json", &json!({
+        "accessPoints": access_points.iter().map(|(mac, ap)| json!({
+            "name": format!("{} {}", ap["ssid"].as_str().unwrap_or_default(), mac).trim(),
+            "vendor": ap["vendor"],
+            "notes": ap["annotation"],
+            "mine": false,
+            "hidden": false,
+            "userDefinedPosition": false,
+            "id": uuid("access point", mac),
+            "status": "CREATED",
+        })).collect::<Vec<serde_json::Value>>(),
+    }))?;
+    zip.add_json("accessPointMeasurements.json", &json!({
+        "accessPointMeasurements": access_points.iter().map(|(mac, ap)| json!({
+            "mac": mac,
+            "ssid": ap["ssid"],
+            "channelByCenterFrequencyDefinedNarrowChannels": ap["channel"].as_u64().map(|c| vec![c]).unwrap_or_default(),
+            "centerFrequency": ap["frequency"],
+            "security": ap["security"],
+            "id": uuid("access point measurement", mac),
+            "status": "CREATED",
+        })).collect::<Vec<serde_json::Value>>(),
+    }))?;
+    zip.add_json("measuredRadios.json", &json!({
+        "measuredRadios": access_points.keys().map(|mac| json!({
+            "accessPointId": uuid("access point", mac),
+            "accessPointMeasurementIds": [uuid("access point measurement", mac)],
+            "id": uuid("measured radio", mac),
+            "status": "CREATED",
+        })).collect::<Vec<serde_json::Value>>(),
+    }))?;
+
+    let mut floor_plans = Vec::<serde_json::Value>::new();
+    let mut images = Vec::<serde_json::Value>::new();
+    let mut surveys = Vec::<serde_json::Value>::new();
+    for (floor, floor_nodes) in by_floor(nodes) {
+        let name = floor.clone().unwrap_or_else(|| scan_map.name.clone());
+        let plan_id = uuid("floor plan", &name);
+        let image_id = uuid("image", &name);
+
+        let (viewport, (width, height), format, image) = match scan_map.floor_plan(floor.as_deref()) {
+            Some(plan) => {
+                let path = map_dir.join(&plan.image);
+                let bytes = fs::read(&path).map_err(|e| InterchangeError{
+                    reason: format!("failed to read floor plan \"{}\": {}", path.display(), e),
+                })?;
+                let format = image::guess_format(&bytes)?;
+                let size = image::load_from_memory(&bytes)?;
+                (Viewport::of_plan(plan), (size.width(), size.height()), format, bytes)
+            },
+            None => {
+                let (viewport, size) = Viewport::fit(&floor_nodes, BLANK_PLAN_SIZE);
+                let blank = image::RgbImage::from_pixel(size.0, size.1, image::Rgb([255, 255, 255]));
+                let mut bytes = Cursor::new(Vec::<u8>::new());
+                image::DynamicImage::ImageRgb8(blank).write_to(&mut bytes, image::ImageOutputFormat::Png)?;
+                (viewport, size, image::ImageFormat::Png, bytes.into_inner())
+            },
+        };
+
+        zip.add(&format!("image-{}", image_id), &image)?;
+        images.push(json!({
+            "imageFormat": match format {
+                image::ImageFormat::Jpeg => "JPEG",
+                image::ImageFormat::Bmp => "BMP",
+                _ => "PNG",
+            },
+            "resolutionWidth": width,
+            "resolutionHeight": height,
+            "id": image_id,
+            "status": "CREATED",
+        }));
+        floor_plans.push(json!({
+            "name": name,
+            "width": width,
+            "height": height,
+            "metersPerUnit": scan_map.units.convert(1.0 / viewport.scale, Units::Meters),
+            "imageId": image_id,
+            "id": plan_id,
+            "status": "CREATED",
+        }));
+
+        let survey_id = uuid("survey", &name);
+        let locations: Vec<serde_json::Value> = floor_nodes.iter()
+            .map(|node| {
+                let (x, y) = viewport.to_pixel(node.position.x, node.position.y);
+                json!({
+                    "location": { "floorPlanId": plan_id, "coord": { "x": x, "y": y } },
+                    "notes": node.notes,
+                    "timestamp": node.networks_in(times).map(|n| n.time_scanned).max(),
+                    "measurements": node.networks_in(times)
+                        .filter(|n| n.strength.is_some())
+                        .map(|n| json!({
+                            "measuredRadioId": uuid("measured radio", &n.mac.to_lowercase()),
+                            "accessPointMeasurementId": uuid("access point measurement", &n.mac.to_lowercase()),
+                            "signalStrength": n.strength,
+                            "noise": n.noise,
+                            "timestamp": n.time_scanned,
+                        }))
+                        .collect::<Vec<serde_json::Value>>(),
+                })
+            })
+            .collect();
+
+        zip.add_json(&format!("survey-{}.json", survey_id), &json!({
+            "surveyId": survey_id,
+            "locations": locations,
+        }))?;
+        surveys.push(json!({
+            "name": format!("{} survey", name),
+            "floorPlanId": plan_id,
+            "surveyType": "PASSIVE",
+            "id": survey_id,
+            "status": "CREATED",
+        }));
+    }
+
+    zip.add_json("images.json", &json!({ "images": images }))?;
+    zip.add_json("floorPlans.json", &json!({ "floorPlans": floor_plans }))?;
+    zip.add_json("surveys.json", &json!({ "surveys": surveys }))?;
+
+    Ok(zip.finish()?)
+}
+
+/// Quote a CSV field if it contains a separator, quote, or line break.
+fn csv_field(s: &str) -> String {
+    match s.contains([',', '"', '\n', '\r']) {
+        true => format!("\"{}\"", s.replace('"', "\"\"")),
+        false => s.to_string(),
+    }
+}
+
+/// Header of NetSpot CSV tables.
+const NETSPOT_HEADERS: &[&str] = &["Zone", "Point", "X", "Y", "SSID", "BSSID", "Channel", "Band", "Security", "Vendor", "Signal", "Noise", "SNR", "Time"];
+
+/// Header line of a NetSpot CSV table.
+pub fn netspot_header() -> String {
+    NETSPOT_HEADERS.join(",")
+}
+
+/// Rows of a NetSpot CSV table for a node, one per network scanned within times. Zones are floors, points are numbered
+/// in the order nodes were recorded, and X and Y are in the map's units.
+pub fn netspot_rows(scan_map: &ScanMap, point: usize, node: &Node, times: &TimeRange) -> Vec<String> {
+    let optional = |v: Option<f32>| v.map(|v| v.to_string()).unwrap_or_default();
+
+    node.networks_in(times)
+        .map(|network| {
+            let row = [
+                node.floor.clone().unwrap_or_else(|| scan_map.name.clone()),
+                point.to_string(),
+                node.position.x.to_string(),
+                node.position.y.to_string(),
+                network.display_ssid().to_string(),
+                network.mac.to_lowercase(),
+                network.channel.clone(),
+                network.band.map(|b| b.to_string()).unwrap_or_default(),
+                network.security.as_ref().map(|s| s.to_string()).unwrap_or_default(),
+                network.vendor.clone().unwrap_or_default(),
+                optional(network.strength),
+                optional(network.noise),
+                optional(network.snr),
+                (network.time_scanned / 1000).to_string(),
+            ];
+            row.iter().map(|f| csv_field(f)).collect::<Vec<String>>().join(",")
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::scan_map;
+
+    /// Names and contents of the files of a ZIP archive.
+    fn unzip(data: &[u8]) -> Vec<(String, Vec<u8>)> {
+        let mut archive = zip::ZipArchive::new(Cursor::new(data)).unwrap();
+        (0..archive.len())
+            .map(|i| {
+                let mut file = archive.by_index(i).unwrap();
+                let name = file.name().unwrap().to_string();
+                assert_eq!(file.compression(), CompressionMethod::Stored, "{} is stored", name);
+                let mut contents = Vec::new();
+                std::io::Read::read_to_end(&mut file, &mut contents).unwrap();
+                (name, contents)
+            })
+            .collect()
+    }
+
+    fn survey() -> ScanMap {
+        scan_map(serde_json::json!({
+            "name": "Office",
+            "nodes": [
+                {"position": {"x": 0.0, "y": 0.0, "z": 0.0}, "notes": "Lobby", "networks": [
+                    {"mac": "84:D4:7E:69:2F:E2", "ssid": "Corp, WiFi", "channel": "36", "strength": -61.0, "time_scanned": 1_700_000_000_000u64},
+                    {"mac": "aa:bb:cc:00:11:22", "channel": "6", "strength": null, "time_scanned": 1_700_000_000_000u64},
+                ]},
+                {"position": {"x": 10.0, "y": 4.0, "z": 0.0}, "networks": [
+                    {"mac": "84:d4:7e:69:2f:e2", "ssid": "Corp, WiFi", "channel": "36", "strength": -75.0, "time_scanned": 1_700_000_060_000u64},
+                ]},
+            ],
+        }))
+    }
+
+    #[test]
+    fn uuid_is_stable_and_version_4() {
+        let id = uuid("project", "Office");
+        assert_eq!(id, uuid("project", "Office"));
+        assert_ne!(id, uuid("survey", "Office"));
+
+        let groups: Vec<usize> = id.split('-').map(str::len).collect();
+        assert_eq!(groups, vec![8, 4, 4, 4, 12]);
+        assert_eq!(&id[14..15], "4");
+        assert!("89ab".contains(&id[19..20]));
+    }
+
+    #[test]
+    fn esx_writes_a_project_with_a_survey_per_floor() {
+        let scan_map = survey();
+        let nodes: Vec<&Node> = scan_map.nodes.iter().collect();
+        let files = unzip(&esx(&scan_map, &nodes, &TimeRange::default(), Path::new(".")).unwrap());
+        let json = |name: &str| -> serde_json::Value {
+            let (_, data) = files.iter().find(|(n, _)| n == name).unwrap_or_else(|| panic!("no {}", name));
+            serde_json::from_slice(data).unwrap()
+        };
+
+        assert_eq!(json("project.json")["project"]["name"], "Office");
+        assert_eq!(json("accessPoints.json")["accessPoints"].as_array().unwrap().len(), 2);
+        assert_eq!(json("floorPlans.json")["floorPlans"][0]["name"], "Office");
+
+        let survey_id = json("surveys.json")["surveys"][0]["id"].as_str().unwrap().to_string();
+        let locations = json(&format!("survey-{}.json", survey_id))["locations"].clone();
+        assert_eq!(locations.as_array().unwrap().len(), 2);
+        assert_eq!(locations[0]["notes"], "Lobby");
+        assert_eq!(locations[0]["measurements"].as_array().unwrap().len(), 1);
+        assert_eq!(locations[0]["measurements"][0]["measuredRadioId"], uuid("measured radio", "84:d4:7e:69:2f:e2"));
+
+        // The blank plan is an image file of its own
+        let image_id = json("images.json")["images"][0]["id"].as_str().unwrap().to_string();
+        assert!(files.iter().any(|(n, data)| *n == format!("image-{}", image_id) && data.starts_with(b"\x89PNG")));
+    }
+
+    #[test]
+    fn esx_needs_local_coordinates_and_nodes() {
+        let scan_map = survey();
+        assert!(esx(&scan_map, &[], &TimeRange::default(), Path::new(".")).is_err());
+
+        let gps = crate::tests::scan_map(serde_json::json!({"coordinate_system": "wgs84"}));
+        let nodes: Vec<&Node> = scan_map.nodes.iter().collect();
+        assert!(esx(&gps, &nodes, &TimeRange::default(), Path::new(".")).is_err());
+    }
+
+    #[test]
+    fn netspot_rows_write_a_row_per_network() {
+        let scan_map = survey();
+        let mut lines = vec![netspot_header()];
+        for (i, node) in scan_map.nodes.iter().enumerate() {
+            lines.extend(netspot_rows(&scan_map, i + 1, node, &TimeRange::default()));
+        }
+
+        assert_eq!(lines[0], "Zone,Point,X,Y,SSID,BSSID,Channel,Band,Security,Vendor,Signal,Noise,SNR,Time");
+        assert_eq!(lines[1], "Office,1,0,0,\"Corp, WiFi\",84:d4:7e:69:2f:e2,36,5 GHz,,Hewlett Packard Enterprise,-61,,,1700000000");
+        assert_eq!(lines[2], "Office,1,0,0,<hidden>,aa:bb:cc:00:11:22,6,2.4 GHz,,,,,,1700000000");
+        assert_eq!(lines[3], "Office,2,10,4,\"Corp, WiFi\",84:d4:7e:69:2f:e2,36,5 GHz,,Hewlett Packard Enterprise,-75,,,1700000060");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+    }
+}
@@ -0,0 +1,351 @@
+//! Estimates signal strength between nodes, turning the measurements of a network into a
+//! regular grid over the surveyed area with inverse distance weighting.
+
+use std::error::Error;
+use std::fmt;
+use std::io::Write;
+
+use serde::Serialize;
+
+use crate::{CoordinateSystem, Node, ScanMap, EARTH_RADIUS};
+
+/// Most cells a grid may have, so a small spacing over a wgs84 map cannot use all memory.
+const MAX_CELLS: usize = 4_000_000;
+
+/// Networks whose strengths are interpolated.
+#[derive(Clone)]
+pub enum Target {
+    /// Every access point broadcasting an SSID, the strongest one counts at each node.
+    Ssid(String),
+
+    /// One access point, by lower case BSSID.
+    Bssid(String),
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Target::Ssid(ssid) => write!(f, "SSID \"{}\"", ssid),
+            Target::Bssid(mac) => write!(f, "BSSID {}", mac),
+        }
+    }
+}
+
+/// Options which control how a grid is interpolated.
+pub struct InterpolateOptions {
+    pub target: Target,
+
+    /// Only use nodes on this floor.
+    pub floor: Option<String>,
+
+    /// Distance between cell centers in the map's units, meters for wgs84 maps.
+    pub spacing: f64,
+
+    /// Exponent of the inverse distance weights, higher values make each node's measurement reach less far.
+    pub power: f64,
+
+    /// Cells farther than this from every node are left empty. None fills every cell.
+    pub radius: Option<f64>,
+
+    /// Strength in dBm used for nodes which did not see the network. None ignores those nodes.
+    pub unseen: Option<f64>,
+}
+
+/// File format grids are written in.
+#[derive(Clone, Copy)]
+pub enum GridFormat {
+    Json,
+    Csv,
+}
+
+impl std::str::FromStr for GridFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<GridFormat, String> {
+        match s.trim().to_lowercase().as_str() {
+            "json" => Ok(GridFormat::Json),
+            "csv" => Ok(GridFormat::Csv),
+            _ => Err(format!("unknown grid format \"{}\", must be json or csv", s.trim())),
+        }
+    }
+}
+
+/// Error which occurs when a grid cannot be interpolated.
+#[derive(Debug)]
+pub struct InterpolateError {
+    /// Reason interpolation failed.
+    reason: String,
+}
+
+impl fmt::Display for InterpolateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.reason)
+    }
+}
+
+impl Error for InterpolateError {}
+
+/// Estimated signal strengths at regularly spaced points. Cells are stored row by row starting
+/// with the southmost row, each row from west to east.
+#[derive(Serialize)]
+pub struct Grid {
+    /// Map position of the center of the first cell.
+    pub origin: (f64, f64),
+
+    /// Distance between cell centers along x and y, in map coordinates.
+    pub spacing: (f64, f64),
+
+    pub columns: usize,
+
+    pub rows: usize,
+
+    /// Estimated strength of each cell in dBm, None if no node is near enough.
+    pub values: Vec<Option<f64>>,
+}
+
+impl Grid {
+    /// Map position of the center of a cell.
+    pub fn position(&self, column: usize, row: usize) -> (f64, f64) {
+        (self.origin.0 + column as f64 * self.spacing.0, self.origin.1 + row as f64 * self.spacing.1)
+    }
+
+    /// Estimated strength of a cell.
+    pub fn value(&self, column: usize, row: usize) -> Option<f64> {
+        self.values[row * self.columns + column]
+    }
+
+    /// Write the grid in format.
+    pub fn write<W: Write>(&self, writer: W, format: GridFormat) -> Result<(), Box<dyn Error>> {
+        match format {
+            GridFormat::Json => self.write_json(writer),
+            GridFormat::Csv => self.write_csv(writer),
+        }
+    }
+
+    /// Write the grid as JSON.
+    pub fn write_json<W: Write>(&self, mut writer: W) -> Result<(), Box<dyn Error>> {
+        serde_json::to_writer_pretty(&mut writer, self)?;
+        writeln!(writer)?;
+
+        Ok(())
+    }
+
+    /// Write one "x,y,strength" line per cell after a header, the strength is empty for empty cells.
+    pub fn write_csv<W: Write>(&self, mut writer: W) -> Result<(), Box<dyn Error>> {
+        writeln!(writer, "x,y,strength")?;
+
+        for row in 0..self.rows {
+            for column in 0..self.columns {
+                let (x, y) = self.position(column, row);
+                let strength = match self.value(column, row) {
+                    Some(v) => format!("{:.2}", v),
+                    None => String::new(),
+                };
+                writeln!(writer, "{},{},{}", x, y, strength)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Strongest signal of the target networks seen at a node.
+pub fn strength_at(node: &Node, target: &Target) -> Option<f64> {
+    node.networks.iter()
+        .filter(|n| match target {
+            Target::Ssid(ssid) => &n.ssid == ssid,
+            Target::Bssid(mac) => &n.mac == mac,
+        })
+        .filter_map(|n| n.strength)
+        .map(|s| s as f64)
+        .fold(None, |best: Option<f64>, s| Some(best.map_or(s, |b| b.max(s))))
+}
+
+/// Position and strength of every node the options use.
+fn samples(scan_map: &ScanMap, opts: &InterpolateOptions) -> Vec<(f64, f64, f64)> {
+    scan_map.nodes.iter()
+        .filter(|n| opts.floor.is_none() || n.floor == opts.floor)
+        .filter_map(|n| strength_at(n, &opts.target).or(opts.unseen).map(|s| (n.position.x, n.position.y, s)))
+        .collect()
+}
+
+/// Lengths of a step of one along x and y at latitude lat, in the units spacings and radii are given in.
+fn unit(crs: CoordinateSystem, lat: f64) -> (f64, f64) {
+    match crs {
+        CoordinateSystem::Local => (1.0, 1.0),
+        CoordinateSystem::Wgs84 => (1f64.to_radians() * lat.to_radians().cos() * EARTH_RADIUS, 1f64.to_radians() * EARTH_RADIUS),
+    }
+}
+
+/// Inverse distance weighted strength at (x, y). None if every sample is farther than radius.
+fn idw(samples: &[(f64, f64, f64)], x: f64, y: f64, unit: (f64, f64), power: f64, radius: Option<f64>) -> Option<f64> {
+    let mut weight_sum = 0.0;
+    let mut value_sum = 0.0;
+    let mut nearest = f64::INFINITY;
+    for (sx, sy, strength) in samples {
+        let distance = ((sx - x) * unit.0).hypot((sy - y) * unit.1);
+        nearest = nearest.min(distance);
+
+        if distance < 1e-9 {
+            return Some(*strength);
+        }
+
+        let weight = distance.powf(-power);
+        weight_sum += weight;
+        value_sum += weight * strength;
+    }
+
+    match radius {
+        Some(r) if nearest > r => None,
+        _ => Some(value_sum / weight_sum),
+    }
+}
+
+/// Interpolate the strength of the target networks at one position. Ok(None) if the position is farther than the radius from every node.
+pub fn estimate(scan_map: &ScanMap, opts: &InterpolateOptions, x: f64, y: f64) -> Result<Option<f64>, InterpolateError> {
+    let samples = samples(scan_map, opts);
+    if samples.is_empty() {
+        return Err(InterpolateError{
+            reason: format!("no nodes measured {}", opts.target),
+        });
+    }
+
+    Ok(idw(&samples, x, y, unit(scan_map.coordinate_system, y), opts.power, opts.radius))
+}
+
+/// Interpolate the strengths of the target networks over the bounding box of the nodes.
+pub fn interpolate(scan_map: &ScanMap, opts: &InterpolateOptions) -> Result<Grid, InterpolateError> {
+    let samples = samples(scan_map, opts);
+    if samples.is_empty() {
+        return Err(InterpolateError{
+            reason: format!("no nodes measured {}", opts.target),
+        });
+    }
+    if opts.spacing <= 0.0 {
+        return Err(InterpolateError{
+            reason: String::from("spacing must be positive"),
+        });
+    }
+
+    let min_x = samples.iter().map(|s| s.0).fold(f64::INFINITY, f64::min);
+    let max_x = samples.iter().map(|s| s.0).fold(f64::NEG_INFINITY, f64::max);
+    let min_y = samples.iter().map(|s| s.1).fold(f64::INFINITY, f64::min);
+    let max_y = samples.iter().map(|s| s.1).fold(f64::NEG_INFINITY, f64::max);
+
+    let unit = unit(scan_map.coordinate_system, (min_y + max_y) / 2.0);
+    let spacing = (opts.spacing / unit.0, opts.spacing / unit.1);
+
+    // The last cells may overhang the nodes so every node is inside the grid
+    let columns = ((max_x - min_x) / spacing.0 - 1e-9).ceil().max(0.0) as usize + 1;
+    let rows = ((max_y - min_y) / spacing.1 - 1e-9).ceil().max(0.0) as usize + 1;
+    if columns.saturating_mul(rows) > MAX_CELLS {
+        return Err(InterpolateError{
+            reason: format!("a spacing of {} makes a grid of {} by {} cells, use a larger spacing", opts.spacing, columns, rows),
+        });
+    }
+
+    let mut values = Vec::<Option<f64>>::with_capacity(columns * rows);
+    for row in 0..rows {
+        for column in 0..columns {
+            let (x, y) = (min_x + column as f64 * spacing.0, min_y + row as f64 * spacing.1);
+            values.push(idw(&samples, x, y, unit, opts.power, opts.radius));
+        }
+    }
+
+    Ok(Grid{
+        origin: (min_x, min_y),
+        spacing,
+        columns,
+        rows,
+        values,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::scan_map;
+
+    /// CorpWiFi measured at -50 at the origin and -70 four meters east, a second node at the origin saw only OldCafe.
+    fn survey() -> ScanMap {
+        scan_map(serde_json::json!({
+            "nodes": [
+                {"position": {"x": 0.0, "y": 0.0, "z": 0.0}, "networks": [
+                    {"mac": "84:d4:7e:69:2f:e2", "ssid": "CorpWiFi", "strength": -50.0},
+                    {"mac": "84:d4:7e:69:2f:e3", "ssid": "CorpWiFi", "strength": -65.0},
+                ]},
+                {"position": {"x": 4.0, "y": 0.0, "z": 0.0}, "networks": [
+                    {"mac": "84:d4:7e:69:2f:e2", "ssid": "CorpWiFi", "strength": -70.0},
+                ]},
+                {"position": {"x": 4.0, "y": 2.0, "z": 0.0}, "networks": [
+                    {"mac": "00:11:22:33:44:55", "ssid": "OldCafe", "strength": -80.0},
+                ]},
+            ],
+        }))
+    }
+
+    fn opts(target: Target) -> InterpolateOptions {
+        InterpolateOptions{
+            target,
+            floor: None,
+            spacing: 2.0,
+            power: 2.0,
+            radius: None,
+            unseen: None,
+        }
+    }
+
+    #[test]
+    fn strength_at_takes_the_strongest_matching_network() {
+        let scan_map = survey();
+        assert_eq!(strength_at(&scan_map.nodes[0], &Target::Ssid(String::from("CorpWiFi"))), Some(-50.0));
+        assert_eq!(strength_at(&scan_map.nodes[0], &Target::Bssid(String::from("84:d4:7e:69:2f:e3"))), Some(-65.0));
+        assert_eq!(strength_at(&scan_map.nodes[2], &Target::Ssid(String::from("CorpWiFi"))), None);
+    }
+
+    #[test]
+    fn interpolate_weights_nodes_by_inverse_distance() {
+        let grid = interpolate(&survey(), &opts(Target::Ssid(String::from("CorpWiFi")))).unwrap();
+        assert_eq!((grid.columns, grid.rows), (3, 1));
+        assert_eq!(grid.position(2, 0), (4.0, 0.0));
+        assert_eq!(grid.values, vec![Some(-50.0), Some(-60.0), Some(-70.0)]);
+
+        // Nodes which did not see the network count with the unseen strength, and widen the grid
+        let grid = interpolate(&survey(), &InterpolateOptions{
+            unseen: Some(-90.0),
+            ..opts(Target::Ssid(String::from("CorpWiFi")))
+        }).unwrap();
+        assert_eq!((grid.columns, grid.rows), (3, 2));
+        assert_eq!(grid.value(2, 1), Some(-90.0));
+    }
+
+    #[test]
+    fn interpolate_leaves_cells_beyond_the_radius_empty() {
+        let grid = interpolate(&survey(), &InterpolateOptions{
+            spacing: 1.0,
+            radius: Some(1.0),
+            ..opts(Target::Ssid(String::from("CorpWiFi")))
+        }).unwrap();
+        assert_eq!(grid.values.iter().map(Option::is_some).collect::<Vec<bool>>(), vec![true, true, false, true, true]);
+
+        let mut csv = Vec::new();
+        grid.write(&mut csv, GridFormat::Csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        assert!(csv.starts_with("x,y,strength\n0,0,-50.00\n"));
+        assert!(csv.contains("\n2,0,\n"));
+    }
+
+    #[test]
+    fn interpolate_rejects_unmeasured_networks_and_bad_spacings() {
+        assert!(interpolate(&survey(), &opts(Target::Ssid(String::from("Nobody")))).is_err());
+        assert!(interpolate(&survey(), &InterpolateOptions{
+            spacing: 0.0,
+            ..opts(Target::Ssid(String::from("CorpWiFi")))
+        }).is_err());
+        assert!(interpolate(&survey(), &InterpolateOptions{
+            spacing: 0.001,
+            unseen: Some(-90.0),
+            ..opts(Target::Ssid(String::from("CorpWiFi")))
+        }).is_err());
+    }
+}
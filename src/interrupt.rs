@@ -0,0 +1,48 @@
+//! Stops recording cleanly when the user presses Ctrl-C. The handler waits for a save which is in progress, so the map
+//! file is never left half written, and discards the measurement which was being recorded.
+
+use std::path::Path;
+use std::process::exit;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard};
+
+/// Held while a map is being saved.
+static SAVING: Mutex<()> = Mutex::new(());
+
+/// Number of nodes in the map when it was last saved.
+static SAVED_NODES: AtomicUsize = AtomicUsize::new(0);
+
+/// Exit code of a process stopped by SIGINT.
+const INTERRUPTED_EXIT_CODE: i32 = 130;
+
+/// Block Ctrl-C from exiting until the returned guard is dropped.
+pub fn saving() -> MutexGuard<'static, ()> {
+    SAVING.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Note that a map with nodes was saved.
+pub fn saved(nodes: usize) {
+    SAVED_NODES.store(nodes, Ordering::SeqCst);
+}
+
+/// Exit with a summary of what was saved to map_path on Ctrl-C. nodes is the number of nodes the map had before recording.
+pub fn install(map_path: &Path, nodes: usize) -> Result<(), ctrlc::Error> {
+    let path = map_path.display().to_string();
+    SAVED_NODES.store(nodes, Ordering::SeqCst);
+
+    ctrlc::set_handler(move || {
+        let _saving = saving();
+        let recorded = SAVED_NODES.load(Ordering::SeqCst).saturating_sub(nodes);
+
+        println!();
+        match recorded {
+            0 => println!("Interrupted, no new nodes were saved"),
+            _ => println!("Interrupted, saved {} new node{} to \"{}\"", recorded, match recorded != 1 {
+                true => "s",
+                false => "",
+            }, path),
+        };
+
+        exit(INTERRUPTED_EXIT_CODE);
+    })
+}
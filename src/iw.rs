@@ -0,0 +1,407 @@
+//! Parses the output of the Linux `iw` tool. The wifiscanner crate only keeps a few fields
+//! from `iw dev <interface> scan`, this module extracts the details it drops.
+
+use std::env;
+use std::error::Error;
+use std::fmt;
+use std::process::Command;
+use std::time::Instant;
+
+use crate::logging;
+use crate::radio::Standard;
+use crate::security::Security;
+
+/// Error which occurs when running iw.
+#[derive(Debug)]
+pub struct IwError {
+    /// Reason iw failed.
+    reason: String,
+}
+
+impl fmt::Display for IwError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "iw: {}", self.reason)
+    }
+}
+
+impl Error for IwError {}
+
+/// A basic service set (ie., one access point radio) from iw scan output.
+#[derive(Default, Debug)]
+pub struct Bss {
+    /// Hardware address.
+    pub mac: String,
+
+    /// Network name.
+    pub ssid: Option<String>,
+
+    /// Center frequency of the primary channel in MHz.
+    pub freq: Option<u32>,
+
+    /// Signal strength in dBm.
+    pub signal: Option<f32>,
+
+    /// Primary channel number.
+    pub channel: Option<String>,
+
+    /// Flags from the capability line, e.g. ESS, Privacy.
+    pub capabilities: Vec<String>,
+
+    /// True if a WPA information element was present.
+    pub wpa: bool,
+
+    /// True if an RSN (WPA2 or newer) information element was present.
+    pub rsn: bool,
+
+    /// Authentication suites from the RSN or WPA element, e.g. PSK, SAE, IEEE 802.1X.
+    pub auth_suites: Vec<String>,
+
+    /// Channel width in MHz from the HT operation element.
+    pub ht_width: Option<u32>,
+
+    /// Channel width in MHz from the VHT operation element. None if the element was missing or defers to the HT width.
+    pub vht_width: Option<u32>,
+
+    /// Newest 802.11 generation whose capabilities element was advertised.
+    pub standard: Option<Standard>,
+
+    /// Time between beacons in time units of 1.024 ms.
+    pub beacon_interval: Option<u32>,
+
+    /// Names of the information elements the access point sent, e.g. RSN, HT capabilities.
+    pub elements: Vec<String>,
+}
+
+impl Bss {
+    /// Security scheme derived from the information elements.
+    pub fn security(&self) -> Security {
+        let has_suite = |name: &str| self.auth_suites.iter().any(|s| s.contains(name));
+
+        if has_suite("802.1X") {
+            Security::Enterprise
+        } else if has_suite("SAE") {
+            Security::Wpa3
+        } else if self.rsn {
+            Security::Wpa2
+        } else if self.wpa {
+            Security::Wpa
+        } else if self.capabilities.iter().any(|c| c == "Privacy") {
+            Security::Wep
+        } else {
+            Security::Open
+        }
+    }
+
+    /// Newest 802.11 generation supported by the access point.
+    pub fn standard(&self) -> Standard {
+        self.standard.unwrap_or(Standard::Legacy)
+    }
+
+    /// Width of the channel in MHz. Access points without HT or VHT operation elements predate 802.11n and always use 20 MHz channels.
+    pub fn channel_width(&self) -> u32 {
+        self.vht_width.or(self.ht_width).unwrap_or(20)
+    }
+}
+
+/// Channel statistics from `iw dev <interface> survey dump`.
+pub struct Survey {
+    /// Center frequency of the channel in MHz.
+    pub freq: u32,
+
+    /// Noise floor in dBm.
+    pub noise: f32,
+}
+
+/// Information element whose fields are printed on the lines which follow its header.
+#[derive(PartialEq)]
+enum Element {
+    None,
+    HtOperation,
+    VhtOperation,
+}
+
+/// Lines indented by one tab which describe the BSS rather than one of its information elements.
+const BSS_FIELDS: [&str; 7] = ["TSF", "freq", "beacon interval", "capability", "signal", "last seen", "Information elements from"];
+
+/// Parse the width out of a VHT operation channel width field, e.g. "1 (80 MHz)" or "0 (20 or 40 MHz)".
+fn parse_vht_width(v: &str) -> Option<u32> {
+    let desc = v.split('(').nth(1)?.trim_end_matches(')');
+
+    match desc {
+        "80 MHz" => Some(80),
+        "160 MHz" | "80+80 MHz" => Some(160),
+        _ => None,
+    }
+}
+
+/// Run a network configuration program with args. The sbin directories are added to the PATH since iw and ip are often installed there.
+fn run(program: &str, args: &[&str]) -> Result<String, Box<dyn Error>> {
+    let path_sbin = "/usr/sbin:/sbin";
+    let path = match env::var_os("PATH") {
+        Some(p) => format!("{}:{}", p.to_string_lossy(), path_sbin),
+        None => String::from(path_sbin),
+    };
+
+    let started = Instant::now();
+    let output = Command::new(program)
+        .env("PATH", path)
+        .args(args)
+        .output()?;
+    logging::command(program, args, &output, started.elapsed());
+
+    if !output.status.success() {
+        return Err(Box::new(IwError{
+            reason: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        }));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Run iw with args.
+fn run_iw(args: &[&str]) -> Result<String, Box<dyn Error>> {
+    run("iw", args)
+}
+
+/// Change the type of interface, e.g. to monitor or managed. The interface is taken down while its type changes.
+#[cfg(feature = "capture")]
+pub fn set_type(interface: &str, interface_type: &str) -> Result<(), Box<dyn Error>> {
+    run("ip", &["link", "set", "dev", interface, "down"])?;
+    let result = run_iw(&["dev", interface, "set", "type", interface_type]);
+    run("ip", &["link", "set", "dev", interface, "up"])?;
+
+    result.map(|_| ())
+}
+
+/// Tune a monitor mode interface to a channel.
+#[cfg(feature = "capture")]
+pub fn set_channel(interface: &str, channel: u32) -> Result<(), Box<dyn Error>> {
+    run_iw(&["dev", interface, "set", "channel", &channel.to_string()]).map(|_| ())
+}
+
+/// Names of wireless interfaces listed by `iw dev`.
+pub fn interfaces() -> Result<Vec<String>, Box<dyn Error>> {
+    let output = run_iw(&["dev"])?;
+
+    Ok(output.lines()
+       .filter_map(|l| l.trim().strip_prefix("Interface "))
+       .map(|i| i.trim().to_string())
+       .collect())
+}
+
+/// Scan for networks with interface. Fails if the interface is busy, e.g. because another program is scanning.
+pub fn scan(interface: &str) -> Result<Vec<Bss>, Box<dyn Error>> {
+    let output = run_iw(&["dev", interface, "scan"])?;
+
+    Ok(parse_scan(&output))
+}
+
+/// Return the results of the most recent scan on interface without triggering a new scan.
+pub fn scan_dump(interface: &str) -> Result<Vec<Bss>, Box<dyn Error>> {
+    let output = run_iw(&["dev", interface, "scan", "dump"])?;
+
+    Ok(parse_scan(&output))
+}
+
+/// Noise floor of each channel interface measured. Many drivers only report the noise of the channel they are tuned to.
+pub fn survey(interface: &str) -> Result<Vec<Survey>, Box<dyn Error>> {
+    let output = run_iw(&["dev", interface, "survey", "dump"])?;
+
+    Ok(parse_survey(&output))
+}
+
+/// Parse the output of `iw dev <interface> survey dump`. Channels without a noise value are skipped.
+pub fn parse_survey(output: &str) -> Vec<Survey> {
+    let mut results = Vec::<Survey>::new();
+    let mut freq: Option<u32> = None;
+
+    for line in output.lines().map(|l| l.trim()) {
+        if let Some(v) = line.strip_prefix("frequency:") {
+            freq = v.split_whitespace().next().and_then(|f| f.split('.').next()).and_then(|f| f.parse::<u32>().ok());
+        } else if let Some(v) = line.strip_prefix("noise:") {
+            if let (Some(f), Some(noise)) = (freq, crate::parse_strength(v)) {
+                results.push(Survey{ freq: f, noise });
+            }
+        }
+    }
+
+    results
+}
+
+/// Cached scan results of the first wireless interface.
+pub fn scan_dump_default() -> Result<Vec<Bss>, Box<dyn Error>> {
+    let interface = match interfaces()?.into_iter().next() {
+        Some(i) => i,
+        None => return Err(Box::new(IwError{
+            reason: String::from("no wireless interfaces found"),
+        })),
+    };
+
+    scan_dump(&interface)
+}
+
+/// Parse the output of `iw dev <interface> scan`.
+pub fn parse_scan(output: &str) -> Vec<Bss> {
+    let mut results = Vec::<Bss>::new();
+    let mut element = Element::None;
+
+    for line in output.lines() {
+        if let Some(rest) = line.strip_prefix("BSS ") {
+            element = Element::None;
+            let mac = rest.split('(').next().unwrap_or("").trim().to_lowercase();
+            results.push(Bss{
+                mac,
+                ..Bss::default()
+            });
+            continue;
+        }
+
+        let bss = match results.last_mut() {
+            Some(b) => b,
+            None => continue,
+        };
+
+        let trimmed = line.trim().trim_start_matches("* ");
+
+        // Element headers are indented by one tab, their fields by more
+        if line.starts_with('\t') && !line.starts_with("\t\t") {
+            element = match trimmed {
+                "HT operation:" => Element::HtOperation,
+                "VHT operation:" => Element::VhtOperation,
+                _ => Element::None,
+            };
+
+            let name = trimmed.split(':').next().unwrap_or("").trim();
+            if !name.is_empty() && !BSS_FIELDS.iter().any(|f| name.starts_with(f)) && !bss.elements.iter().any(|e| e == name) {
+                bss.elements.push(name.to_string());
+            }
+        }
+
+        let advertised = match trimmed {
+            "HT capabilities:" => Some(Standard::N),
+            "VHT capabilities:" => Some(Standard::Ac),
+            "HE capabilities:" => Some(Standard::Ax),
+            "EHT capabilities:" => Some(Standard::Be),
+            _ => None,
+        };
+        if advertised > bss.standard {
+            bss.standard = advertised;
+        }
+
+        match element {
+            Element::HtOperation => {
+                if let Some(v) = trimmed.strip_prefix("secondary channel offset: ") {
+                    if v == "above" || v == "below" {
+                        bss.ht_width = Some(40);
+                    }
+                } else if trimmed == "STA channel width: 20 MHz" && bss.ht_width.is_none() {
+                    bss.ht_width = Some(20);
+                }
+            },
+            Element::VhtOperation => {
+                if let Some(v) = trimmed.strip_prefix("channel width: ") {
+                    bss.vht_width = parse_vht_width(v);
+                }
+            },
+            Element::None => (),
+        };
+
+        if let Some(v) = trimmed.strip_prefix("freq: ") {
+            // Newer versions of iw print fractional frequencies, e.g. 2412.0
+            bss.freq = v.split('.').next().and_then(|f| f.trim().parse::<u32>().ok());
+        } else if let Some(v) = trimmed.strip_prefix("signal: ") {
+            bss.signal = crate::parse_strength(v);
+        } else if let Some(v) = trimmed.strip_prefix("SSID: ") {
+            bss.ssid = Some(v.to_string());
+        } else if trimmed == "SSID:" {
+            bss.ssid = Some(String::new());
+        } else if let Some(v) = trimmed.strip_prefix("DS Parameter set: channel ") {
+            bss.channel = Some(v.trim().to_string());
+        } else if let Some(v) = trimmed.strip_prefix("beacon interval: ") {
+            bss.beacon_interval = v.trim_end_matches("TUs").trim().parse::<u32>().ok();
+        } else if let Some(v) = trimmed.strip_prefix("capability: ") {
+            bss.capabilities = v.split_whitespace()
+                .filter(|c| !c.starts_with("(0x"))
+                .map(|c| c.to_string())
+                .collect();
+        } else if trimmed.starts_with("RSN:") {
+            bss.rsn = true;
+        } else if trimmed.starts_with("WPA:") {
+            bss.wpa = true;
+        }
+
+        // Element fields are either on the same line as the element name or on their own line
+        if let Some(idx) = trimmed.find("Authentication suites: ") {
+            let suites = &trimmed[idx + "Authentication suites: ".len()..];
+            bss.auth_suites.extend(suites.split_whitespace()
+                .filter(|s| *s != "IEEE")
+                .map(|s| s.to_string()));
+        }
+    }
+
+    results
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_scan_reads_every_bss() {
+        let bss = parse_scan(include_str!("../tests/fixtures/iw-scan.txt"));
+        assert_eq!(bss.len(), 3);
+
+        let corp = &bss[0];
+        assert_eq!(corp.mac, "84:d4:7e:69:2f:e2");
+        assert_eq!(corp.ssid.as_deref(), Some("CorpWiFi"));
+        assert_eq!(corp.freq, Some(5180));
+        assert_eq!(corp.signal, Some(-58.0));
+        assert_eq!(corp.channel.as_deref(), Some("36"));
+        assert_eq!(corp.beacon_interval, Some(100));
+        assert_eq!(corp.capabilities, vec!["ESS", "Privacy", "SpectrumMgmt"]);
+        assert_eq!(corp.auth_suites, vec!["802.1X"]);
+        assert_eq!(corp.security(), Security::Enterprise);
+        assert_eq!(corp.standard(), Standard::Ax);
+        assert_eq!(corp.ht_width, Some(40));
+        assert_eq!(corp.channel_width(), 80);
+        assert_eq!(corp.elements, vec!["SSID", "Supported rates", "DS Parameter set", "RSN", "HT capabilities", "HT operation", "VHT capabilities", "VHT operation", "HE capabilities"]);
+    }
+
+    #[test]
+    fn parse_scan_reads_hidden_and_older_networks() {
+        let bss = parse_scan(include_str!("../tests/fixtures/iw-scan.txt"));
+
+        let hidden = &bss[1];
+        assert_eq!(hidden.ssid.as_deref(), Some(""));
+        assert_eq!(hidden.freq, Some(2437));
+        assert_eq!(hidden.security(), Security::Wpa3);
+        assert_eq!(hidden.standard(), Standard::Legacy);
+        assert_eq!(hidden.channel_width(), 20);
+
+        let cafe = &bss[2];
+        assert_eq!(cafe.security(), Security::Wpa);
+        assert_eq!(cafe.beacon_interval, None);
+        assert_eq!(cafe.channel_width(), 20);
+    }
+
+    #[test]
+    fn parse_scan_ignores_text_before_the_first_bss() {
+        assert!(parse_scan("command failed: Device or resource busy (-16)\n").is_empty());
+        assert!(parse_scan("").is_empty());
+    }
+
+    #[test]
+    fn parse_vht_width_reads_the_description() {
+        assert_eq!(parse_vht_width("1 (80 MHz)"), Some(80));
+        assert_eq!(parse_vht_width("2 (160 MHz)"), Some(160));
+        assert_eq!(parse_vht_width("0 (20 or 40 MHz)"), None);
+        assert_eq!(parse_vht_width("1"), None);
+    }
+
+    #[test]
+    fn parse_survey_skips_channels_without_noise() {
+        let survey = parse_survey(include_str!("../tests/fixtures/iw-survey.txt"));
+        let channels: Vec<(u32, f32)> = survey.iter().map(|s| (s.freq, s.noise)).collect();
+        assert_eq!(channels, vec![(2412, -95.0), (5180, -102.0)]);
+    }
+}
@@ -0,0 +1,84 @@
+//! Lists the nodes of a scan map.
+
+use serde::Serialize;
+
+use crate::table;
+use crate::timestamp::{self, TimeRange};
+use crate::{Coordinate, CoordinateSystem, Node, ScanMap, Units};
+
+/// Node of the map, summarized for a listing.
+#[derive(Serialize)]
+pub struct Listed {
+    /// Index of the node in the map.
+    pub index: usize,
+
+    pub floor: Option<String>,
+
+    /// Position in the units the nodes are listed in, wgs84 longitudes and latitudes stay in degrees.
+    pub position: Coordinate,
+
+    /// Number of networks measured at the node.
+    pub networks: usize,
+
+    /// Time the node was measured, its last network scan. None if it has no networks.
+    #[serde(serialize_with = "timestamp::serialize_option")]
+    pub measured: Option<u128>,
+
+    pub tags: Vec<String>,
+
+    pub notes: String,
+}
+
+/// Nodes with positions in units. If floor is set only nodes on that floor are listed, if tags are given only nodes with
+/// all of them, and if times are limited only nodes with networks scanned within them.
+pub fn list(scan_map: &ScanMap, floor: Option<&str>, tags: &[String], times: &TimeRange, units: Units) -> Vec<Listed> {
+    scan_map.nodes.iter()
+        .enumerate()
+        .filter_map(|(i, node)| list_node(scan_map, i, node, floor, tags, times, units))
+        .collect()
+}
+
+/// Summary of the node at index i like list, None if floor, tags, or times leave it out. Used for streamed maps, whose nodes are not loaded.
+pub fn list_node(scan_map: &ScanMap, i: usize, node: &Node, floor: Option<&str>, tags: &[String], times: &TimeRange, units: Units) -> Option<Listed> {
+    let length = |v: f64| scan_map.units.convert(v, units);
+
+    if (floor.is_some() && node.floor.as_deref() != floor) || !node.has_tags(tags)
+        || (!times.is_open() && node.networks_in(times).next().is_none()) {
+        return None;
+    }
+
+    Some(Listed{
+        index: i,
+        floor: node.floor.clone(),
+        position: match scan_map.coordinate_system {
+            CoordinateSystem::Local => Coordinate{ x: length(node.position.x), y: length(node.position.y), z: length(node.position.z) },
+            CoordinateSystem::Wgs84 => Coordinate{ x: node.position.x, y: node.position.y, z: length(node.position.z) },
+        },
+        networks: node.networks.len(),
+        measured: node.measured(),
+        tags: node.tags.clone(),
+        notes: node.notes.clone(),
+    })
+}
+
+/// Print a table of listed nodes, whose positions are in units.
+pub fn print_list(scan_map: &ScanMap, listed: &[Listed], units: Units) {
+    if listed.is_empty() {
+        println!("No nodes");
+        return;
+    }
+
+    let rows: Vec<Vec<String>> = listed.iter()
+        .map(|l| vec![
+            l.index.to_string(),
+            l.floor.clone().unwrap_or_default(),
+            l.position.format(scan_map.coordinate_system, units, units),
+            l.networks.to_string(),
+            l.measured.map(timestamp::format).unwrap_or_default(),
+            l.tags.join(","),
+            l.notes.clone(),
+        ])
+        .collect();
+
+    table::print_table(&["Index", "Floor", "Position", "Networks", "Measured", "Tags", "Notes"], &rows);
+}
@@ -0,0 +1,397 @@
+//! Estimates where access points are from the signal strengths nodes measured.
+//!
+//! Each strength is converted to a distance with the map's path loss model, then
+//! the position whose distances to the nodes best match is found with least squares.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Coordinate, CoordinateSystem, ScanMap, EARTH_RADIUS, HIDDEN_SSID_LABEL};
+
+/// Most least squares iterations per access point.
+const MAX_ITERATIONS: usize = 100;
+
+/// Iterations stop once a step moves the estimate less than this many meters.
+const CONVERGED_M: f64 = 1e-4;
+
+/// Log-distance path loss model with walls: strength = reference - 10 * exponent * log10(distance) - wall_attenuation * distance / wall_spacing,
+/// with distances in meters. Saved in the map so every command which models signals uses the constants tuned to the building.
+#[derive(Serialize, Deserialize, Clone, Copy)]
+pub struct PathLoss {
+    /// Strength one meter from an access point in dBm.
+    pub reference: f64,
+
+    /// How quickly signals weaken with distance, 2 in free space and 3 to 4 indoors.
+    pub exponent: f64,
+
+    /// Strength lost through each wall in dB, e.g. 3 for drywall and 10 or more for concrete.
+    pub wall_attenuation: f64,
+
+    /// Average distance between walls in meters. None if signals cross no walls, e.g. in an open plan office.
+    pub wall_spacing: Option<f64>,
+}
+
+impl Default for PathLoss {
+    fn default() -> PathLoss {
+        PathLoss{
+            reference: -40.0,
+            exponent: 3.0,
+            wall_attenuation: 0.0,
+            wall_spacing: None,
+        }
+    }
+}
+
+impl fmt::Display for PathLoss {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} dBm at 1 m, exponent {}", self.reference, self.exponent)?;
+        match self.wall_spacing {
+            Some(spacing) => write!(f, ", {} dB per wall every {} m", self.wall_attenuation, spacing),
+            None => write!(f, ", no walls"),
+        }
+    }
+}
+
+impl PathLoss {
+    /// Strength lost through walls per meter in dB.
+    fn wall_loss(&self) -> f64 {
+        match self.wall_spacing {
+            Some(spacing) => self.wall_attenuation / spacing,
+            None => 0.0,
+        }
+    }
+
+    /// Distance in meters at which a signal of strength dBm is expected.
+    pub fn distance(&self, strength: f64) -> f64 {
+        let open = 10f64.powf((self.reference - strength) / (10.0 * self.exponent));
+        if self.wall_loss() <= 0.0 || open <= 1.0 {
+            return open;
+        }
+
+        // Walls only make signals weaker, so the distance is between one meter and the distance without them
+        let (mut near, mut far) = (1.0, open);
+        for _ in 0..60 {
+            let middle = (near + far) / 2.0;
+            match self.strength(middle) > strength {
+                true => near = middle,
+                false => far = middle,
+            };
+        }
+        (near + far) / 2.0
+    }
+
+    /// Strength in dBm expected at a distance in meters. Distances under a meter count as one meter.
+    pub fn strength(&self, distance: f64) -> f64 {
+        let distance = distance.max(1.0);
+
+        self.reference - 10.0 * self.exponent * distance.log10() - self.wall_loss() * distance
+    }
+}
+
+/// Options which control which access points are located.
+pub struct LocateOptions {
+    pub path_loss: PathLoss,
+
+    /// Access points seen by fewer nodes are not located.
+    pub min_nodes: usize,
+
+    /// Only locate access points on this floor.
+    pub floor: Option<String>,
+}
+
+/// Estimated position of an access point.
+#[derive(Serialize, Deserialize)]
+pub struct AccessPointLocation {
+    /// Network name the access point broadcast, empty for hidden networks.
+    pub ssid: String,
+
+    /// Estimated position, z is the average height of the nodes which saw it.
+    pub position: Coordinate,
+
+    /// Floor of the node which measured the strongest signal. Only nodes on this floor are used.
+    pub floor: Option<String>,
+
+    /// Root mean square difference between the distances the strengths imply and the distances to the
+    /// estimate, in the map's units. The access point is likely within this distance of the estimate.
+    pub radius: f64,
+
+    /// Number of nodes the estimate is based on.
+    pub nodes: usize,
+}
+
+/// Signal strength measured at a horizontal position, in meters from the centroid of the measurements.
+struct Observation {
+    x: f64,
+    y: f64,
+    strength: f64,
+}
+
+/// Converts map positions to meters on a plane around a center point and back.
+struct Projection {
+    crs: CoordinateSystem,
+
+    /// Length of a map unit in meters.
+    unit: f64,
+
+    center: (f64, f64),
+}
+
+impl Projection {
+    /// Position in meters east and north of the center.
+    fn project(&self, x: f64, y: f64) -> (f64, f64) {
+        match self.crs {
+            CoordinateSystem::Local => ((x - self.center.0) * self.unit, (y - self.center.1) * self.unit),
+            CoordinateSystem::Wgs84 => ((x - self.center.0).to_radians() * self.center.1.to_radians().cos() * EARTH_RADIUS,
+                                        (y - self.center.1).to_radians() * EARTH_RADIUS),
+        }
+    }
+
+    /// Map position of a point in meters east and north of the center.
+    fn unproject(&self, x: f64, y: f64) -> (f64, f64) {
+        match self.crs {
+            CoordinateSystem::Local => (x / self.unit + self.center.0, y / self.unit + self.center.1),
+            CoordinateSystem::Wgs84 => ((x / (EARTH_RADIUS * self.center.1.to_radians().cos())).to_degrees() + self.center.0,
+                                        (y / EARTH_RADIUS).to_degrees() + self.center.1),
+        }
+    }
+}
+
+/// Sum of the squared differences between the distances to (x, y) and the distances the strengths imply.
+fn cost(observations: &[Observation], distances: &[f64], x: f64, y: f64) -> f64 {
+    observations.iter()
+        .zip(distances)
+        .map(|(o, d)| ((x - o.x).hypot(y - o.y) - d).powi(2))
+        .sum()
+}
+
+/// Position which best fits the distances with Levenberg-Marquardt least squares, starting from the
+/// centroid of the observations weighted by their power.
+fn solve(observations: &[Observation], distances: &[f64]) -> (f64, f64) {
+    let weights: Vec<f64> = observations.iter().map(|o| 10f64.powf(o.strength / 10.0)).collect();
+    let total: f64 = weights.iter().sum();
+    let mut x = observations.iter().zip(&weights).map(|(o, w)| o.x * w).sum::<f64>() / total;
+    let mut y = observations.iter().zip(&weights).map(|(o, w)| o.y * w).sum::<f64>() / total;
+
+    let mut current = cost(observations, distances, x, y);
+    let mut damping = 1e-3;
+
+    for _ in 0..MAX_ITERATIONS {
+        // Normal equations of the distance residuals: (JᵀJ + damping * diag(JᵀJ)) step = -Jᵀr
+        let (mut jxx, mut jxy, mut jyy, mut gx, mut gy) = (0.0, 0.0, 0.0, 0.0, 0.0);
+        for (o, d) in observations.iter().zip(distances) {
+            let range = (x - o.x).hypot(y - o.y).max(1e-6);
+            let (ux, uy) = ((x - o.x) / range, (y - o.y) / range);
+            let residual = range - d;
+
+            jxx += ux * ux;
+            jxy += ux * uy;
+            jyy += uy * uy;
+            gx += ux * residual;
+            gy += uy * residual;
+        }
+
+        let (axx, ayy) = (jxx * (1.0 + damping) + 1e-9, jyy * (1.0 + damping) + 1e-9);
+        let det = axx * ayy - jxy * jxy;
+        if det.abs() < 1e-12 {
+            break;
+        }
+        let step_x = -(ayy * gx - jxy * gy) / det;
+        let step_y = -(axx * gy - jxy * gx) / det;
+
+        let next = cost(observations, distances, x + step_x, y + step_y);
+        match next < current {
+            true => {
+                x += step_x;
+                y += step_y;
+                current = next;
+                damping /= 10.0;
+
+                if step_x.hypot(step_y) < CONVERGED_M {
+                    break;
+                }
+            },
+            false => damping *= 10.0,
+        };
+    }
+
+    (x, y)
+}
+
+/// Estimate the position of every access point seen by at least min_nodes nodes, keyed by BSSID.
+pub fn locate(scan_map: &ScanMap, opts: &LocateOptions) -> BTreeMap<String, AccessPointLocation> {
+    // Strength and node index of every measurement of each BSSID
+    let mut measurements = BTreeMap::<&str, Vec<(usize, f64)>>::new();
+    for (i, node) in scan_map.nodes.iter().enumerate() {
+        if opts.floor.is_some() && node.floor != opts.floor {
+            continue;
+        }
+
+        for network in &node.networks {
+            if let Some(strength) = network.strength {
+                measurements.entry(&network.mac).or_default().push((i, strength as f64));
+            }
+        }
+    }
+
+    let mut locations = BTreeMap::<String, AccessPointLocation>::new();
+
+    for (mac, seen) in measurements {
+        let strongest = seen.iter()
+            .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal))
+            .expect("every BSSID was measured at least once");
+        let floor = &scan_map.nodes[strongest.0].floor;
+
+        let seen: Vec<&(usize, f64)> = seen.iter()
+            .filter(|(i, _)| &scan_map.nodes[*i].floor == floor)
+            .collect();
+        if seen.len() < opts.min_nodes.max(1) {
+            continue;
+        }
+
+        let positions: Vec<&Coordinate> = seen.iter().map(|(i, _)| &scan_map.nodes[*i].position).collect();
+        let count = positions.len() as f64;
+        let projection = Projection{
+            crs: scan_map.coordinate_system,
+            unit: scan_map.units.meters(),
+            center: (positions.iter().map(|p| p.x).sum::<f64>() / count, positions.iter().map(|p| p.y).sum::<f64>() / count),
+        };
+
+        let observations: Vec<Observation> = seen.iter()
+            .zip(&positions)
+            .map(|((_, strength), position)| {
+                let (x, y) = projection.project(position.x, position.y);
+                Observation{ x, y, strength: *strength }
+            })
+            .collect();
+        let distances: Vec<f64> = observations.iter().map(|o| opts.path_loss.distance(o.strength)).collect();
+
+        let (x, y) = solve(&observations, &distances);
+        let rms = (cost(&observations, &distances, x, y) / count).sqrt();
+        let (x, y) = projection.unproject(x, y);
+
+        // wgs84 maps always measure lengths in meters
+        let radius = match scan_map.coordinate_system {
+            CoordinateSystem::Local => rms / projection.unit,
+            CoordinateSystem::Wgs84 => rms,
+        };
+
+        let ssid = scan_map.nodes[strongest.0].networks.iter()
+            .find(|n| n.mac == mac && !n.is_hidden)
+            .map(|n| n.ssid.clone())
+            .unwrap_or_default();
+
+        locations.insert(mac.to_string(), AccessPointLocation{
+            ssid,
+            position: Coordinate{ x, y, z: positions.iter().map(|p| p.z).sum::<f64>() / count },
+            floor: floor.clone(),
+            radius,
+            nodes: seen.len(),
+        });
+    }
+
+    locations
+}
+
+/// Print a table of estimated access point positions.
+pub fn print_locations(scan_map: &ScanMap, locations: &BTreeMap<String, AccessPointLocation>) {
+    let units = scan_map.units;
+    let rows: Vec<Vec<String>> = locations.iter()
+        .map(|(mac, l)| vec![
+            mac.clone(),
+            match l.ssid.is_empty() {
+                true => String::from(HIDDEN_SSID_LABEL),
+                false => l.ssid.clone(),
+            },
+            l.floor.clone().unwrap_or_default(),
+            l.position.format(scan_map.coordinate_system, units, units),
+            format!("{:.1} {}", l.radius, units.abbreviation()),
+            l.nodes.to_string(),
+        ])
+        .collect();
+
+    crate::table::print_table(&["BSSID", "SSID", "Floor", "Position", "Radius", "Nodes"], &rows);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::scan_map;
+
+    /// Free space path loss without walls.
+    fn path_loss() -> PathLoss {
+        PathLoss{
+            reference: -40.0,
+            exponent: 2.0,
+            ..PathLoss::default()
+        }
+    }
+
+    /// Nodes at the corners of a 10 meter square, which measured an access point at (4, 3) on floor F1 exactly as
+    /// the path loss model expects. A second access point was only seen from the first corner.
+    fn survey() -> ScanMap {
+        let corners: [(f64, f64); 4] = [(0.0, 0.0), (10.0, 0.0), (0.0, 10.0), (10.0, 10.0)];
+        let nodes: Vec<serde_json::Value> = corners.iter()
+            .enumerate()
+            .map(|(i, &(x, y))| {
+                let distance = (4.0 - x).hypot(3.0 - y);
+                let strength = path_loss().strength(distance);
+                let mut networks = vec![serde_json::json!({"mac": "84:d4:7e:69:2f:e2", "ssid": "CorpWiFi", "strength": strength})];
+                if i == 0 {
+                    networks.push(serde_json::json!({"mac": "aa:bb:cc:00:11:22", "ssid": "", "strength": -70.0}));
+                }
+                serde_json::json!({"position": {"x": x, "y": y, "z": 1.0}, "floor": "F1", "networks": networks})
+            })
+            .collect();
+        scan_map(serde_json::json!({"nodes": nodes}))
+    }
+
+    fn opts(min_nodes: usize, floor: Option<&str>) -> LocateOptions {
+        LocateOptions{
+            path_loss: path_loss(),
+            min_nodes,
+            floor: floor.map(String::from),
+        }
+    }
+
+    #[test]
+    fn path_loss_distance_inverts_the_model() {
+        assert!((path_loss().distance(-40.0) - 1.0).abs() < 1e-9);
+        assert!((path_loss().distance(-60.0) - 10.0).abs() < 1e-9);
+        assert_eq!(path_loss().strength(0.5), -40.0);
+    }
+
+    #[test]
+    fn path_loss_walls_shorten_the_distance() {
+        let walls = PathLoss{
+            wall_attenuation: 5.0,
+            wall_spacing: Some(2.5),
+            ..path_loss()
+        };
+        assert!((walls.strength(10.0) + 80.0).abs() < 1e-9);
+        assert!((walls.distance(-80.0) - 10.0).abs() < 1e-6);
+        assert_eq!(walls.to_string(), "-40 dBm at 1 m, exponent 2, 5 dB per wall every 2.5 m");
+        assert_eq!(path_loss().to_string(), "-40 dBm at 1 m, exponent 2, no walls");
+    }
+
+    #[test]
+    fn locate_finds_the_position_which_fits_the_strengths() {
+        let locations = locate(&survey(), &opts(3, None));
+        assert_eq!(locations.keys().collect::<Vec<_>>(), vec!["84:d4:7e:69:2f:e2"]);
+
+        let corp = &locations["84:d4:7e:69:2f:e2"];
+        assert_eq!(corp.ssid, "CorpWiFi");
+        assert_eq!(corp.floor.as_deref(), Some("F1"));
+        assert_eq!(corp.nodes, 4);
+        assert!((corp.position.x - 4.0).abs() < 0.01 && (corp.position.y - 3.0).abs() < 0.01, "estimate is {} {}", corp.position.x, corp.position.y);
+        assert!((corp.position.z - 1.0).abs() < 1e-9);
+        assert!(corp.radius < 0.01);
+    }
+
+    #[test]
+    fn locate_skips_access_points_seen_by_too_few_nodes_or_on_other_floors() {
+        assert_eq!(locate(&survey(), &opts(1, None)).len(), 2);
+        assert!(locate(&survey(), &opts(1, Some("F2"))).is_empty());
+    }
+}
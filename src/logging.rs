@@ -0,0 +1,90 @@
+//! Messages about what the tool is doing, as opposed to its results. Warnings and errors are
+//! shown by default, -v adds how long scans and saves take, and -vv the commands backends run,
+//! e.g. to find out why a backend returns nothing. --log-file keeps every message, whatever
+//! the verbosity, with the time it was logged.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::process::Output;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Importance of a message, the most important first.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Level {
+    Error,
+    Warning,
+    Info,
+    Debug,
+}
+
+impl Level {
+    /// Prefix messages at this level are printed with.
+    fn label(self) -> &'static str {
+        match self {
+            Level::Error => "Error",
+            Level::Warning => "Warning",
+            Level::Info => "Info",
+            Level::Debug => "Debug",
+        }
+    }
+}
+
+/// Least important level printed to stderr.
+static LEVEL: AtomicU8 = AtomicU8::new(Level::Warning as u8);
+
+/// File every message is appended to.
+static FILE: Mutex<Option<File>> = Mutex::new(None);
+
+/// Print messages up to level to stderr, and append every message to the file at path if one is given.
+pub fn init(level: Level, path: Option<&Path>) -> io::Result<()> {
+    LEVEL.store(level as u8, Ordering::SeqCst);
+
+    if let Some(p) = path {
+        let file = OpenOptions::new().create(true).append(true).open(p)?;
+        *FILE.lock().unwrap_or_else(|e| e.into_inner()) = Some(file);
+    }
+
+    Ok(())
+}
+
+/// Print msg if level is shown, and add it to the log file.
+pub fn log(level: Level, msg: &str) {
+    if level as u8 <= LEVEL.load(Ordering::SeqCst) {
+        eprintln!("{}: {}", level.label(), msg);
+    }
+
+    let mut file = FILE.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(f) = file.as_mut() {
+        let time = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis()).unwrap_or(0);
+        // A log which cannot be written must not stop a survey
+        let _ = writeln!(f, "{} {} {}", time, level.label().to_uppercase(), msg);
+    }
+}
+
+/// Log a problem which the tool works around, shown by default.
+pub fn warn(msg: &str) {
+    log(Level::Warning, msg);
+}
+
+/// Log what the tool did, e.g. how long a save took, shown with -v.
+pub fn info(msg: &str) {
+    log(Level::Info, msg);
+}
+
+/// Log details for finding out why something went wrong, e.g. what a backend returned, shown with -vv.
+pub fn debug(msg: &str) {
+    log(Level::Debug, msg);
+}
+
+/// Log how a command the tool ran went, e.g. a backend's scan.
+pub fn command(program: &str, args: &[&str], output: &Output, elapsed: Duration) {
+    debug(&format!("Ran \"{} {}\" in {:.2}s, {}, {} bytes of output", program, args.join(" "), elapsed.as_secs_f64(), output.status, output.stdout.len()));
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !stderr.trim().is_empty() {
+        debug(&format!("{} printed to stderr: {}", program, stderr.trim()));
+    }
+}
@@ -4,29 +4,197 @@ extern crate serde;
 extern crate serde_json;
 
 use std::error::Error;
-use std::path::Path;
-use std::fs::{File,OpenOptions};
-use std::io::{stdin,stdout,Write,BufReader,BufWriter};
-use std::time::{SystemTime,UNIX_EPOCH};
+use std::path::{Path,PathBuf};
+use std::fs::{self,File,OpenOptions};
+use std::io::{stdin,stdout,Write,BufReader,BufWriter,IsTerminal};
+use std::time::{Duration,Instant,SystemTime,UNIX_EPOCH};
 use std::convert::From;
 use std::fmt;
 use std::process::exit;
+use std::thread;
+use std::collections::BTreeMap;
 
-use clap::{Arg,App,SubCommand,ArgMatches};
+use clap::parser::ValueSource;
+use clap::{Arg,ArgAction,ArgGroup,ArgMatches,Command};
+use clap_complete::Shell;
 use serde::{Deserialize, Serialize};
 
+mod migrate;
+mod radio;
+mod security;
+mod iw;
+mod nmcli;
+mod netsh;
+mod airport;
+mod mock;
+mod capture;
+mod ble;
+mod oui;
+mod anonymize;
+mod gps;
+mod table;
+mod list;
+mod render;
+mod view;
+mod report;
+mod serve;
+mod remote;
+mod mqtt;
+mod exporter;
+mod export;
+mod predict;
+mod gaps;
+mod placement;
+mod channels;
+mod roaming;
+mod trend;
+mod alert;
+mod tui;
+mod logging;
+mod config;
+mod validate;
+mod expr;
+mod interchange;
+mod stream;
+mod aps;
+mod manpage;
+mod timestamp;
+mod transform;
+mod dedupe;
+mod merge;
+mod diff;
+mod stats;
+mod query;
+mod locate;
+mod interpolate;
+mod prune;
+mod session;
+mod walk;
+mod grid;
+mod schedule;
+mod interrupt;
+mod progress;
+mod scanner;
+
+use radio::{Band,Standard};
+use security::Security;
+use ble::BleDevice;
+
 /// Print an error message to stderr and exit the process with exit code 1.
-fn die(msg: &str) {
-    eprintln!("Error: {}", msg);
+fn die(msg: &str) -> ! {
+    logging::log(logging::Level::Error, msg);
     exit(1);
 }
 
+/// Mean radius of the earth in meters.
+const EARTH_RADIUS: f64 = 6_371_000.0;
+
 /// Indicates position in coordinate system. It is suggested that x and y are positions in a horizontal 2D plane and z is the height.
 #[derive(Serialize, Deserialize)]
 struct Coordinate {
-    x: f32,
-    y: f32,
-    z: f32,
+    x: f64,
+    y: f64,
+    z: f64,
+}
+
+/// Coordinate system which the positions of a ScanMap's nodes are in.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+enum CoordinateSystem {
+    /// Arbitrary local cartesian coordinates, e.g. meters from a corner of the building.
+    #[serde(rename = "local")]
+    Local,
+
+    /// WGS84 geographic coordinates: x is the longitude and y the latitude in degrees, z is the altitude in meters.
+    #[serde(rename = "wgs84")]
+    Wgs84,
+}
+
+impl fmt::Display for CoordinateSystem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CoordinateSystem::Local => write!(f, "local"),
+            CoordinateSystem::Wgs84 => write!(f, "wgs84"),
+        }
+    }
+}
+
+impl std::str::FromStr for CoordinateSystem {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<CoordinateSystem, String> {
+        match s.trim().to_lowercase().as_str() {
+            "local" => Ok(CoordinateSystem::Local),
+            "wgs84" => Ok(CoordinateSystem::Wgs84),
+            _ => Err(format!("unknown coordinate system \"{}\", must be local or wgs84", s.trim())),
+        }
+    }
+}
+
+impl CoordinateSystem {
+    /// Names of the x, y, and z axes.
+    fn axis_names(&self) -> [&'static str; 3] {
+        match self {
+            CoordinateSystem::Local => ["x", "y", "z"],
+            CoordinateSystem::Wgs84 => ["longitude", "latitude", "altitude"],
+        }
+    }
+}
+
+/// Unit of length which local coordinates, altitudes, and floor elevations are measured in.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+enum Units {
+    #[serde(rename = "meters")]
+    Meters,
+
+    #[serde(rename = "feet")]
+    Feet,
+}
+
+impl fmt::Display for Units {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Units::Meters => write!(f, "meters"),
+            Units::Feet => write!(f, "feet"),
+        }
+    }
+}
+
+impl std::str::FromStr for Units {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Units, String> {
+        match s.trim().to_lowercase().as_str() {
+            "meters" | "metres" | "m" => Ok(Units::Meters),
+            "feet" | "ft" => Ok(Units::Feet),
+            _ => Err(format!("unknown units \"{}\", must be meters or feet", s.trim())),
+        }
+    }
+}
+
+impl Units {
+    /// Length of one unit in meters.
+    fn meters(&self) -> f64 {
+        match self {
+            Units::Meters => 1.0,
+            Units::Feet => 0.3048,
+        }
+    }
+
+    /// Short name printed after lengths.
+    fn abbreviation(&self) -> &'static str {
+        match self {
+            Units::Meters => "m",
+            Units::Feet => "ft",
+        }
+    }
+
+    /// Convert a length in these units to other units.
+    fn convert(&self, length: f64, to: Units) -> f64 {
+        match *self == to {
+            true => length,
+            false => length * self.meters() / to.meters(),
+        }
+    }
 }
 
 impl Coordinate {
@@ -38,6 +206,153 @@ impl Coordinate {
             z: 0.0,
         }
     }
+
+    /// Coordinate of a GPS fix, x is the longitude, y the latitude, and z the altitude.
+    fn from_fix(fix: &gps::Fix) -> Coordinate {
+        Coordinate{
+            x: fix.lon,
+            y: fix.lat,
+            z: fix.alt.unwrap_or(0.0),
+        }
+    }
+
+    /// Ask the user to enter a coordinate in units, repeats until a valid coordinate is entered. In local maps an entry
+    /// starting with + is an offset from previous, e.g. "+1.5 0 0" is 1.5 further along x than the previous node.
+    /// If reuse is true an empty entry is the previous coordinate. Values which cannot be parsed are asked for again
+    /// one at a time, so the others do not have to be typed again.
+    fn prompt(crs: CoordinateSystem, units: Units, previous: Option<&Coordinate>, reuse: bool) -> Coordinate {
+        let [x_name, y_name, z_name] = crs.axis_names();
+        let format_str = format!("{} {} {}", x_name, y_name, z_name);
+        let default = previous.filter(|_| reuse);
+        let default_str = match default {
+            Some(p) => format!(" [{} {} {}]", p.x, p.y, p.z),
+            None => String::new(),
+        };
+        let relative_str = match previous.filter(|_| crs == CoordinateSystem::Local) {
+            Some(_) => format!(", or +d{} d{} d{} from the previous node", x_name, y_name, z_name),
+            None => String::new(),
+        };
+
+        loop {
+            print!("    Coordinates ({} in {}{}){}: ", format_str, units, relative_str, default_str);
+            stdout().flush().expect("failed to flush stdout");
+            let mut pos_str = String::new();
+            stdin().read_line(&mut pos_str).expect("failed to read input");
+            pos_str = pos_str.replace("\n", "");
+
+            if let (Some(p), true) = (default, pos_str.trim().is_empty()) {
+                return Coordinate{ x: p.x, y: p.y, z: p.z };
+            }
+
+            let (origin, values) = match Coordinate::parse_values(&pos_str, crs, previous) {
+                Ok(v) => v,
+                Err(e) => {
+                    println!("    Error: {}", e);
+                    continue;
+                },
+            };
+
+            let is_offset = pos_str.trim().starts_with('+');
+            let values: Vec<f64> = values.into_iter()
+                .zip(crs.axis_names().iter())
+                .map(|(value, name)| value.unwrap_or_else(|e| {
+                    println!("    Error: {}", e);
+                    loop {
+                        match is_offset {
+                            true => print!("        d{}: ", name),
+                            false => print!("        {}: ", name),
+                        };
+                        stdout().flush().expect("failed to flush stdout");
+                        let mut value_str = String::new();
+                        stdin().read_line(&mut value_str).expect("failed to read input");
+
+                        match expr::eval(&value_str) {
+                            Ok(v) => break v,
+                            Err(e) => println!("        Error: {}", e),
+                        };
+                    }
+                }))
+                .collect();
+
+            return Coordinate{
+                x: origin.x + values[0],
+                y: origin.y + values[1],
+                z: origin.z + values[2],
+            };
+        }
+    }
+
+    /// Parse a coordinate entered as "x y z". In local maps an entry starting with + is an offset from previous.
+    fn parse(s: &str, crs: CoordinateSystem, previous: Option<&Coordinate>) -> Result<Coordinate, String> {
+        let (origin, values) = Coordinate::parse_values(s, crs, previous)?;
+        let values = values.into_iter().collect::<Result<Vec<f64>, String>>()?;
+
+        Ok(Coordinate{
+            x: origin.x + values[0],
+            y: origin.y + values[1],
+            z: origin.z + values[2],
+        })
+    }
+
+    /// Coordinate the values of an entry are added to and the three values, each an error if it cannot be parsed. The
+    /// values may be separated by spaces, tabs, commas, or semicolons, use a decimal comma when they are separated
+    /// by whitespace, and be arithmetic like "3.5+0.6".
+    fn parse_values(s: &str, crs: CoordinateSystem, previous: Option<&Coordinate>) -> Result<(Coordinate, Vec<Result<f64, String>>), String> {
+        let [x_name, y_name, z_name] = crs.axis_names();
+        let previous = previous.filter(|_| crs == CoordinateSystem::Local);
+        let s = s.trim();
+
+        let origin = match (s.starts_with('+'), previous) {
+            (false, _) => Coordinate::new(),
+            (true, Some(p)) => Coordinate{ x: p.x, y: p.y, z: p.z },
+            (true, None) if crs != CoordinateSystem::Local => return Err(String::from("Offsets can only be entered in local maps")),
+            (true, None) => return Err(String::from("There is no previous node to offset from")),
+        };
+
+        // Commas which are not next to whitespace are decimal separators, unless there is nothing else to separate values
+        let mut parts: Vec<&str> = s.split(|c: char| c.is_whitespace() || c == ';')
+            .map(|p| p.trim_matches(','))
+            .filter(|p| !p.is_empty())
+            .collect();
+        if parts.len() == 1 {
+            parts = parts[0].split(',').collect();
+        }
+        if parts.len() != 3 {
+            return Err(format!("Must be in format \"{} {} {}\"", x_name, y_name, z_name));
+        }
+
+        let values = parts.iter()
+            .zip([x_name, y_name, z_name].iter())
+            .map(|(part, name)| expr::eval(part).map_err(|e| format!("Failed to parse {} \"{}\": {}", name, part, e)))
+            .collect();
+
+        Ok((origin, values))
+    }
+
+    /// Distance to other in the map's units. wgs84 distances are in meters and use an equirectangular approximation, which is accurate over the size of a survey.
+    fn distance(&self, other: &Coordinate, crs: CoordinateSystem) -> f64 {
+        let (dx, dy) = match crs {
+            CoordinateSystem::Local => (other.x - self.x, other.y - self.y),
+            CoordinateSystem::Wgs84 => {
+                let mean_lat = ((self.y + other.y) / 2.0).to_radians();
+                ((other.x - self.x).to_radians() * mean_lat.cos() * EARTH_RADIUS, (other.y - self.y).to_radians() * EARTH_RADIUS)
+            },
+        };
+        let dz = other.z - self.z;
+
+        (dx * dx + dy * dy + dz * dz).sqrt()
+    }
+
+    /// Position formatted for display. Lengths are converted from the map's units to units, wgs84 longitudes and latitudes are always in degrees.
+    fn format(&self, crs: CoordinateSystem, map_units: Units, units: Units) -> String {
+        // Round away the noise which converting units adds
+        let length = |v: f64| (map_units.convert(v, units) * 1000.0).round() / 1000.0;
+
+        match crs {
+            CoordinateSystem::Local => format!("{} {} {} {}", length(self.x), length(self.y), length(self.z), units.abbreviation()),
+            CoordinateSystem::Wgs84 => format!("{} {} {} {}", self.x, self.y, length(self.z), units.abbreviation()),
+        }
+    }
 }
 
 /// Network holds information about a wireless network.
@@ -49,32 +364,166 @@ struct Network {
     /// Name of network.
     ssid: String,
 
+    /// True if the network does not broadcast its name.
+    is_hidden: bool,
+
+    /// Manufacturer of the access point, looked up from the mac prefix.
+    vendor: Option<String>,
+
     /// Channel network is broadcast on.
     channel: String,
+
+    /// Center frequency of the channel in MHz.
+    frequency: Option<u32>,
+
+    /// Frequency band the network is broadcast in.
+    band: Option<Band>,
+
+    /// Width of the channel in MHz. None if the scan backend did not report it.
+    channel_width: Option<u32>,
+
+    /// Newest 802.11 generation the access point supports. None if the scan backend did not report capabilities.
+    standard: Option<Standard>,
+
+    /// Security scheme used by the network. None if the scan backend did not report it.
+    security: Option<Security>,
     
-    /// Strength of network signal in dBm.
-    strength: String,
+    /// Strength of network signal in dBm. None if the value reported by the scan backend could not be parsed.
+    strength: Option<f32>,
+
+    /// Signal strength exactly as reported by the scan backend. Only stored when it could not be parsed into strength.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    strength_raw: Option<String>,
+
+    /// Number of scans the network was seen in. More than 1 if the node was recorded with several samples.
+    samples: u32,
+
+    /// Standard deviation of the strength across samples in dB. None if the network was measured once.
+    strength_stddev: Option<f32>,
+
+    /// Noise floor on the network's channel in dBm. None if the scan backend did not report it.
+    noise: Option<f32>,
+
+    /// Signal to noise ratio in dB, the strength minus the noise floor. None if either is unknown.
+    snr: Option<f32>,
+
+    /// Wireless interface which measured the network. None if the default interface was used.
+    interface: Option<String>,
+
+    /// Time between beacons in time units of 1.024 ms. None if the scan backend did not report it.
+    beacon_interval: Option<u32>,
+
+    /// Flags from the capability information field, e.g. ESS, Privacy. Empty if the scan backend did not report them.
+    capabilities: Vec<String>,
+
+    /// Names of the information elements the access point sent, e.g. RSN, HT capabilities. Empty if the scan backend did not report them.
+    information_elements: Vec<String>,
     
     /// When the measurement was taken, unix time.
     time_scanned: u128,
 }
 
 impl fmt::Display for Network {
+    /// Formats a one line summary. If a width is specified the SSID is padded to it, so summaries can be aligned.
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-	   write!(f, "{ssid} ({mac}, {strength} dBm)", ssid=self.ssid, mac=self.mac, strength=self.strength)
+	   let band_str = match self.band {
+		  Some(band) => format!(", {}", band),
+		  None => String::new(),
+	   };
+	   let width_str = match self.channel_width {
+		  Some(width) => format!(", {} MHz", width),
+		  None => String::new(),
+	   };
+	   let standard_str = match self.standard {
+		  Some(standard) => format!(", {}", standard),
+		  None => String::new(),
+	   };
+	   let security_str = match self.security {
+		  Some(security) => format!(", {}", security),
+		  None => String::new(),
+	   };
+	   let vendor_str = match &self.vendor {
+		  Some(vendor) => format!(", {}", vendor),
+		  None => String::new(),
+	   };
+	   let interface_str = match &self.interface {
+		  Some(interface) => format!(", via {}", interface),
+		  None => String::new(),
+	   };
+	   let stddev_str = match self.strength_stddev {
+		  Some(stddev) => format!(" ± {:.2}", stddev),
+		  None => String::new(),
+	   };
+	   let snr_str = match self.snr {
+		  Some(snr) => format!(", SNR {:.0} dB", snr),
+		  None => String::new(),
+	   };
+	   write!(f, "{ssid:<width$} ({mac}{vendor_str}, {strength}{stddev_str} dBm{snr_str}{band_str}{width_str}{standard_str}{security_str}{interface_str})", ssid=self.display_ssid(), width=f.width().unwrap_or(0), mac=self.mac, vendor_str=vendor_str, strength=self.strength_str(), stddev_str=stddev_str, snr_str=snr_str, band_str=band_str, width_str=width_str, standard_str=standard_str, security_str=security_str, interface_str=interface_str)
     }
 }
 
+/// Label shown in place of the name of hidden networks.
+const HIDDEN_SSID_LABEL: &str = "<hidden>";
+
+/// True if an SSID indicates a hidden network. Hidden networks broadcast an empty SSID or one made of null bytes, which iw prints as "\x00".
+fn is_hidden_ssid(ssid: &str) -> bool {
+    ssid.replace("\\x00", "").trim_matches(|c: char| c == '\0' || c.is_whitespace()).is_empty()
+}
+
+/// Parse a signal strength reported by a scan backend, like "-66.00" or "-66 dBm", into dBm.
+fn parse_strength(s: &str) -> Option<f32> {
+    s.trim()
+        .trim_end_matches("dBm")
+        .trim()
+        .parse::<f32>()
+        .ok()
+        .filter(|v| v.is_finite())
+}
+
+/// Why a wifi scan failed.
+#[derive(Debug)]
+enum ScanFailure {
+    /// wifiscanner could not scan.
+    Scanner(wifiscanner::Error),
+
+    /// A scan command failed, with its error message.
+    Backend(String),
+}
+
 /// Error which occurs during a wifi scan.
 #[derive(Debug)]
 struct ScanError {
     /// Reason scan failed.
-    reason: wifiscanner::Error,
+    reason: ScanFailure,
+
+    /// Number of times the scan was tried.
+    attempts: u32,
+}
+
+impl ScanError {
+    /// True if trying again may succeed. The scan output can be cut short or garbled while another program, like NetworkManager, holds the interface, in which case iw reports it as busy. A missing scan command will not fix itself.
+    fn is_transient(&self) -> bool {
+        match &self.reason {
+            ScanFailure::Scanner(wifiscanner::Error::NoMatch | wifiscanner::Error::FailedToParse | wifiscanner::Error::NoValue) => true,
+            ScanFailure::Scanner(wifiscanner::Error::CommandNotFound | wifiscanner::Error::SyntaxRegexError) => false,
+            ScanFailure::Backend(message) => message.contains("busy"),
+        }
+    }
 }
 
 impl fmt::Display for ScanError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-	   write!(f, "resource={:?}", self.reason)
+	   let reason = match &self.reason {
+		  ScanFailure::Scanner(wifiscanner::Error::CommandNotFound) => "the scan command could not be run, check that iw (Linux), netsh (Windows), or airport (macOS) is installed",
+		  ScanFailure::Scanner(wifiscanner::Error::SyntaxRegexError) => "the scanner failed to build its output parser",
+		  ScanFailure::Scanner(wifiscanner::Error::NoMatch | wifiscanner::Error::FailedToParse | wifiscanner::Error::NoValue) => "the output of the scan command could not be parsed",
+		  ScanFailure::Backend(message) => message,
+	   };
+
+	   match self.attempts > 1 {
+		  true => write!(f, "{}, gave up after {} attempts", reason, self.attempts),
+		  false => write!(f, "{}", reason),
+	   }
     }
 }
 
@@ -84,316 +533,4120 @@ impl From<wifiscanner::Error> for ScanError {
     /// Create a ScanError from a wifiscanner::Error.
     fn from(e: wifiscanner::Error) -> ScanError {
 	   ScanError{
-		  reason: e,
+		  reason: ScanFailure::Scanner(e),
+		  attempts: 1,
 	   }
     }
 }
 
-impl Network {
-    /// Scan wifi networks.
-    fn scan() -> Result<Vec<Network>, Box<dyn Error>> {
-	   let scan_time = (SystemTime::now().duration_since(UNIX_EPOCH)?).as_millis();
-	   
-        let scan = match wifiscanner::scan() {
-		  Ok(s) => s,
-		  Err(e) => return Err(Box::new(ScanError::from(e))),
-	   };
-	   
-        let mut networks = Vec::<Network>::new();
-        
-        for network in scan {
-            networks.push(Network{
-                mac: network.mac,
-                ssid: network.ssid,
-                channel: network.channel,
-                strength: network.signal_level,
-                time_scanned: scan_time,
-            });
+/// Run scan, retrying transient failures. The wait before each retry doubles, starting at the retry delay.
+fn scan_with_retries<T, F>(opts: &ScanOptions, mut scan: F) -> Result<T, ScanError>
+where F: FnMut() -> Result<T, ScanError> {
+    let mut delay = opts.retry_delay;
+    let mut attempts = 1;
+
+    loop {
+        let mut e = match scan() {
+            Ok(s) => return Ok(s),
+            Err(e) => e,
+        };
+        e.attempts = attempts;
+
+        if !e.is_transient() || attempts > opts.retries {
+            return Err(e);
         }
 
-	   Ok(networks)
+        logging::warn(&format!("Scan failed ({}), retrying in {:.1}s ({} of {})", e, delay.as_secs_f64(), attempts, opts.retries));
+        thread::sleep(delay);
+
+        delay *= 2;
+        attempts += 1;
     }
 }
 
-/// Node is the result of a scan at a location.
-#[derive(Serialize, Deserialize)]
-struct Node {
-    position: Coordinate,
-    notes: String,
-    networks: Vec<Network>,
-}
+impl Network {
+    /// Name of the network for display, hidden networks are labeled as such.
+    fn display_ssid(&self) -> &str {
+        match self.is_hidden {
+            true => HIDDEN_SSID_LABEL,
+            false => &self.ssid,
+        }
+    }
 
-impl Node {
-    /// Create a new Node by asking the user for data and scanning.
-    fn acquire() -> Result<Node, Box<dyn Error>> {
-	   println!("New measurement properties:");
-	   
-	   // Prompt user for position
-        let mut position = Coordinate::new();
-	   
-	   let mut get_pos_done = false;
-        while !get_pos_done {
-            print!("    Coordinates (x y z): ");
-            stdout().flush().expect("failed to flush stdout");
-            let mut pos_str = String::new();
-            stdin().read_line(&mut pos_str).expect("failed to read input");
-            pos_str = pos_str.replace("\n", "");
+    /// Signal strength formatted for display, falls back to the raw backend value if it could not be parsed.
+    fn strength_str(&self) -> String {
+        match (self.strength, &self.strength_raw) {
+            (Some(v), _) => format!("{:.2}", v),
+            (None, Some(raw)) => raw.clone(),
+            (None, None) => String::from("?"),
+        }
+    }
 
-            let parts: Vec<&str> = pos_str.split(" ").collect();
-            if parts.len() != 3 {
-                println!("    Error: Must be in format \"x y z\"");
-                continue;
-            }
+    /// Headers of the columns table_row returns.
+    const TABLE_HEADERS: [&'static str; 7] = ["SSID", "BSSID", "Channel", "Band", "Strength", "Security", "Vendor"];
 
-		  position.x = match parts[0].parse::<f32>() {
-			 Ok(v) => v,
-			 Err(e) => {
-				println!("    Error: Failed to parse x as float: {}", e);
-				continue;
-			 },
-		  };
-		  position.y = match parts[1].parse::<f32>() {
-			 Ok(v) => v,
-			 Err(e) => {
-				println!("    Error: Failed to parse y as float: {}", e);
-				continue;
-			 },
-		  };
-		  position.z = match parts[2].parse::<f32>() {
-			 Ok(v) => v,
-			 Err(e) => {
-				println!("    Error: Failed to parse z as float: {}", e);
-				continue;
-			 },
-		  };
-		  
-            get_pos_done = true;
+    /// Values of the network for a row of a table of scan results.
+    fn table_row(&self) -> Vec<String> {
+        vec![
+            self.display_ssid().to_string(),
+            self.mac.clone(),
+            self.channel.clone(),
+            self.band.map(|b| b.to_string()).unwrap_or_default(),
+            format!("{} dBm", self.strength_str()),
+            self.security.as_ref().map(|s| s.to_string()).unwrap_or_default(),
+            self.vendor.clone().unwrap_or_default(),
+        ]
+    }
+
+    /// Create a Network from the results of an iw scan.
+    fn from_bss(bss: &iw::Bss, scan_time: u128, opts: &ScanOptions) -> Network {
+        let ssid = bss.ssid.clone().unwrap_or_default();
+        let channel = bss.channel.clone().unwrap_or_default();
+        let frequency = bss.freq.or_else(|| radio::channel_to_frequency(&channel));
+
+        Network{
+            vendor: opts.vendors.lookup(&bss.mac),
+            mac: bss.mac.clone(),
+            is_hidden: is_hidden_ssid(&ssid),
+            ssid,
+            channel,
+            frequency,
+            band: frequency.and_then(Band::from_frequency),
+            channel_width: Some(bss.channel_width()),
+            standard: Some(bss.standard()),
+            security: Some(bss.security()),
+            strength: bss.signal,
+            strength_raw: None,
+            samples: 1,
+            strength_stddev: None,
+            noise: None,
+            snr: None,
+            interface: None,
+            beacon_interval: bss.beacon_interval,
+            capabilities: bss.capabilities.clone(),
+            information_elements: bss.elements.clone(),
+            time_scanned: scan_time,
         }
+    }
 
-        // Prompt user for notes
-        print!("    Notes (empty for none): ");
+    /// Calculate the signal to noise ratio from the strength and noise.
+    fn update_snr(&mut self) {
+        self.snr = match (self.strength, self.noise) {
+            (Some(strength), Some(noise)) => Some(strength - noise),
+            _ => None,
+        };
+    }
 
-        let mut notes = String::new();
-        stdout().flush().expect("failed to flush stdout");
-        stdin().read_line(&mut notes).expect("failed to read input");
-        notes = notes.replace("\n", "");
+    /// Scan wifi networks with the backend in opts.
+    fn scan(opts: &ScanOptions) -> Result<Vec<Network>, Box<dyn Error>> {
+	   let scan_time = (SystemTime::now().duration_since(UNIX_EPOCH)?).as_millis();
+	   let scanner = opts.backend.scanner();
 
-        // Scan networks
-	   println!("Scanning");
-	   
-	   let mut networks = Network::scan()?;
-	   networks.sort_by_key(|n| n.mac.clone());
-	   
-	   if networks.len() == 0 {
-		  println!("Warning: No networks were found, this indicates that you may have to run this tool with elevated privileges");
+	   let started = Instant::now();
+	   let mut networks = match opts.interfaces.is_empty() {
+		  true => scan_with_retries(opts, || scanner.scan(None, opts, scan_time))?,
+		  false => Network::scan_interfaces(scanner.as_ref(), opts, scan_time)?,
+	   };
+	   let found = networks.len();
+	   networks.retain(|n| opts.filters.matches(n));
+	   logging::info(&format!("Scanned with {} in {:.2}s, found {} network{}", opts.backend, started.elapsed().as_secs_f64(), found, match found != 1 {
+		  true => "s",
+		  false => "",
+	   }));
+	   if networks.len() < found {
+		  logging::debug(&format!("Scan filters dropped {} of them", found - networks.len()));
 	   }
+	   networks.iter_mut().for_each(Network::update_snr);
 
-	   let mut ssid_max_len = 0;
-	   for network in &networks {
-		  if network.ssid.len() > ssid_max_len {
-			 ssid_max_len = network.ssid.len();
-		  }
-	   }
-	   
-	   let mut ssid_len_match = Vec::<Network>::new();
-	   for network in &networks {
-		  let mut matched = network.clone();
+	   Ok(networks)
+    }
 
-		  while matched.ssid.len() < ssid_max_len {
-			 matched.ssid += " ";
-		  }
-		  
-		  ssid_len_match.push(matched);
-	   }
+    /// Scan with each of the interfaces in opts at the same time. The strongest reading of each BSSID is kept. Fails only if every interface fails.
+    fn scan_interfaces(scanner: &dyn scanner::Scanner, opts: &ScanOptions, scan_time: u128) -> Result<Vec<Network>, ScanError> {
+        let results: Vec<(&String, Result<Vec<Network>, ScanError>)> = thread::scope(|scope| {
+            let handles: Vec<_> = opts.interfaces.iter()
+                .map(|interface| (interface, scope.spawn(move || scan_with_retries(opts, || scanner.scan(Some(interface), opts, scan_time)))))
+                .collect();
 
-	   let networks_plural_str = match networks.len() != 1 {
-		  true => "s",
-		  false => "",
-	   };
+            handles.into_iter()
+                .map(|(interface, handle)| (interface, handle.join().expect("scan thread panicked")))
+                .collect()
+        });
 
-	   println!("Measured {} network{}:", networks.len(), networks_plural_str);
+        let mut networks = Vec::<Network>::new();
+        let mut errors = Vec::<ScanError>::new();
 
-	   for network in &ssid_len_match {
-		  println!("    {}", network);
-	   }
+        for (interface, result) in results {
+            let scan = match result {
+                Ok(s) => s,
+                Err(e) => {
+                    if opts.interfaces.len() > 1 {
+                        logging::warn(&format!("Scan with {} failed: {}", interface, e));
+                    }
+                    errors.push(e);
+                    continue;
+                },
+            };
 
-        Ok(Node{
-            position: position,
-            notes: notes,
-            networks: networks,
-        })
+            for mut network in scan {
+                network.interface = Some(interface.clone());
+
+                match networks.iter_mut().find(|n| n.mac == network.mac) {
+                    Some(existing) if network.strength > existing.strength => *existing = network,
+                    Some(_) => (),
+                    None => networks.push(network),
+                };
+            }
+        }
+
+        match errors.len() == opts.interfaces.len() {
+            true => Err(errors.remove(0)),
+            false => Ok(networks),
+        }
     }
-}
 
-/// Holds nodes with their scans. Saved to a file.
-#[derive(Serialize, Deserialize)]
-struct ScanMap {
-    /// Title of the scan map.
-    name: String,
+    /// Scan wifi networks several times, waiting interval between scans, and average the measurements of each BSSID.
+    fn scan_samples(opts: &ScanOptions, samples: u32, interval: Duration) -> Result<Vec<Network>, Box<dyn Error>> {
+        let mut by_mac = Vec::<(String, Vec<Network>)>::new();
 
-    /// Free-form description of any additional details.
-    notes: String,
+        for i in 0..samples {
+            if i > 0 {
+                thread::sleep(interval);
+            }
 
-    /// Scan data points.
-    nodes: Vec<Node>,
-}
+            let scan = progress::spin("    Waiting for results", opts.progress, || Network::scan(opts))?;
+            if samples > 1 {
+                println!("    Sample {} of {}", i + 1, samples);
+            }
 
-impl fmt::Display for ScanMap {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-	   let notes_str = match self.notes.len() > 0 {
-		  true => format!("{}, ", self.notes),
-		  false => String::new(),
-	   };
-	   let node_plural_str = match self.nodes.len() > 0 {
-		  true => "s",
-		  false => "",
-	   };
-	   write!(f, "{name} Scan Map ({notes_str}{node_count} Node{node_plural_str})", name=self.name, notes_str=notes_str, node_count=self.nodes.len(), node_plural_str=node_plural_str)
-    }
-}
+            for network in scan {
+                match by_mac.iter_mut().find(|(mac, _)| *mac == network.mac) {
+                    Some((_, measurements)) => measurements.push(network),
+                    None => by_mac.push((network.mac.clone(), vec![network])),
+                };
+            }
+        }
 
-impl ScanMap {
-    /// Initialize an empty ScanMap.
-    fn new() -> ScanMap {
-	   ScanMap{
-		  name: String::from(""),
-		  notes: String::from(""),
-		  nodes: Vec::<Node>::new(),
-	   }
+        Ok(by_mac.into_iter()
+            .map(|(_, measurements)| Network::average(measurements))
+            .collect())
     }
-    
-    /// Creates a new ScanMap from an existing json file.
-    fn read(p: &Path) -> Result<ScanMap, Box<dyn Error>> {
-	   let file = File::open(p)?;
-	   let reader = BufReader::new(file);
 
-	   let scan_map = serde_json::from_reader(reader)?;
+    /// Combine the measurements of one BSSID from consecutive scans. Other fields are taken from the last scan.
+    fn average(measurements: Vec<Network>) -> Network {
+        let strengths: Vec<f32> = measurements.iter().filter_map(|n| n.strength).collect();
+        let noises: Vec<f32> = measurements.iter().filter_map(|n| n.noise).collect();
+        let count = measurements.len() as u32;
 
-	   Ok(scan_map)
-    }
+        let mut network = measurements.into_iter()
+            .last()
+            .expect("no measurements to average");
+        network.samples = count;
 
-    /// Write curren ScanMap to .json file
-    fn write(&self, p: &Path) -> Result<(), Box<dyn Error>> {
-	   let file = OpenOptions::new().read(true).write(true).create(true).open(p)?;
-	   let writer = BufWriter::new(file);
+        if count > 1 && !strengths.is_empty() {
+            let mean = strengths.iter().sum::<f32>() / strengths.len() as f32;
+            let variance = strengths.iter().map(|s| (s - mean).powi(2)).sum::<f32>() / strengths.len() as f32;
 
-	   serde_json::to_writer(writer, self)?;
+            network.strength = Some(mean);
+            network.strength_raw = None;
+            network.strength_stddev = Some(variance.sqrt());
+        }
+        if count > 1 && !noises.is_empty() {
+            network.noise = Some(noises.iter().sum::<f32>() / noises.len() as f32);
+        }
+        network.update_snr();
 
-	   Ok(())
+        network
     }
+}
 
-    /// Acquire a new reading.
-    fn acquire(&mut self) -> Result<(), Box<dyn Error>> {
-	   let node = Node::acquire()?;
+/// Length of time given on the command line, like "2s", "500ms", "5m", or "1h". Numbers without a unit are seconds.
+struct DurationArg(Duration);
 
-	   let networks_plural_str = match node.networks.len() != 1 {
-		  true => "s",
+impl std::str::FromStr for DurationArg {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<DurationArg, String> {
+        let s = s.trim();
+        let split = s.find(|c: char| !(c.is_ascii_digit() || c == '.')).unwrap_or(s.len());
+        let (number, unit) = s.split_at(split);
+
+        let number: f64 = match number.parse() {
+            Ok(n) => n,
+            Err(_) => return Err(String::from("must be a number followed by ms, s, m, or h")),
+        };
+        let seconds = match unit.trim() {
+            "ms" => number / 1000.0,
+            "" | "s" => number,
+            "m" => number * 60.0,
+            "h" => number * 3600.0,
+            u => return Err(format!("unknown unit \"{}\", must be ms, s, m, or h", u)),
+        };
+
+        Ok(DurationArg(Duration::from_secs_f64(seconds)))
+    }
+}
+
+/// Networks which are recorded. Empty lists do not filter anything.
+#[derive(Serialize, Deserialize, Default, Clone)]
+struct ScanFilters {
+    /// Globs of SSIDs to record, if not empty networks whose SSID matches none of them are dropped.
+    only_ssids: Vec<String>,
+
+    /// Globs of SSIDs which are dropped.
+    ignore_ssids: Vec<String>,
+
+    /// BSSID prefixes to record, may contain globs. If not empty networks whose BSSID starts with none of them are dropped.
+    only_bssid_prefixes: Vec<String>,
+}
+
+impl ScanFilters {
+    /// True if no networks are dropped.
+    fn is_empty(&self) -> bool {
+        self.only_ssids.is_empty() && self.ignore_ssids.is_empty() && self.only_bssid_prefixes.is_empty()
+    }
+
+    /// True if network passes the filters. SSIDs are matched as displayed, so hidden networks match "<hidden>".
+    fn matches(&self, network: &Network) -> bool {
+        let ssid = network.display_ssid();
+        let mac = network.mac.to_lowercase();
+
+        (self.only_ssids.is_empty() || self.only_ssids.iter().any(|p| query::glob_match(p, ssid)))
+            && !self.ignore_ssids.iter().any(|p| query::glob_match(p, ssid))
+            && (self.only_bssid_prefixes.is_empty() || self.only_bssid_prefixes.iter().any(|p| query::glob_match(&format!("{}*", p.to_lowercase()), &mac)))
+    }
+}
+
+impl fmt::Display for ScanFilters {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_empty() {
+            return write!(f, "none");
+        }
+
+        let mut parts = Vec::<String>::new();
+        if !self.only_ssids.is_empty() {
+            parts.push(format!("only SSIDs {}", self.only_ssids.join(", ")));
+        }
+        if !self.ignore_ssids.is_empty() {
+            parts.push(format!("ignore SSIDs {}", self.ignore_ssids.join(", ")));
+        }
+        if !self.only_bssid_prefixes.is_empty() {
+            parts.push(format!("only BSSIDs {}", self.only_bssid_prefixes.join(", ")));
+        }
+
+        write!(f, "{}", parts.join("; "))
+    }
+}
+
+/// Options which control how networks are scanned.
+struct ScanOptions {
+    /// Used to look up the vendor of each access point.
+    vendors: oui::OuiTable,
+
+    /// Number of times a scan which failed for a transient reason is tried again.
+    retries: u32,
+
+    /// Time to wait before the first retry, doubled for each following retry.
+    retry_delay: Duration,
+
+    /// Show a spinner while scanning.
+    progress: bool,
+
+    /// Networks which are kept from each scan.
+    filters: ScanFilters,
+
+    /// Wireless interfaces to scan with at the same time. If empty the backend's default interface is used.
+    interfaces: Vec<String>,
+
+    /// How networks are scanned.
+    backend: scanner::Backend,
+
+    /// Time the capture backend listens on each channel.
+    dwell: Duration,
+
+    /// Radios which are scanned.
+    protocol: Protocol,
+
+    /// Time to listen for Bluetooth LE advertisements.
+    ble_duration: Duration,
+}
+
+/// Kinds of radio which can be scanned.
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Protocol {
+    Wifi,
+    Ble,
+    Both,
+}
+
+impl Protocol {
+    /// True if wifi networks are scanned.
+    fn wifi(self) -> bool {
+        self != Protocol::Ble
+    }
+
+    /// True if Bluetooth LE devices are scanned.
+    fn ble(self) -> bool {
+        self != Protocol::Wifi
+    }
+}
+
+impl std::str::FromStr for Protocol {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Protocol, String> {
+        match s.to_lowercase().as_str() {
+            "wifi" => Ok(Protocol::Wifi),
+            "ble" => Ok(Protocol::Ble),
+            "both" => Ok(Protocol::Both),
+            _ => Err(String::from("must be wifi, ble, or both")),
+        }
+    }
+}
+
+/// Format sub-commands print their results in.
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum OutputFormat {
+    /// Tables and sentences for people.
+    Text,
+
+    /// JSON for scripts, the same as each sub-command's --json.
+    Json,
+}
+
+impl std::str::FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<OutputFormat, String> {
+        match s.to_lowercase().as_str() {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            _ => Err(String::from("must be text or json")),
+        }
+    }
+}
+
+/// True if the results of a sub-command should be printed as JSON, because of its --json or the global --output-format json.
+fn json_arg(args: &ArgMatches, output: OutputFormat) -> bool {
+    output == OutputFormat::Json || args.get_flag("json")
+}
+
+/// Options which control how the properties of a new Node are determined.
+struct RecordOptions {
+    /// If set the position is read from gpsd instead of asking the user.
+    gps: Option<gps::GpsOptions>,
+
+    /// Floor new nodes are on. If None and the map has floors the user is asked.
+    floor: Option<String>,
+
+    /// Tags added to new nodes.
+    tags: Vec<String>,
+
+    /// Session new nodes are part of.
+    session: Option<u32>,
+
+    /// Number of scans averaged into each node.
+    samples: u32,
+
+    /// Time to wait between the scans of a node.
+    interval: Duration,
+
+    /// If true pressing enter at the coordinate and notes prompts reuses the values of the previous node.
+    reuse_previous: bool,
+
+    /// If true the probe requests of client devices are captured after the networks are scanned.
+    clients: bool,
+}
+
+impl RecordOptions {
+    /// Floor of a new node in scan_map, asks the user if the map has floors and none was given.
+    fn determine_floor(&self, scan_map: &ScanMap) -> Option<String> {
+	   match &self.floor {
+		  Some(f) => Some(f.clone()),
+		  None if !scan_map.floors.is_empty() => Some(prompt_floor(&scan_map.floors)),
+		  None => None,
+	   }
+    }
+
+    /// Position of a new node in scan_map, read from gpsd or asked for. Entered offsets are from the map's last node.
+    fn determine_position(&self, scan_map: &ScanMap) -> Coordinate {
+	   let crs = scan_map.coordinate_system;
+	   let units = scan_map.units;
+	   let previous = scan_map.nodes.last().map(|n| &n.position);
+
+	   match &self.gps {
+		  Some(gps_opts) => match gps::read_fix(gps_opts) {
+			 Ok(fix) => {
+				let position = Coordinate::from_fix(&fix);
+				println!("    Coordinates (from GPS): {}", position.format(crs, units, units));
+				position
+			 },
+			 Err(e) => {
+				logging::warn(&format!("Failed to get a GPS fix, enter coordinates manually: {}", e));
+				Coordinate::prompt(crs, units, previous, self.reuse_previous)
+			 },
+		  },
+		  None => Coordinate::prompt(crs, units, previous, self.reuse_previous),
+	   }
+    }
+}
+
+/// Node is the result of a scan at a location.
+#[derive(Serialize, Deserialize)]
+struct Node {
+    position: Coordinate,
+
+    /// Name of the floor the node was recorded on, the position's z is the height above this floor. None if the map has no floors.
+    floor: Option<String>,
+
+    notes: String,
+
+    /// Labels used to group nodes, e.g. the room the node is in.
+    tags: Vec<String>,
+
+    /// Session the node was recorded in. None for nodes recorded before sessions existed.
+    session: Option<u32>,
+
+    /// Identifier record --remote sent the node with, so the server adds each node once even if it is sent again. None
+    /// for nodes which were not received from another computer.
+    upload_id: Option<String>,
+
+    networks: Vec<Network>,
+
+    /// Client devices heard while the node was measured. Empty unless the node was recorded with --clients.
+    clients: Vec<Client>,
+
+    /// Bluetooth LE devices heard while the node was measured. Empty unless the node was recorded with --protocol ble or both.
+    ble_devices: Vec<BleDevice>,
+}
+
+/// A client device which was heard sending probe requests, e.g. a phone or laptop looking for networks.
+#[derive(Serialize, Deserialize, Clone)]
+struct Client {
+    /// Hardware address of the device.
+    mac: String,
+
+    /// True if the address is locally administered. Most phones pick a random address for probe requests, so one device may show up under several addresses.
+    randomized: bool,
+
+    /// Networks the device asked for. Devices which asked for any network are not listed here.
+    probed_ssids: Vec<String>,
+
+    /// Average strength of the device's probe requests in dBm. None if the driver did not report it.
+    strength: Option<f32>,
+
+    /// Number of probe requests which were heard.
+    samples: u32,
+}
+
+impl Client {
+    /// Capture probe requests with the first interface in opts and combine them per device.
+    fn listen(opts: &ScanOptions) -> Result<Vec<Client>, Box<dyn Error>> {
+        let interface = match opts.interfaces.first().cloned().or_else(|| iw::interfaces().ok().and_then(|i| i.into_iter().next())) {
+            Some(i) => i,
+            None => return Err(String::from("no wireless interface to capture client devices with").into()),
+        };
+
+        let requests = progress::spin("    Listening for client devices", opts.progress, || capture::probe_requests(&interface, opts.dwell))?;
+
+        let mut clients = Vec::<(Client, Vec<f32>)>::new();
+        for request in requests {
+            let (client, strengths) = match clients.iter_mut().position(|(c, _)| c.mac == request.mac) {
+                Some(i) => &mut clients[i],
+                None => {
+                    // The locally administered bit of the first octet
+                    let randomized = u8::from_str_radix(&request.mac[..2], 16).map(|o| o & 0x02 != 0).unwrap_or(false);
+                    clients.push((Client{
+                        mac: request.mac.clone(),
+                        randomized,
+                        probed_ssids: Vec::new(),
+                        strength: None,
+                        samples: 0,
+                    }, Vec::new()));
+                    clients.last_mut().expect("client was just added")
+                },
+            };
+
+            client.samples += 1;
+            strengths.extend(request.signal);
+            if !request.ssid.is_empty() && !client.probed_ssids.contains(&request.ssid) {
+                client.probed_ssids.push(request.ssid);
+            }
+        }
+
+        let clients: Vec<Client> = clients.into_iter()
+            .map(|(mut client, strengths)| {
+                if !strengths.is_empty() {
+                    client.strength = Some(strengths.iter().sum::<f32>() / strengths.len() as f32);
+                }
+                client
+            })
+            .collect();
+
+        println!("Heard {} client device{}", clients.len(), match clients.len() != 1 {
+            true => "s",
+            false => "",
+        });
+
+        Ok(clients)
+    }
+}
+
+/// Level of a building.
+#[derive(Serialize, Deserialize, Clone)]
+struct Floor {
+    /// Unique name, e.g. "2" or "Basement".
+    name: String,
+
+    /// Height of the floor above the map's origin.
+    elevation: f64,
+
+    /// Plan of this floor, if None the map's floor plan is used.
+    floor_plan: Option<FloorPlan>,
+}
+
+/// Image of a building's layout which nodes can be drawn on.
+#[derive(Serialize, Deserialize, Clone)]
+struct FloorPlan {
+    /// Path of the image. Relative paths are relative to the directory of the map file.
+    image: String,
+
+    /// Pixels per map coordinate unit.
+    scale: f64,
+
+    /// Horizontal pixel position of the map's origin, from the left of the image.
+    origin_x: f64,
+
+    /// Vertical pixel position of the map's origin, from the top of the image. Map y coordinates increase towards the top of the image.
+    origin_y: f64,
+}
+
+/// One walkthrough of a survey. Sessions keep repeated surveys of the same place distinguishable.
+#[derive(Serialize, Deserialize)]
+struct Session {
+    /// Identifier which nodes refer to the session by, starting at 1.
+    id: u32,
+
+    /// Time the session was started in milliseconds since the unix epoch.
+    started: u128,
+
+    /// Person who recorded the session.
+    operator: Option<String>,
+
+    /// Device the session was recorded with.
+    device: Option<String>,
+}
+
+/// Ask the user which floor they are on, repeats until the name of one of floors is entered.
+fn prompt_floor(floors: &[Floor]) -> String {
+    let names: Vec<&str> = floors.iter().map(|f| f.name.as_str()).collect();
+
+    loop {
+        print!("    Floor ({}): ", names.join(", "));
+        stdout().flush().expect("failed to flush stdout");
+        let mut floor_str = String::new();
+        stdin().read_line(&mut floor_str).expect("failed to read input");
+        let floor_str = floor_str.trim();
+
+        if names.contains(&floor_str) {
+            return floor_str.to_string();
+        }
+
+        println!("    Error: Unknown floor \"{}\"", floor_str);
+    }
+}
+
+impl Node {
+    /// True if the node has all of tags.
+    fn has_tags(&self, tags: &[String]) -> bool {
+        tags.iter().all(|t| self.tags.contains(t))
+    }
+
+    /// Networks of the node which were scanned within times.
+    fn networks_in<'a>(&'a self, times: &'a timestamp::TimeRange) -> impl Iterator<Item = &'a Network> + 'a {
+        self.networks.iter().filter(move |n| times.contains(n.time_scanned))
+    }
+
+    /// Time the node was measured, the time its last network was scanned. None if it has no networks.
+    fn measured(&self) -> Option<u128> {
+        self.networks.iter().map(|n| n.time_scanned).max()
+    }
+
+    /// Create a new Node for scan_map by asking the user for data and scanning.
+    fn acquire(scan_map: &ScanMap, record_opts: &RecordOptions, scan_opts: &ScanOptions) -> Result<Node, Box<dyn Error>> {
+	   println!("New measurement properties:");
+
+	   let floor = record_opts.determine_floor(scan_map);
+	   let position = record_opts.determine_position(scan_map);
+
+        // Prompt user for notes
+        let previous_notes = scan_map.nodes.last()
+            .map(|n| n.notes.as_str())
+            .filter(|n| record_opts.reuse_previous && !n.is_empty());
+        match previous_notes {
+            Some(n) => print!("    Notes (enter to reuse \"{}\", - for none): ", n),
+            None => print!("    Notes (empty for none): "),
+        };
+
+        let mut notes = String::new();
+        stdout().flush().expect("failed to flush stdout");
+        stdin().read_line(&mut notes).expect("failed to read input");
+        notes = notes.replace("\n", "");
+
+        if let Some(n) = previous_notes {
+            notes = match notes.trim() {
+                "" => n.to_string(),
+                "-" => String::new(),
+                _ => notes,
+            };
+        }
+
+	   Node::measure(scan_map, record_opts, scan_opts, floor, position, notes)
+    }
+
+    /// Create a new Node for scan_map at a known position by scanning.
+    fn measure(scan_map: &ScanMap, record_opts: &RecordOptions, scan_opts: &ScanOptions, floor: Option<String>, position: Coordinate, notes: String) -> Result<Node, Box<dyn Error>> {
+        // Scan networks
+	   println!("Scanning");
+
+	   let networks = match scan_opts.protocol.wifi() {
+		  true => Node::measure_networks(scan_map, record_opts, scan_opts)?,
+		  false => Vec::new(),
+	   };
+	   let ble_devices = match scan_opts.protocol.ble() {
+		  true => Node::measure_ble(scan_opts)?,
+		  false => Vec::new(),
+	   };
+
+	   let clients = match record_opts.clients {
+		  true => Client::listen(scan_opts)?,
+		  false => Vec::new(),
+	   };
+
+        Ok(Node{
+            position,
+            floor,
+            notes,
+            tags: record_opts.tags.clone(),
+            session: record_opts.session,
+            upload_id: None,
+            networks,
+            clients,
+            ble_devices,
+        })
+    }
+
+    /// Scan the wifi networks of a new node and print them.
+    fn measure_networks(scan_map: &ScanMap, record_opts: &RecordOptions, scan_opts: &ScanOptions) -> Result<Vec<Network>, Box<dyn Error>> {
+	   let mut networks = Network::scan_samples(scan_opts, record_opts.samples, record_opts.interval)?;
+	   networks.sort_by_key(|n| n.mac.clone());
+	   
+	   if networks.is_empty() {
+		  logging::warn("No networks were found, this indicates that you may have to run this tool with elevated privileges, or scan through NetworkManager with --backend nmcli");
+	   }
+
+	   let mut ssid_max_len = 0;
+	   for network in &networks {
+		  if network.display_ssid().chars().count() > ssid_max_len {
+			 ssid_max_len = network.display_ssid().chars().count();
+		  }
+	   }
+
+	   let networks_plural_str = match networks.len() != 1 {
+		  true => "s",
 		  false => "",
 	   };
-	   println!("Recorded a new measurement with {} network{}", node.networks.len(), networks_plural_str);
-        
-        self.nodes.push(node);
 
-	   Ok(())
-    }
-}
+	   println!("Measured {} network{}:", networks.len(), networks_plural_str);
+
+	   for network in &networks {
+		  match scan_map.annotations.get(&network.mac) {
+			 Some(note) => println!("    {:width$} - {}", network, note, width=ssid_max_len),
+			 None => println!("    {:width$}", network, width=ssid_max_len),
+		  };
+	   }
+
+	   let legacy_count = networks.iter()
+		  .filter(|n| n.security.map(|s| s.is_legacy()).unwrap_or(false))
+		  .count();
+	   if legacy_count > 0 {
+		  logging::warn(&format!("{} {} open or legacy encryption", legacy_count, match legacy_count != 1 {
+			 true => "networks use",
+			 false => "network uses",
+		  }));
+	   }
+
+	   Ok(networks)
+    }
+
+    /// Scan the Bluetooth LE devices of a new node and print them.
+    fn measure_ble(scan_opts: &ScanOptions) -> Result<Vec<BleDevice>, Box<dyn Error>> {
+        let mut devices = progress::spin("    Listening for Bluetooth devices", scan_opts.progress, || ble::scan(scan_opts.ble_duration))?;
+        devices.sort_by_key(|d| d.address.clone());
+
+        let name_max_len = devices.iter()
+            .map(|d| d.name.as_deref().unwrap_or("<unnamed>").chars().count())
+            .max()
+            .unwrap_or(0);
+
+        println!("Measured {} Bluetooth device{}:", devices.len(), match devices.len() != 1 {
+            true => "s",
+            false => "",
+        });
+        for device in &devices {
+            println!("    {:width$}", device, width=name_max_len);
+        }
+
+        Ok(devices)
+    }
+}
+
+/// Holds nodes with their scans. Saved to a file.
+#[derive(Serialize, Deserialize)]
+struct ScanMap {
+    /// Schema version of the file, see the migrate module.
+    version: u64,
+
+    /// Title of the scan map.
+    name: String,
+
+    /// Free-form description of any additional details.
+    notes: String,
+
+    /// Coordinate system of node positions.
+    coordinate_system: CoordinateSystem,
+
+    /// Unit of local coordinates, altitudes, and floor elevations. Always meters for wgs84 maps, since gpsd reports altitudes in meters.
+    units: Units,
+
+    /// Levels of the building, empty if the map does not distinguish floors.
+    floors: Vec<Floor>,
+
+    /// Plan of the building, used for floors without their own plan.
+    floor_plan: Option<FloorPlan>,
+
+    /// Notes about access points keyed by lower case BSSID, e.g. where the device is mounted.
+    annotations: BTreeMap<String, String>,
+
+    /// Walkthroughs the nodes were recorded in.
+    sessions: Vec<Session>,
+
+    /// Filters used when recording unless others are given.
+    scan_filters: ScanFilters,
+
+    /// Scan data points.
+    nodes: Vec<Node>,
+
+    /// Estimated positions of access points keyed by lower case BSSID, saved by the locate command.
+    access_points: BTreeMap<String, locate::AccessPointLocation>,
+
+    /// Propagation model of the building, used unless its parameters are given on the command line.
+    path_loss: locate::PathLoss,
+
+    /// Nodes which are in the file but were not loaded, when the map is streamed.
+    #[serde(skip)]
+    streamed: stream::Streamed,
+}
+
+impl fmt::Display for ScanMap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+	   let notes_str = match !self.notes.is_empty() {
+		  true => format!("{}, ", self.notes),
+		  false => String::new(),
+	   };
+	   let node_plural_str = match self.node_count() > 0 {
+		  true => "s",
+		  false => "",
+	   };
+	   write!(f, "{name} Scan Map ({notes_str}{node_count} Node{node_plural_str})", name=self.name, notes_str=notes_str, node_count=self.node_count(), node_plural_str=node_plural_str)
+    }
+}
+
+impl ScanMap {
+    /// Initialize an empty ScanMap.
+    fn new() -> ScanMap {
+	   ScanMap{
+		  version: migrate::SCHEMA_VERSION,
+		  name: String::from(""),
+		  notes: String::from(""),
+		  coordinate_system: CoordinateSystem::Local,
+		  units: Units::Meters,
+		  floors: Vec::<Floor>::new(),
+		  floor_plan: None,
+		  annotations: BTreeMap::new(),
+		  sessions: Vec::<Session>::new(),
+		  scan_filters: ScanFilters::default(),
+		  nodes: Vec::<Node>::new(),
+		  access_points: BTreeMap::new(),
+		  path_loss: locate::PathLoss::default(),
+		  streamed: stream::Streamed::default(),
+	   }
+    }
+
+    /// Number of nodes in the map, including those which were not loaded because the map is streamed.
+    fn node_count(&self) -> usize {
+	   self.streamed.nodes + self.nodes.len()
+    }
+    
+    /// Creates a new ScanMap by asking the user for its properties. The name and notes are only asked for if they are None. default_crs is used if the user does not enter a coordinate system.
+    fn prompt_new(p: &Path, name: Option<&str>, notes: Option<&str>, default_crs: CoordinateSystem) -> ScanMap {
+        println!("Creating a new scan map in \"{}\"", p.display());
+        println!("New scan map properties:");
+
+        let mut scan_map = ScanMap::new();
+
+        if let Some(name) = name {
+            scan_map.name = name.to_string();
+        }
+
+        let mut get_name_done = !scan_map.name.is_empty();
+        while !get_name_done {
+            print!("    Name: ");
+            stdout().flush().expect("failed to flush stdout");
+            stdin().read_line(&mut scan_map.name)
+                .expect("failed to read input");
+            scan_map.name = scan_map.name.replace("\n", "");
+
+            if !scan_map.name.is_empty() {
+                get_name_done = true;
+            } else {
+                println!("    Error: Name cannot be empty");
+            }
+        }
+
+        match notes {
+            Some(notes) => scan_map.notes = notes.to_string(),
+            None => {
+                print!("    Notes (empty for none): ");
+                stdout().flush().expect("failed to flush stdout");
+                stdin().read_line(&mut scan_map.notes).expect("failed to read input");
+                scan_map.notes = scan_map.notes.replace("\n", "");
+            },
+        };
+
+        let mut get_crs_done = false;
+        while !get_crs_done {
+            print!("    Coordinate system (local, wgs84) [{}]: ", default_crs);
+            stdout().flush().expect("failed to flush stdout");
+            let mut crs_str = String::new();
+            stdin().read_line(&mut crs_str).expect("failed to read input");
+
+            if crs_str.trim().is_empty() {
+                scan_map.coordinate_system = default_crs;
+                get_crs_done = true;
+                continue;
+            }
+
+            match crs_str.parse::<CoordinateSystem>() {
+                Ok(crs) => {
+                    scan_map.coordinate_system = crs;
+                    get_crs_done = true;
+                },
+                Err(e) => println!("    Error: {}", e),
+            };
+        }
+
+        let mut get_units_done = scan_map.coordinate_system == CoordinateSystem::Wgs84;
+        while !get_units_done {
+            print!("    Units (meters, feet) [{}]: ", Units::Meters);
+            stdout().flush().expect("failed to flush stdout");
+            let mut units_str = String::new();
+            stdin().read_line(&mut units_str).expect("failed to read input");
+
+            if units_str.trim().is_empty() {
+                get_units_done = true;
+                continue;
+            }
+
+            match units_str.parse::<Units>() {
+                Ok(units) => {
+                    scan_map.units = units;
+                    get_units_done = true;
+                },
+                Err(e) => println!("    Error: {}", e),
+            };
+        }
+
+        scan_map
+    }
+
+    /// Creates a new ScanMap from an existing json file. Files written with an older schema are upgraded.
+    fn read(p: &Path) -> Result<ScanMap, Box<dyn Error>> {
+	   let started = Instant::now();
+	   let file = File::open(p)?;
+	   let reader = BufReader::new(file);
+
+	   let mut value: serde_json::Value = serde_json::from_reader(reader)?;
+	   let from_version = migrate::migrate(&mut value)?;
+	   if from_version != migrate::SCHEMA_VERSION {
+		  logging::info(&format!("Upgraded scan map from schema version {} to {}", from_version, migrate::SCHEMA_VERSION));
+	   }
+
+	   let scan_map: ScanMap = serde_json::from_value(value)?;
+	   logging::info(&format!("Read {} nodes from \"{}\" in {:.2}s", scan_map.nodes.len(), p.display(), started.elapsed().as_secs_f64()));
+
+	   Ok(scan_map)
+    }
+
+    /// Write current ScanMap to .json file. The map is first written to a temporary file next to p which then replaces p, so an interrupted write never corrupts an existing map.
+    /// New nodes of streamed maps are appended to their file instead, unless backups are kept.
+    fn write(&self, p: &Path, opts: &WriteOptions) -> Result<(), Box<dyn Error>> {
+        let _saving = interrupt::saving();
+        let started = Instant::now();
+        let streamed = self.streamed.nodes > 0 && self.streamed.path == p;
+
+        if !(streamed && opts.backups == 0 && stream::append(self)?) {
+            let tmp_path = sibling_path(p, ".", ".tmp");
+            let mut nodes_end = None;
+
+            {
+                let file = OpenOptions::new().write(true).create(true).truncate(true).open(&tmp_path)?;
+                let mut writer = BufWriter::new(file);
+
+                match (self.streamed.nodes > 0, opts.pretty) {
+                    // Nodes which were not loaded are copied from the map file, which is only replaced once they are written
+                    (true, _) => nodes_end = Some(stream::write(self, &mut writer)?),
+                    (false, true) => {
+                        // Round trip through a Value so object keys are written in sorted order
+                        let value = serde_json::to_value(self)?;
+                        serde_json::to_writer_pretty(&mut writer, &value)?;
+                        writeln!(writer)?;
+                    },
+                    (false, false) => serde_json::to_writer(&mut writer, self)?,
+                };
+
+                writer.flush()?;
+                writer.get_ref().sync_all()?;
+            }
+
+            if opts.backups > 0 && p.exists() {
+                rotate_backups(p, opts.backups)?;
+            }
+
+            fs::rename(&tmp_path, p)?;
+            match (streamed, nodes_end) {
+                (true, Some(end)) => stream::rewritten(self, end),
+                _ if self.streamed.path == p => self.streamed.version.set(migrate::SCHEMA_VERSION),
+                _ => (),
+            };
+        }
+        interrupt::saved(self.node_count());
+        logging::info(&format!("Saved {} nodes to \"{}\" in {:.2}s", self.node_count(), p.display(), started.elapsed().as_secs_f64()));
+
+        if let Some(remote) = &opts.remote {
+            remote.push(self);
+        }
+
+        Ok(())
+    }
+
+    /// Floor with a name.
+    fn floor(&self, name: &str) -> Option<&Floor> {
+        self.floors.iter().find(|f| f.name == name)
+    }
+
+    /// Start a new session and return its id.
+    fn start_session(&mut self, operator: Option<String>, device: Option<String>) -> Result<u32, Box<dyn Error>> {
+        let id = self.sessions.iter().map(|s| s.id).max().unwrap_or(0) + 1;
+
+        self.sessions.push(Session{
+            id,
+            started: SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis(),
+            operator,
+            device,
+        });
+
+        Ok(id)
+    }
+
+    /// Session with an id.
+    fn session(&self, id: u32) -> Option<&Session> {
+        self.sessions.iter().find(|s| s.id == id)
+    }
+
+    /// Keep only the nodes recorded in a session, exits with an error message if the map has no such session.
+    fn retain_session(&mut self, id: u32) {
+        if self.session(id).is_none() {
+            die(&format!("map has no session {}", id));
+        }
+
+        self.nodes.retain(|n| n.session == Some(id));
+    }
+
+    /// SSID of an access point as shown to users, None if no node has seen it.
+    fn ssid_of(&self, mac: &str) -> Option<&str> {
+        self.nodes.iter()
+            .flat_map(|n| n.networks.iter())
+            .find(|n| n.mac == mac)
+            .map(|n| n.display_ssid())
+    }
+
+    /// Floor plan to draw nodes of a floor on, or the map's floor plan if floor is None.
+    fn floor_plan(&self, floor: Option<&str>) -> Option<&FloorPlan> {
+        floor.and_then(|name| self.floor(name))
+            .and_then(|f| f.floor_plan.as_ref())
+            .or(self.floor_plan.as_ref())
+    }
+
+    /// Acquire a new reading.
+    fn acquire(&mut self, record_opts: &RecordOptions, scan_opts: &ScanOptions) -> Result<(), Box<dyn Error>> {
+	   let node = Node::acquire(self, record_opts, scan_opts)?;
+
+	   let networks_plural_str = match node.networks.len() != 1 {
+		  true => "s",
+		  false => "",
+	   };
+	   println!("Recorded a new measurement with {} network{}", node.networks.len(), networks_plural_str);
+        
+        self.nodes.push(node);
+
+	   Ok(())
+    }
+
+    /// Remove the most recently recorded node. Prints which node was removed, or that there was none.
+    fn undo(&mut self) -> Option<Node> {
+        let node = self.nodes.pop();
+
+        match &node {
+            Some(n) => println!("Removed node {} at {} with {} network{}", self.node_count(), n.position.format(self.coordinate_system, self.units, self.units), n.networks.len(), match n.networks.len() != 1 {
+                true => "s",
+                false => "",
+            }),
+            None => println!("No nodes to undo"),
+        };
+
+        node
+    }
+}
+
+/// Options which control how a ScanMap is saved.
+struct WriteOptions {
+    /// Number of previous versions of the map file to keep as .bak files.
+    backups: usize,
+
+    /// Indent output and sort object keys so map files produce readable diffs.
+    pretty: bool,
+
+    /// Server new nodes are sent to after saving.
+    remote: Option<remote::Remote>,
+}
+
+/// Path of a file in the same directory as p, named prefix + p's file name + suffix.
+fn sibling_path(p: &Path, prefix: &str, suffix: &str) -> PathBuf {
+    let file_name = p.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+
+    p.with_file_name(format!("{}{}{}", prefix, file_name, suffix))
+}
+
+/// Path of the n-th most recent backup of p, starting at 1.
+fn backup_path(p: &Path, n: usize) -> PathBuf {
+    match n {
+        1 => sibling_path(p, "", ".bak"),
+        _ => sibling_path(p, "", &format!(".{}.bak", n)),
+    }
+}
+
+/// Shift existing backups of p back by one, dropping the oldest, then copy p to the most recent backup slot.
+fn rotate_backups(p: &Path, keep: usize) -> Result<(), Box<dyn Error>> {
+    let oldest = backup_path(p, keep);
+    if oldest.exists() {
+        fs::remove_file(&oldest)?;
+    }
+
+    for n in (1..keep).rev() {
+        let from = backup_path(p, n);
+        if from.exists() {
+            fs::rename(&from, backup_path(p, n + 1))?;
+        }
+    }
+
+    fs::copy(p, backup_path(p, 1))?;
+
+    Ok(())
+}
+
+/// Directory which contains a map file.
+fn map_dir(map_file_path: &Path) -> &Path {
+    match map_file_path.parent() {
+        Some(p) if !p.as_os_str().is_empty() => p,
+        _ => Path::new("."),
+    }
+}
+
+/// Path of a file to store in a map, relative to the directory of the map file if the file is inside it. Exits if the file does not exist.
+fn relative_to_map(p: &Path, map_file_path: &Path) -> String {
+    let file = match fs::canonicalize(p) {
+        Ok(f) => f,
+        Err(e) => die(&format!("failed to find \"{}\": {}", p.display(), e)),
+    };
+
+    let stored = match fs::canonicalize(map_dir(map_file_path)) {
+        Ok(dir) => file.strip_prefix(&dir).map(|r| r.to_path_buf()).unwrap_or_else(|_| file.clone()),
+        Err(_) => file.clone(),
+    };
+
+    stored.to_string_lossy().into_owned()
+}
+
+/// Values of a command line argument which may be given more than once, empty if it was not provided.
+fn string_values(args: &ArgMatches, name: &str) -> Vec<String> {
+    args.get_many::<String>(name)
+        .map(|v| v.cloned().collect())
+        .unwrap_or_default()
+}
+
+/// Parse the index argument of a node sub-command, exits with an error message if the map has no such node.
+fn parse_node_index(args: &ArgMatches, scan_map: &ScanMap) -> usize {
+    let index: usize = parse_arg(args, "index");
+    let count = scan_map.nodes.len();
+
+    if index >= count {
+        die(&format!("map has no node {}, node indexes start at 0 and it has {} node{}", index, count, match count != 1 {
+            true => "s",
+            false => "",
+        }));
+    }
+
+    index
+}
+
+/// Ask the user a yes or no question, anything but yes is taken as no.
+fn confirm(question: &str) -> bool {
+    print!("{} [y/N]: ", question);
+    stdout().flush().expect("failed to flush stdout");
+
+    let mut answer = String::new();
+    stdin().read_line(&mut answer).expect("failed to read input");
+
+    matches!(answer.trim().to_lowercase().as_str(), "y" | "yes")
+}
+
+/// Read a scan map given as a sub-command argument, exits with an error message if it cannot be loaded.
+fn read_map(p: &Path) -> ScanMap {
+    match ScanMap::read(p) {
+        Ok(m) => m,
+        Err(e) => die(&format!("failed to load scan map \"{}\": {}", p.display(), e)),
+    }
+}
+
+/// Script which completes the command line app in shell.
+fn completions(app: &Command, shell: Shell) -> String {
+    let mut script = Vec::<u8>::new();
+    clap_complete::generate(shell, &mut app.clone(), "wifi-mapper", &mut script);
+    let script = String::from_utf8_lossy(&script).into_owned();
+
+    match shell {
+        // The cases of sub-commands replace the dash in the binary name with the sub-command separator, but the
+        // loop which picks the case replaces it with two underscores
+        Shell::Bash => script.replace("wifi__subcmd__mapper", "wifi__mapper"),
+        _ => script,
+    }
+}
+
+/// Parse the value of a command line argument, exits with an error message if it is invalid.
+fn parse_arg<T>(args: &ArgMatches, name: &str) -> T
+where T: std::str::FromStr, T::Err: fmt::Display {
+    let value = args.get_one::<String>(name).map(String::as_str).unwrap();
+
+    match value.parse::<T>() {
+        Ok(v) => v,
+        Err(e) => die(&format!("invalid value \"{}\" for --{}: {}", value, name.replace("_", "-"), e)),
+    }
+}
+
+/// Parse the value of an optional command line argument, None if it was not provided.
+fn parse_optional_arg<T>(args: &ArgMatches, name: &str) -> Option<T>
+where T: std::str::FromStr, T::Err: fmt::Display {
+    args.get_one::<String>(name).map(String::as_str).map(|_| parse_arg(args, name))
+}
+
+/// Times given to --since and --until in args, from the first millisecond --since names to the last --until names.
+fn time_range_arg(args: &ArgMatches) -> timestamp::TimeRange {
+    let range = timestamp::TimeRange{
+	   since: parse_optional_arg(args, "since").map(|t: timestamp::TimeArg| t.earliest),
+	   until: parse_optional_arg(args, "until").map(|t: timestamp::TimeArg| t.latest),
+    };
+
+    if let (Some(since), Some(until)) = (range.since, range.until) {
+	   if since > until {
+		  die("--since must not be after --until");
+	   }
+    }
+
+    range
+}
+
+/// Path loss model of scan_map with the parameters given in args replacing its own. Exits if the model is invalid.
+fn path_loss_arg(args: &ArgMatches, scan_map: &ScanMap) -> locate::PathLoss {
+    let model = scan_map.path_loss;
+    let path_loss = locate::PathLoss{
+	   reference: parse_optional_arg(args, "reference_power").unwrap_or(model.reference),
+	   exponent: parse_optional_arg(args, "path_loss_exponent").unwrap_or(model.exponent),
+	   wall_attenuation: parse_optional_arg(args, "wall_attenuation").unwrap_or(model.wall_attenuation),
+	   wall_spacing: parse_optional_arg(args, "wall_spacing").or(model.wall_spacing),
+    };
+
+    if path_loss.exponent <= 0.0 {
+	   die("path loss exponent must be positive");
+    }
+    if path_loss.wall_attenuation < 0.0 {
+	   die("wall attenuation cannot be negative");
+    }
+    if path_loss.wall_spacing.is_some_and(|s| s <= 0.0) {
+	   die("wall spacing must be positive");
+    }
+
+    path_loss
+}
+
+/// Arguments which replace parameters of the map's path loss model, see path_loss_arg.
+fn path_loss_args() -> [Arg; 4] {
+    [
+	   Arg::new("reference_power")
+		  .long("reference-power")
+		  .value_name("DBM")
+		  .help("Strength one meter from an access point, defaults to the map's path loss model")
+		  .allow_hyphen_values(true),
+	   Arg::new("path_loss_exponent")
+		  .long("path-loss-exponent")
+		  .value_name("EXPONENT")
+		  .help("How quickly signals weaken with distance, 2 in free space and 3 to 4 indoors, defaults to the map's path loss model"),
+	   Arg::new("wall_attenuation")
+		  .long("wall-attenuation")
+		  .value_name("DB")
+		  .help("Strength lost through each wall, defaults to the map's path loss model"),
+	   Arg::new("wall_spacing")
+		  .long("wall-spacing")
+		  .value_name("METERS")
+		  .help("Average distance between walls, defaults to the map's path loss model"),
+    ]
+}
+
+/// Arguments of the inverse distance weighted grids drawn from the nodes: the spacing, power, radius, and unseen strength.
+fn heatmap_args() -> [Arg; 4] {
+    [
+	   Arg::new("spacing")
+		  .long("spacing")
+		  .value_name("LENGTH")
+		  .help("Distance between heatmap points in the map's units, meters for wgs84 maps"),
+	   Arg::new("power")
+		  .long("power")
+		  .value_name("P")
+		  .help("Inverse distance weighting exponent, higher values let each node's measurement reach less far")
+		  .default_value("2"),
+	   Arg::new("radius")
+		  .long("radius")
+		  .value_name("LENGTH")
+		  .help("Leave areas farther than this from every node blank"),
+	   Arg::new("unseen")
+		  .long("unseen")
+		  .value_name("DBM")
+		  .help("Strength used for nodes which did not see the network, they are ignored by default")
+		  .allow_hyphen_values(true),
+    ]
+}
+
+/// Possible sub-commands.
+enum SubCmd<'a> {
+    /// Record wireless information.
+    Record(&'a ArgMatches),
+
+    /// Record wireless information in a full screen interface.
+    Tui(&'a ArgMatches),
+
+    /// Print the networks in range without a map.
+    Scan(&'a ArgMatches),
+
+    /// Check the map file for corruption.
+    Validate(&'a ArgMatches),
+
+    /// Write a copy of the map with identifying information removed.
+    Anonymize(&'a ArgMatches),
+
+    /// Manage the floors of the map.
+    Floor(&'a ArgMatches),
+
+    /// Print the nodes of the map.
+    List(&'a ArgMatches),
+
+    /// Attach a floor plan image to the map.
+    FloorPlan(&'a ArgMatches),
+
+    /// Draw the map to an image.
+    Render(&'a ArgMatches),
+
+    /// Draw a heatmap in the terminal.
+    View(&'a ArgMatches),
+
+    /// Write an HTML report of the survey.
+    Report(&'a ArgMatches),
+
+    /// Host a live web viewer of the map.
+    Serve(&'a ArgMatches),
+
+    /// Serve scan results as Prometheus metrics.
+    Exporter(&'a ArgMatches),
+
+    /// Write a copy of the map with moved, rotated, or scaled positions.
+    Transform(&'a ArgMatches),
+
+    /// Merge nodes measured at the same position.
+    Dedupe(&'a ArgMatches),
+
+    /// Combine several map files into a new one.
+    Merge(&'a ArgMatches),
+
+    /// Compare two map files.
+    Diff(&'a ArgMatches),
+
+    /// Summarize the networks in the map.
+    Stats(&'a ArgMatches),
+
+    /// Find networks matching filters.
+    Query(&'a ArgMatches),
+
+    /// Summarize each access point across the nodes.
+    Aps(&'a ArgMatches),
+
+    /// Estimate the positions of access points.
+    Locate(&'a ArgMatches),
+
+    /// Estimate signal strengths on a grid between nodes.
+    Interpolate(&'a ArgMatches),
+
+    /// Estimate signal strengths at a position which was not surveyed.
+    Predict(&'a ArgMatches),
+
+    /// Analyze the coverage of the map.
+    Analyze(&'a ArgMatches),
+
+    /// Write the measurements in a format other tools read.
+    Export(&'a ArgMatches),
+
+    /// Write a copy of the map without irrelevant networks and nodes.
+    Prune(&'a ArgMatches),
+
+    /// Change individual nodes.
+    Node(&'a ArgMatches),
+
+    /// Remove the most recently recorded node.
+    Undo,
+
+    /// Show or change the name and notes of the map.
+    Meta(&'a ArgMatches),
+
+    /// Attach notes to access points.
+    Annotate(&'a ArgMatches),
+
+    /// List survey sessions.
+    Session,
+
+    /// Print a shell completion script.
+    Completions(&'a ArgMatches),
+
+    /// Print the manual page, or write every page into a directory.
+    Manpage(&'a ArgMatches),
+}
+
+fn main() {
+    // Command line arguments
+    let app = Command::new("Wifi Scanner")
+        .about("Map wireless networks")
+        .arg(Arg::new("map_file")
+             .short('f')
+             .long("map-file")
+             .value_name("MAP_FILE")
+             .help("File to save scan map, required by all sub-commands except merge, diff, exporter, completions, and manpage"))
+        .arg(Arg::new("backups")
+             .long("backups")
+             .value_name("N")
+             .help("Number of previous versions of the map file to keep as .bak files when saving")
+             .default_value("0"))
+        .arg(Arg::new("oui_file")
+             .long("oui-file")
+             .value_name("OUI_FILE")
+             .help("IEEE OUI registry (oui.csv or oui.txt) used to look up access point vendors instead of the built in copy"))
+        .arg(Arg::new("scan_retries")
+             .long("scan-retries")
+             .value_name("COUNT")
+             .help("Number of times a scan is tried again if it fails for a transient reason, e.g. the interface is busy")
+             .default_value("3"))
+        .arg(Arg::new("scan_retry_delay")
+             .long("scan-retry-delay")
+             .value_name("DURATION")
+             .help("Time to wait before retrying a failed scan, doubled for each following retry")
+             .default_value("1s"))
+        .arg(Arg::new("dwell")
+             .long("dwell")
+             .value_name("DURATION")
+             .help("Time the capture backend listens for beacons on each channel")
+             .default_value("250ms"))
+        .arg(Arg::new("backend")
+             .long("backend")
+             .value_name("BACKEND")
+             .help("How networks are scanned: wifiscanner, iw, nmcli, netsh, airport, capture, or mock, defaults to mock if WIFI_MAPPER_MOCK is set, otherwise to wifiscanner unless interfaces are chosen"))
+        .arg(Arg::new("interface")
+             .short('i')
+             .long("interface")
+             .value_name("INTERFACE")
+             .help("Wireless interface to scan with, e.g. wlan1, defaults to the first interface. May be given more than once to scan with several interfaces at the same time")
+             .action(ArgAction::Append))
+        .arg(Arg::new("all_interfaces")
+             .long("all-interfaces")
+             .help("Scan with every wireless interface at the same time")
+             .conflicts_with("interface")
+             .action(ArgAction::SetTrue))
+        .arg(Arg::new("quiet")
+             .short('q')
+             .long("quiet")
+             .help("Only print errors, and do not show a spinner while scanning")
+             .action(ArgAction::SetTrue))
+        .arg(Arg::new("verbose")
+             .short('v')
+             .long("verbose")
+             .action(ArgAction::Count)
+             .conflicts_with("quiet")
+             .help("Print how long scans and saves take, twice to also print the commands backends run"))
+        .arg(Arg::new("log_file")
+             .long("log-file")
+             .value_name("FILE")
+             .help("Append every message to FILE with the time it was logged, whatever the verbosity"))
+        .arg(Arg::new("config")
+             .long("config")
+             .value_name("FILE")
+             .help("Read default flags from FILE instead of ~/.config/wifi-mapper/config.toml"))
+        .arg(Arg::new("no_config")
+             .long("no-config")
+             .help("Do not read default flags from a config file")
+             .conflicts_with("config")
+             .action(ArgAction::SetTrue))
+        .arg(Arg::new("output_format")
+             .long("output-format")
+             .visible_alias("output")
+             .value_name("FORMAT")
+             .help("Print the results of sub-commands as text or json, e.g. for jq")
+             .value_parser(["text", "json"])
+             .default_value("text"))
+        .arg(Arg::new("pretty")
+             .long("pretty")
+             .help("Save the map file as indented JSON with sorted keys")
+             .action(ArgAction::SetTrue))
+        .arg(Arg::new("stream")
+             .long("stream")
+             .help("Read the map one node at a time instead of loading it, for maps larger than memory. Works with list, query, aps, export, record, and tui")
+             .action(ArgAction::SetTrue))
+        .subcommand(Command::new("record")
+                    .about("Records a new scan to the map")
+                    .arg(Arg::new("name")
+                         .long("name")
+                         .value_name("NAME")
+                         .help("Name of the map if it is created, instead of asking"))
+                    .arg(Arg::new("notes")
+                         .long("notes")
+                         .value_name("NOTES")
+                         .help("Notes of the map if it is created, instead of asking"))
+				.arg(Arg::new("loop")
+					.short('l')
+					.help("Keep recording new nodes until q is entered, u undoes a node recorded at the wrong spot")
+					.action(ArgAction::SetTrue))
+                    .arg(Arg::new("alert_ssid")
+                         .long("alert-ssid")
+                         .value_name("SSID")
+                         .help("Warn right after a node is recorded if this network is weaker than --alert-below there, may be given more than once")
+                         .action(ArgAction::Append)
+                         .requires("alert_below")
+                         .conflicts_with_all(["continuous", "every", "grid"]))
+                    .arg(Arg::new("alert_below")
+                         .long("alert-below")
+                         .value_name("DBM")
+                         .help("Strength below which --alert-ssid networks raise a warning")
+                         .allow_hyphen_values(true)
+                         .requires("alert_ssid"))
+                    .arg(Arg::new("beep")
+                         .long("beep")
+                         .help("Ring the terminal bell with each alert")
+                         .requires("alert_ssid")
+                         .action(ArgAction::SetTrue))
+                    .arg(Arg::new("continuous")
+                         .long("continuous")
+                         .help("Scan every --interval while walking between waypoints, node positions are interpolated between them")
+                         .conflicts_with("loop")
+                         .action(ArgAction::SetTrue))
+                    .arg(Arg::new("every")
+                         .long("every")
+                         .value_name("DURATION")
+                         .help("Record a node at the same position on this schedule, e.g. 60s, until --count or --for is reached or the tool is stopped")
+                         .conflicts_with_all(["loop", "continuous"]))
+                    .arg(Arg::new("count")
+                         .long("count")
+                         .value_name("COUNT")
+                         .help("Number of nodes recorded with --every")
+                         .requires("every")
+                         .conflicts_with("for"))
+                    .arg(Arg::new("for")
+                         .long("for")
+                         .value_name("DURATION")
+                         .help("How long to keep recording with --every, e.g. 2h")
+                         .requires("every"))
+                    .arg(Arg::new("mqtt")
+                         .long("mqtt")
+                         .value_name("HOST[:PORT]")
+                         .help("Publish each node recorded with --every as JSON to this MQTT broker, IPv6 addresses with a port are written [ADDRESS]:PORT")
+                         .requires("every"))
+                    .arg(Arg::new("mqtt_tls")
+                         .long("mqtt-tls")
+                         .help("Connect to the MQTT broker with TLS, on port 8883 unless --mqtt gives one")
+                         .requires("mqtt")
+                         .action(ArgAction::SetTrue))
+                    .arg(Arg::new("mqtt_ca")
+                         .long("mqtt-ca")
+                         .value_name("PEM_FILE")
+                         .help("Verify the MQTT broker with these certificate authorities instead of the system's, implies --mqtt-tls")
+                         .requires("mqtt"))
+                    .arg(Arg::new("mqtt_topic")
+                         .long("mqtt-topic")
+                         .value_name("TOPIC")
+                         .help("Topic nodes are published to")
+                         .default_value("wifi-mapper/nodes"))
+                    .arg(Arg::new("mqtt_username")
+                         .long("mqtt-username")
+                         .value_name("NAME")
+                         .help("Username to log into the MQTT broker with")
+                         .requires("mqtt"))
+                    .arg(Arg::new("mqtt_password")
+                         .long("mqtt-password")
+                         .value_name("PASSWORD")
+                         .help("Password to log into the MQTT broker with")
+                         .env("WIFI_MAPPER_MQTT_PASSWORD")
+                         .hide_env_values(true))
+                    .arg(Arg::new("mqtt_client_id")
+                         .long("mqtt-client-id")
+                         .value_name("ID")
+                         .help("Identifier the MQTT broker knows this sensor by, must be unique per broker, defaults to wifi-mapper-PID"))
+                    .arg(Arg::new("mqtt_retain")
+                         .long("mqtt-retain")
+                         .help("Ask the MQTT broker to keep the latest node for subscribers which connect later")
+                         .requires("mqtt")
+                         .action(ArgAction::SetTrue))
+                    .arg(Arg::new("no_save")
+                         .long("no-save")
+                         .help("Only publish nodes to --mqtt, do not add them to the map file")
+                         .requires("mqtt")
+                         .action(ArgAction::SetTrue))
+                    .arg(Arg::new("grid")
+                         .long("grid")
+                         .value_name("MIN_X,MIN_Y,MAX_X,MAX_Y")
+                         .help("Guide the survey through the points of a grid covering this area, points which already have a node are skipped")
+                         .allow_hyphen_values(true)
+                         .conflicts_with_all(["loop", "continuous", "every", "gps"]))
+                    .arg(Arg::new("grid_spacing")
+                         .long("grid-spacing")
+                         .value_name("DISTANCE")
+                         .help("Distance between grid points, in the map's units")
+                         .default_value("1"))
+                    .arg(Arg::new("grid_z")
+                         .long("grid-z")
+                         .value_name("Z")
+                         .help("Height of grid points")
+                         .allow_hyphen_values(true)
+                         .default_value("0"))
+                    .arg(Arg::new("tag")
+                         .long("tag")
+                         .value_name("TAG")
+                         .help("Label added to recorded nodes, may be given more than once")
+                         .action(ArgAction::Append))
+                    .arg(Arg::new("floor")
+                         .long("floor")
+                         .value_name("FLOOR")
+                         .help("Floor new nodes are recorded on, if not provided and the map has floors you will be asked"))
+                    .arg(Arg::new("only_ssid")
+                         .long("only-ssid")
+                         .value_name("GLOB")
+                         .help("Only record networks whose SSID matches, may be given more than once")
+                         .action(ArgAction::Append))
+                    .arg(Arg::new("ignore_ssid")
+                         .long("ignore-ssid")
+                         .value_name("GLOB")
+                         .help("Do not record networks whose SSID matches, may be given more than once")
+                         .action(ArgAction::Append))
+                    .arg(Arg::new("only_bssid_prefix")
+                         .long("only-bssid-prefix")
+                         .value_name("PREFIX")
+                         .help("Only record networks whose BSSID starts with this, may be given more than once")
+                         .action(ArgAction::Append))
+                    .arg(Arg::new("save_filters")
+                         .long("save-filters")
+                         .help("Save the given SSID and BSSID filters in the map as the defaults for future recordings")
+                         .action(ArgAction::SetTrue))
+                    .arg(Arg::new("protocol")
+                         .long("protocol")
+                         .value_name("PROTOCOL")
+                         .help("Radios to scan: wifi, ble for Bluetooth LE advertisements, or both. Bluetooth needs a build with the ble feature")
+                         .default_value("wifi"))
+                    .arg(Arg::new("ble_time")
+                         .long("ble-time")
+                         .value_name("DURATION")
+                         .help("Time to listen for Bluetooth LE advertisements at each node")
+                         .default_value("5s"))
+                    .arg(Arg::new("remote")
+                         .long("remote")
+                         .value_name("URL")
+                         .help("Also send new nodes to the map of a serve --accept-nodes server, e.g. http://host:8080"))
+                    .arg(Arg::new("remote_token")
+                         .long("remote-token")
+                         .value_name("TOKEN")
+                         .help("Token of the --remote server")
+                         .requires("remote"))
+                    .arg(Arg::new("clients")
+                         .long("clients")
+                         .help("Also record the client devices heard sending probe requests at each node, needs a build with the capture feature")
+                         .action(ArgAction::SetTrue))
+                    .arg(Arg::new("samples")
+                         .long("samples")
+                         .value_name("COUNT")
+                         .help("Number of scans averaged into each node, repeated scans smooth out noisy signal strengths")
+                         .default_value("1"))
+                    .arg(Arg::new("interval")
+                         .long("interval")
+                         .value_name("DURATION")
+                         .help("Time to wait between the scans of a node, or between nodes with --continuous, e.g. 2s or 500ms")
+                         .default_value("2s"))
+                    .arg(Arg::new("session")
+                         .long("session")
+                         .value_name("ID")
+                         .help("Session to resume, defaults to the most recent session")
+                         .conflicts_with("new_session"))
+                    .arg(Arg::new("new_session")
+                         .long("new-session")
+                         .help("Start a new session, e.g. for a repeated survey of the same place")
+                         .action(ArgAction::SetTrue))
+                    .arg(Arg::new("operator")
+                         .long("operator")
+                         .value_name("NAME")
+                         .help("Person recording the survey, saved if a session is started"))
+                    .arg(Arg::new("device")
+                         .long("device")
+                         .value_name("DEVICE")
+                         .help("Device recording the survey, saved if a session is started"))
+                    .arg(Arg::new("gps")
+                         .long("gps")
+                         .help("Read coordinates from gpsd instead of prompting, requires a map in the wgs84 coordinate system")
+                         .action(ArgAction::SetTrue))
+                    .arg(Arg::new("gpsd")
+                         .long("gpsd")
+                         .value_name("HOST:PORT")
+                         .help("Address of gpsd")
+                         .default_value("localhost:2947"))
+                    .arg(Arg::new("gps_max_error")
+                         .long("gps-max-error")
+                         .value_name("METERS")
+                         .help("Largest acceptable horizontal error of a GPS fix, coordinates are prompted for if no better fix is found")
+                         .default_value("25"))
+                    .arg(Arg::new("gps_timeout")
+                         .long("gps-timeout")
+                         .value_name("SECONDS")
+                         .help("How long to wait for an acceptable GPS fix")
+                         .default_value("10")))
+        .subcommand(Command::new("scan")
+                    .about("Scans once and prints the networks in range, does not use a map file")
+                    .arg(Arg::new("json")
+                         .long("json")
+                         .help("Print the networks as JSON")
+                         .action(ArgAction::SetTrue)))
+        .subcommand(Command::new("tui")
+                    .about("Records scans in a full screen interface with a live table of the networks in range")
+                    .arg(Arg::new("floor")
+                         .long("floor")
+                         .value_name("FLOOR")
+                         .help("Floor new nodes start on, f switches floors"))
+                    .arg(Arg::new("tag")
+                         .long("tag")
+                         .value_name("TAG")
+                         .help("Tag added to new nodes, may be given more than once")
+                         .action(ArgAction::Append))
+                    .arg(Arg::new("new_session")
+                         .long("new-session")
+                         .help("Start a new session instead of resuming the most recent one")
+                         .action(ArgAction::SetTrue))
+                    .arg(Arg::new("interval")
+                         .long("interval")
+                         .value_name("DURATION")
+                         .help("Time between scans, e.g. 2s")
+                         .default_value("2s")))
+        .subcommand(Command::new("floor")
+                    .about("Manages the floors of a multi-story map")
+                    .subcommand(Command::new("add")
+                                .about("Adds a floor")
+                                .arg(Arg::new("name")
+                                     .help("Name of the floor")
+                                     .required(true))
+                                .arg(Arg::new("elevation")
+                                     .long("elevation")
+                                     .value_name("ELEVATION")
+                                     .help("Height of the floor above the map's origin, in the map's units")
+                                     .allow_hyphen_values(true)
+                                     .required(true)))
+                    .subcommand(Command::new("remove")
+                                .about("Removes a floor which no nodes are on")
+                                .arg(Arg::new("name")
+                                     .help("Name of the floor")
+                                     .required(true)))
+                    .subcommand(Command::new("list")
+                                .about("Lists floors")))
+        .subcommand(Command::new("list")
+                    .about("Lists the nodes of the map")
+                    .arg(Arg::new("floor")
+                         .long("floor")
+                         .value_name("FLOOR")
+                         .help("Only list nodes on this floor"))
+                    .arg(Arg::new("tag")
+                         .long("tag")
+                         .value_name("TAG")
+                         .help("Only list nodes with this tag, may be given more than once")
+                         .action(ArgAction::Append))
+                    .arg(Arg::new("units")
+                         .long("units")
+                         .value_name("UNITS")
+                         .help("Show positions in these units (meters, feet), if not provided the map's units are used"))
+                    .arg(Arg::new("since")
+                         .long("since")
+                         .value_name("TIME")
+                         .help("Only list nodes with networks scanned at or after this time, see --until"))
+                    .arg(Arg::new("until")
+                         .long("until")
+                         .value_name("TIME")
+                         .help("Only list nodes with networks scanned at or before this time: an RFC 3339 timestamp, a local date or time like 2024-05-01 or \"2024-05-01 14:30\" which includes the whole day or minute, a unix time in seconds, or a duration before now like 2h")))
+        .subcommand(Command::new("floor-plan")
+                    .about("Attaches a floor plan image which nodes are drawn on when rendering")
+                    .arg(Arg::new("image")
+                         .help("Floor plan image file")
+                         .required(true))
+                    .arg(Arg::new("scale")
+                         .long("scale")
+                         .value_name("PIXELS")
+                         .help("Pixels per map coordinate unit")
+                         .required(true))
+                    .arg(Arg::new("origin_x")
+                         .long("origin-x")
+                         .value_name("PIXELS")
+                         .help("Pixels from the left of the image to the map's origin")
+                         .allow_hyphen_values(true)
+                         .default_value("0"))
+                    .arg(Arg::new("origin_y")
+                         .long("origin-y")
+                         .value_name("PIXELS")
+                         .help("Pixels from the top of the image to the map's origin")
+                         .allow_hyphen_values(true)
+                         .default_value("0"))
+                    .arg(Arg::new("floor")
+                         .long("floor")
+                         .value_name("FLOOR")
+                         .help("Floor the plan shows, if not provided the plan is used for all floors without their own plan")))
+        .subcommand(Command::new("render")
+                    .about("Draws a heatmap of a network's signal strength and the nodes, on top of the floor plan if there is one")
+                    .arg(Arg::new("ssid")
+                         .long("ssid")
+                         .value_name("SSID")
+                         .help("Nodes are colored by the strongest signal of networks with this name")
+                         .required(true))
+                    .arg(Arg::new("floor")
+                         .long("floor")
+                         .value_name("FLOOR")
+                         .help("Only draw nodes on this floor"))
+                    .arg(Arg::new("output")
+                         .short('o')
+                         .long("output")
+                         .value_name("OUTPUT_FILE")
+                         .help("File to save the image")
+                         .required(true))
+                    .arg(Arg::new("format")
+                         .long("format")
+                         .value_name("FORMAT")
+                         .help("Image format: png or svg")
+                         .default_value("png"))
+                    .arg(Arg::new("no_heatmap")
+                         .long("no-heatmap")
+                         .help("Only draw the nodes")
+                         .action(ArgAction::SetTrue))
+                    .args(heatmap_args())
+                    .mut_arg("spacing", |arg| arg.help("Distance between heatmap points in the map's units, meters for wgs84 maps, defaults to a few pixels"))
+                    .arg(Arg::new("gaps")
+                         .long("gaps")
+                         .value_name("DBM")
+                         .help("Outline and number the regions of the heatmap weaker than this")
+                         .allow_hyphen_values(true)
+                         .conflicts_with("no_heatmap")))
+        .subcommand(Command::new("view")
+                    .about("Draws a heatmap of a network's signal strength in the terminal")
+                    .arg(Arg::new("ssid")
+                         .long("ssid")
+                         .value_name("SSID")
+                         .help("Draw the strongest signal of networks with this name")
+                         .required(true))
+                    .arg(Arg::new("floor")
+                         .long("floor")
+                         .value_name("FLOOR")
+                         .help("Only use nodes on this floor"))
+                    .arg(Arg::new("width")
+                         .long("width")
+                         .value_name("COLUMNS")
+                         .help("Width of the heatmap in characters, defaults to the width of the terminal"))
+                    .arg(Arg::new("height")
+                         .long("height")
+                         .value_name("LINES")
+                         .help("Height of the heatmap and legend in lines, defaults to the height of the terminal"))
+                    .arg(Arg::new("no_color")
+                         .long("no-color")
+                         .help("Draw strengths with characters instead of colors, the default if stdout is not a terminal")
+                         .action(ArgAction::SetTrue))
+                    .args(heatmap_args())
+                    .mut_arg("spacing", |arg| arg.help("Distance between characters in the map's units, meters for wgs84 maps, defaults to fitting the view")))
+        .subcommand(Command::new("report")
+                    .about("Writes a single HTML file with the summary, statistics, signal tables, and heatmaps of the survey")
+                    .arg(Arg::new("output")
+                         .short('o')
+                         .long("output")
+                         .value_name("OUTPUT_FILE")
+                         .help("HTML file to save the report")
+                         .required(true))
+                    .arg(Arg::new("heatmaps")
+                         .long("heatmaps")
+                         .value_name("COUNT")
+                         .help("Number of SSIDs, best covered first, to draw heatmaps of")
+                         .default_value("3"))
+                    .args(heatmap_args())
+                    .mut_arg("spacing", |arg| arg.help("Distance between heatmap points in the map's units, meters for wgs84 maps, defaults to a few pixels"))
+                    .arg(Arg::new("gaps")
+                         .long("gaps")
+                         .value_name("DBM")
+                         .help("Outline the regions of each heatmap weaker than this and list them")
+                         .allow_hyphen_values(true)))
+        .subcommand(Command::new("predict")
+                    .about("Estimates the strength of each network at a position which was not surveyed")
+                    .arg(Arg::new("position")
+                         .value_name("X,Y[,Z]")
+                         .help("Position in the map's coordinates, e.g. 12,7,1")
+                         .allow_hyphen_values(true)
+                         .required(true))
+                    .arg(Arg::new("floor")
+                         .long("floor")
+                         .value_name("NAME")
+                         .help("Floor the position is on, required if the map has floors"))
+                    .arg(Arg::new("ssid")
+                         .long("ssid")
+                         .value_name("SSID")
+                         .help("Only predict this network"))
+                    .arg(Arg::new("usable")
+                         .long("usable")
+                         .value_name("DBM")
+                         .help("Weakest strength which counts as usable")
+                         .allow_hyphen_values(true)
+                         .default_value("-70"))
+                    .arg(Arg::new("power")
+                         .long("power")
+                         .value_name("POWER")
+                         .help("Exponent of the inverse distance weights used to interpolate between nodes")
+                         .default_value("2"))
+                    .arg(Arg::new("radius")
+                         .long("radius")
+                         .value_name("DISTANCE")
+                         .help("Do not interpolate networks whose nearest node is farther than this"))
+                    .args(path_loss_args())
+                    .arg(Arg::new("json")
+                         .long("json")
+                         .help("Print the predictions as JSON")
+                         .action(ArgAction::SetTrue)))
+        .subcommand(Command::new("analyze")
+                    .about("Analyzes the coverage of the map")
+                    .subcommand_required(true)
+                    .arg_required_else_help(true)
+                    .subcommand(Command::new("gaps")
+                                .about("Finds regions where a network is weaker than a threshold")
+                                .arg(Arg::new("ssid")
+                                     .long("ssid")
+                                     .value_name("SSID")
+                                     .help("Network whose coverage is analyzed")
+                                     .required(true))
+                                .arg(Arg::new("threshold")
+                                     .long("threshold")
+                                     .value_name("DBM")
+                                     .help("Strength below which a region is a gap")
+                                     .allow_hyphen_values(true)
+                                     .default_value("-70"))
+                                .arg(Arg::new("floor")
+                                     .long("floor")
+                                     .value_name("FLOOR")
+                                     .help("Only use nodes on this floor"))
+                                .args(heatmap_args())
+                                .mut_arg("spacing", |arg| arg.help("Distance between grid points in the map's units, meters for wgs84 maps").default_value("1"))
+                                .mut_arg("unseen", |arg| arg.help("Strength used for nodes which did not see the network").default_value("-100"))
+                                .arg(Arg::new("min_area")
+                                     .long("min-area")
+                                     .value_name("AREA")
+                                     .help("Leave out gaps smaller than this, in square map units")
+                                     .default_value("0"))
+                                .arg(Arg::new("json")
+                                     .long("json")
+                                     .help("Print the gaps as JSON")
+                                     .action(ArgAction::SetTrue)))
+                    .subcommand(Command::new("placement")
+                                .about("Suggests positions for new access points which cover as much of the surveyed area as possible")
+                                .arg(Arg::new("ssid")
+                                     .long("ssid")
+                                     .value_name("SSID")
+                                     .help("Network whose coverage the new access points extend")
+                                     .required(true))
+                                .arg(Arg::new("threshold")
+                                     .long("threshold")
+                                     .value_name("DBM")
+                                     .help("Weakest strength which counts as covered")
+                                     .allow_hyphen_values(true)
+                                     .default_value("-70"))
+                                .arg(Arg::new("count")
+                                     .long("count")
+                                     .value_name("N")
+                                     .help("Number of access points to place")
+                                     .default_value("1"))
+                                .arg(Arg::new("floor")
+                                     .long("floor")
+                                     .value_name("FLOOR")
+                                     .help("Only use nodes on this floor"))
+                                .args(heatmap_args())
+                                .mut_arg("spacing", |arg| arg.help("Distance between candidate positions in the map's units, meters for wgs84 maps").default_value("1"))
+                                .mut_arg("unseen", |arg| arg.help("Strength used for nodes which did not see the network").default_value("-100"))
+                                .args(path_loss_args())
+                                .arg(Arg::new("json")
+                                     .long("json")
+                                     .help("Print the suggestions as JSON")
+                                     .action(ArgAction::SetTrue)))
+                    .subcommand(Command::new("channels")
+                                .about("Counts the BSSIDs which share or overlap each channel in each region of the map, the most congested first")
+                                .arg(Arg::new("region")
+                                     .long("region")
+                                     .value_name("LENGTH")
+                                     .help("Width and height of the square regions the map is divided into, in the map's units, meters for wgs84 maps")
+                                     .default_value("10"))
+                                .arg(Arg::new("floor")
+                                     .long("floor")
+                                     .value_name("FLOOR")
+                                     .help("Only use nodes on this floor"))
+                                .arg(Arg::new("min_strength")
+                                     .long("min-strength")
+                                     .value_name("DBM")
+                                     .help("Ignore networks weaker than this, they are too faint to congest a channel")
+                                     .allow_hyphen_values(true)
+                                     .default_value("-85"))
+                                .arg(Arg::new("json")
+                                     .long("json")
+                                     .help("Print the channels as JSON")
+                                     .action(ArgAction::SetTrue)))
+                    .subcommand(Command::new("recommend")
+                                .about("Recommends the least contended channel for each access point of your networks")
+                                .arg(Arg::new("ssid")
+                                     .long("ssid")
+                                     .value_name("SSID")
+                                     .help("Network whose access points channels are recommended for, may be given more than once")
+                                     .action(ArgAction::Append)
+                                     .required(true))
+                                .arg(Arg::new("domain")
+                                     .long("domain")
+                                     .value_name("DOMAIN")
+                                     .help("Regulatory domain which decides the allowed channels: fcc or etsi")
+                                     .default_value("fcc"))
+                                .arg(Arg::new("no_dfs")
+                                     .long("no-dfs")
+                                     .help("Do not recommend 5 GHz channels which require radar detection")
+                                     .action(ArgAction::SetTrue))
+                                .arg(Arg::new("radius")
+                                     .long("radius")
+                                     .value_name("LENGTH")
+                                     .help("Networks heard by nodes within this distance of an access point are its neighbors")
+                                     .default_value("10"))
+                                .arg(Arg::new("floor")
+                                     .long("floor")
+                                     .value_name("FLOOR")
+                                     .help("Only recommend channels for access points on this floor"))
+                                .arg(Arg::new("min_strength")
+                                     .long("min-strength")
+                                     .value_name("DBM")
+                                     .help("Ignore neighbors weaker than this, they are too faint to contend")
+                                     .allow_hyphen_values(true)
+                                     .default_value("-85"))
+                                .args(path_loss_args())
+                                .mut_arg("reference_power", |arg| arg.help("Strength one meter from an access point, used if the map has no saved access point locations, defaults to the map's path loss model"))
+                                .arg(Arg::new("json")
+                                     .long("json")
+                                     .help("Print the recommendations as JSON")
+                                     .action(ArgAction::SetTrue)))
+                    .subcommand(Command::new("roaming")
+                                .about("Shows which access point of a network is strongest at each node and where clients may roam poorly")
+                                .arg(Arg::new("ssid")
+                                     .long("ssid")
+                                     .value_name("SSID")
+                                     .help("Network whose access points are compared")
+                                     .required(true))
+                                .arg(Arg::new("floor")
+                                     .long("floor")
+                                     .value_name("FLOOR")
+                                     .help("Only use nodes on this floor"))
+                                .arg(Arg::new("margin")
+                                     .long("margin")
+                                     .value_name("DB")
+                                     .help("Flag nodes where the two strongest access points are closer than this")
+                                     .default_value("5"))
+                                .arg(Arg::new("usable")
+                                     .long("usable")
+                                     .value_name("DBM")
+                                     .help("Weakest strength a client can use")
+                                     .allow_hyphen_values(true)
+                                     .default_value("-70"))
+                                .arg(Arg::new("neighbor")
+                                     .long("neighbor")
+                                     .value_name("LENGTH")
+                                     .help("Compare the strongest access points of nodes closer than this")
+                                     .default_value("5"))
+                                .arg(Arg::new("json")
+                                     .long("json")
+                                     .help("Print the analysis as JSON")
+                                     .action(ArgAction::SetTrue)))
+                    .subcommand(Command::new("trend")
+                                .about("Follows the strength of each access point across repeated surveys of the same checkpoints")
+                                .arg(Arg::new("with")
+                                     .long("with")
+                                     .value_name("MAP_FILE")
+                                     .help("Map file of another survey of the same place, may be given more than once")
+                                     .action(ArgAction::Append))
+                                .arg(Arg::new("tolerance")
+                                     .long("tolerance")
+                                     .value_name("DISTANCE")
+                                     .help("Largest distance between nodes of the same checkpoint, in the map's units")
+                                     .default_value("0.5"))
+                                .arg(Arg::new("threshold")
+                                     .long("threshold")
+                                     .value_name("DB")
+                                     .help("Flag access points which weakened by at least this much")
+                                     .default_value("6"))
+                                .arg(Arg::new("ssid")
+                                     .long("ssid")
+                                     .value_name("SSID")
+                                     .help("Only follow access points of this network"))
+                                .arg(Arg::new("json")
+                                     .long("json")
+                                     .help("Print the trends as JSON")
+                                     .action(ArgAction::SetTrue))))
+        .subcommand(Command::new("export")
+                    .about("Writes the measurements of the map in a format other tools read, e.g. InfluxDB line protocol or an Ekahau project")
+                    .arg(Arg::new("format")
+                         .long("format")
+                         .value_name("FORMAT")
+                         .help("Format to write: influx, esx (an Ekahau project), netspot (a NetSpot CSV table), or ply or xyz (point clouds)")
+                         .default_value("influx"))
+                    .arg(Arg::new("output")
+                         .short('o')
+                         .long("output")
+                         .value_name("OUTPUT_FILE")
+                         .help("File to write, printed if not given. Required for esx")
+                         .conflicts_with("url"))
+                    .arg(Arg::new("floor")
+                         .long("floor")
+                         .value_name("NAME")
+                         .help("Only export nodes on this floor"))
+                    .arg(Arg::new("tag")
+                         .long("tag")
+                         .value_name("TAG")
+                         .help("Only export nodes with this tag, can be repeated to require several tags")
+                         .action(ArgAction::Append))
+                    .arg(Arg::new("measurement")
+                         .long("measurement")
+                         .value_name("NAME")
+                         .help("InfluxDB measurement points are written to")
+                         .default_value("wifi"))
+                    .arg(Arg::new("ssid")
+                         .long("ssid")
+                         .value_name("GLOB")
+                         .help("Color ply and xyz points by the strongest network whose SSID matches, instead of the strongest network"))
+                    .arg(Arg::new("since")
+                         .long("since")
+                         .value_name("TIME")
+                         .help("Only export networks scanned at or after this time, see --until"))
+                    .arg(Arg::new("until")
+                         .long("until")
+                         .value_name("TIME")
+                         .help("Only export networks scanned at or before this time: an RFC 3339 timestamp, a local date or time like 2024-05-01 or \"2024-05-01 14:30\" which includes the whole day or minute, a unix time in seconds, or a duration before now like 2h"))
+                    .arg(Arg::new("url")
+                         .long("url")
+                         .value_name("URL")
+                         .help("Send points to this InfluxDB write endpoint instead, e.g. http://host:8086/api/v2/write?org=ORG&bucket=BUCKET"))
+                    .arg(Arg::new("token")
+                         .long("token")
+                         .value_name("TOKEN")
+                         .help("InfluxDB API token used with --url")
+                         .env("INFLUX_TOKEN")
+                         .hide_env_values(true))
+                    .arg(Arg::new("batch_size")
+                         .long("batch-size")
+                         .value_name("LINES")
+                         .help("Number of points sent per request with --url")
+                         .default_value("5000")))
+        .subcommand(Command::new("serve")
+                    .about("Hosts a web page which shows the map and follows the map file as nodes are recorded")
+                    .arg(Arg::new("port")
+                         .long("port")
+                         .value_name("PORT")
+                         .help("TCP port to listen on")
+                         .default_value("8080"))
+                    .arg(Arg::new("bind")
+                         .long("bind")
+                         .value_name("ADDRESS")
+                         .help("Address to listen on, 0.0.0.0 also accepts connections from other computers")
+                         .default_value("127.0.0.1"))
+                    .arg(Arg::new("accept_nodes")
+                         .long("accept-nodes")
+                         .help("Add nodes sent by record --remote to the map, needs --token")
+                         .requires("token")
+                         .action(ArgAction::SetTrue))
+                    .arg(Arg::new("token")
+                         .long("token")
+                         .value_name("TOKEN")
+                         .help("Secret clients must send, the viewer is then opened with ?token=TOKEN")))
+        .subcommand(Command::new("exporter")
+                    .about("Scans on an interval and serves the signal strength of each BSSID as Prometheus metrics, does not use a map file")
+                    .arg(Arg::new("listen")
+                         .long("listen")
+                         .value_name("HOST:PORT")
+                         .help("Address to serve metrics on, 0.0.0.0:9300 exposes them to the whole network")
+                         .default_value("127.0.0.1:9300"))
+                    .arg(Arg::new("interval")
+                         .long("interval")
+                         .value_name("DURATION")
+                         .help("Time between scans, e.g. 30s")
+                         .default_value("30s")))
+        .subcommand(Command::new("anonymize")
+                    .about("Writes a copy of the map with BSSIDs replaced by salted hashes, so it can be shared")
+                    .arg(Arg::new("salt")
+                         .long("salt")
+                         .value_name("SALT")
+                         .help("Secret mixed into hashes, the same salt always produces the same replacement for a BSSID")
+                         .required(true))
+                    .arg(Arg::new("strip_ssids")
+                         .long("strip-ssids")
+                         .help("Also replace SSIDs with salted hashes")
+                         .action(ArgAction::SetTrue))
+                    .arg(Arg::new("output")
+                         .short('o')
+                         .long("output")
+                         .value_name("OUTPUT_FILE")
+                         .help("File to save the anonymized map")
+                         .required(true)))
+        .subcommand(Command::new("transform")
+                    .about("Writes a copy of the map with positions scaled, rotated about the z axis, then translated")
+                    .arg(Arg::new("translate_x")
+                         .long("translate-x")
+                         .value_name("DISTANCE")
+                         .help("Distance to move positions along the x axis")
+                         .allow_hyphen_values(true)
+                         .default_value("0"))
+                    .arg(Arg::new("translate_y")
+                         .long("translate-y")
+                         .value_name("DISTANCE")
+                         .help("Distance to move positions along the y axis")
+                         .allow_hyphen_values(true)
+                         .default_value("0"))
+                    .arg(Arg::new("translate_z")
+                         .long("translate-z")
+                         .value_name("DISTANCE")
+                         .help("Distance to move positions and floor elevations along the z axis")
+                         .allow_hyphen_values(true)
+                         .default_value("0"))
+                    .arg(Arg::new("rotate")
+                         .long("rotate")
+                         .value_name("DEGREES")
+                         .help("Counterclockwise rotation about the z axis")
+                         .allow_hyphen_values(true)
+                         .default_value("0"))
+                    .arg(Arg::new("scale")
+                         .long("scale")
+                         .value_name("FACTOR")
+                         .help("Factor all lengths are multiplied by")
+                         .default_value("1"))
+                    .arg(Arg::new("output")
+                         .short('o')
+                         .long("output")
+                         .value_name("OUTPUT_FILE")
+                         .help("File to save the transformed map")
+                         .required(true)))
+        .subcommand(Command::new("dedupe")
+                    .about("Merges nodes on the same floor at (nearly) the same position, averaging signal strengths per BSSID")
+                    .arg(Arg::new("tolerance")
+                         .long("tolerance")
+                         .value_name("DISTANCE")
+                         .help("Largest distance between nodes which are merged, in the map's units")
+                         .default_value("0.1")))
+        .subcommand(Command::new("merge")
+                    .about("Combines map files into a new map, e.g. surveys of different wings of a building")
+                    .arg(Arg::new("inputs")
+                         .help("Map files to combine")
+                         .num_args(1..)
+                         .required(true))
+                    .arg(Arg::new("offset")
+                         .long("offset")
+                         .value_name("X,Y,Z")
+                         .help("Distance to move the positions of an input, in the units of the first input. Given once per input in the order of the inputs")
+                         .action(ArgAction::Append)
+                         .allow_hyphen_values(true))
+                    .arg(Arg::new("name")
+                         .long("name")
+                         .value_name("NAME")
+                         .help("Name of the combined map, required if the inputs have different names"))
+                    .arg(Arg::new("notes")
+                         .long("notes")
+                         .value_name("NOTES")
+                         .help("Notes of the combined map, if not provided the notes of the inputs are joined"))
+                    .arg(Arg::new("output")
+                         .short('o')
+                         .long("output")
+                         .value_name("OUTPUT_FILE")
+                         .help("File to save the combined map")
+                         .required(true)))
+        .subcommand(Command::new("diff")
+                    .about("Reports networks which appeared, disappeared, or changed strength between two surveys")
+                    .arg(Arg::new("before")
+                         .help("Map file of the earlier survey")
+                         .required(true))
+                    .arg(Arg::new("after")
+                         .help("Map file of the later survey")
+                         .required(true))
+                    .arg(Arg::new("tolerance")
+                         .long("tolerance")
+                         .value_name("DISTANCE")
+                         .help("Largest distance between nodes which are compared, in the units of the earlier map")
+                         .default_value("0.5"))
+                    .arg(Arg::new("threshold")
+                         .long("threshold")
+                         .value_name("DB")
+                         .help("Smallest change in signal strength which is reported")
+                         .default_value("6"))
+                    .arg(Arg::new("before_session")
+                         .long("before-session")
+                         .value_name("ID")
+                         .help("Only compare nodes of this session of the earlier map"))
+                    .arg(Arg::new("after_session")
+                         .long("after-session")
+                         .value_name("ID")
+                         .help("Only compare nodes of this session of the later map"))
+                    .arg(Arg::new("json")
+                         .long("json")
+                         .help("Print the differences as JSON")
+                         .action(ArgAction::SetTrue)))
+        .subcommand(Command::new("stats")
+                    .about("Prints a summary of the networks seen per SSID and per channel")
+                    .arg(Arg::new("floor")
+                         .long("floor")
+                         .value_name("FLOOR")
+                         .help("Only include nodes on this floor"))
+                    .arg(Arg::new("session")
+                         .long("session")
+                         .value_name("ID")
+                         .help("Only include nodes recorded in this session")))
+        .subcommand(Command::new("session")
+                    .about("Lists the survey sessions nodes were recorded in"))
+        .subcommand(Command::new("completions")
+                    .about("Prints a script which completes sub-commands and flags in a shell, e.g. wifi-mapper completions bash > /etc/bash_completion.d/wifi-mapper")
+                    .arg(Arg::new("shell")
+                         .help("Shell to complete in")
+                         .required(true)
+                         .value_parser(clap::value_parser!(Shell))))
+        .subcommand(Command::new("manpage")
+                    .about("Prints the manual page, e.g. wifi-mapper manpage > /usr/local/share/man/man1/wifi-mapper.1")
+                    .arg(Arg::new("dir")
+                         .long("dir")
+                         .value_name("DIR")
+                         .help("Write the main page and a page for every sub-command into DIR instead, e.g. /usr/local/share/man/man1")))
+        .subcommand(Command::new("query")
+                    .about("Lists the networks measured at nodes which match all filters")
+                    .arg(Arg::new("ssid")
+                         .long("ssid")
+                         .value_name("GLOB")
+                         .help("SSID pattern, * matches any characters and ? one character"))
+                    .arg(Arg::new("bssid")
+                         .long("bssid")
+                         .value_name("PREFIX")
+                         .help("Start of the BSSID, e.g. 84:d4:7e"))
+                    .arg(Arg::new("bbox")
+                         .long("bbox")
+                         .value_name("MIN_X,MIN_Y,MAX_X,MAX_Y")
+                         .help("Only include nodes inside this area")
+                         .allow_hyphen_values(true))
+                    .arg(Arg::new("floor")
+                         .long("floor")
+                         .value_name("FLOOR")
+                         .help("Only include nodes on this floor"))
+                    .arg(Arg::new("tag")
+                         .long("tag")
+                         .value_name("TAG")
+                         .help("Only include nodes with this tag, may be given more than once")
+                         .action(ArgAction::Append))
+                    .arg(Arg::new("min_strength")
+                         .long("min-strength")
+                         .value_name("DBM")
+                         .help("Only include networks at least this strong")
+                         .allow_hyphen_values(true))
+                    .arg(Arg::new("max_strength")
+                         .long("max-strength")
+                         .value_name("DBM")
+                         .help("Only include networks at most this strong")
+                         .allow_hyphen_values(true))
+                    .arg(Arg::new("since")
+                         .long("since")
+                         .visible_alias("after")
+                         .value_name("TIME")
+                         .help("Only include networks scanned at or after this time, see --until"))
+                    .arg(Arg::new("until")
+                         .long("until")
+                         .visible_alias("before")
+                         .value_name("TIME")
+                         .help("Only include networks scanned at or before this time: an RFC 3339 timestamp, a local date or time like 2024-05-01 or \"2024-05-01 14:30\" which includes the whole day or minute, a unix time in seconds, or a duration before now like 2h"))
+                    .arg(Arg::new("json")
+                         .long("json")
+                         .help("Print the matches as JSON")
+                         .action(ArgAction::SetTrue)))
+        .subcommand(Command::new("aps")
+                    .about("Summarizes each access point: the nodes which heard it, where it was strongest and weakest, and the channels it used")
+                    .arg(Arg::new("ssid")
+                         .long("ssid")
+                         .value_name("GLOB")
+                         .help("SSID pattern, * matches any characters and ? one character"))
+                    .arg(Arg::new("bssid")
+                         .long("bssid")
+                         .value_name("PREFIX")
+                         .help("Start of the BSSID, e.g. 84:d4:7e"))
+                    .arg(Arg::new("floor")
+                         .long("floor")
+                         .value_name("FLOOR")
+                         .help("Only include nodes on this floor"))
+                    .arg(Arg::new("tag")
+                         .long("tag")
+                         .value_name("TAG")
+                         .help("Only include nodes with this tag, may be given more than once")
+                         .action(ArgAction::Append))
+                    .arg(Arg::new("json")
+                         .long("json")
+                         .help("Print the access points as JSON")
+                         .action(ArgAction::SetTrue)))
+        .subcommand(Command::new("locate")
+                    .about("Estimates the position of each access point from the signal strengths measured at nodes")
+                    .args(path_loss_args())
+                    .arg(Arg::new("min_nodes")
+                         .long("min-nodes")
+                         .value_name("COUNT")
+                         .help("Only locate access points seen by at least this many nodes")
+                         .default_value("3"))
+                    .arg(Arg::new("floor")
+                         .long("floor")
+                         .value_name("FLOOR")
+                         .help("Only use nodes on this floor"))
+                    .arg(Arg::new("save")
+                         .long("save")
+                         .help("Save the estimates in the map, so render draws them")
+                         .action(ArgAction::SetTrue))
+                    .arg(Arg::new("json")
+                         .long("json")
+                         .help("Print the estimates as JSON")
+                         .action(ArgAction::SetTrue)))
+        .subcommand(Command::new("interpolate")
+                    .about("Estimates the signal strength of a network on a regular grid over the surveyed area")
+                    .arg(Arg::new("ssid")
+                         .long("ssid")
+                         .value_name("SSID")
+                         .help("Interpolate the strongest signal of networks with this name")
+                         .required_unless_present("bssid")
+                         .conflicts_with("bssid"))
+                    .arg(Arg::new("bssid")
+                         .long("bssid")
+                         .value_name("BSSID")
+                         .help("Interpolate the signal of this access point"))
+                    .arg(Arg::new("floor")
+                         .long("floor")
+                         .value_name("FLOOR")
+                         .help("Only use nodes on this floor"))
+                    .args(heatmap_args())
+                    .mut_arg("spacing", |arg| arg.help("Distance between grid points in the map's units, meters for wgs84 maps").default_value("1"))
+                    .arg(Arg::new("format")
+                         .long("format")
+                         .value_name("FORMAT")
+                         .help("Format of the grid: json or csv")
+                         .default_value("json"))
+                    .arg(Arg::new("output")
+                         .short('o')
+                         .long("output")
+                         .value_name("OUTPUT_FILE")
+                         .help("File to save the grid, printed if not given")))
+        .subcommand(Command::new("prune")
+                    .about("Writes a copy of the map without weak networks, unwanted SSIDs, or old nodes")
+                    .arg(Arg::new("min_strength")
+                         .long("min-strength")
+                         .value_name("DBM")
+                         .help("Remove networks weaker than this")
+                         .allow_hyphen_values(true))
+                    .arg(Arg::new("ssid")
+                         .long("ssid")
+                         .value_name("GLOB")
+                         .help("Remove networks whose SSID matches this pattern, may be given more than once")
+                         .action(ArgAction::Append))
+                    .arg(Arg::new("before")
+                         .long("before")
+                         .value_name("TIME")
+                         .help("Remove nodes measured before this time: an RFC 3339 timestamp, a local date like 2024-05-01 which starts at midnight, a unix time in seconds, or a duration before now like 720h"))
+                    .arg(Arg::new("output")
+                         .short('o')
+                         .long("output")
+                         .value_name("OUTPUT_FILE")
+                         .help("File to save the pruned map")
+                         .required(true)))
+        .subcommand(Command::new("validate")
+                    .about("Checks the map file for schema errors, duplicate nodes, malformed MAC addresses, out of order timestamps, and impossible strengths")
+                    .arg(Arg::new("fix")
+                         .long("fix")
+                         .help("Repair the problems which can be repaired safely and save the map")
+                         .action(ArgAction::SetTrue))
+                    .arg(Arg::new("json")
+                         .long("json")
+                         .help("Print the problems as JSON")
+                         .action(ArgAction::SetTrue)))
+        .subcommand(Command::new("meta")
+                    .about("Shows the properties of the map, or changes its name, notes, and path loss model")
+                    .arg(Arg::new("name")
+                         .long("name")
+                         .value_name("NAME")
+                         .help("New name of the map"))
+                    .arg(Arg::new("notes")
+                         .long("notes")
+                         .value_name("NOTES")
+                         .help("New notes of the map, empty to remove them"))
+                    .arg(Arg::new("reference_power")
+                         .long("reference-power")
+                         .value_name("DBM")
+                         .help("Strength one meter from an access point in the building")
+                         .allow_hyphen_values(true))
+                    .arg(Arg::new("path_loss_exponent")
+                         .long("path-loss-exponent")
+                         .value_name("EXPONENT")
+                         .help("How quickly signals weaken with distance in the building, 2 in free space and 3 to 4 indoors"))
+                    .arg(Arg::new("wall_attenuation")
+                         .long("wall-attenuation")
+                         .value_name("DB")
+                         .help("Strength lost through each wall, e.g. 3 for drywall and 10 or more for concrete"))
+                    .arg(Arg::new("wall_spacing")
+                         .long("wall-spacing")
+                         .value_name("METERS")
+                         .help("Average distance between walls"))
+                    .arg(Arg::new("no_walls")
+                         .long("no-walls")
+                         .help("Model the building as an open space without walls")
+                         .conflicts_with_all(["wall_attenuation", "wall_spacing"])
+                         .action(ArgAction::SetTrue)))
+        .subcommand(Command::new("annotate")
+                    .about("Attaches notes to access points, e.g. where they are mounted")
+                    .subcommand(Command::new("set")
+                                .about("Sets the note of an access point")
+                                .arg(Arg::new("bssid")
+                                     .help("Hardware address of the access point")
+                                     .required(true))
+                                .arg(Arg::new("note")
+                                     .help("Note to attach")
+                                     .required(true)))
+                    .subcommand(Command::new("remove")
+                                .about("Removes the note of an access point")
+                                .arg(Arg::new("bssid")
+                                     .help("Hardware address of the access point")
+                                     .required(true)))
+                    .subcommand(Command::new("list")
+                                .about("Lists notes")))
+        .subcommand(Command::new("undo")
+                    .about("Removes the most recently recorded node"))
+        .subcommand(Command::new("node")
+                    .about("Changes individual nodes, see the list sub-command for their indexes")
+                    .subcommand_required(true)
+                    .arg_required_else_help(true)
+                    .subcommand(Command::new("delete")
+                                .about("Removes a node")
+                                .arg(Arg::new("index")
+                                     .help("Index of the node")
+                                     .required(true))
+                                .arg(Arg::new("yes")
+                                     .short('y')
+                                     .long("yes")
+                                     .help("Do not ask for confirmation")
+                                     .action(ArgAction::SetTrue)))
+                    .subcommand(Command::new("edit")
+                                .about("Changes the position, floor, notes, or tags of a node")
+                                .arg(Arg::new("index")
+                                     .help("Index of the node")
+                                     .required(true))
+                                .arg(Arg::new("x")
+                                     .long("x")
+                                     .value_name("X")
+                                     .help("New x coordinate")
+                                     .allow_hyphen_values(true))
+                                .arg(Arg::new("y")
+                                     .long("y")
+                                     .value_name("Y")
+                                     .help("New y coordinate")
+                                     .allow_hyphen_values(true))
+                                .arg(Arg::new("z")
+                                     .long("z")
+                                     .value_name("Z")
+                                     .help("New z coordinate")
+                                     .allow_hyphen_values(true))
+                                .arg(Arg::new("floor")
+                                     .long("floor")
+                                     .value_name("FLOOR")
+                                     .help("New floor"))
+                                .arg(Arg::new("notes")
+                                     .long("notes")
+                                     .value_name("NOTES")
+                                     .help("New notes"))
+                                .arg(Arg::new("tag")
+                                     .long("tag")
+                                     .value_name("TAG")
+                                     .help("Tag to add, may be given more than once")
+                                     .action(ArgAction::Append))
+                                .arg(Arg::new("untag")
+                                     .long("untag")
+                                     .value_name("TAG")
+                                     .help("Tag to remove, may be given more than once")
+                                     .action(ArgAction::Append))
+                                .group(ArgGroup::new("changes")
+                                       .args(["x", "y", "z", "floor", "notes", "tag", "untag"])
+                                       .multiple(true)
+                                       .required(true))));
+
+    // Kept to generate completions and the manual page from
+    let (arg_matches, config_path) = match config::matches(app.clone()) {
+        Ok(m) => m,
+        Err(e) => die(&format!("failed to load config file: {}", e)),
+    };
+
+    let level = match (arg_matches.get_flag("quiet"), arg_matches.get_count("verbose")) {
+        (true, _) => logging::Level::Error,
+        (false, 0) => logging::Level::Warning,
+        (false, 1) => logging::Level::Info,
+        (false, _) => logging::Level::Debug,
+    };
+    if let Err(e) = logging::init(level, arg_matches.get_one::<String>("log_file").map(String::as_str).map(Path::new)) {
+        die(&format!("failed to open log file: {}", e));
+    }
+    logging::debug(&format!("Started with {}", std::env::args().collect::<Vec<String>>().join(" ")));
+    if let Some(p) = config_path {
+        logging::info(&format!("Read default flags from \"{}\"", p.display()));
+    }
+
+    let output: OutputFormat = parse_arg(&arg_matches, "output_format");
+
+    let mut write_opts = WriteOptions{
+        backups: parse_arg(&arg_matches, "backups"),
+        pretty: arg_matches.get_flag("pretty"),
+        remote: None,
+    };
+
+    let mut scan_opts = ScanOptions{
+        vendors: match arg_matches.get_one::<String>("oui_file").map(String::as_str) {
+            Some(p) => match oui::OuiTable::load(Path::new(p)) {
+                Ok(t) => t,
+                Err(e) => die(&format!("failed to load OUI file \"{}\": {}", p, e)),
+            },
+            None => oui::OuiTable::new(),
+        },
+        retries: parse_arg(&arg_matches, "scan_retries"),
+        retry_delay: parse_arg::<DurationArg>(&arg_matches, "scan_retry_delay").0,
+        dwell: parse_arg::<DurationArg>(&arg_matches, "dwell").0,
+        protocol: Protocol::Wifi,
+        ble_duration: Duration::ZERO,
+        progress: !arg_matches.get_flag("quiet") && stdout().is_terminal(),
+        filters: ScanFilters::default(),
+        interfaces: string_values(&arg_matches, "interface"),
+        // wifiscanner cannot choose an interface, so iw is used if one is given
+        backend: match (parse_optional_arg(&arg_matches, "backend"), arg_matches.contains_id("interface") || arg_matches.get_flag("all_interfaces")) {
+            (Some(b), _) => b,
+            (None, _) if mock::fixture_path().is_some() => scanner::Backend::Mock,
+            (None, true) => scanner::Backend::Iw,
+            (None, false) => scanner::Backend::Wifiscanner,
+        },
+    };
+
+    // Determine sub-command to run
+    let subcmd = match arg_matches.subcommand() {
+	   Some(("record", c)) => SubCmd::Record(c),
+	   Some(("tui", c)) => SubCmd::Tui(c),
+	   Some(("scan", c)) => SubCmd::Scan(c),
+	   Some(("validate", c)) => SubCmd::Validate(c),
+	   Some(("anonymize", c)) => SubCmd::Anonymize(c),
+	   Some(("floor", c)) => SubCmd::Floor(c),
+	   Some(("list", c)) => SubCmd::List(c),
+	   Some(("floor-plan", c)) => SubCmd::FloorPlan(c),
+	   Some(("render", c)) => SubCmd::Render(c),
+	   Some(("view", c)) => SubCmd::View(c),
+	   Some(("report", c)) => SubCmd::Report(c),
+	   Some(("serve", c)) => SubCmd::Serve(c),
+	   Some(("exporter", c)) => SubCmd::Exporter(c),
+	   Some(("transform", c)) => SubCmd::Transform(c),
+	   Some(("dedupe", c)) => SubCmd::Dedupe(c),
+	   Some(("merge", c)) => SubCmd::Merge(c),
+	   Some(("diff", c)) => SubCmd::Diff(c),
+	   Some(("stats", c)) => SubCmd::Stats(c),
+	   Some(("query", c)) => SubCmd::Query(c),
+	   Some(("aps", c)) => SubCmd::Aps(c),
+	   Some(("locate", c)) => SubCmd::Locate(c),
+	   Some(("interpolate", c)) => SubCmd::Interpolate(c),
+	   Some(("predict", c)) => SubCmd::Predict(c),
+	   Some(("analyze", c)) => SubCmd::Analyze(c),
+	   Some(("export", c)) => SubCmd::Export(c),
+	   Some(("prune", c)) => SubCmd::Prune(c),
+	   Some(("node", c)) => SubCmd::Node(c),
+	   Some(("undo", _)) => SubCmd::Undo,
+	   Some(("meta", c)) => SubCmd::Meta(c),
+	   Some(("annotate", c)) => SubCmd::Annotate(c),
+	   Some(("session", _)) => SubCmd::Session,
+	   Some(("completions", c)) => SubCmd::Completions(c),
+	   Some(("manpage", c)) => SubCmd::Manpage(c),
+	   _ => die("invalid sub-command"),
+    };
+
+    // Completions and the manual page describe the command line, not a map
+    if let SubCmd::Completions(subcmd_args) = subcmd {
+	   print!("{}", completions(&app, *subcmd_args.get_one::<Shell>("shell").unwrap()));
+	   return;
+    }
+    if let SubCmd::Manpage(subcmd_args) = subcmd {
+	   match subcmd_args.get_one::<String>("dir") {
+		  Some(dir) => match manpage::write_all(&app, "wifi-mapper", Path::new(dir)) {
+			 Ok(pages) => logging::info(&format!("Wrote {} manual pages to \"{}\"", pages.len(), dir)),
+			 Err(e) => die(&format!("failed to write manual pages to \"{}\": {}", dir, e)),
+		  },
+		  None => if let Err(e) = manpage::write(&app, "wifi-mapper", stdout().lock()) {
+			 die(&format!("failed to write manual page: {}", e));
+		  },
+	   };
+	   return;
+    }
+
+    // Merging reads its own input files
+    if let SubCmd::Merge(subcmd_args) = subcmd {
+	   let paths: Vec<&Path> = subcmd_args.get_many::<String>("inputs").unwrap().map(Path::new).collect();
+	   let offsets: Vec<(f64, f64, f64)> = string_values(subcmd_args, "offset")
+		  .into_iter()
+		  .map(|o| merge::parse_offset(&o).unwrap_or_else(|e| die(&e)))
+		  .collect();
+	   if offsets.len() > paths.len() {
+		  die(&format!("{} offsets given for {} maps", offsets.len(), paths.len()));
+	   }
+
+	   let inputs = paths.iter().enumerate().map(|(i, p)| merge::MergeInput{
+		  map: read_map(p),
+		  path: p,
+		  offset: offsets.get(i).copied().unwrap_or((0.0, 0.0, 0.0)),
+	   }).collect();
+
+	   let output_path = Path::new(subcmd_args.get_one::<String>("output").map(String::as_str).unwrap());
+	   let opts = merge::MergeOptions{
+		  name: subcmd_args.get_one::<String>("name").map(String::as_str).map(|n| n.to_string()),
+		  notes: subcmd_args.get_one::<String>("notes").map(String::as_str).map(|n| n.to_string()),
+		  output: output_path,
+	   };
+
+	   let merged = match merge::merge(inputs, &opts) {
+		  Ok(m) => m,
+		  Err(e) => die(&format!("failed to merge maps: {}", e)),
+	   };
+
+	   merged.write(output_path, &write_opts).expect("failed to save merged scan map");
+	   println!("Saved {} to \"{}\"", merged, output_path.display());
+	   return;
+    }
+
+    // Diffs compare their own input files
+    if let SubCmd::Diff(subcmd_args) = subcmd {
+	   let before_path = Path::new(subcmd_args.get_one::<String>("before").map(String::as_str).unwrap());
+	   let mut before = read_map(before_path);
+	   let mut after = read_map(Path::new(subcmd_args.get_one::<String>("after").map(String::as_str).unwrap()));
+
+	   if let Some(id) = parse_optional_arg(subcmd_args, "before_session") {
+		  before.retain_session(id);
+	   }
+	   if let Some(id) = parse_optional_arg(subcmd_args, "after_session") {
+		  after.retain_session(id);
+	   }
+
+	   let opts = diff::DiffOptions{
+		  tolerance: parse_arg(subcmd_args, "tolerance"),
+		  threshold: parse_arg(subcmd_args, "threshold"),
+	   };
+
+	   let report = match diff::diff(&before, after, &opts) {
+		  Ok(r) => r,
+		  Err(e) => die(&format!("failed to compare maps: {}", e)),
+	   };
+
+	   match json_arg(subcmd_args, output) {
+		  true => println!("{}", serde_json::to_string_pretty(&report).expect("failed to serialize differences")),
+		  false => diff::print_report(&report, &before),
+	   };
+	   return;
+    }
+
+    // The exporter only scans
+    if let SubCmd::Exporter(subcmd_args) = subcmd {
+	   let opts = exporter::ExporterOptions{
+		  listen: subcmd_args.get_one::<String>("listen").map(String::as_str).unwrap().to_string(),
+		  interval: parse_arg::<DurationArg>(subcmd_args, "interval").0,
+	   };
+	   if opts.interval.is_zero() {
+		  die("--interval must be greater than 0");
+	   }
+	   scan_opts.progress = false;
+
+	   if let Err(e) = exporter::export(&scan_opts, &opts) {
+		  die(&format!("failed to export metrics: {}", e));
+	   }
+	   return;
+    }
+
+    // One off scans do not use a map either
+    if let SubCmd::Scan(subcmd_args) = subcmd {
+	   let mut networks = match progress::spin("Scanning", scan_opts.progress, || Network::scan(&scan_opts)) {
+		  Ok(n) => n,
+		  Err(e) => die(&format!("failed to scan: {}", e)),
+	   };
+	   networks.sort_by(|a, b| b.strength.partial_cmp(&a.strength).unwrap_or(std::cmp::Ordering::Equal));
+
+	   match json_arg(subcmd_args, output) {
+		  true => println!("{}", serde_json::to_string_pretty(&networks).expect("failed to serialize networks")),
+		  false => {
+			 let rows: Vec<Vec<String>> = networks.iter().map(Network::table_row).collect();
+			 table::print_table(&Network::TABLE_HEADERS, &rows);
+		  },
+	   };
+	   return;
+    }
+
+    let map_file = match arg_matches.get_one::<String>("map_file").map(String::as_str) {
+	   Some(f) => f,
+	   None => die("--map-file is required"),
+    };
+
+    // Initialize scan map
+    let map_file_path = Path::new(map_file);
+
+    // Validating reads the map itself, so files which do not match the schema can be reported
+    if let SubCmd::Validate(subcmd_args) = subcmd {
+	   if !map_file_path.exists() {
+		  die(&format!("scan map \"{}\" does not exist", map_file_path.display()));
+	   }
+
+	   let fix = subcmd_args.get_flag("fix");
+	   let problems = match validate::read(map_file_path) {
+		  Ok(mut scan_map) => {
+			 let problems = validate::validate(&mut scan_map, fix);
+			 if problems.iter().any(|p| p.fixed) {
+				scan_map.write(map_file_path, &write_opts).expect("failed to save scan map");
+			 }
+			 problems
+		  },
+		  Err(problems) => problems,
+	   };
+
+	   match json_arg(subcmd_args, output) {
+		  true => println!("{}", serde_json::to_string_pretty(&problems).expect("failed to serialize problems")),
+		  false => validate::print_problems(&problems),
+	   };
+
+	   let fixed = problems.iter().filter(|p| p.fixed).count();
+	   let fixable = problems.iter().filter(|p| p.repair.is_some() && !p.fixed).count();
+	   if fixed > 0 {
+		  logging::info(&format!("Fixed {} problem{}, saved scan map to \"{}\"", fixed, match fixed != 1 {
+			 true => "s",
+			 false => "",
+		  }, map_file_path.display()));
+	   } else if fixable > 0 {
+		  logging::info(&format!("{} problem{} can be fixed with --fix", fixable, match fixable != 1 {
+			 true => "s",
+			 false => "",
+		  }));
+	   }
+
+	   if problems.iter().any(|p| !p.fixed) {
+		  exit(1);
+	   }
+	   return;
+    }
+
+    let stream = arg_matches.get_flag("stream");
+    if stream && !matches!(subcmd, SubCmd::List(_) | SubCmd::Query(_) | SubCmd::Aps(_) | SubCmd::Export(_) | SubCmd::Record(_) | SubCmd::Tui(_)) {
+	   die("--stream only works with the list, query, aps, export, record, and tui sub-commands");
+    }
+
+    let mut scan_map = match map_file_path.exists() {
+	   true => {
+		  // Read existing scan map file
+		  let scan_map = match (stream, &subcmd) {
+			 // Recording only needs the last node, e.g. for offsets from the previous position
+			 (true, SubCmd::Record(_) | SubCmd::Tui(_)) => stream::read_last(map_file_path),
+			 (true, _) => stream::read_header(map_file_path),
+			 (false, _) => ScanMap::read(map_file_path),
+		  }.expect("failed to load existing scan map");
+
+		  logging::info(&format!("Loaded {} from \"{}\"", scan_map, map_file_path.display()));
+		  
+		  scan_map
+	   },
+	   false => match subcmd {
+		  // Only recording creates new scan maps
+		  SubCmd::Record(subcmd_args) => {
+			 let default_crs = match subcmd_args.get_flag("gps") {
+				true => CoordinateSystem::Wgs84,
+				false => CoordinateSystem::Local,
+			 };
+			 ScanMap::prompt_new(map_file_path, subcmd_args.get_one::<String>("name").map(String::as_str), subcmd_args.get_one::<String>("notes").map(String::as_str), default_crs)
+		  },
+		  SubCmd::Tui(_) => ScanMap::prompt_new(map_file_path, None, None, CoordinateSystem::Local),
+		  _ => die(&format!("scan map \"{}\" does not exist", map_file_path.display())),
+	   },
+    };
+
+    // Run sub-command
+    match subcmd {
+	   SubCmd::Record(subcmd_args) => {
+		  let mut record_opts = RecordOptions{
+			 gps: match subcmd_args.get_flag("gps") {
+				true => Some(gps::GpsOptions{
+				    address: subcmd_args.get_one::<String>("gpsd").map(String::as_str).unwrap().to_string(),
+				    max_error: parse_arg(subcmd_args, "gps_max_error"),
+				    timeout: Duration::from_secs(parse_arg(subcmd_args, "gps_timeout")),
+				}),
+				false => None,
+			 },
+			 floor: subcmd_args.get_one::<String>("floor").map(String::as_str).map(|f| f.to_string()),
+			 tags: string_values(subcmd_args, "tag"),
+			 session: None,
+			 samples: parse_arg(subcmd_args, "samples"),
+			 interval: parse_arg::<DurationArg>(subcmd_args, "interval").0,
+			 reuse_previous: subcmd_args.get_flag("loop"),
+			 clients: subcmd_args.get_flag("clients"),
+		  };
+		  if record_opts.samples == 0 {
+			 die("--samples must be at least 1");
+		  }
+		  if record_opts.clients && !cfg!(feature = "capture") {
+			 die("--clients needs a build with the capture feature, rebuild with --features capture");
+		  }
+		  scan_opts.protocol = parse_arg(subcmd_args, "protocol");
+		  scan_opts.ble_duration = parse_arg::<DurationArg>(subcmd_args, "ble_time").0;
+		  if scan_opts.protocol.ble() && !cfg!(feature = "ble") {
+			 die("--protocol ble needs a build with the ble feature, rebuild with --features ble");
+		  }
+
+		  if let Some(floor) = &record_opts.floor {
+			 if scan_map.floor(floor).is_none() {
+				die(&format!("map has no floor named \"{}\", add it with the floor add sub-command", floor));
+			 }
+		  }
+
+		  if map_file_path.exists() && (subcmd_args.contains_id("name") || subcmd_args.contains_id("notes")) {
+			 logging::warn("--name and --notes are only used when creating a map, use the meta sub-command to change them");
+		  }
+
+		  if record_opts.gps.is_some() && scan_map.coordinate_system != CoordinateSystem::Wgs84 {
+			 die(&format!("--gps requires a map in the wgs84 coordinate system, this map uses {} coordinates", scan_map.coordinate_system));
+		  }
+
+		  // Resume the latest session unless asked otherwise
+		  let resume = match parse_optional_arg(subcmd_args, "session") {
+			 Some(id) => match scan_map.session(id) {
+				Some(s) => Some(s.id),
+				None => die(&format!("map has no session {}, see the session sub-command", id)),
+			 },
+			 None => match subcmd_args.get_flag("new_session") {
+				true => None,
+				false => scan_map.sessions.last().map(|s| s.id),
+			 },
+		  };
+		  let session = match resume {
+			 Some(id) => {
+				if subcmd_args.contains_id("operator") || subcmd_args.contains_id("device") {
+				    logging::warn("--operator and --device are only used when starting a session, pass --new-session to start one");
+				}
+				println!("Resuming session {}", id);
+				id
+			 },
+			 None => {
+				let id = scan_map.start_session(subcmd_args.get_one::<String>("operator").map(String::as_str).map(|o| o.to_string()),
+										  subcmd_args.get_one::<String>("device").map(String::as_str).map(|d| d.to_string()))
+				    .expect("failed to start session");
+				println!("Started session {}", id);
+				id
+			 },
+		  };
+		  record_opts.session = Some(session);
+
+		  // Filters given on the command line replace the map's defaults
+		  let filters = ScanFilters{
+			 only_ssids: string_values(subcmd_args, "only_ssid"),
+			 ignore_ssids: string_values(subcmd_args, "ignore_ssid"),
+			 only_bssid_prefixes: string_values(subcmd_args, "only_bssid_prefix"),
+		  };
+		  let save_filters = subcmd_args.get_flag("save_filters");
+		  scan_opts.filters = match filters.is_empty() && !save_filters {
+			 true => scan_map.scan_filters.clone(),
+			 false => filters,
+		  };
+		  if save_filters {
+			 scan_map.scan_filters = scan_opts.filters.clone();
+		  }
+		  if !scan_opts.filters.is_empty() || save_filters {
+			 println!("Scan filters: {}", scan_opts.filters);
+		  }
+
+		  let scanner = scan_opts.backend.scanner();
+		  if !scan_opts.interfaces.is_empty() || arg_matches.get_flag("all_interfaces") {
+			 let interfaces = scanner.interfaces();
+			 if interfaces.is_empty() {
+				die(&format!("the {} backend found no wireless interfaces it can choose from", scan_opts.backend));
+			 }
+
+			 if let Some(i) = scan_opts.interfaces.iter().find(|i| !interfaces.contains(i)) {
+				die(&format!("no wireless interface named \"{}\", found: {}", i, interfaces.join(", ")));
+			 }
+			 if arg_matches.get_flag("all_interfaces") {
+				scan_opts.interfaces = interfaces;
+			 }
+		  }
+
+		  match (scan_opts.interfaces.is_empty(), scanner.default_interface()) {
+			 (true, Some(i)) => println!("Scanning with {} using {}", i, scan_opts.backend),
+			 (true, None) => println!("Scanning using {}", scan_opts.backend),
+			 (false, _) => println!("Scanning with {} using {}", scan_opts.interfaces.join(", "), scan_opts.backend),
+		  };
+
+		  if let Some(url) = subcmd_args.get_one::<String>("remote").map(String::as_str) {
+			 match remote::Remote::connect(url, subcmd_args.get_one::<String>("remote_token").cloned(), &scan_map) {
+				Ok(r) => write_opts.remote = Some(r),
+				Err(e) => die(&format!("failed to use remote map: {}", e)),
+			 };
+		  }
+
+		  if let Err(e) = interrupt::install(map_file_path, scan_map.node_count()) {
+			 die(&format!("failed to handle Ctrl-C: {}", e));
+		  }
+
+		  if let Some(bbox) = subcmd_args.get_one::<String>("grid").map(String::as_str) {
+			 if scan_map.coordinate_system != CoordinateSystem::Local {
+				die(&format!("--grid requires a map in local coordinates, this map uses {}", scan_map.coordinate_system));
+			 }
+
+			 let grid_opts = grid::GridOptions{
+				bbox: query::parse_bbox(bbox).unwrap_or_else(|e| die(&e)),
+				spacing: parse_arg(subcmd_args, "grid_spacing"),
+				z: parse_arg(subcmd_args, "grid_z"),
+			 };
+			 if grid_opts.spacing <= 0.0 {
+				die("--grid-spacing must be greater than 0");
+			 }
+
+			 if let Err(e) = grid::grid(&mut scan_map, &record_opts, &scan_opts, &grid_opts, map_file_path, &write_opts) {
+				die(&format!("failed to record grid: {}", e));
+			 }
+			 return;
+		  }
+
+		  if subcmd_args.contains_id("every") {
+			 let mut schedule_opts = schedule::ScheduleOptions{
+				every: parse_arg::<DurationArg>(subcmd_args, "every").0,
+				count: parse_optional_arg(subcmd_args, "count"),
+				duration: parse_optional_arg::<DurationArg>(subcmd_args, "for").map(|d| d.0),
+				publisher: None,
+				save: !subcmd_args.get_flag("no_save"),
+			 };
+			 if schedule_opts.count == Some(0) {
+				die("--count must be at least 1");
+			 }
+
+			 if let Some(broker) = subcmd_args.get_one::<String>("mqtt").map(String::as_str) {
+				let client_id = subcmd_args.get_one::<String>("mqtt_client_id").map(String::as_str)
+				    .map(|id| id.to_string())
+				    .unwrap_or_else(|| format!("wifi-mapper-{}", std::process::id()));
+				let ca = subcmd_args.get_one::<String>("mqtt_ca").map(String::as_str).map(|p| std::fs::read(p).unwrap_or_else(|e| die(&format!("failed to read \"{}\": {}", p, e))));
+				let tls = ca.is_some() || subcmd_args.get_flag("mqtt_tls");
+				let (host, port) = mqtt::parse_address(broker, match tls {
+				    true => mqtt::DEFAULT_TLS_PORT,
+				    false => mqtt::DEFAULT_PORT,
+				}).unwrap_or_else(|e| die(&format!("invalid --mqtt address: {}", e)));
+				let mqtt_opts = mqtt::MqttOptions{
+				    host,
+				    port,
+				    tls,
+				    ca,
+				    topic: subcmd_args.get_one::<String>("mqtt_topic").map(String::as_str).expect("topic has a default").to_string(),
+				    username: subcmd_args.get_one::<String>("mqtt_username").map(String::as_str).map(|u| u.to_string()),
+				    password: subcmd_args.get_one::<String>("mqtt_password").map(String::as_str).map(|p| p.to_string()),
+				    client_id,
+				    retain: subcmd_args.get_flag("mqtt_retain"),
+				};
+				match mqtt::Publisher::connect(mqtt_opts) {
+				    Ok(p) => schedule_opts.publisher = Some(p),
+				    Err(e) => die(&format!("failed to connect to MQTT broker: {}", e)),
+				};
+			 }
+
+			 if let Err(e) = schedule::repeat(&mut scan_map, &record_opts, &scan_opts, &mut schedule_opts, map_file_path, &write_opts) {
+				die(&format!("failed to record scheduled measurements: {}", e));
+			 }
+			 return;
+		  }
+
+		  if subcmd_args.get_flag("continuous") {
+			 if subcmd_args.value_source("samples") == Some(ValueSource::CommandLine) {
+				die("--samples can not be used with --continuous, each scan is recorded as its own node");
+			 }
+
+			 if let Err(e) = walk::walk(&mut scan_map, &record_opts, &scan_opts, record_opts.interval, map_file_path, &write_opts) {
+				die(&format!("failed to record walk: {}", e));
+			 }
+			 return;
+		  }
+
+		  let alert = match subcmd_args.contains_id("alert_ssid") {
+			 true => Some(alert::Alert{
+				ssids: string_values(subcmd_args, "alert_ssid"),
+				below: parse_arg(subcmd_args, "alert_below"),
+				beep: subcmd_args.get_flag("beep"),
+			 }),
+			 false => None,
+		  };
 
-/// Possible sub-commands.
-enum SubCmd<'a> {
-    /// Record wireless information.
-    Record(&'a ArgMatches<'a>)
-}
+		  let mut done_recording = false;
+		  while !done_recording {
+			 // Acquire new reading
+			 if let Err(e) = scan_map.acquire(&record_opts, &scan_opts) {
+				die(&format!("failed to acquire new reading: {}", e));
+			 }
+			 if let (Some(alert), Some(node)) = (&alert, scan_map.nodes.last()) {
+				alert.check(node);
+			 }
 
-fn main() {
-    // Command line arguments
-    let arg_matches = App::new("Wifi Scanner")
-        .about("Map wireless networks")
-        .arg(Arg::with_name("map_file")
-             .short("f")
-             .long("map-file")
-             .value_name("MAP_FILE")
-             .help("File to save scan map")
-             .takes_value(true)
-             .required(true))
-        .subcommand(SubCommand::with_name("record")
-                    .about("Records a new scan to the map")
-				.arg(Arg::with_name("loop")
-					.short("l")
-					.help("Loop and keep prompting for new recordings until the user kills the process")))
-	   .get_matches();
+			 // Save scan map
+			 scan_map.write(map_file_path, &write_opts).expect("failed to save scan map");
 
-    let map_file = arg_matches.value_of("map_file").unwrap();
+			 if !subcmd_args.get_flag("loop") {
+				done_recording = true;
+				continue;
+			 }
 
-    // Determine sub-command to run
-    let mut subcmd: Option<SubCmd> = None;
+			 // Let the user take back a scan made at the wrong spot before moving on
+			 loop {
+				print!("Press enter to record the next node, u to undo the last one, or q to quit: ");
+				stdout().flush().expect("failed to flush stdout");
+				let mut answer = String::new();
+				let read = stdin().read_line(&mut answer).expect("failed to read input");
 
-    if let Some(c) = arg_matches.subcommand_matches("record") {
-	   subcmd = Some(SubCmd::Record(c));
-    }
+				match answer.trim() {
+				    _ if read == 0 => {
+					   done_recording = true;
+					   break;
+				    },
+				    "u" => {
+					   if scan_map.undo().is_some() {
+						  scan_map.write(map_file_path, &write_opts).expect("failed to save scan map");
+					   }
+				    },
+				    "q" => {
+					   done_recording = true;
+					   break;
+				    },
+				    _ => break,
+				};
+			 }
 
-    if subcmd.is_none() {
-	   die("invalid sub-command");
-    }
+			 println!();
+		  }
+	   },
+	   SubCmd::Tui(subcmd_args) => {
+		  let floor = subcmd_args.get_one::<String>("floor").map(String::as_str).map(|f| f.to_string());
+		  if let Some(f) = &floor {
+			 if scan_map.floor(f).is_none() {
+				die(&format!("map has no floor named \"{}\", add it with the floor add sub-command", f));
+			 }
+		  }
 
-    // Initialize scan map
-    let map_file_path = Path::new(map_file);
-    let mut scan_map = match map_file_path.exists() {
-	   true => {
-		  // Read existing scan map file
-		  let scan_map = ScanMap::read(map_file_path).expect("failed to load existing scan map");
+		  let session = match (subcmd_args.get_flag("new_session"), scan_map.sessions.last()) {
+			 (false, Some(s)) => s.id,
+			 _ => scan_map.start_session(None, None).expect("failed to start session"),
+		  };
+		  let record_opts = RecordOptions{
+			 gps: None,
+			 floor,
+			 tags: string_values(subcmd_args, "tag"),
+			 session: Some(session),
+			 samples: 1,
+			 interval: Duration::ZERO,
+			 reuse_previous: true,
+			 clients: false,
+		  };
 
-		  println!("Loaded {} from \"{}\"", scan_map, map_file_path.display());
-		  
-		  scan_map
+		  let opts = tui::TuiOptions{
+			 interval: parse_arg::<DurationArg>(subcmd_args, "interval").0,
+		  };
+		  if opts.interval.is_zero() {
+			 die("--interval must be greater than 0");
+		  }
+
+		  // The screen would be scrolled by a spinner
+		  scan_opts.progress = false;
+		  scan_opts.filters = scan_map.scan_filters.clone();
+		  if arg_matches.get_flag("all_interfaces") {
+			 scan_opts.interfaces = scan_opts.backend.scanner().interfaces();
+		  }
+
+		  if let Err(e) = tui::run(&mut scan_map, &record_opts, &scan_opts, &opts, map_file_path, &write_opts) {
+			 die(&format!("failed to run the tui: {}", e));
+		  }
+
+		  println!("Saved {} to \"{}\"", scan_map, map_file_path.display());
 	   },
-	   false => {
-		  // Create new scan map
-		  println!("Creating a new scan map in \"{}\"", map_file_path.display());
-		  println!("New scan map properties:");
-		  
-		  let mut scan_map = ScanMap::new();
+	   SubCmd::Anonymize(subcmd_args) => {
+		  let salt = subcmd_args.get_one::<String>("salt").map(String::as_str).unwrap();
+		  let output_path = Path::new(subcmd_args.get_one::<String>("output").map(String::as_str).unwrap());
 
-		  let mut get_name_done = false;
-		  while !get_name_done {
-			 print!("    Name: ");
-			 stdout().flush().expect("failed to flush stdout");
-			 stdin().read_line(&mut scan_map.name)
-				.expect("failed to read input");
-			 scan_map.name = scan_map.name.replace("\n", "");
+		  anonymize::anonymize(&mut scan_map, salt, subcmd_args.get_flag("strip_ssids"));
 
-			 if scan_map.name.len() > 0 {
-				get_name_done = true;
-			 } else {
-				println!("    Error: Name cannot be empty");
- 			 }
+		  scan_map.write(output_path, &write_opts).expect("failed to save anonymized scan map");
+
+		  println!("Saved anonymized scan map to \"{}\"", output_path.display());
+	   },
+	   SubCmd::Transform(subcmd_args) => {
+		  if scan_map.coordinate_system != CoordinateSystem::Local {
+			 die(&format!("only maps in local coordinates can be transformed, this map uses {}", scan_map.coordinate_system));
+		  }
+
+		  let t = transform::Transform{
+			 translate: (parse_arg(subcmd_args, "translate_x"), parse_arg(subcmd_args, "translate_y"), parse_arg(subcmd_args, "translate_z")),
+			 rotate: parse_arg(subcmd_args, "rotate"),
+			 scale: parse_arg(subcmd_args, "scale"),
+		  };
+		  if t.scale <= 0.0 {
+			 die("--scale must be greater than 0");
 		  }
 
-		  print!("    Notes (empty for none): ");
-		  stdout().flush().expect("failed to flush stdout");
-		  stdin().read_line(&mut scan_map.notes).expect("failed to read input");
-		  scan_map.notes = scan_map.notes.replace("\n", "");
+		  let output_path = Path::new(subcmd_args.get_one::<String>("output").map(String::as_str).unwrap());
 
-		  scan_map
+		  let misaligned = transform::transform(&mut scan_map, &t);
+		  if misaligned > 0 {
+			 logging::warn(&format!("{} floor plan{} no longer line{} up with the rotated nodes and must be attached again", misaligned, match misaligned != 1 {
+				true => "s",
+				false => "",
+			 }, match misaligned != 1 {
+				true => "",
+				false => "s",
+			 }));
+		  }
+
+		  scan_map.write(output_path, &write_opts).expect("failed to save transformed scan map");
+
+		  println!("Saved transformed scan map to \"{}\"", output_path.display());
 	   },
-    };
+	   // Handled before the scan map is loaded
+	   SubCmd::Merge(_) | SubCmd::Diff(_) | SubCmd::Exporter(_) | SubCmd::Scan(_) | SubCmd::Validate(_) | SubCmd::Completions(_) | SubCmd::Manpage(_) => (),
+	   SubCmd::Dedupe(subcmd_args) => {
+		  let removed = dedupe::dedupe(&mut scan_map, parse_arg(subcmd_args, "tolerance"));
+		  if removed == 0 {
+			 println!("No duplicate nodes");
+			 return;
+		  }
 
-    // Run sub-command
-    match subcmd.unwrap() {
-	   SubCmd::Record(subcmd_args) => {
-		  let mut done_recording = false;
-		  while !done_recording {
-			 // Acquire new reading
-			 scan_map.acquire().expect("failed to acquire new reading");
+		  scan_map.write(map_file_path, &write_opts).expect("failed to save scan map");
+		  println!("Merged {} duplicate node{}, {} node{} left", removed, match removed != 1 {
+			 true => "s",
+			 false => "",
+		  }, scan_map.nodes.len(), match scan_map.nodes.len() != 1 {
+			 true => "s",
+			 false => "",
+		  });
+	   },
+	   SubCmd::Floor(subcmd_args) => {
+		  if let Some(args) = subcmd_args.subcommand_matches("add") {
+			 let name = args.get_one::<String>("name").map(String::as_str).unwrap();
+			 if scan_map.floor(name).is_some() {
+				die(&format!("map already has a floor named \"{}\"", name));
+			 }
 
-			 // Save scan map
-			 scan_map.write(map_file_path).expect("failed to save scan map");
+			 scan_map.floors.push(Floor{
+				name: name.to_string(),
+				elevation: parse_arg(args, "elevation"),
+				floor_plan: None,
+			 });
+			 scan_map.floors.sort_by(|a, b| a.elevation.partial_cmp(&b.elevation).unwrap_or(std::cmp::Ordering::Equal));
 
-			 if !subcmd_args.is_present("loop") {
-				done_recording = true;
-			 } else {
-				println!("");
+			 scan_map.write(map_file_path, &write_opts).expect("failed to save scan map");
+			 println!("Added floor \"{}\"", name);
+		  } else if let Some(args) = subcmd_args.subcommand_matches("remove") {
+			 let name = args.get_one::<String>("name").map(String::as_str).unwrap();
+			 if scan_map.floor(name).is_none() {
+				die(&format!("map has no floor named \"{}\"", name));
+			 }
+
+			 let node_count = scan_map.nodes.iter().filter(|n| n.floor.as_deref() == Some(name)).count();
+			 if node_count > 0 {
+				die(&format!("{} node{} on floor \"{}\"", node_count, match node_count != 1 {
+				    true => "s are",
+				    false => " is",
+				}, name));
+			 }
+
+			 scan_map.floors.retain(|f| f.name != name);
+
+			 scan_map.write(map_file_path, &write_opts).expect("failed to save scan map");
+			 println!("Removed floor \"{}\"", name);
+		  } else if output == OutputFormat::Json {
+			 println!("{}", serde_json::to_string_pretty(&scan_map.floors).expect("failed to serialize floors"));
+		  } else {
+			 let rows: Vec<Vec<String>> = scan_map.floors.iter()
+				.map(|f| vec![
+				    f.name.clone(),
+				    format!("{} {}", f.elevation, scan_map.units.abbreviation()),
+				    scan_map.nodes.iter().filter(|n| n.floor.as_ref() == Some(&f.name)).count().to_string(),
+				])
+				.collect();
+
+			 match rows.is_empty() {
+				true => println!("No floors"),
+				false => table::print_table(&["Name", "Elevation", "Nodes"], &rows),
+			 };
+		  }
+	   },
+	   SubCmd::List(subcmd_args) => {
+		  let units = parse_optional_arg(subcmd_args, "units").unwrap_or(scan_map.units);
+
+		  let (floor, tags, times) = (subcmd_args.get_one::<String>("floor").map(String::as_str), string_values(subcmd_args, "tag"), time_range_arg(subcmd_args));
+		  let listed = match stream {
+			 true => {
+				let mut listed = Vec::new();
+				if let Err(e) = stream::visit_nodes(&scan_map, |i, node| {
+				    listed.extend(list::list_node(&scan_map, i, node, floor, &tags, &times, units));
+				    Ok(())
+				}) {
+				    die(&format!("failed to read nodes: {}", e));
+				}
+				listed
+			 },
+			 false => list::list(&scan_map, floor, &tags, &times, units),
+		  };
+		  match output {
+			 OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&listed).expect("failed to serialize nodes")),
+			 OutputFormat::Text => list::print_list(&scan_map, &listed, units),
+		  };
+	   },
+	   SubCmd::Stats(subcmd_args) => {
+		  let session = parse_optional_arg(subcmd_args, "session");
+		  if let Some(id) = session {
+			 if scan_map.session(id).is_none() {
+				die(&format!("map has no session {}", id));
+			 }
+		  }
+
+		  let summary = stats::summarize(&scan_map, subcmd_args.get_one::<String>("floor").map(String::as_str), session);
+		  match output {
+			 OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&summary).expect("failed to serialize statistics")),
+			 OutputFormat::Text => stats::print_summary(&summary),
+		  };
+	   },
+	   SubCmd::Query(subcmd_args) => {
+		  let times = time_range_arg(subcmd_args);
+		  let filters = query::Filters{
+			 ssid: subcmd_args.get_one::<String>("ssid").map(String::as_str).map(|s| s.to_string()),
+			 bssid: subcmd_args.get_one::<String>("bssid").map(String::as_str).map(|s| s.to_string()),
+			 bbox: subcmd_args.get_one::<String>("bbox").map(String::as_str).map(|b| query::parse_bbox(b).unwrap_or_else(|e| die(&e))),
+			 floor: subcmd_args.get_one::<String>("floor").map(String::as_str).map(|s| s.to_string()),
+			 tags: string_values(subcmd_args, "tag"),
+			 min_strength: parse_optional_arg(subcmd_args, "min_strength"),
+			 max_strength: parse_optional_arg(subcmd_args, "max_strength"),
+			 times,
+		  };
+
+		  // Streamed maps print each match as it is read instead of loading the nodes
+		  match (stream, json_arg(subcmd_args, output)) {
+			 (true, json) => if let Err(e) = query::write_streamed_matches(&scan_map, &filters, json, BufWriter::new(stdout().lock())) {
+				die(&format!("failed to query nodes: {}", e));
+			 },
+			 (false, true) => println!("{}", serde_json::to_string_pretty(&query::query(&scan_map, &filters)).expect("failed to serialize matches")),
+			 (false, false) => query::print_matches(&scan_map, &query::query(&scan_map, &filters)),
+		  };
+	   },
+	   SubCmd::Aps(subcmd_args) => {
+		  let opts = aps::ApOptions{
+			 floor: subcmd_args.get_one::<String>("floor").map(String::as_str).map(|s| s.to_string()),
+			 tags: string_values(subcmd_args, "tag"),
+			 ssid: subcmd_args.get_one::<String>("ssid").map(String::as_str).map(|s| s.to_string()),
+			 bssid: subcmd_args.get_one::<String>("bssid").map(String::as_str).map(|s| s.to_string()),
+		  };
+
+		  let access_points = aps::access_points(&scan_map, &opts).unwrap_or_else(|e| die(&format!("failed to read nodes: {}", e)));
+		  match json_arg(subcmd_args, output) {
+			 true => println!("{}", serde_json::to_string_pretty(&access_points).expect("failed to serialize access points")),
+			 false => aps::print_access_points(&scan_map, &access_points),
+		  };
+	   },
+	   SubCmd::Locate(subcmd_args) => {
+		  let opts = locate::LocateOptions{
+			 path_loss: path_loss_arg(subcmd_args, &scan_map),
+			 min_nodes: parse_arg(subcmd_args, "min_nodes"),
+			 floor: subcmd_args.get_one::<String>("floor").map(String::as_str).map(|s| s.to_string()),
+		  };
+
+		  let locations = locate::locate(&scan_map, &opts);
+		  match (json_arg(subcmd_args, output), locations.is_empty()) {
+			 (true, _) => println!("{}", serde_json::to_string_pretty(&locations).expect("failed to serialize locations")),
+			 (false, true) => println!("No access points were seen by at least {} node{}", opts.min_nodes, match opts.min_nodes != 1 {
+				true => "s",
+				false => "",
+			 }),
+			 (false, false) => locate::print_locations(&scan_map, &locations),
+		  };
+
+		  if subcmd_args.get_flag("save") {
+			 let count = locations.len();
+			 scan_map.access_points = locations;
+			 scan_map.write(map_file_path, &write_opts).expect("failed to save scan map");
+			 logging::info(&format!("Saved the locations of {} access point{}", count, match count != 1 {
+				true => "s",
+				false => "",
+			 }));
+		  }
+	   },
+	   SubCmd::Interpolate(subcmd_args) => {
+		  let target = match subcmd_args.get_one::<String>("bssid").map(String::as_str) {
+			 Some(mac) => interpolate::Target::Bssid(mac.to_lowercase()),
+			 None => interpolate::Target::Ssid(subcmd_args.get_one::<String>("ssid").map(String::as_str).unwrap().to_string()),
+		  };
+		  let opts = interpolate::InterpolateOptions{
+			 target,
+			 floor: subcmd_args.get_one::<String>("floor").map(String::as_str).map(|s| s.to_string()),
+			 spacing: parse_arg(subcmd_args, "spacing"),
+			 power: parse_arg(subcmd_args, "power"),
+			 radius: parse_optional_arg(subcmd_args, "radius"),
+			 unseen: parse_optional_arg(subcmd_args, "unseen"),
+		  };
+		  let format: interpolate::GridFormat = parse_arg(subcmd_args, "format");
+
+		  let grid = interpolate::interpolate(&scan_map, &opts).unwrap_or_else(|e| die(&format!("failed to interpolate: {}", e)));
+
+		  let written = match subcmd_args.get_one::<String>("output").map(String::as_str) {
+			 Some(path) => File::create(path)
+				.map_err(|e| Box::new(e) as Box<dyn Error>)
+				.and_then(|file| {
+				    let mut writer = BufWriter::new(file);
+				    grid.write(&mut writer, format)?;
+				    writer.flush()?;
+				    Ok(())
+				})
+				.map(|_| logging::info(&format!("Saved a {} by {} grid to \"{}\"", grid.columns, grid.rows, path))),
+			 None => grid.write(stdout().lock(), format),
+		  };
+		  if let Err(e) = written {
+			 die(&format!("failed to write grid: {}", e));
+		  }
+	   },
+	   SubCmd::Predict(subcmd_args) => {
+		  let floor = subcmd_args.get_one::<String>("floor").map(String::as_str).map(|s| s.to_string());
+		  match &floor {
+			 Some(f) if scan_map.floor(f).is_none() => die(&format!("no floor named \"{}\"", f)),
+			 None if !scan_map.floors.is_empty() => die("--floor is required since the map has floors"),
+			 _ => (),
+		  };
+
+		  let opts = predict::PredictOptions{
+			 position: predict::parse_position(subcmd_args.get_one::<String>("position").map(String::as_str).unwrap()).unwrap_or_else(|e| die(&e)),
+			 floor: floor.clone(),
+			 ssid: subcmd_args.get_one::<String>("ssid").map(String::as_str).map(|s| s.to_string()),
+			 power: parse_arg(subcmd_args, "power"),
+			 radius: parse_optional_arg(subcmd_args, "radius"),
+			 locate: locate::LocateOptions{
+				path_loss: path_loss_arg(subcmd_args, &scan_map),
+				min_nodes: 3,
+				floor,
+			 },
+			 usable: parse_arg(subcmd_args, "usable"),
+		  };
+
+		  let predictions = predict::predict(&scan_map, &opts);
+		  match (json_arg(subcmd_args, output), predictions.is_empty()) {
+			 (true, _) => println!("{}", serde_json::to_string_pretty(&predictions).expect("failed to serialize predictions")),
+			 (false, true) => println!("No networks to predict"),
+			 (false, false) => predict::print_predictions(&scan_map, &predictions),
+		  };
+	   },
+	   SubCmd::Analyze(subcmd_args) => {
+		  if let Some(args) = subcmd_args.subcommand_matches("gaps") {
+			 let floor = args.get_one::<String>("floor").map(String::as_str).map(|s| s.to_string());
+			 if let Some(f) = &floor {
+				if scan_map.floor(f).is_none() {
+				    die(&format!("no floor named \"{}\"", f));
+				}
+			 }
+
+			 let threshold: f64 = parse_arg(args, "threshold");
+			 let opts = interpolate::InterpolateOptions{
+				target: interpolate::Target::Ssid(args.get_one::<String>("ssid").map(String::as_str).unwrap().to_string()),
+				floor,
+				spacing: parse_arg(args, "spacing"),
+				power: parse_arg(args, "power"),
+				radius: parse_optional_arg(args, "radius"),
+				unseen: Some(parse_arg(args, "unseen")),
+			 };
+			 let found = gaps::gaps(&scan_map, &opts, threshold, parse_arg(args, "min_area"))
+				.unwrap_or_else(|e| die(&format!("failed to interpolate: {}", e)));
+
+			 match (json_arg(args, output), found.is_empty()) {
+				(true, _) => println!("{}", serde_json::to_string_pretty(&found).expect("failed to serialize gaps")),
+				(false, true) => println!("No areas weaker than {} dBm", threshold),
+				(false, false) => gaps::print_gaps(&scan_map, &found),
+			 };
+		  } else if let Some(args) = subcmd_args.subcommand_matches("placement") {
+			 let floor = args.get_one::<String>("floor").map(String::as_str).map(|s| s.to_string());
+			 if let Some(f) = &floor {
+				if scan_map.floor(f).is_none() {
+				    die(&format!("no floor named \"{}\"", f));
+				}
+			 }
+
+			 let opts = placement::PlacementOptions{
+				interpolate: interpolate::InterpolateOptions{
+				    target: interpolate::Target::Ssid(args.get_one::<String>("ssid").map(String::as_str).unwrap().to_string()),
+				    floor,
+				    spacing: parse_arg(args, "spacing"),
+				    power: parse_arg(args, "power"),
+				    radius: parse_optional_arg(args, "radius"),
+				    unseen: Some(parse_arg(args, "unseen")),
+				},
+				threshold: parse_arg(args, "threshold"),
+				count: parse_arg(args, "count"),
+				path_loss: path_loss_arg(args, &scan_map),
+			 };
+
+			 let found = placement::place(&scan_map, &opts).unwrap_or_else(|e| die(&format!("failed to interpolate: {}", e)));
+			 match json_arg(args, output) {
+				true => println!("{}", serde_json::to_string_pretty(&found).expect("failed to serialize placement")),
+				false => placement::print_placement(&scan_map, &found),
+			 };
+		  } else if let Some(args) = subcmd_args.subcommand_matches("channels") {
+			 let floor = args.get_one::<String>("floor").map(String::as_str).map(|s| s.to_string());
+			 if let Some(f) = &floor {
+				if scan_map.floor(f).is_none() {
+				    die(&format!("no floor named \"{}\"", f));
+				}
+			 }
+
+			 let opts = channels::ChannelOptions{
+				region: parse_arg(args, "region"),
+				floor,
+				min_strength: parse_arg(args, "min_strength"),
+			 };
+			 if opts.region <= 0.0 {
+				die("region must be positive");
+			 }
+
+			 let uses = channels::analyze_channels(&scan_map, &opts);
+			 match (json_arg(args, output), uses.is_empty()) {
+				(true, _) => println!("{}", serde_json::to_string_pretty(&uses).expect("failed to serialize channels")),
+				(false, true) => println!("No networks with a known channel"),
+				(false, false) => channels::print_channels(&scan_map, &uses),
+			 };
+		  } else if let Some(args) = subcmd_args.subcommand_matches("recommend") {
+			 let floor = args.get_one::<String>("floor").map(String::as_str).map(|s| s.to_string());
+			 if let Some(f) = &floor {
+				if scan_map.floor(f).is_none() {
+				    die(&format!("no floor named \"{}\"", f));
+				}
+			 }
+
+			 let opts = channels::RecommendOptions{
+				ssids: string_values(args, "ssid"),
+				domain: parse_arg(args, "domain"),
+				dfs: !args.get_flag("no_dfs"),
+				radius: parse_arg(args, "radius"),
+				min_strength: parse_arg(args, "min_strength"),
+				locate: locate::LocateOptions{
+				    path_loss: path_loss_arg(args, &scan_map),
+				    min_nodes: 3,
+				    floor,
+				},
+			 };
+
+			 let recommendations = channels::recommend(&scan_map, &opts);
+			 match (json_arg(args, output), recommendations.is_empty()) {
+				(true, _) => println!("{}", serde_json::to_string_pretty(&recommendations).expect("failed to serialize recommendations")),
+				(false, true) => println!("No located access points of {}, run locate or survey more of their surroundings", opts.ssids.join(", ")),
+				(false, false) => channels::print_recommendations(&recommendations),
+			 };
+		  } else if let Some(args) = subcmd_args.subcommand_matches("roaming") {
+			 let floor = args.get_one::<String>("floor").map(String::as_str).map(|s| s.to_string());
+			 if let Some(f) = &floor {
+				if scan_map.floor(f).is_none() {
+				    die(&format!("no floor named \"{}\"", f));
+				}
 			 }
+
+			 let opts = roaming::RoamingOptions{
+				ssid: args.get_one::<String>("ssid").map(String::as_str).unwrap().to_string(),
+				floor,
+				margin: parse_arg(args, "margin"),
+				usable: parse_arg(args, "usable"),
+				neighbor: parse_arg(args, "neighbor"),
+			 };
+
+			 let analysis = roaming::analyze_roaming(&scan_map, &opts);
+			 match (json_arg(args, output), analysis.nodes.is_empty()) {
+				(true, _) => println!("{}", serde_json::to_string_pretty(&analysis).expect("failed to serialize roaming analysis")),
+				(false, true) => println!("No nodes saw {}", opts.ssid),
+				(false, false) => roaming::print_roaming(&scan_map, &analysis),
+			 };
+		  } else if let Some(args) = subcmd_args.subcommand_matches("trend") {
+			 let others = string_values(args, "with").into_iter()
+				.map(|path| {
+				    let map = read_map(Path::new(&path));
+				    (path, map)
+				})
+				.collect();
+			 let opts = trend::TrendOptions{
+				tolerance: parse_arg(args, "tolerance"),
+				threshold: parse_arg(args, "threshold"),
+				ssid: args.get_one::<String>("ssid").map(String::as_str).map(|s| s.to_string()),
+			 };
+
+			 let report = trend::trend(&scan_map, others, &opts).unwrap_or_else(|e| die(&format!("failed to compare surveys: {}", e)));
+			 match (json_arg(args, output), report.visits.len() < 2) {
+				(true, _) => println!("{}", serde_json::to_string_pretty(&report).expect("failed to serialize trends")),
+				(false, true) => println!("Only one visit, record another session or pass --with"),
+				(false, false) => trend::print_trend(&report),
+			 };
+		  }
+	   },
+	   SubCmd::Export(subcmd_args) => {
+		  let opts = export::ExportOptions{
+			 format: parse_arg(subcmd_args, "format"),
+			 floor: subcmd_args.get_one::<String>("floor").map(String::as_str).map(|s| s.to_string()),
+			 tags: string_values(subcmd_args, "tag"),
+			 measurement: subcmd_args.get_one::<String>("measurement").map(String::as_str).unwrap().to_string(),
+			 ssid: subcmd_args.get_one::<String>("ssid").map(String::as_str).map(|s| s.to_string()),
+			 times: time_range_arg(subcmd_args),
+		  };
+
+		  if let Some(url) = subcmd_args.get_one::<String>("url").map(String::as_str) {
+			 if !matches!(opts.format, export::ExportFormat::Influx) {
+				die("--url can only be used with --format influx");
+			 }
+			 let sent = match export::send_all(&scan_map, &opts, url, subcmd_args.get_one::<String>("token").map(String::as_str), parse_arg(subcmd_args, "batch_size")) {
+				Ok(sent) => sent,
+				Err(e) => die(&format!("failed to send points: {}", e)),
+			 };
+			 logging::info(&format!("Sent {} point{} to {}", sent, match sent != 1 {
+				true => "s",
+				false => "",
+			 }, url));
+			 return;
+		  }
+
+		  let map_dir = map_dir(map_file_path);
+		  let written = match subcmd_args.get_one::<String>("output").map(String::as_str) {
+			 Some(path) => File::create(path)
+				.map_err(|e| Box::new(e) as Box<dyn Error>)
+				.and_then(|file| export::export(&scan_map, &opts, map_dir, BufWriter::new(file)))
+				.map(|count| logging::info(&format!("Saved {} {}{} to \"{}\"", count, opts.format.counts(), match count != 1 {
+				    true => "s",
+				    false => "",
+				}, path))),
+			 // Projects are archives which would garble a terminal
+			 None if matches!(opts.format, export::ExportFormat::Esx) => die("--output is required to export an Ekahau project"),
+			 None => export::export(&scan_map, &opts, map_dir, stdout().lock()).map(|_| ()),
+		  };
+		  if let Err(e) = written {
+			 die(&format!("failed to export: {}", e));
+		  }
+	   },
+	   SubCmd::Prune(subcmd_args) => {
+		  let opts = prune::PruneOptions{
+			 min_strength: parse_optional_arg(subcmd_args, "min_strength"),
+			 ssids: string_values(subcmd_args, "ssid"),
+			 before: parse_optional_arg(subcmd_args, "before").map(|t: timestamp::TimeArg| t.earliest),
+		  };
+		  let output_path = Path::new(subcmd_args.get_one::<String>("output").map(String::as_str).unwrap());
+
+		  let counts = prune::prune(&mut scan_map, &opts);
+
+		  scan_map.write(output_path, &write_opts).expect("failed to save pruned scan map");
+
+		  println!("Removed {} network{} and {} node{}, saved pruned scan map to \"{}\"", counts.networks, match counts.networks != 1 {
+			 true => "s",
+			 false => "",
+		  }, counts.nodes, match counts.nodes != 1 {
+			 true => "s",
+			 false => "",
+		  }, output_path.display());
+	   },
+	   SubCmd::Meta(subcmd_args) => {
+		  let name = subcmd_args.get_one::<String>("name").map(String::as_str);
+		  let notes = subcmd_args.get_one::<String>("notes").map(String::as_str);
+
+		  let model_args = ["reference_power", "path_loss_exponent", "wall_attenuation", "wall_spacing", "no_walls"];
+		  let change_model = model_args.iter().any(|a| subcmd_args.value_source(a) == Some(ValueSource::CommandLine));
+
+		  if name.is_none() && notes.is_none() && !change_model {
+			 println!("Name:               {}", scan_map.name);
+			 println!("Notes:              {}", scan_map.notes);
+			 println!("Coordinate system:  {}", scan_map.coordinate_system);
+			 println!("Units:              {}", scan_map.units);
+			 println!("Scan filters:       {}", scan_map.scan_filters);
+			 println!("Path loss model:    {}", scan_map.path_loss);
+			 println!("Schema version:     {}", scan_map.version);
+			 return;
+		  }
+
+		  if let Some(name) = name {
+			 if name.is_empty() {
+				die("name cannot be empty");
+			 }
+			 scan_map.name = name.to_string();
+		  }
+		  if let Some(notes) = notes {
+			 scan_map.notes = notes.to_string();
+		  }
+		  if change_model {
+			 scan_map.path_loss = path_loss_arg(subcmd_args, &scan_map);
+			 if subcmd_args.get_flag("no_walls") {
+				scan_map.path_loss.wall_spacing = None;
+			 }
+			 println!("Path loss model: {}", scan_map.path_loss);
+		  }
+
+		  scan_map.write(map_file_path, &write_opts).expect("failed to save scan map");
+		  println!("Updated {}", scan_map);
+	   },
+	   SubCmd::Session => {
+		  let sessions = session::sessions(&scan_map);
+		  match output {
+			 OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&sessions).expect("failed to serialize sessions")),
+			 OutputFormat::Text => session::print_sessions(&sessions),
+		  };
+	   },
+	   SubCmd::Annotate(subcmd_args) => {
+		  if let Some(args) = subcmd_args.subcommand_matches("set") {
+			 let mac = args.get_one::<String>("bssid").map(String::as_str).unwrap().to_lowercase();
+			 if scan_map.ssid_of(&mac).is_none() {
+				logging::warn(&format!("{} has not been seen by any node", mac));
+			 }
+
+			 scan_map.annotations.insert(mac.clone(), args.get_one::<String>("note").map(String::as_str).unwrap().to_string());
+
+			 scan_map.write(map_file_path, &write_opts).expect("failed to save scan map");
+			 println!("Annotated {}", mac);
+		  } else if let Some(args) = subcmd_args.subcommand_matches("remove") {
+			 let mac = args.get_one::<String>("bssid").map(String::as_str).unwrap().to_lowercase();
+			 if scan_map.annotations.remove(&mac).is_none() {
+				die(&format!("{} has no note", mac));
+			 }
+
+			 scan_map.write(map_file_path, &write_opts).expect("failed to save scan map");
+			 println!("Removed the note of {}", mac);
+		  } else {
+			 let rows: Vec<Vec<String>> = scan_map.annotations.iter()
+				.map(|(mac, note)| vec![mac.clone(), scan_map.ssid_of(mac).unwrap_or("").to_string(), note.clone()])
+				.collect();
+
+			 match rows.is_empty() {
+				true => println!("No annotations"),
+				false => table::print_table(&["BSSID", "SSID", "Note"], &rows),
+			 };
+		  }
+	   },
+	   SubCmd::Undo => {
+		  if scan_map.undo().is_some() {
+			 scan_map.write(map_file_path, &write_opts).expect("failed to save scan map");
+		  }
+	   },
+	   SubCmd::Node(subcmd_args) => {
+		  if let Some(args) = subcmd_args.subcommand_matches("delete") {
+			 let index = parse_node_index(args, &scan_map);
+			 let node = &scan_map.nodes[index];
+
+			 let description = format!("node {} at {} with {} network{}", index, node.position.format(scan_map.coordinate_system, scan_map.units, scan_map.units), node.networks.len(), match node.networks.len() != 1 {
+				true => "s",
+				false => "",
+			 });
+			 if !args.get_flag("yes") && !confirm(&format!("Delete {}?", description)) {
+				println!("Kept {}", description);
+				return;
+			 }
+
+			 scan_map.nodes.remove(index);
+
+			 scan_map.write(map_file_path, &write_opts).expect("failed to save scan map");
+			 println!("Deleted {}", description);
+		  } else if let Some(args) = subcmd_args.subcommand_matches("edit") {
+			 let index = parse_node_index(args, &scan_map);
+			 if let Some(name) = args.get_one::<String>("floor").map(String::as_str) {
+				if scan_map.floor(name).is_none() {
+				    die(&format!("map has no floor named \"{}\"", name));
+				}
+			 }
+
+			 let (crs, units) = (scan_map.coordinate_system, scan_map.units);
+			 let node = &mut scan_map.nodes[index];
+
+			 if let Some(x) = parse_optional_arg(args, "x") {
+				node.position.x = x;
+			 }
+			 if let Some(y) = parse_optional_arg(args, "y") {
+				node.position.y = y;
+			 }
+			 if let Some(z) = parse_optional_arg(args, "z") {
+				node.position.z = z;
+			 }
+			 if let Some(floor) = args.get_one::<String>("floor").map(String::as_str) {
+				node.floor = Some(floor.to_string());
+			 }
+			 if let Some(notes) = args.get_one::<String>("notes").map(String::as_str) {
+				node.notes = notes.to_string();
+			 }
+			 let untags = string_values(args, "untag");
+			 node.tags.retain(|t| !untags.contains(t));
+			 for tag in string_values(args, "tag") {
+				if !node.tags.contains(&tag) {
+				    node.tags.push(tag);
+				}
+			 }
+
+			 let position = node.position.format(crs, units, units);
+
+			 scan_map.write(map_file_path, &write_opts).expect("failed to save scan map");
+			 println!("Updated node {}, now at {}", index, position);
+		  }
+	   },
+	   SubCmd::FloorPlan(subcmd_args) => {
+		  let image = Path::new(subcmd_args.get_one::<String>("image").map(String::as_str).unwrap());
+		  let plan = FloorPlan{
+			 image: relative_to_map(image, map_file_path),
+			 scale: parse_arg(subcmd_args, "scale"),
+			 origin_x: parse_arg(subcmd_args, "origin_x"),
+			 origin_y: parse_arg(subcmd_args, "origin_y"),
+		  };
+
+		  match subcmd_args.get_one::<String>("floor").map(String::as_str) {
+			 Some(name) => match scan_map.floors.iter_mut().find(|f| f.name == name) {
+				Some(floor) => floor.floor_plan = Some(plan),
+				None => die(&format!("map has no floor named \"{}\"", name)),
+			 },
+			 None => scan_map.floor_plan = Some(plan),
+		  };
+
+		  scan_map.write(map_file_path, &write_opts).expect("failed to save scan map");
+		  println!("Attached floor plan \"{}\"", image.display());
+	   },
+	   SubCmd::Render(subcmd_args) => {
+		  let opts = render::RenderOptions{
+			 ssid: subcmd_args.get_one::<String>("ssid").map(String::as_str).unwrap().to_string(),
+			 floor: subcmd_args.get_one::<String>("floor").map(String::as_str).map(|f| f.to_string()),
+			 output: PathBuf::from(subcmd_args.get_one::<String>("output").map(String::as_str).unwrap()),
+			 format: parse_arg(subcmd_args, "format"),
+			 heatmap: match subcmd_args.get_flag("no_heatmap") {
+				true => None,
+				false => Some(render::HeatmapOptions{
+				    spacing: parse_optional_arg(subcmd_args, "spacing"),
+				    power: parse_arg(subcmd_args, "power"),
+				    radius: parse_optional_arg(subcmd_args, "radius"),
+				    unseen: parse_optional_arg(subcmd_args, "unseen"),
+				}),
+			 },
+			 gaps: parse_optional_arg(subcmd_args, "gaps"),
+		  };
+
+		  if let Err(e) = render::render(&scan_map, map_dir(map_file_path), &opts) {
+			 die(&format!("failed to render map: {}", e));
+		  }
+
+		  println!("Saved rendering to \"{}\"", opts.output.display());
+	   },
+	   SubCmd::Report(subcmd_args) => {
+		  let opts = report::ReportOptions{
+			 heatmaps: parse_arg(subcmd_args, "heatmaps"),
+			 heatmap: render::HeatmapOptions{
+				spacing: parse_optional_arg(subcmd_args, "spacing"),
+				power: parse_arg(subcmd_args, "power"),
+				radius: parse_optional_arg(subcmd_args, "radius"),
+				unseen: parse_optional_arg(subcmd_args, "unseen"),
+			 },
+			 gaps: parse_optional_arg(subcmd_args, "gaps"),
+		  };
+		  let output_path = Path::new(subcmd_args.get_one::<String>("output").map(String::as_str).unwrap());
+
+		  let html = report::report(&scan_map, map_dir(map_file_path), &opts).unwrap_or_else(|e| die(&format!("failed to write report: {}", e)));
+		  if let Err(e) = fs::write(output_path, html) {
+			 die(&format!("failed to save report to \"{}\": {}", output_path.display(), e));
+		  }
+
+		  println!("Saved report to \"{}\"", output_path.display());
+	   },
+	   SubCmd::Serve(subcmd_args) => {
+		  let opts = serve::ServeOptions{
+			 bind: subcmd_args.get_one::<String>("bind").map(String::as_str).unwrap().to_string(),
+			 port: parse_arg(subcmd_args, "port"),
+			 accept_nodes: subcmd_args.get_flag("accept_nodes"),
+			 token: subcmd_args.get_one::<String>("token").map(String::as_str).map(|t| t.to_string()),
+		  };
+
+		  if let Err(e) = serve::serve(map_file_path, &opts, &write_opts) {
+			 die(&format!("failed to serve map: {}", e));
+		  }
+	   },
+	   SubCmd::View(subcmd_args) => {
+		  let terminal = terminal_size::terminal_size().map(|(w, h)| (w.0 as usize, h.0 as usize)).unwrap_or((80, 24));
+		  let opts = view::ViewOptions{
+			 ssid: subcmd_args.get_one::<String>("ssid").map(String::as_str).unwrap().to_string(),
+			 floor: subcmd_args.get_one::<String>("floor").map(String::as_str).map(|f| f.to_string()),
+			 size: (parse_optional_arg(subcmd_args, "width").unwrap_or(terminal.0), parse_optional_arg(subcmd_args, "height").unwrap_or(terminal.1)),
+			 color: !subcmd_args.get_flag("no_color") && stdout().is_terminal(),
+			 heatmap: render::HeatmapOptions{
+				spacing: parse_optional_arg(subcmd_args, "spacing"),
+				power: parse_arg(subcmd_args, "power"),
+				radius: parse_optional_arg(subcmd_args, "radius"),
+				unseen: parse_optional_arg(subcmd_args, "unseen"),
+			 },
+		  };
+
+		  if let Err(e) = view::view(&scan_map, &opts) {
+			 die(&format!("failed to draw heatmap: {}", e));
 		  }
 	   },
     };
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// value with the fields of given laid over it, arrays element by element.
+    fn overlay(value: &mut serde_json::Value, given: &serde_json::Value) {
+        match (value, given) {
+            (serde_json::Value::Object(value), serde_json::Value::Object(given)) => {
+                for (key, v) in given {
+                    match value.get_mut(key) {
+                        Some(existing) => overlay(existing, v),
+                        None => {
+                            value.insert(key.clone(), v.clone());
+                        },
+                    };
+                }
+            },
+            (serde_json::Value::Array(value), serde_json::Value::Array(given)) if value.len() == given.len() => {
+                for (existing, v) in value.iter_mut().zip(given) {
+                    overlay(existing, v);
+                }
+            },
+            (value, given) => *value = given.clone(),
+        }
+    }
+
+    /// Map of the fields of given. The fields it leaves out are filled in like a map file of the first schema version
+    /// is upgraded, so tests only give the fields they are about.
+    pub fn scan_map(given: serde_json::Value) -> ScanMap {
+        let mut value = serde_json::json!({
+            "name": "Test",
+            "notes": "",
+            "nodes": [],
+        });
+        overlay(&mut value, &given);
+        value.as_object_mut().expect("map is an object").remove("version");
+
+        for node in value["nodes"].as_array_mut().expect("nodes is an array") {
+            let node = node.as_object_mut().expect("node is an object");
+            node.entry("notes").or_insert_with(|| serde_json::json!(""));
+            node.entry("networks").or_insert_with(|| serde_json::json!([]));
+            for network in node["networks"].as_array_mut().expect("networks is an array") {
+                let network = network.as_object_mut().expect("network is an object");
+                network.entry("ssid").or_insert_with(|| serde_json::json!(""));
+                network.entry("channel").or_insert_with(|| serde_json::json!("1"));
+                network.entry("time_scanned").or_insert_with(|| serde_json::json!(0));
+            }
+        }
+
+        migrate::migrate(&mut value).expect("map migrates");
+        overlay(&mut value, &given);
+        value["version"] = serde_json::json!(migrate::SCHEMA_VERSION);
+        serde_json::from_value(value).expect("map deserializes")
+    }
+
+    /// Empty directory for the files of a test.
+    pub fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("wifi-mapper-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).expect("failed to create test directory");
+        dir
+    }
+
+    #[test]
+    fn rotate_backups_keeps_the_newest_copies() {
+        let dir = test_dir("rotate");
+        let p = dir.join("map.json");
+
+        for version in 1..=4 {
+            fs::write(&p, version.to_string()).unwrap();
+            rotate_backups(&p, 2).unwrap();
+        }
+
+        assert_eq!(fs::read_to_string(backup_path(&p, 1)).unwrap(), "4");
+        assert_eq!(fs::read_to_string(backup_path(&p, 2)).unwrap(), "3");
+        assert!(!backup_path(&p, 3).exists());
+        assert_eq!(backup_path(&p, 2), dir.join("map.json.2.bak"));
+    }
+
+    #[test]
+    fn write_replaces_the_map_and_backs_up_the_old_one() {
+        let dir = test_dir("write");
+        let p = dir.join("map.json");
+        let opts = WriteOptions{
+            backups: 1,
+            pretty: false,
+            remote: None,
+        };
+
+        let mut scan_map = ScanMap::new();
+        scan_map.name = String::from("First");
+        scan_map.write(&p, &opts).unwrap();
+        scan_map.name = String::from("Second");
+        scan_map.write(&p, &opts).unwrap();
+
+        assert_eq!(ScanMap::read(&p).unwrap().name, "Second");
+        assert_eq!(ScanMap::read(&backup_path(&p, 1)).unwrap().name, "First");
+        assert!(!sibling_path(&p, ".", ".tmp").exists());
+    }
+
+    #[test]
+    fn write_pretty_sorts_keys() {
+        let p = test_dir("pretty").join("map.json");
+        ScanMap::new().write(&p, &WriteOptions{
+            backups: 0,
+            pretty: true,
+            remote: None,
+        }).unwrap();
+
+        let text = fs::read_to_string(&p).unwrap();
+        let keys: Vec<&str> = text.lines()
+            .filter(|l| l.starts_with("  \"") && !l.starts_with("   "))
+            .map(|l| l.trim().split('"').nth(1).unwrap())
+            .collect();
+        let mut sorted = keys.clone();
+        sorted.sort_unstable();
+        assert_eq!(keys, sorted);
+        assert!(keys.contains(&"version"));
+        assert!(text.ends_with("}\n"));
+    }
+
+    #[test]
+    fn parse_strength_accepts_decibels_with_or_without_a_unit() {
+        assert_eq!(parse_strength("-66.00"), Some(-66.0));
+        assert_eq!(parse_strength(" -66 dBm "), Some(-66.0));
+        assert_eq!(parse_strength("-71dBm"), Some(-71.0));
+        assert_eq!(parse_strength("unknown"), None);
+        assert_eq!(parse_strength("NaN"), None);
+    }
+
+    #[test]
+    fn hidden_ssids_are_empty_or_null_bytes() {
+        assert!(is_hidden_ssid(""));
+        assert!(is_hidden_ssid(" "));
+        assert!(is_hidden_ssid("\\x00\\x00\\x00"));
+        assert!(is_hidden_ssid("\0\0"));
+        assert!(!is_hidden_ssid("CorpWiFi"));
+    }
+
+    #[test]
+    fn scan_filters_match_ssid_globs_and_bssid_prefixes() {
+        let scan_map = scan_map(serde_json::json!({
+            "nodes": [{"position": {"x": 0.0, "y": 0.0, "z": 0.0}, "networks": [
+                {"mac": "84:D4:7E:69:2F:E2", "ssid": "CorpWiFi"},
+                {"mac": "84:d4:7e:00:00:01", "ssid": "CorpGuest"},
+                {"mac": "aa:bb:cc:00:11:22", "ssid": ""},
+            ]}],
+        }));
+        let kept = |filters: &ScanFilters| -> Vec<String> {
+            scan_map.nodes[0].networks.iter()
+                .filter(|n| filters.matches(n))
+                .map(|n| n.display_ssid().to_string())
+                .collect()
+        };
+
+        assert_eq!(kept(&ScanFilters::default()).len(), 3);
+        assert_eq!(kept(&ScanFilters{
+            only_ssids: vec![String::from("Corp*")],
+            ignore_ssids: vec![String::from("*Guest")],
+            ..ScanFilters::default()
+        }), vec!["CorpWiFi"]);
+        assert_eq!(kept(&ScanFilters{
+            only_bssid_prefixes: vec![String::from("84:D4:7E:69")],
+            ..ScanFilters::default()
+        }), vec!["CorpWiFi"]);
+        assert_eq!(kept(&ScanFilters{
+            only_ssids: vec![String::from("<hidden>")],
+            ..ScanFilters::default()
+        }), vec!["<hidden>"]);
+        assert_eq!(ScanFilters::default().to_string(), "none");
+    }
+
+    #[test]
+    fn networks_in_keeps_networks_scanned_in_the_range() {
+        let scan_map = scan_map(serde_json::json!({
+            "nodes": [{"position": {"x": 0.0, "y": 0.0, "z": 0.0}, "networks": [
+                {"mac": "84:d4:7e:69:2f:e2", "ssid": "Early", "time_scanned": 1000},
+                {"mac": "84:d4:7e:69:2f:e3", "ssid": "Late", "time_scanned": 5000},
+            ]}],
+        }));
+        let node = &scan_map.nodes[0];
+        let ssids = |times: timestamp::TimeRange| -> Vec<String> {
+            node.networks_in(&times).map(|n| n.display_ssid().to_string()).collect()
+        };
+
+        assert_eq!(ssids(timestamp::TimeRange::default()), vec!["Early", "Late"]);
+        assert_eq!(ssids(timestamp::TimeRange{ since: Some(2000), until: None }), vec!["Late"]);
+        assert_eq!(ssids(timestamp::TimeRange{ since: None, until: Some(1000) }), vec!["Early"]);
+        assert_eq!(node.measured(), Some(5000));
+    }
+
+    /// Values an entry parses to, added to the coordinate it is relative to.
+    fn parsed(s: &str, crs: CoordinateSystem, previous: Option<&Coordinate>) -> Result<Vec<Result<f64, String>>, String> {
+        Coordinate::parse_values(s, crs, previous).map(|(origin, values)| {
+            values.into_iter()
+                .zip([origin.x, origin.y, origin.z].iter())
+                .map(|(v, o)| v.map(|v| v + o))
+                .collect()
+        })
+    }
+
+    #[test]
+    fn parse_values_accepts_other_separators_and_decimal_commas() {
+        for entry in ["1.5 2 3", "1.5,2,3", "1.5, 2, 3", "1.5;2;3", "1,5 2 3", "1.5\t2\t3", " 1.5  2 3 ", "1+0.5 4/2 3"] {
+            assert_eq!(parsed(entry, CoordinateSystem::Local, None), Ok(vec![Ok(1.5), Ok(2.0), Ok(3.0)]), "{:?}", entry);
+        }
+
+        assert_eq!(parsed("1 2", CoordinateSystem::Local, None), Err(String::from("Must be in format \"x y z\"")));
+        let values = parsed("1 two 3", CoordinateSystem::Local, None).unwrap();
+        assert!(values[1].as_ref().unwrap_err().starts_with("Failed to parse y \"two\""));
+    }
+
+    #[test]
+    fn parse_values_adds_offsets_to_the_previous_node() {
+        let previous = Coordinate{ x: 10.0, y: 5.0, z: 1.0 };
+        assert_eq!(parsed("+1.5 0 0", CoordinateSystem::Local, Some(&previous)), Ok(vec![Ok(11.5), Ok(5.0), Ok(1.0)]));
+        assert_eq!(parsed("+-1.5 -1 0", CoordinateSystem::Local, Some(&previous)), Ok(vec![Ok(8.5), Ok(4.0), Ok(1.0)]));
+        assert_eq!(parsed("1.5 0 0", CoordinateSystem::Local, Some(&previous)), Ok(vec![Ok(1.5), Ok(0.0), Ok(0.0)]));
+
+        assert_eq!(parsed("+1 0 0", CoordinateSystem::Local, None), Err(String::from("There is no previous node to offset from")));
+        assert_eq!(parsed("+1 0 0", CoordinateSystem::Wgs84, Some(&previous)), Err(String::from("Offsets can only be entered in local maps")));
+    }
+}
@@ -0,0 +1,95 @@
+//! Writes the manual pages of the command line with clap_mangen. The pages are generated from
+//! the clap definition of the command line, so new sub-commands and flags appear in them without
+//! editing this module. The main page lists the sub-commands, which each have their own page
+//! named like wifi-mapper-record(1).
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use clap::Command;
+use clap_mangen::roff::{bold, italic, roman, Roff};
+use clap_mangen::Man;
+
+/// app as it is run as bin_name, with the display names of its sub-commands set to the names of their pages.
+fn built(app: &Command, bin_name: &'static str) -> Command {
+    let mut app = app.clone()
+        .name(bin_name)
+        .bin_name(bin_name)
+        .disable_help_subcommand(true);
+    app.build();
+    app
+}
+
+fn man(app: Command, bin_name: &'static str) -> Man {
+    Man::new(app).source(format!("{} {}", bin_name, env!("CARGO_PKG_VERSION")))
+}
+
+/// Write a section which clap_mangen renders, without the preamble it starts with since the title already wrote it.
+fn write_section(out: &mut impl Write, render: impl Fn(&mut dyn Write) -> io::Result<()>) -> io::Result<()> {
+    let mut section = Vec::<u8>::new();
+    render(&mut section)?;
+
+    let start = section.windows(4).position(|w| w == b"\n.SH").map_or(0, |i| i + 1);
+    out.write_all(&section[start..])
+}
+
+/// Write the main manual page of app, which is run as bin_name.
+pub fn write(app: &Command, bin_name: &'static str, mut out: impl Write) -> io::Result<()> {
+    let page = man(built(app, bin_name), bin_name);
+    page.render_title(&mut out)?;
+    write_section(&mut out, |w| page.render_name_section(w))?;
+    write_section(&mut out, |w| page.render_synopsis_section(w))?;
+    write_section(&mut out, |w| page.render_options_section(w))?;
+    write_section(&mut out, |w| page.render_subcommands_section(w))?;
+
+    let mut files = Roff::default();
+    files.control("SH", ["FILES"]);
+    files.control("TP", []);
+    files.text([italic("~/.config/wifi-mapper/config.toml")]);
+    files.text([roman("Default flags, see "), bold("--config"), roman(".")]);
+    out.write_all(files.to_roff().as_bytes())?;
+
+    out.flush()
+}
+
+/// Write the pages of a sub-command and its own sub-commands into dir.
+fn write_subcommand(app: &Command, bin_name: &'static str, dir: &Path, written: &mut Vec<PathBuf>) -> io::Result<()> {
+    for subcommand in app.get_subcommands().filter(|s| !s.is_hide_set()) {
+        written.push(man(subcommand.clone(), bin_name).generate_to(dir)?);
+        write_subcommand(subcommand, bin_name, dir, written)?;
+    }
+
+    Ok(())
+}
+
+/// Write the main page and the page of every sub-command into dir, returns the paths of the pages.
+pub fn write_all(app: &Command, bin_name: &'static str, dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let app = built(app, bin_name);
+
+    let main_page = dir.join(format!("{}.1", bin_name));
+    write(&app, bin_name, fs::File::create(&main_page)?)?;
+
+    let mut written = vec![main_page];
+    write_subcommand(&app, bin_name, dir, &mut written)?;
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write_adds_the_config_file_to_the_main_page() {
+        let app = Command::new("app").about("Maps things").subcommand(Command::new("record").about("Records a node"));
+        let mut page = Vec::new();
+        write(&app, "wifi-mapper", &mut page).unwrap();
+        let page = String::from_utf8(page).unwrap();
+
+        assert!(page.starts_with(".ie"));
+        assert_eq!(page.matches(".TH").count(), 1);
+        assert!(page.contains("wifi\\-mapper\\-record(1)"), "{}", page);
+        assert!(page.contains(".SH FILES"));
+        assert!(page.contains("config.toml"));
+    }
+}
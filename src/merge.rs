@@ -0,0 +1,264 @@
+//! Combines scan maps which were surveyed separately, e.g. different wings of one building,
+//! into a single map.
+
+use std::error::Error;
+use std::fmt;
+use std::path::Path;
+
+use crate::transform::{self, Transform};
+use crate::{CoordinateSystem, FloorPlan, ScanMap};
+
+/// Error which occurs when maps cannot be combined.
+#[derive(Debug)]
+pub struct MergeError {
+    /// Reason the maps could not be merged.
+    reason: String,
+}
+
+impl fmt::Display for MergeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.reason)
+    }
+}
+
+impl Error for MergeError {}
+
+/// A map to merge.
+pub struct MergeInput<'a> {
+    /// Map which was read from path.
+    pub map: ScanMap,
+
+    /// File the map was read from.
+    pub path: &'a Path,
+
+    /// Distance to move the map's positions along the x, y, and z axes, in the units of the first input.
+    pub offset: (f64, f64, f64),
+}
+
+/// Options which resolve conflicts between the maps being merged.
+pub struct MergeOptions<'a> {
+    /// Name of the merged map, required if the inputs have different names.
+    pub name: Option<String>,
+
+    /// Notes of the merged map. If None the distinct notes of the inputs are joined.
+    pub notes: Option<String>,
+
+    /// File the merged map will be saved to, floor plan paths are made relative to it.
+    pub output: &'a Path,
+}
+
+/// Parse an offset in the form "x,y,z". The z distance may be left out.
+pub fn parse_offset(s: &str) -> Result<(f64, f64, f64), String> {
+    let parts: Vec<&str> = s.split(',').map(|p| p.trim()).collect();
+    if parts.len() != 2 && parts.len() != 3 {
+        return Err(format!("invalid offset \"{}\", must be in format \"x,y,z\"", s));
+    }
+
+    let mut offset = [0.0; 3];
+    for (i, part) in parts.iter().enumerate() {
+        offset[i] = match part.parse::<f64>() {
+            Ok(v) => v,
+            Err(e) => return Err(format!("invalid offset \"{}\": {}", s, e)),
+        };
+    }
+
+    Ok((offset[0], offset[1], offset[2]))
+}
+
+/// Point a floor plan read from input_path at the same image when the map is saved to output_path.
+fn rebase_plan(plan: &mut FloorPlan, input_path: &Path, output_path: &Path) {
+    let image = crate::map_dir(input_path).join(&plan.image);
+
+    if image.exists() {
+        plan.image = crate::relative_to_map(&image, output_path);
+    }
+}
+
+/// Combine maps into one. Inputs are converted to the units of the first input and moved by their offsets. Floors with the same name are combined and sessions are renumbered.
+pub fn merge(inputs: Vec<MergeInput>, opts: &MergeOptions) -> Result<ScanMap, MergeError> {
+    let first = match inputs.first() {
+        Some(i) => i,
+        None => return Err(MergeError{
+            reason: String::from("no maps to merge"),
+        }),
+    };
+    let crs = first.map.coordinate_system;
+    let units = first.map.units;
+
+    let mut names = Vec::<String>::new();
+    let mut notes = Vec::<String>::new();
+    let mut merged = ScanMap::new();
+    merged.coordinate_system = crs;
+    merged.units = units;
+    merged.path_loss = first.map.path_loss;
+
+    for mut input in inputs {
+        let path = input.path.display();
+
+        if input.map.coordinate_system != crs {
+            return Err(MergeError{
+                reason: format!("\"{}\" uses {} coordinates but the first map uses {}", path, input.map.coordinate_system, crs),
+            });
+        }
+
+        let moved = input.offset != (0.0, 0.0, 0.0);
+        if moved && crs != CoordinateSystem::Local {
+            return Err(MergeError{
+                reason: format!("only maps in local coordinates can be offset, \"{}\" uses {}", path, crs),
+            });
+        }
+
+        if moved || input.map.units != units {
+            let scale = input.map.units.convert(1.0, units);
+            transform::transform(&mut input.map, &Transform{
+                translate: input.offset,
+                rotate: 0.0,
+                scale,
+            });
+        }
+
+        if !names.contains(&input.map.name) {
+            names.push(input.map.name.clone());
+        }
+        if !input.map.notes.is_empty() && !notes.contains(&input.map.notes) {
+            notes.push(input.map.notes.clone());
+        }
+
+        if let Some(mut plan) = input.map.floor_plan.take() {
+            if merged.floor_plan.is_none() {
+                rebase_plan(&mut plan, input.path, opts.output);
+                merged.floor_plan = Some(plan);
+            }
+        }
+
+        for mut floor in input.map.floors.drain(..) {
+            if let Some(plan) = &mut floor.floor_plan {
+                rebase_plan(plan, input.path, opts.output);
+            }
+
+            match merged.floors.iter_mut().find(|f| f.name == floor.name) {
+                Some(existing) if (existing.elevation - floor.elevation).abs() > 1e-9 => return Err(MergeError{
+                    reason: format!("floor \"{}\" is at elevation {} in \"{}\" but {} in an earlier map", floor.name, floor.elevation, path, existing.elevation),
+                }),
+                Some(existing) => {
+                    if existing.floor_plan.is_none() {
+                        existing.floor_plan = floor.floor_plan;
+                    }
+                },
+                None => merged.floors.push(floor),
+            };
+        }
+
+        for (mac, note) in input.map.annotations {
+            merged.annotations.entry(mac).or_insert(note);
+        }
+        for (mac, location) in input.map.access_points {
+            merged.access_points.entry(mac).or_insert(location);
+        }
+
+        // Sessions are renumbered so the walkthroughs of each input stay apart
+        let session_offset = merged.sessions.iter().map(|s| s.id).max().unwrap_or(0);
+        for mut session in input.map.sessions.drain(..) {
+            session.id += session_offset;
+            merged.sessions.push(session);
+        }
+        for node in &mut input.map.nodes {
+            node.session = node.session.map(|id| id + session_offset);
+        }
+
+        merged.nodes.append(&mut input.map.nodes);
+    }
+
+    merged.name = match (&opts.name, names.len()) {
+        (Some(name), _) => name.clone(),
+        (None, 1) => names.remove(0),
+        (None, _) => return Err(MergeError{
+            reason: format!("maps have different names ({}), choose one with --name", names.join(", ")),
+        }),
+    };
+    merged.notes = match &opts.notes {
+        Some(n) => n.clone(),
+        None => notes.join("; "),
+    };
+    merged.floors.sort_by(|a, b| a.elevation.partial_cmp(&b.elevation).unwrap_or(std::cmp::Ordering::Equal));
+
+    Ok(merged)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::scan_map;
+    use serde_json::json;
+
+    fn input(map: ScanMap, offset: (f64, f64, f64)) -> MergeInput<'static> {
+        MergeInput{
+            map,
+            path: Path::new("wing.json"),
+            offset,
+        }
+    }
+
+    fn opts(name: Option<&str>) -> MergeOptions<'static> {
+        MergeOptions{
+            name: name.map(String::from),
+            notes: None,
+            output: Path::new("merged.json"),
+        }
+    }
+
+    #[test]
+    fn parse_offset_accepts_two_or_three_distances() {
+        assert_eq!(parse_offset("1,2,3"), Ok((1.0, 2.0, 3.0)));
+        assert_eq!(parse_offset(" 1.5, -2 "), Ok((1.5, -2.0, 0.0)));
+        assert!(parse_offset("1").is_err());
+        assert!(parse_offset("1,2,3,4").is_err());
+        assert!(parse_offset("1,north").is_err());
+    }
+
+    #[test]
+    fn merge_moves_and_converts_the_later_maps() {
+        let east = scan_map(json!({
+            "name": "Office",
+            "notes": "East wing",
+            "floors": [{"name": "Ground", "elevation": 0.0}],
+            "nodes": [{"position": {"x": 1.0, "y": 0.0, "z": 0.0}, "floor": "Ground"}],
+        }));
+        let west = scan_map(json!({
+            "name": "Office",
+            "notes": "West wing",
+            "units": "feet",
+            "floors": [{"name": "Ground", "elevation": 0.0}, {"name": "Roof", "elevation": 30.0}],
+            "nodes": [{"position": {"x": 10.0, "y": 0.0, "z": 0.0}, "floor": "Ground"}],
+        }));
+
+        let merged = merge(vec![input(east, (0.0, 0.0, 0.0)), input(west, (5.0, 0.0, 0.0))], &opts(None)).unwrap();
+        assert_eq!(merged.name, "Office");
+        assert_eq!(merged.notes, "East wing; West wing");
+        assert!(merged.units == crate::Units::Meters);
+        assert_eq!(merged.nodes.len(), 2);
+        assert!((merged.nodes[1].position.x - (10.0 * 0.3048 + 5.0)).abs() < 1e-9);
+
+        let floors: Vec<(&str, f64)> = merged.floors.iter().map(|f| (f.name.as_str(), (f.elevation * 1000.0).round() / 1000.0)).collect();
+        assert_eq!(floors, vec![("Ground", 0.0), ("Roof", 9.144)]);
+    }
+
+    #[test]
+    fn merge_reports_conflicts() {
+        let named = |name: &str| scan_map(json!({"name": name}));
+
+        let e = merge(vec![input(named("East"), (0.0, 0.0, 0.0)), input(named("West"), (0.0, 0.0, 0.0))], &opts(None)).err().unwrap();
+        assert!(e.to_string().contains("choose one with --name"), "{}", e);
+        let merged = merge(vec![input(named("East"), (0.0, 0.0, 0.0)), input(named("West"), (0.0, 0.0, 0.0))], &opts(Some("Office"))).unwrap();
+        assert_eq!(merged.name, "Office");
+
+        let floor = |elevation: f64| scan_map(json!({"floors": [{"name": "Ground", "elevation": elevation}]}));
+        let e = merge(vec![input(floor(0.0), (0.0, 0.0, 0.0)), input(floor(1.0), (0.0, 0.0, 0.0))], &opts(None)).err().unwrap();
+        assert!(e.to_string().contains("floor \"Ground\" is at elevation 1"), "{}", e);
+
+        let gps = scan_map(json!({"coordinate_system": "wgs84"}));
+        let e = merge(vec![input(named("Test"), (0.0, 0.0, 0.0)), input(gps, (0.0, 0.0, 0.0))], &opts(None)).err().unwrap();
+        assert!(e.to_string().contains("uses wgs84 coordinates"), "{}", e);
+        assert!(merge(Vec::new(), &opts(None)).is_err());
+    }
+}
@@ -0,0 +1,546 @@
+//! Upgrades scan map files written by older versions of the tool to the current schema.
+//!
+//! Migrations operate on the raw JSON value before it is deserialized into a ScanMap, so
+//! old files never have to be deserializable by the current structs. To change the
+//! schema: append a migration to MIGRATIONS which converts a value of the previous
+//! version to the new format. SCHEMA_VERSION is derived from the number of migrations.
+//! A migration must upgrade each node on its own, without looking at other nodes or the
+//! rest of the map, since streamed maps are upgraded one node at a time by migrate_node.
+
+use std::error::Error;
+use std::fmt;
+
+use serde_json::Value;
+
+/// Converts a scan map JSON value from one schema version to the next.
+type Migration = fn(&mut Value) -> Result<(), Box<dyn Error>>;
+
+/// Ordered list of migrations. The migration at index i upgrades a version i map to version i + 1.
+const MIGRATIONS: &[Migration] = &[
+    v0_add_version,
+    v1_numeric_strength,
+    v2_frequency_band,
+    v3_security,
+    v4_channel_width,
+    v5_standard,
+    v6_vendor,
+    v7_hidden,
+    v8_coordinate_system,
+    v9_floors,
+    v10_floor_plan,
+    v11_units,
+    v12_tags,
+    v13_annotations,
+    v14_sessions,
+    v15_samples,
+    v16_scan_filters,
+    v17_interfaces,
+    v18_beacon_details,
+    v19_noise,
+    v20_clients,
+    v21_snr,
+    v22_ble_devices,
+    v23_access_points,
+    v24_path_loss,
+    v25_lowercase_bssids,
+    v26_upload_ids,
+];
+
+/// Schema version of scan maps written by this build.
+pub const SCHEMA_VERSION: u64 = MIGRATIONS.len() as u64;
+
+/// Error which occurs while upgrading a scan map.
+#[derive(Debug)]
+pub enum MigrationError {
+    /// The top level JSON value is not an object.
+    NotAnObject,
+
+    /// The version field is present but is not a non-negative integer.
+    InvalidVersion(Value),
+
+    /// The file was written by a newer build of the tool.
+    UnsupportedVersion(u64),
+}
+
+impl fmt::Display for MigrationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MigrationError::NotAnObject => write!(f, "scan map must be a JSON object"),
+            MigrationError::InvalidVersion(v) => write!(f, "invalid scan map version: {}", v),
+            MigrationError::UnsupportedVersion(v) => write!(f, "scan map schema version {} is newer than the latest version supported by this build ({}), upgrade wifi-mapper to load it", v, SCHEMA_VERSION),
+        }
+    }
+}
+
+impl Error for MigrationError {}
+
+/// Determine the schema version of a scan map JSON value. Files written before the version field existed are version 0.
+pub fn version_of(value: &Value) -> Result<u64, MigrationError> {
+    let obj = value.as_object().ok_or(MigrationError::NotAnObject)?;
+
+    match obj.get("version") {
+        None => Ok(0),
+        Some(v) => v.as_u64().ok_or_else(|| MigrationError::InvalidVersion(v.clone())),
+    }
+}
+
+/// Upgrade a scan map JSON value in place to SCHEMA_VERSION. Returns the version the value was at before migrating.
+pub fn migrate(value: &mut Value) -> Result<u64, Box<dyn Error>> {
+    let from_version = version_of(value)?;
+    if from_version > SCHEMA_VERSION {
+        return Err(Box::new(MigrationError::UnsupportedVersion(from_version)));
+    }
+
+    for (i, migration) in MIGRATIONS.iter().enumerate().skip(from_version as usize) {
+        migration(value)?;
+
+        value.as_object_mut()
+            .ok_or(MigrationError::NotAnObject)?
+            .insert(String::from("version"), Value::from(i as u64 + 1));
+    }
+
+    Ok(from_version)
+}
+
+/// Upgrade a node of a version version map to SCHEMA_VERSION, without the rest of the map.
+pub fn migrate_node(version: u64, node: Value) -> Result<Value, Box<dyn Error>> {
+    let mut value = serde_json::json!({
+        "version": version,
+        "nodes": [node],
+    });
+    migrate(&mut value)?;
+
+    Ok(value.get_mut("nodes")
+        .and_then(|n| n.as_array_mut())
+        .and_then(|n| n.pop())
+        .unwrap_or(Value::Null))
+}
+
+/// Version 0 maps predate the version field, the field itself is added by migrate().
+fn v0_add_version(_value: &mut Value) -> Result<(), Box<dyn Error>> {
+    Ok(())
+}
+
+/// Call f with every node object in a scan map value.
+fn for_each_node<F>(value: &mut Value, mut f: F) -> Result<(), Box<dyn Error>>
+where F: FnMut(&mut serde_json::Map<String, Value>) -> Result<(), Box<dyn Error>> {
+    let nodes = match value.get_mut("nodes").and_then(|n| n.as_array_mut()) {
+        Some(nodes) => nodes,
+        None => return Ok(()),
+    };
+
+    for node in nodes {
+        if let Some(obj) = node.as_object_mut() {
+            f(obj)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Call f with every network object of every node in a scan map value.
+fn for_each_network<F>(value: &mut Value, mut f: F) -> Result<(), Box<dyn Error>>
+where F: FnMut(&mut serde_json::Map<String, Value>) -> Result<(), Box<dyn Error>> {
+    for_each_node(value, |node| {
+        let networks = match node.get_mut("networks").and_then(|n| n.as_array_mut()) {
+            Some(networks) => networks,
+            None => return Ok(()),
+        };
+
+        for network in networks {
+            if let Some(obj) = network.as_object_mut() {
+                f(obj)?;
+            }
+        }
+
+        Ok(())
+    })
+}
+
+/// Version 1 stored strength as the string reported by wifiscanner. Parse it into a number, keeping the original string in strength_raw if it is not numeric.
+fn v1_numeric_strength(value: &mut Value) -> Result<(), Box<dyn Error>> {
+    for_each_network(value, |network| {
+        let raw = match network.get("strength") {
+            Some(Value::String(s)) => s.clone(),
+            _ => return Ok(()),
+        };
+
+        match crate::parse_strength(&raw) {
+            Some(v) => {
+                network.insert(String::from("strength"), Value::from(v));
+            },
+            None => {
+                network.insert(String::from("strength"), Value::Null);
+                network.insert(String::from("strength_raw"), Value::String(raw));
+            },
+        };
+
+        Ok(())
+    })
+}
+
+/// Version 3 added frequency and band fields, derive them from the recorded channel.
+fn v2_frequency_band(value: &mut Value) -> Result<(), Box<dyn Error>> {
+    for_each_network(value, |network| {
+        let frequency = network.get("channel")
+            .and_then(|c| c.as_str())
+            .and_then(crate::radio::channel_to_frequency);
+        let band = frequency.and_then(crate::radio::Band::from_frequency);
+
+        network.insert(String::from("frequency"), serde_json::to_value(frequency)?);
+        network.insert(String::from("band"), serde_json::to_value(band)?);
+
+        Ok(())
+    })
+}
+
+/// Version 4 added the security field. Older scans did not record it, so it is unknown.
+fn v3_security(value: &mut Value) -> Result<(), Box<dyn Error>> {
+    for_each_network(value, |network| {
+        network.entry("security").or_insert(Value::Null);
+
+        Ok(())
+    })
+}
+
+/// Version 5 added the channel_width field, which older scans did not record.
+fn v4_channel_width(value: &mut Value) -> Result<(), Box<dyn Error>> {
+    for_each_network(value, |network| {
+        network.entry("channel_width").or_insert(Value::Null);
+
+        Ok(())
+    })
+}
+
+/// Version 6 added the standard field, which older scans did not record.
+fn v5_standard(value: &mut Value) -> Result<(), Box<dyn Error>> {
+    for_each_network(value, |network| {
+        network.entry("standard").or_insert(Value::Null);
+
+        Ok(())
+    })
+}
+
+/// Version 7 added the vendor field, look it up for existing networks using the built in OUI registry.
+fn v6_vendor(value: &mut Value) -> Result<(), Box<dyn Error>> {
+    let vendors = crate::oui::OuiTable::new();
+
+    for_each_network(value, |network| {
+        let vendor = network.get("mac")
+            .and_then(|m| m.as_str())
+            .and_then(|m| vendors.lookup(m));
+
+        network.insert(String::from("vendor"), serde_json::to_value(vendor)?);
+
+        Ok(())
+    })
+}
+
+/// Version 8 added the is_hidden flag, derive it from the recorded SSID.
+fn v7_hidden(value: &mut Value) -> Result<(), Box<dyn Error>> {
+    for_each_network(value, |network| {
+        let hidden = network.get("ssid")
+            .and_then(|s| s.as_str())
+            .map(crate::is_hidden_ssid)
+            .unwrap_or(true);
+
+        network.insert(String::from("is_hidden"), Value::Bool(hidden));
+
+        Ok(())
+    })
+}
+
+/// Version 9 added the coordinate_system field. Older maps could only be in local coordinates.
+fn v8_coordinate_system(value: &mut Value) -> Result<(), Box<dyn Error>> {
+    value.as_object_mut()
+        .ok_or(MigrationError::NotAnObject)?
+        .entry("coordinate_system")
+        .or_insert_with(|| Value::from("local"));
+
+    Ok(())
+}
+
+/// Version 10 added floors to maps and a floor reference to nodes.
+fn v9_floors(value: &mut Value) -> Result<(), Box<dyn Error>> {
+    value.as_object_mut()
+        .ok_or(MigrationError::NotAnObject)?
+        .entry("floors")
+        .or_insert_with(|| Value::Array(Vec::new()));
+
+    for_each_node(value, |node| {
+        node.entry("floor").or_insert(Value::Null);
+
+        Ok(())
+    })
+}
+
+/// Version 11 added floor plans to maps and floors.
+fn v10_floor_plan(value: &mut Value) -> Result<(), Box<dyn Error>> {
+    let obj = value.as_object_mut().ok_or(MigrationError::NotAnObject)?;
+    obj.entry("floor_plan").or_insert(Value::Null);
+
+    if let Some(floors) = obj.get_mut("floors").and_then(|f| f.as_array_mut()) {
+        for floor in floors {
+            if let Some(floor) = floor.as_object_mut() {
+                floor.entry("floor_plan").or_insert(Value::Null);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Version 12 added the units field. Older maps did not record units, meters is assumed.
+fn v11_units(value: &mut Value) -> Result<(), Box<dyn Error>> {
+    value.as_object_mut()
+        .ok_or(MigrationError::NotAnObject)?
+        .entry("units")
+        .or_insert_with(|| Value::from("meters"));
+
+    Ok(())
+}
+
+/// Version 13 added tags to nodes.
+fn v12_tags(value: &mut Value) -> Result<(), Box<dyn Error>> {
+    for_each_node(value, |node| {
+        node.entry("tags").or_insert_with(|| Value::Array(Vec::new()));
+
+        Ok(())
+    })
+}
+
+/// Version 14 added notes about access points to maps.
+fn v13_annotations(value: &mut Value) -> Result<(), Box<dyn Error>> {
+    value.as_object_mut()
+        .ok_or(MigrationError::NotAnObject)?
+        .entry("annotations")
+        .or_insert_with(|| Value::Object(serde_json::Map::new()));
+
+    Ok(())
+}
+
+/// Version 15 added survey sessions to maps and a session reference to nodes.
+fn v14_sessions(value: &mut Value) -> Result<(), Box<dyn Error>> {
+    value.as_object_mut()
+        .ok_or(MigrationError::NotAnObject)?
+        .entry("sessions")
+        .or_insert_with(|| Value::Array(Vec::new()));
+
+    for_each_node(value, |node| {
+        node.entry("session").or_insert(Value::Null);
+
+        Ok(())
+    })
+}
+
+/// Version 16 added the number of averaged scans and the spread of their strengths to networks.
+fn v15_samples(value: &mut Value) -> Result<(), Box<dyn Error>> {
+    for_each_network(value, |network| {
+        network.entry("samples").or_insert_with(|| Value::from(1));
+        network.entry("strength_stddev").or_insert(Value::Null);
+
+        Ok(())
+    })
+}
+
+/// Version 17 added default scan filters to maps.
+fn v16_scan_filters(value: &mut Value) -> Result<(), Box<dyn Error>> {
+    value.as_object_mut()
+        .ok_or(MigrationError::NotAnObject)?
+        .entry("scan_filters")
+        .or_insert_with(|| serde_json::json!({
+            "only_ssids": [],
+            "ignore_ssids": [],
+            "only_bssid_prefixes": [],
+        }));
+
+    Ok(())
+}
+
+/// Version 18 added the wireless interface which measured each network.
+fn v17_interfaces(value: &mut Value) -> Result<(), Box<dyn Error>> {
+    for_each_network(value, |network| {
+        network.entry("interface").or_insert(Value::Null);
+
+        Ok(())
+    })
+}
+
+/// Version 19 added the beacon interval, capabilities, and information elements of networks.
+fn v18_beacon_details(value: &mut Value) -> Result<(), Box<dyn Error>> {
+    for_each_network(value, |network| {
+        network.entry("beacon_interval").or_insert(Value::Null);
+        network.entry("capabilities").or_insert_with(|| Value::Array(Vec::new()));
+        network.entry("information_elements").or_insert_with(|| Value::Array(Vec::new()));
+
+        Ok(())
+    })
+}
+
+/// Version 20 added the noise floor of networks.
+fn v19_noise(value: &mut Value) -> Result<(), Box<dyn Error>> {
+    for_each_network(value, |network| {
+        network.entry("noise").or_insert(Value::Null);
+
+        Ok(())
+    })
+}
+
+/// Version 21 added the client devices heard at each node.
+fn v20_clients(value: &mut Value) -> Result<(), Box<dyn Error>> {
+    for_each_node(value, |node| {
+        node.entry("clients").or_insert_with(|| Value::Array(Vec::new()));
+
+        Ok(())
+    })
+}
+
+/// Version 22 added the signal to noise ratio of networks.
+fn v21_snr(value: &mut Value) -> Result<(), Box<dyn Error>> {
+    for_each_network(value, |network| {
+        let snr = match (network.get("strength").and_then(|s| s.as_f64()), network.get("noise").and_then(|n| n.as_f64())) {
+            (Some(strength), Some(noise)) => serde_json::json!(strength - noise),
+            _ => Value::Null,
+        };
+        network.entry("snr").or_insert(snr);
+
+        Ok(())
+    })
+}
+
+/// Version 23 added the Bluetooth LE devices heard at each node.
+fn v22_ble_devices(value: &mut Value) -> Result<(), Box<dyn Error>> {
+    for_each_node(value, |node| {
+        node.entry("ble_devices").or_insert_with(|| Value::Array(Vec::new()));
+
+        Ok(())
+    })
+}
+
+/// Version 24 added the estimated positions of access points to maps.
+fn v23_access_points(value: &mut Value) -> Result<(), Box<dyn Error>> {
+    value.as_object_mut()
+        .ok_or(MigrationError::NotAnObject)?
+        .entry("access_points")
+        .or_insert_with(|| Value::Object(serde_json::Map::new()));
+
+    Ok(())
+}
+
+/// Version 25 added the path loss model used to estimate strengths and distances.
+fn v24_path_loss(value: &mut Value) -> Result<(), Box<dyn Error>> {
+    value.as_object_mut()
+        .ok_or(MigrationError::NotAnObject)?
+        .entry("path_loss")
+        .or_insert_with(|| serde_json::json!({
+            "reference": -40.0,
+            "exponent": 3.0,
+            "wall_attenuation": 0.0,
+            "wall_spacing": null,
+        }));
+
+    Ok(())
+}
+
+/// Version 26 stores BSSIDs in lower case, which notes and estimated positions of access points are looked up by. The
+/// wifiscanner backend used to save them as the scan command printed them.
+fn v25_lowercase_bssids(value: &mut Value) -> Result<(), Box<dyn Error>> {
+    for key in ["annotations", "access_points"] {
+        if let Some(by_mac) = value.get_mut(key).and_then(|a| a.as_object_mut()) {
+            *by_mac = std::mem::take(by_mac).into_iter()
+                .map(|(mac, v)| (mac.to_lowercase(), v))
+                .collect();
+        }
+    }
+
+    for_each_network(value, |network| {
+        if let Some(Value::String(mac)) = network.get_mut("mac") {
+            *mac = mac.to_lowercase();
+        }
+
+        Ok(())
+    })
+}
+
+/// Version 27 added the identifier nodes received from record --remote were sent with.
+fn v26_upload_ids(value: &mut Value) -> Result<(), Box<dyn Error>> {
+    for_each_node(value, |node| {
+        node.entry("upload_id").or_insert(Value::Null);
+
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn v0_map() -> Value {
+        serde_json::from_str(include_str!("../tests/fixtures/map-v0.json")).expect("fixture is json")
+    }
+
+    #[test]
+    fn migrate_upgrades_version_0_maps_to_the_current_schema() {
+        let mut value = v0_map();
+        assert_eq!(migrate(&mut value).expect("v0 map migrates"), 0);
+        assert_eq!(value["version"], json!(SCHEMA_VERSION));
+
+        let network = &value["nodes"][0]["networks"][0];
+        assert_eq!(network["mac"], json!("84:d4:7e:69:2f:e2"));
+        assert_eq!(network["strength"], json!(-61.0));
+        assert_eq!(network["frequency"], json!(5180));
+        assert_eq!(network["band"], json!("5GHz"));
+        assert_eq!(network["is_hidden"], json!(false));
+        assert_eq!(network["snr"], Value::Null);
+
+        let unparsable = &value["nodes"][0]["networks"][1];
+        assert_eq!(unparsable["strength"], Value::Null);
+        assert_eq!(unparsable["strength_raw"], json!("unknown"));
+        assert_eq!(unparsable["is_hidden"], json!(true));
+
+        serde_json::from_value::<crate::ScanMap>(value).expect("migrated map deserializes");
+    }
+
+    #[test]
+    fn migrate_leaves_current_maps_alone() {
+        let mut value = v0_map();
+        migrate(&mut value).expect("v0 map migrates");
+        let migrated = value.clone();
+
+        assert_eq!(migrate(&mut value).expect("current map migrates"), SCHEMA_VERSION);
+        assert_eq!(value, migrated);
+    }
+
+    #[test]
+    fn v25_lowercases_the_keys_of_notes_and_positions() {
+        let mut value = json!({
+            "version": 25,
+            "annotations": {"84:D4:7E:69:2F:E2": "Ceiling"},
+            "access_points": {"84:D4:7E:69:2F:E2": {}},
+            "nodes": [{"networks": [{"mac": "84:D4:7E:69:2F:E2"}]}],
+        });
+        migrate(&mut value).expect("v25 map migrates");
+        assert_eq!(value["annotations"], json!({"84:d4:7e:69:2f:e2": "Ceiling"}));
+        assert_eq!(value["access_points"], json!({"84:d4:7e:69:2f:e2": {}}));
+        assert_eq!(value["nodes"][0]["networks"][0]["mac"], json!("84:d4:7e:69:2f:e2"));
+    }
+
+    #[test]
+    fn migrate_node_upgrades_a_node_on_its_own() {
+        let node = v0_map()["nodes"][0].clone();
+        let node = migrate_node(0, node).expect("v0 node migrates");
+        assert_eq!(node["session"], Value::Null);
+        assert_eq!(node["tags"], json!([]));
+        assert_eq!(node["upload_id"], Value::Null);
+        assert_eq!(node["networks"][0]["mac"], json!("84:d4:7e:69:2f:e2"));
+    }
+
+    #[test]
+    fn migrate_rejects_newer_and_invalid_versions() {
+        let err = migrate(&mut json!({"version": SCHEMA_VERSION + 1})).expect_err("newer map is an error");
+        assert!(err.to_string().contains("newer than the latest version"));
+        assert!(migrate(&mut json!({"version": "one"})).is_err());
+        assert!(migrate(&mut json!([])).is_err());
+    }
+}
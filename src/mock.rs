@@ -0,0 +1,91 @@
+//! Synthetic scan results read from a fixture file, for demos and for testing without a wireless interface.
+
+use std::env;
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::BufReader;
+
+use serde::Deserialize;
+
+use crate::security::Security;
+
+/// Environment variable which holds the path of the fixture file.
+pub const FIXTURE_VAR: &str = "WIFI_MAPPER_MOCK";
+
+/// Error which occurs when loading a fixture.
+#[derive(Debug)]
+pub struct MockError {
+    /// Reason the fixture could not be loaded.
+    reason: String,
+}
+
+impl fmt::Display for MockError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "mock: {}", self.reason)
+    }
+}
+
+impl Error for MockError {}
+
+/// A network in a fixture. Only the BSSID is required.
+#[derive(Deserialize, Clone)]
+pub struct MockNetwork {
+    pub mac: String,
+
+    #[serde(default)]
+    pub ssid: String,
+
+    #[serde(default)]
+    pub channel: String,
+
+    /// Signal strength in dBm.
+    pub strength: Option<f32>,
+
+    /// Noise floor in dBm.
+    pub noise: Option<f32>,
+
+    pub security: Option<Security>,
+}
+
+/// Contents of a fixture file: either the networks every scan finds, or a list of scans which are returned in turn.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Fixture {
+    Networks(Vec<MockNetwork>),
+    Scans(Vec<Vec<MockNetwork>>),
+}
+
+/// Path of the fixture file, from the environment.
+pub fn fixture_path() -> Option<String> {
+    env::var(FIXTURE_VAR).ok().filter(|p| !p.is_empty())
+}
+
+/// Load the scans of the fixture file. A fixture with a single list of networks is one scan.
+pub fn load() -> Result<Vec<Vec<MockNetwork>>, Box<dyn Error>> {
+    let path = match fixture_path() {
+        Some(p) => p,
+        None => return Err(Box::new(MockError{
+            reason: format!("set {} to the path of a fixture file", FIXTURE_VAR),
+        })),
+    };
+
+    let file = File::open(&path).map_err(|e| MockError{
+        reason: format!("failed to open fixture \"{}\": {}", path, e),
+    })?;
+    let fixture: Fixture = serde_json::from_reader(BufReader::new(file)).map_err(|e| MockError{
+        reason: format!("failed to parse fixture \"{}\": {}", path, e),
+    })?;
+
+    let scans = match fixture {
+        Fixture::Networks(networks) => vec![networks],
+        Fixture::Scans(scans) => scans,
+    };
+
+    match scans.is_empty() {
+        true => Err(Box::new(MockError{
+            reason: format!("fixture \"{}\" has no scans", path),
+        })),
+        false => Ok(scans),
+    }
+}
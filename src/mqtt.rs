@@ -0,0 +1,245 @@
+//! Publishes recorded nodes to an MQTT broker, e.g. so fixed sensors can feed Home Assistant
+//! or a central collector. Nodes are published with QoS 1, optionally over TLS.
+
+use std::error::Error;
+use std::fmt;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use rumqttc::{Client, Connection, ConnectionError, Event, Packet, QoS, Transport};
+use serde::Serialize;
+
+use crate::Node;
+use crate::logging;
+
+/// Port brokers listen on without TLS.
+pub const DEFAULT_PORT: u16 = 1883;
+
+/// Port brokers listen on with TLS.
+pub const DEFAULT_TLS_PORT: u16 = 8883;
+
+/// How long to wait for the broker to answer.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Longest string MQTT can encode, e.g. a topic or password, in bytes.
+const MAX_STRING: usize = u16::MAX as usize;
+
+/// Largest packet MQTT can encode, in bytes. Nodes with many networks are larger than rumqttc's default limit.
+const MAX_PACKET: usize = 268_435_455;
+
+/// Options which control where nodes are published.
+pub struct MqttOptions {
+    /// Host name or IP address of the broker.
+    pub host: String,
+
+    pub port: u16,
+
+    /// Connect with TLS, verifying the broker with ca or the system's certificates if ca is None.
+    pub tls: bool,
+
+    /// PEM certificates of the authorities which may sign the broker's certificate.
+    pub ca: Option<Vec<u8>>,
+
+    /// Topic nodes are published to.
+    pub topic: String,
+
+    pub username: Option<String>,
+
+    /// Password sent with username, brokers only accept a password with a username.
+    pub password: Option<String>,
+
+    /// Identifier the broker knows this client by.
+    pub client_id: String,
+
+    /// Ask the broker to keep the last node for clients which subscribe later.
+    pub retain: bool,
+}
+
+/// Host and port of a broker given as HOST, HOST:PORT, or [IPV6]:PORT. IPv6 addresses without a port need no brackets.
+/// default_port is used if no port is given.
+pub fn parse_address(address: &str, default_port: u16) -> Result<(String, u16), String> {
+    let port = |p: &str| p.parse::<u16>().map_err(|_| format!("invalid port \"{}\"", p));
+
+    if let Some(rest) = address.strip_prefix('[') {
+        return match rest.split_once(']') {
+            Some((host, "")) => Ok((host.to_string(), default_port)),
+            Some((host, p)) => match p.strip_prefix(':') {
+                Some(p) => Ok((host.to_string(), port(p)?)),
+                None => Err(format!("expected :PORT after \"[{}]\"", host)),
+            },
+            None => Err(String::from("missing ] after the IPv6 address")),
+        };
+    }
+
+    match address.split_once(':') {
+        // More than one colon is an IPv6 address without a port
+        Some((host, p)) if !p.contains(':') => Ok((host.to_string(), port(p)?)),
+        _ if address.is_empty() => Err(String::from("no broker address given")),
+        _ => Ok((address.to_string(), default_port)),
+    }
+}
+
+/// Error which occurs when talking to the broker.
+#[derive(Debug)]
+pub struct MqttError {
+    /// Reason publishing failed.
+    reason: String,
+}
+
+impl fmt::Display for MqttError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "mqtt: {}", self.reason)
+    }
+}
+
+impl Error for MqttError {}
+
+/// Boxed MqttError with reason, for returning from functions which can fail in other ways too.
+fn error(reason: String) -> Box<dyn Error> {
+    Box::new(MqttError{ reason })
+}
+
+/// Message published for each node.
+#[derive(Serialize)]
+struct Message<'a> {
+    /// Name of the map the node was recorded for.
+    map: &'a str,
+
+    /// Time the node was published in milliseconds since the unix epoch.
+    time: u128,
+
+    #[serde(flatten)]
+    node: &'a Node,
+}
+
+/// Connection to a broker which is opened again when it breaks.
+pub struct Publisher {
+    opts: MqttOptions,
+
+    connection: Option<(Client, Connection)>,
+}
+
+impl Publisher {
+    /// Connect to the broker, so bad addresses and credentials are noticed before recording starts.
+    pub fn connect(opts: MqttOptions) -> Result<Publisher, Box<dyn Error>> {
+        // rumqttc writes the length of strings as 16 bits without checking them
+        let strings = [("topic", Some(&opts.topic)), ("client identifier", Some(&opts.client_id)),
+                       ("username", opts.username.as_ref()), ("password", opts.password.as_ref())];
+        for (name, value) in strings {
+            if value.is_some_and(|v| v.len() > MAX_STRING) {
+                return Err(error(format!("the {} is longer than the {} bytes MQTT allows", name, MAX_STRING)));
+            }
+        }
+        if !opts.tls && (opts.username.is_some() || opts.password.is_some()) {
+            logging::warn("the MQTT username and password are sent unencrypted, pass --mqtt-tls if the broker supports TLS");
+        }
+
+        let mut publisher = Publisher{
+            opts,
+            connection: None,
+        };
+        publisher.connection = Some(publisher.open()?);
+
+        println!("Publishing nodes to \"{}\" on {}", publisher.opts.topic, publisher.address());
+
+        Ok(publisher)
+    }
+
+    /// Address of the broker for messages.
+    fn address(&self) -> String {
+        match self.opts.host.contains(':') {
+            true => format!("[{}]:{}", self.opts.host, self.opts.port),
+            false => format!("{}:{}", self.opts.host, self.opts.port),
+        }
+    }
+
+    /// Open a session with the broker. Keep alive is off since nodes may be minutes apart.
+    fn open(&self) -> Result<(Client, Connection), Box<dyn Error>> {
+        let mut options = rumqttc::MqttOptions::new(self.opts.client_id.clone(), self.opts.host.clone(), self.opts.port);
+        options.set_keep_alive(Duration::ZERO);
+        options.set_clean_session(true);
+        options.set_max_packet_size(MAX_PACKET, MAX_PACKET);
+        if let (Some(username), password) = (&self.opts.username, &self.opts.password) {
+            options.set_credentials(username.clone(), password.clone().unwrap_or_default());
+        }
+        if self.opts.tls {
+            options.set_transport(match &self.opts.ca {
+                Some(ca) => Transport::tls(ca.clone(), None, None),
+                None => Transport::tls_with_default_config(),
+            });
+        }
+
+        let (client, mut connection) = Client::new(options, 1);
+        self.wait_for(&mut connection, "the connection to be accepted", |packet| matches!(packet, Packet::ConnAck(_)))?;
+
+        Ok((client, connection))
+    }
+
+    /// Process the connection until the broker sends a packet which done accepts.
+    fn wait_for<F>(&self, connection: &mut Connection, what: &str, done: F) -> Result<(), Box<dyn Error>>
+    where F: Fn(&Packet) -> bool {
+        loop {
+            match connection.recv_timeout(TIMEOUT) {
+                Ok(Ok(Event::Incoming(packet))) if done(&packet) => return Ok(()),
+                Ok(Ok(_)) => (),
+                Ok(Err(ConnectionError::ConnectionRefused(code))) => {
+                    return Err(error(format!("{} refused the connection: {:?}", self.address(), code)));
+                },
+                Ok(Err(e)) => return Err(error(format!("failed to talk to {}: {}", self.address(), e))),
+                Err(_) => return Err(error(format!("timed out waiting for {} from {}", what, self.address()))),
+            };
+        }
+    }
+
+    /// Publish payload and wait for the broker to acknowledge it.
+    fn send(&mut self, payload: &[u8]) -> Result<(), Box<dyn Error>> {
+        let (client, mut connection) = match self.connection.take() {
+            Some(connection) => connection,
+            None => self.open()?,
+        };
+
+        client.publish(self.opts.topic.clone(), QoS::AtLeastOnce, self.opts.retain, payload)
+            .map_err(|e| error(format!("failed to publish: {}", e)))?;
+        self.wait_for(&mut connection, "the node to be acknowledged", |packet| matches!(packet, Packet::PubAck(_)))?;
+
+        self.connection = Some((client, connection));
+        Ok(())
+    }
+
+    /// Publish node as JSON. If the connection broke it is opened again and the node sent once more.
+    pub fn publish(&mut self, map_name: &str, node: &Node) -> Result<(), Box<dyn Error>> {
+        let payload = serde_json::to_vec(&Message{
+            map: map_name,
+            time: SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis()).unwrap_or(0),
+            node,
+        })?;
+
+        // A failed send drops the connection, so the node is not sent again along with the next one
+        if let Err(e) = self.send(&payload) {
+            logging::warn(&format!("lost the connection to the broker, reconnecting: {}", e));
+            self.send(&payload)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_address_reads_hosts_ports_and_ipv6() {
+        assert_eq!(parse_address("broker.local", 1883), Ok((String::from("broker.local"), 1883)));
+        assert_eq!(parse_address("broker.local:8883", 1883), Ok((String::from("broker.local"), 8883)));
+        assert_eq!(parse_address("fe80::1", 1883), Ok((String::from("fe80::1"), 1883)));
+        assert_eq!(parse_address("[fe80::1]", 1883), Ok((String::from("fe80::1"), 1883)));
+        assert_eq!(parse_address("[fe80::1]:8883", 1883), Ok((String::from("fe80::1"), 8883)));
+    }
+
+    #[test]
+    fn parse_address_rejects_invalid_addresses() {
+        for address in ["", "broker:port", "broker:70000", "[fe80::1", "[fe80::1]8883"] {
+            assert!(parse_address(address, 1883).is_err(), "{}", address);
+        }
+    }
+}
@@ -0,0 +1,195 @@
+//! Parses the output of the Windows `netsh wlan` commands. The wifiscanner crate only keeps the first BSSID of each
+//! SSID and the raw signal percentage, this module reads every BSSID.
+
+use std::error::Error;
+use std::fmt;
+use std::process::Command;
+use std::time::Instant;
+
+use crate::logging;
+use crate::radio::{Band, Standard};
+
+/// Error which occurs when running netsh.
+#[derive(Debug)]
+pub struct NetshError {
+    /// Reason netsh failed.
+    reason: String,
+}
+
+impl fmt::Display for NetshError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "netsh: {}", self.reason)
+    }
+}
+
+impl Error for NetshError {}
+
+/// An access point from `netsh wlan show networks mode=bssid` output.
+#[derive(Default, Debug)]
+pub struct AccessPoint {
+    /// Hardware address, lowercase.
+    pub mac: String,
+
+    /// Network name, empty for hidden networks.
+    pub ssid: String,
+
+    /// Authentication method of the network, e.g. "WPA2-Personal", "Open".
+    pub authentication: String,
+
+    /// Primary channel number.
+    pub channel: String,
+
+    /// Band the access point is broadcasting in. Only reported by Windows 11.
+    pub band: Option<Band>,
+
+    /// Signal quality from 0 to 100 percent.
+    pub quality: Option<u32>,
+
+    /// 802.11 generation the access point is using, from the radio type.
+    pub standard: Option<Standard>,
+}
+
+/// Run netsh wlan with args.
+fn run_netsh(args: &[&str]) -> Result<String, Box<dyn Error>> {
+    let started = Instant::now();
+    let output = Command::new("netsh")
+        .arg("wlan")
+        .args(args)
+        .output()?;
+    logging::command("netsh wlan", args, &output, started.elapsed());
+
+    // netsh prints its errors to stdout
+    if !output.status.success() {
+        return Err(Box::new(NetshError{
+            reason: String::from_utf8_lossy(&output.stdout).trim().to_string(),
+        }));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Split a "key : value" line into its trimmed key and value. Values may contain colons, e.g. BSSIDs.
+fn key_value(line: &str) -> Option<(&str, &str)> {
+    let idx = line.find(" : ").or_else(|| line.trim_end().strip_suffix(" :").map(|l| l.len()))?;
+
+    Some((line[..idx].trim(), line.get(idx + 3..).unwrap_or("").trim()))
+}
+
+/// Parse a radio type, e.g. "802.11ac". None if it is not recognized.
+fn parse_radio_type(radio_type: &str) -> Option<Standard> {
+    match radio_type.trim().trim_start_matches("802.11") {
+        "a" | "b" | "g" => Some(Standard::Legacy),
+        "n" => Some(Standard::N),
+        "ac" => Some(Standard::Ac),
+        "ax" => Some(Standard::Ax),
+        "be" => Some(Standard::Be),
+        _ => None,
+    }
+}
+
+/// Names of wireless interfaces listed by `netsh wlan show interfaces`.
+pub fn interfaces() -> Result<Vec<String>, Box<dyn Error>> {
+    let output = run_netsh(&["show", "interfaces"])?;
+
+    Ok(output.lines()
+       .filter_map(key_value)
+       .filter(|(k, _)| *k == "Name")
+       .map(|(_, v)| v.to_string())
+       .collect())
+}
+
+/// List the access points Windows last saw with interface, or every interface if None.
+pub fn scan(interface: Option<&str>) -> Result<Vec<AccessPoint>, Box<dyn Error>> {
+    let interface_arg = interface.map(|i| format!("interface={}", i));
+    let mut args = vec!["show", "networks", "mode=bssid"];
+    if let Some(i) = &interface_arg {
+        args.push(i);
+    }
+
+    let output = run_netsh(&args)?;
+
+    Ok(parse_networks(&output))
+}
+
+/// Parse the output of `netsh wlan show networks mode=bssid`. Each SSID is followed by its
+/// authentication and then its BSSIDs, which are followed by their own fields.
+pub fn parse_networks(output: &str) -> Vec<AccessPoint> {
+    let mut results = Vec::<AccessPoint>::new();
+    let mut ssid = String::new();
+    let mut authentication = String::new();
+    let mut in_bssid = false;
+
+    for (key, value) in output.lines().filter_map(key_value) {
+        if key.starts_with("SSID ") {
+            ssid = value.to_string();
+            authentication = String::new();
+            in_bssid = false;
+        } else if key == "Authentication" {
+            authentication = value.to_string();
+        } else if key.starts_with("BSSID ") {
+            in_bssid = true;
+            results.push(AccessPoint{
+                mac: value.to_lowercase(),
+                ssid: ssid.clone(),
+                authentication: authentication.clone(),
+                ..AccessPoint::default()
+            });
+        } else if in_bssid {
+            let ap = match results.last_mut() {
+                Some(ap) => ap,
+                None => continue,
+            };
+
+            match key {
+                "Signal" => ap.quality = value.trim_end_matches('%').parse::<u32>().ok(),
+                "Channel" => ap.channel = value.to_string(),
+                "Band" => ap.band = match value {
+                    "2.4 GHz" => Some(Band::TwoPointFour),
+                    "5 GHz" => Some(Band::Five),
+                    "6 GHz" => Some(Band::Six),
+                    _ => None,
+                },
+                "Radio type" => ap.standard = parse_radio_type(value),
+                _ => (),
+            };
+        }
+    }
+
+    results
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_networks_reads_every_bssid() {
+        let aps = parse_networks(include_str!("../tests/fixtures/netsh-networks.txt"));
+        assert_eq!(aps.len(), 3);
+
+        assert_eq!(aps[0].mac, "84:d4:7e:69:2f:e2");
+        assert_eq!(aps[0].ssid, "CorpWiFi");
+        assert_eq!(aps[0].authentication, "WPA2-Enterprise");
+        assert_eq!(aps[0].quality, Some(86));
+        assert_eq!(aps[0].standard, Some(Standard::Ax));
+        assert_eq!(aps[0].band, Some(Band::Five));
+        assert_eq!(aps[0].channel, "36");
+
+        assert_eq!(aps[1].ssid, "CorpWiFi");
+        assert_eq!(aps[1].band, Some(Band::TwoPointFour));
+        assert_eq!(aps[1].standard, Some(Standard::N));
+
+        // Windows 10 does not print the band
+        assert_eq!(aps[2].ssid, "");
+        assert_eq!(aps[2].authentication, "Open");
+        assert_eq!(aps[2].band, None);
+        assert_eq!(aps[2].standard, Some(Standard::Legacy));
+    }
+
+    #[test]
+    fn key_value_keeps_colons_in_values() {
+        assert_eq!(key_value("    BSSID 1                 : 84:d4:7e:69:2f:e2"), Some(("BSSID 1", "84:d4:7e:69:2f:e2")));
+        assert_eq!(key_value("SSID 2 :"), Some(("SSID 2", "")));
+        assert_eq!(key_value("There are 2 networks currently visible."), None);
+    }
+}
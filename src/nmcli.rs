@@ -0,0 +1,160 @@
+//! Parses the output of NetworkManager's `nmcli` tool. NetworkManager scans on behalf of unprivileged users, so
+//! this works on laptops where iw needs root.
+
+use std::error::Error;
+use std::fmt;
+use std::process::Command;
+use std::time::Instant;
+
+use crate::logging;
+
+/// Fields requested from `nmcli dev wifi list`, in the order they are printed.
+const FIELDS: &str = "BSSID,SSID,CHAN,FREQ,SIGNAL,SECURITY";
+
+/// Error which occurs when running nmcli.
+#[derive(Debug)]
+pub struct NmcliError {
+    /// Reason nmcli failed.
+    reason: String,
+}
+
+impl fmt::Display for NmcliError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "nmcli: {}", self.reason)
+    }
+}
+
+impl Error for NmcliError {}
+
+/// An access point from `nmcli dev wifi list` output.
+#[derive(Default, Debug)]
+pub struct AccessPoint {
+    /// Hardware address, lowercase.
+    pub mac: String,
+
+    /// Network name, empty for hidden networks.
+    pub ssid: String,
+
+    /// Primary channel number.
+    pub channel: String,
+
+    /// Center frequency of the primary channel in MHz.
+    pub freq: Option<u32>,
+
+    /// Signal quality from 0 to 100 percent.
+    pub quality: Option<u32>,
+
+    /// Security description, e.g. "WPA2", "WPA2 802.1X". Empty for open networks.
+    pub security: String,
+}
+
+/// Run nmcli with args in terse mode.
+fn run_nmcli(args: &[&str]) -> Result<String, Box<dyn Error>> {
+    let started = Instant::now();
+    let output = Command::new("nmcli")
+        .arg("--terse")
+        .args(args)
+        .output()?;
+    logging::command("nmcli --terse", args, &output, started.elapsed());
+
+    if !output.status.success() {
+        return Err(Box::new(NmcliError{
+            reason: String::from_utf8_lossy(&output.stderr).trim().trim_start_matches("Error: ").to_string(),
+        }));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Split a line of terse output into fields. Colons and backslashes inside fields are escaped with a backslash.
+fn split_fields(line: &str) -> Vec<String> {
+    let mut fields = vec![String::new()];
+    let mut chars = line.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => if let Some(escaped) = chars.next() {
+                fields.last_mut().expect("fields is never empty").push(escaped);
+            },
+            ':' => fields.push(String::new()),
+            _ => fields.last_mut().expect("fields is never empty").push(c),
+        };
+    }
+
+    fields
+}
+
+/// Names of the wireless interfaces NetworkManager manages.
+pub fn interfaces() -> Result<Vec<String>, Box<dyn Error>> {
+    let output = run_nmcli(&["-f", "DEVICE,TYPE", "device"])?;
+
+    Ok(output.lines()
+       .map(split_fields)
+       .filter(|f| f.len() == 2 && f[1] == "wifi")
+       .map(|f| f[0].clone())
+       .collect())
+}
+
+/// Ask NetworkManager to scan with interface, or every wireless interface if None, and list the access points it found.
+pub fn scan(interface: Option<&str>) -> Result<Vec<AccessPoint>, Box<dyn Error>> {
+    let mut args = vec!["-f", FIELDS, "device", "wifi", "list", "--rescan", "yes"];
+    if let Some(i) = interface {
+        args.extend(["ifname", i]);
+    }
+
+    let output = run_nmcli(&args)?;
+
+    Ok(parse_list(&output))
+}
+
+/// Parse the terse output of `nmcli -f BSSID,SSID,CHAN,FREQ,SIGNAL,SECURITY device wifi list`.
+pub fn parse_list(output: &str) -> Vec<AccessPoint> {
+    output.lines()
+        .map(split_fields)
+        .filter(|f| f.len() == 6 && !f[0].is_empty())
+        .map(|f| AccessPoint{
+            mac: f[0].to_lowercase(),
+            ssid: f[1].clone(),
+            channel: f[2].clone(),
+            freq: f[3].trim_end_matches("MHz").trim().parse::<u32>().ok(),
+            quality: f[4].trim().parse::<u32>().ok(),
+            // nmcli prints -- for fields without a value
+            security: match f[5].as_str() {
+                "--" => String::new(),
+                s => s.to_string(),
+            },
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_fields_unescapes_colons_and_backslashes() {
+        assert_eq!(split_fields(r"84\:D4\:7E\:69\:2F\:E2:Cafe\: Free \\ Fast:1"), vec!["84:D4:7E:69:2F:E2", r"Cafe: Free \ Fast", "1"]);
+        assert_eq!(split_fields("wlan0:wifi"), vec!["wlan0", "wifi"]);
+        assert_eq!(split_fields("::"), vec!["", "", ""]);
+        assert_eq!(split_fields(""), vec![""]);
+    }
+
+    #[test]
+    fn parse_list_reads_every_access_point() {
+        let aps = parse_list(include_str!("../tests/fixtures/nmcli-list.txt"));
+        assert_eq!(aps.len(), 3);
+
+        assert_eq!(aps[0].mac, "84:d4:7e:69:2f:e2");
+        assert_eq!(aps[0].ssid, "CorpWiFi");
+        assert_eq!(aps[0].channel, "36");
+        assert_eq!(aps[0].freq, Some(5180));
+        assert_eq!(aps[0].quality, Some(85));
+        assert_eq!(aps[0].security, "WPA2 802.1X");
+
+        assert_eq!(aps[1].ssid, "");
+        assert_eq!(aps[1].quality, Some(30));
+
+        assert_eq!(aps[2].ssid, r"Cafe: Free \ Fast");
+        assert_eq!(aps[2].security, "");
+    }
+}
@@ -0,0 +1,134 @@
+//! Looks up the vendor which manufactured a device from the OUI prefix of its MAC address.
+//!
+//! A copy of the IEEE registries is embedded in the binary via the oui-data crate. A newer
+//! copy of the IEEE CSV (oui.csv, mam.csv, oas.csv) or oui.txt file can be loaded to override it.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+/// Error which occurs when loading an OUI file.
+#[derive(Debug)]
+pub struct OuiFileError {
+    /// Line the error occurred on, starting at 1.
+    line: usize,
+
+    /// Reason the line could not be parsed.
+    reason: String,
+}
+
+impl fmt::Display for OuiFileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.reason)
+    }
+}
+
+impl Error for OuiFileError {}
+
+/// Table of vendor names keyed by MAC address prefix.
+#[derive(Default)]
+pub struct OuiTable {
+    /// Vendors loaded from a file, keyed by upper case hex prefix of 6, 7, or 9 digits.
+    entries: HashMap<String, String>,
+}
+
+/// Upper case hex digits of a MAC address, without separators.
+fn mac_hex(mac: &str) -> String {
+    mac.chars()
+        .filter(|c| c.is_ascii_hexdigit())
+        .map(|c| c.to_ascii_uppercase())
+        .collect()
+}
+
+/// True if the locally administered bit of a MAC address is set. These addresses are assigned by software (e.g. MAC randomization) and do not have a vendor.
+pub fn is_locally_administered(mac: &str) -> bool {
+    let hex = mac_hex(mac);
+
+    match hex.get(0..2).and_then(|b| u8::from_str_radix(b, 16).ok()) {
+        Some(first_octet) => first_octet & 0x02 != 0,
+        None => false,
+    }
+}
+
+/// Split one line of a CSV file into fields, handling double quoted fields.
+fn split_csv(line: &str) -> Vec<String> {
+    let mut fields = vec![String::new()];
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match (c, quoted) {
+            ('"', true) if chars.peek() == Some(&'"') => {
+                chars.next();
+                fields.last_mut().unwrap().push('"');
+            },
+            ('"', _) => quoted = !quoted,
+            (',', false) => fields.push(String::new()),
+            (c, _) => fields.last_mut().unwrap().push(c),
+        };
+    }
+
+    fields
+}
+
+impl OuiTable {
+    /// Table which only uses the embedded registry.
+    pub fn new() -> OuiTable {
+        OuiTable::default()
+    }
+
+    /// Load vendors from an IEEE registry CSV file or the oui.txt text file.
+    pub fn load(p: &Path) -> Result<OuiTable, Box<dyn Error>> {
+        let contents = fs::read_to_string(p)?;
+        let mut table = OuiTable::new();
+
+        for (i, line) in contents.lines().enumerate() {
+            if line.starts_with("Registry,") || line.trim().is_empty() {
+                continue;
+            }
+
+            if let Some(idx) = line.find("(base 16)") {
+                // oui.txt: "00000C     (base 16)\t\tCisco Systems, Inc"
+                let prefix = mac_hex(&line[..idx]);
+                let name = line[idx + "(base 16)".len()..].trim();
+                table.entries.insert(prefix, name.to_string());
+            } else if line.contains("(hex)") || line.starts_with(char::is_whitespace) {
+                // Other oui.txt lines repeat the entry or hold addresses
+                continue;
+            } else {
+                // CSV: "MA-L,00000C,\"Cisco Systems, Inc\",address"
+                let fields = split_csv(line);
+                if fields.len() < 3 || !fields[1].chars().all(|c| c.is_ascii_hexdigit()) || fields[1].is_empty() {
+                    return Err(Box::new(OuiFileError{
+                        line: i + 1,
+                        reason: String::from("expected \"Registry,Assignment,Organization Name,...\" CSV or oui.txt format"),
+                    }));
+                }
+
+                table.entries.insert(fields[1].to_uppercase(), fields[2].trim().to_string());
+            }
+        }
+
+        Ok(table)
+    }
+
+    /// Name of the vendor which was assigned the prefix of mac. None if the address is locally administered or the prefix is not registered.
+    pub fn lookup(&self, mac: &str) -> Option<String> {
+        if is_locally_administered(mac) {
+            return None;
+        }
+
+        let hex = mac_hex(mac);
+
+        // Longer prefixes come from the smaller MA-M and MA-S blocks, which are carved out of MA-L assignments
+        for len in &[9, 7, 6] {
+            if let Some(name) = hex.get(..*len).and_then(|prefix| self.entries.get(prefix)) {
+                return Some(name.clone());
+            }
+        }
+
+        oui_data::lookup(&hex).map(|record| record.organization().to_string())
+    }
+}
@@ -0,0 +1,228 @@
+//! Suggests where to put new access points so as much of the surveyed area as possible is
+//! covered.
+//!
+//! The surveyed area is interpolated on a grid, and access points are placed one at a time on
+//! the grid point whose path loss range covers the most points which are still too weak. The
+//! model only knows the walls on average, so suggestions are a starting point for a follow up
+//! survey.
+
+use serde::Serialize;
+
+use crate::gaps::format_area;
+use crate::interpolate::{self, Grid, InterpolateError, InterpolateOptions};
+use crate::locate::PathLoss;
+use crate::{CoordinateSystem, ScanMap};
+
+/// Options which control how access points are placed.
+pub struct PlacementOptions {
+    /// Grid the surveyed area is interpolated on, the access points are placed on its points.
+    pub interpolate: InterpolateOptions,
+
+    /// Weakest strength in dBm which counts as covered.
+    pub threshold: f64,
+
+    /// Number of access points to place.
+    pub count: usize,
+
+    /// Model of the strength new access points reach.
+    pub path_loss: PathLoss,
+}
+
+/// Position suggested for a new access point.
+#[derive(Serialize)]
+pub struct Suggestion {
+    pub position: (f64, f64),
+
+    /// Area which this access point covers and none before it did, in square map units, square meters for wgs84 maps.
+    pub added_area: f64,
+
+    /// Fraction of the surveyed area covered once this access point and the ones before it are added.
+    pub coverage: f64,
+}
+
+/// Suggested access points and the coverage before they are added.
+#[derive(Serialize)]
+pub struct Placement {
+    /// Area of the grid points which have an estimate, in square map units, square meters for wgs84 maps.
+    pub area: f64,
+
+    /// Fraction of the area covered by the existing access points.
+    pub coverage: f64,
+
+    pub suggestions: Vec<Suggestion>,
+}
+
+/// Indexes of the cells within range cells of the cell at (column, row).
+fn in_range(grid: &Grid, column: usize, row: usize, range: f64) -> Vec<usize> {
+    let reach = range.floor() as isize;
+    let mut cells = Vec::new();
+    for dr in -reach..=reach {
+        for dc in -reach..=reach {
+            let (c, r) = (column as isize + dc, row as isize + dr);
+            if ((dc * dc + dr * dr) as f64) <= range * range && c >= 0 && r >= 0 && c < grid.columns as isize && r < grid.rows as isize {
+                cells.push(r as usize * grid.columns + c as usize);
+            }
+        }
+    }
+    cells
+}
+
+/// Place opts.count access points greedily, each where it covers the most of what is still weaker than opts.threshold.
+/// Fewer are suggested if everything is covered before all are placed.
+pub fn place(scan_map: &ScanMap, opts: &PlacementOptions) -> Result<Placement, InterpolateError> {
+    let grid = interpolate::interpolate(scan_map, &opts.interpolate)?;
+
+    // Lengths are in the map's units, meters for wgs84 maps
+    let meters = match scan_map.coordinate_system {
+        CoordinateSystem::Local => scan_map.units.meters(),
+        CoordinateSystem::Wgs84 => 1.0,
+    };
+    let cell_area = opts.interpolate.spacing * opts.interpolate.spacing;
+
+    // Range of a new access point in cells, the grid is square in meters
+    let range = opts.path_loss.distance(opts.threshold) / (opts.interpolate.spacing * meters);
+
+    let surveyed = grid.values.iter().filter(|v| v.is_some()).count();
+    let mut covered: Vec<bool> = grid.values.iter()
+        .map(|v| v.is_some_and(|v| v >= opts.threshold))
+        .collect();
+    let mut covered_count = covered.iter().filter(|c| **c).count();
+
+    let coverage = |count: usize| match surveyed {
+        0 => 0.0,
+        _ => count as f64 / surveyed as f64,
+    };
+
+    let mut placement = Placement{
+        area: surveyed as f64 * cell_area,
+        coverage: coverage(covered_count),
+        suggestions: Vec::new(),
+    };
+
+    for _ in 0..opts.count {
+        let best = (0..grid.rows)
+            .flat_map(|row| (0..grid.columns).map(move |column| (column, row)))
+            .filter(|(column, row)| grid.value(*column, *row).is_some())
+            .map(|(column, row)| {
+                let added = in_range(&grid, column, row, range).into_iter().filter(|i| !covered[*i] && grid.values[*i].is_some()).count();
+                (column, row, added)
+            })
+            // The first cell wins ties so suggestions do not depend on iteration details
+            .fold(None, |best: Option<(usize, usize, usize)>, candidate| match best {
+                Some(b) if b.2 >= candidate.2 => Some(b),
+                _ => Some(candidate),
+            });
+
+        let (column, row, added) = match best {
+            Some(b) if b.2 > 0 => b,
+            _ => break,
+        };
+
+        for i in in_range(&grid, column, row, range) {
+            if grid.values[i].is_some() {
+                covered[i] = true;
+            }
+        }
+        covered_count += added;
+
+        placement.suggestions.push(Suggestion{
+            position: grid.position(column, row),
+            added_area: added as f64 * cell_area,
+            coverage: coverage(covered_count),
+        });
+    }
+
+    Ok(placement)
+}
+
+/// Print the coverage and a table of suggestions.
+pub fn print_placement(scan_map: &ScanMap, placement: &Placement) {
+    let units = scan_map.units;
+    // Degrees need more digits than lengths
+    let precision = match scan_map.coordinate_system {
+        CoordinateSystem::Local => 2,
+        CoordinateSystem::Wgs84 => 6,
+    };
+
+    println!("Existing access points cover {:.0}% of {}", placement.coverage * 100.0, format_area(placement.area, units));
+    if placement.suggestions.is_empty() {
+        match placement.coverage >= 1.0 {
+            true => println!("No new access points are needed"),
+            false => println!("No position would cover more"),
+        };
+        return;
+    }
+
+    let rows: Vec<Vec<String>> = placement.suggestions.iter()
+        .enumerate()
+        .map(|(i, s)| vec![
+            (i + 1).to_string(),
+            format!("{:.*} {:.*}", precision, s.position.0, precision, s.position.1),
+            format_area(s.added_area, units),
+            format!("{:.0}%", s.coverage * 100.0),
+        ])
+        .collect();
+
+    crate::table::print_table(&["AP", "Position", "Added", "Coverage"], &rows);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpolate::Target;
+    use crate::tests::scan_map;
+
+    /// CorpWiFi at -50 at the origin and -90 ten meters east, so it is weaker than -70 past the middle.
+    fn survey() -> ScanMap {
+        scan_map(serde_json::json!({
+            "nodes": [
+                {"position": {"x": 0.0, "y": 0.0, "z": 0.0}, "networks": [{"mac": "84:d4:7e:69:2f:e2", "ssid": "CorpWiFi", "strength": -50.0}]},
+                {"position": {"x": 10.0, "y": 0.0, "z": 0.0}, "networks": [{"mac": "84:d4:7e:69:2f:e2", "ssid": "CorpWiFi", "strength": -90.0}]},
+            ],
+        }))
+    }
+
+    /// Options which place up to count access points which reach a little over three meters.
+    fn opts(count: usize) -> PlacementOptions {
+        PlacementOptions{
+            interpolate: InterpolateOptions{
+                target: Target::Ssid(String::from("CorpWiFi")),
+                floor: None,
+                spacing: 1.0,
+                power: 2.0,
+                radius: None,
+                unseen: None,
+            },
+            threshold: -70.0,
+            count,
+            path_loss: PathLoss{
+                reference: -40.0,
+                exponent: 6.0,
+                ..PathLoss::default()
+            },
+        }
+    }
+
+    #[test]
+    fn place_covers_the_most_weak_cells_first() {
+        let placement = place(&survey(), &opts(2)).unwrap();
+        assert_eq!(placement.area, 11.0);
+        assert!((placement.coverage - 6.0 / 11.0).abs() < 1e-9);
+
+        // One access point covers the rest, so the second is not suggested
+        assert_eq!(placement.suggestions.len(), 1);
+        assert_eq!(placement.suggestions[0].position, (7.0, 0.0));
+        assert_eq!(placement.suggestions[0].added_area, 5.0);
+        assert_eq!(placement.suggestions[0].coverage, 1.0);
+    }
+
+    #[test]
+    fn place_suggests_nothing_when_everything_is_covered() {
+        let placement = place(&survey(), &PlacementOptions{
+            threshold: -95.0,
+            ..opts(3)
+        }).unwrap();
+        assert_eq!(placement.coverage, 1.0);
+        assert!(placement.suggestions.is_empty());
+    }
+}
@@ -0,0 +1,261 @@
+//! Estimates the strength of each network at a position which was not surveyed, e.g. to tell
+//! whether a room will have usable coverage.
+//!
+//! Two estimates are made: one interpolated between the nodes which saw the network, and one
+//! from the path loss model and the distance to the network's nearest located access point.
+//! Interpolation cannot tell that a signal weakens past the last node, while the model only
+//! knows the walls on average, so the weaker of the two is expected.
+
+use std::collections::BTreeSet;
+
+use serde::Serialize;
+
+use crate::interpolate::{self, InterpolateOptions, Target};
+use crate::locate::{self, LocateOptions};
+use crate::{Coordinate, CoordinateSystem, ScanMap};
+
+/// Options which control how strengths are predicted.
+pub struct PredictOptions {
+    pub position: Coordinate,
+
+    /// Floor the position is on, only nodes and access points on it are used.
+    pub floor: Option<String>,
+
+    /// Only predict this SSID. None predicts every SSID seen on the floor.
+    pub ssid: Option<String>,
+
+    /// Exponent of the inverse distance weights used to interpolate.
+    pub power: f64,
+
+    /// Interpolated estimates are left out if every node which saw the network is farther than this.
+    pub radius: Option<f64>,
+
+    /// Path loss model and which access points are located, if the map has no saved locations.
+    pub locate: LocateOptions,
+
+    /// Weakest strength in dBm which counts as usable.
+    pub usable: f64,
+}
+
+/// Estimated strength of an SSID at the position.
+#[derive(Serialize)]
+pub struct Prediction {
+    pub ssid: String,
+
+    /// Weaker of the two estimates, in dBm.
+    pub expected: Option<f64>,
+
+    /// Strength interpolated between nodes, in dBm.
+    pub interpolated: Option<f64>,
+
+    /// Strength the path loss model expects from the nearest access point, in dBm.
+    pub modeled: Option<f64>,
+
+    /// BSSID of the nearest located access point.
+    pub access_point: Option<String>,
+
+    /// Distance to the nearest located access point, in the map's units.
+    pub access_point_distance: Option<f64>,
+
+    /// Distance to the nearest node which saw the SSID, in the map's units.
+    pub node_distance: f64,
+
+    pub usable: bool,
+}
+
+/// Parse a position in the form "x,y" or "x,y,z". z defaults to 0.
+pub fn parse_position(s: &str) -> Result<Coordinate, String> {
+    let parts: Result<Vec<f64>, _> = s.split(',').map(|p| p.trim().parse::<f64>()).collect();
+
+    match parts {
+        Ok(p) if p.len() == 2 || p.len() == 3 => Ok(Coordinate{ x: p[0], y: p[1], z: p.get(2).copied().unwrap_or(0.0) }),
+        Ok(_) => Err(format!("invalid position \"{}\", must be in format \"x,y\" or \"x,y,z\"", s)),
+        Err(e) => Err(format!("invalid position \"{}\": {}", s, e)),
+    }
+}
+
+/// Predict the strength of each SSID at opts.position, the strongest expected first.
+pub fn predict(scan_map: &ScanMap, opts: &PredictOptions) -> Vec<Prediction> {
+    let nodes: Vec<_> = scan_map.nodes.iter()
+        .filter(|n| opts.floor.is_none() || n.floor == opts.floor)
+        .collect();
+
+    let ssids: BTreeSet<&str> = nodes.iter()
+        .flat_map(|n| n.networks.iter())
+        .filter(|n| !n.is_hidden && n.strength.is_some())
+        .map(|n| n.ssid.as_str())
+        .filter(|s| opts.ssid.as_deref().is_none_or(|ssid| ssid == *s))
+        .collect();
+
+    // Saved locations are used as they are, they may have been located with a tuned model
+    let computed;
+    let located = match scan_map.access_points.is_empty() {
+        true => {
+            computed = locate::locate(scan_map, &opts.locate);
+            &computed
+        },
+        false => &scan_map.access_points,
+    };
+
+    // Lengths are in the map's units, meters for wgs84 maps
+    let meters = match scan_map.coordinate_system {
+        CoordinateSystem::Local => scan_map.units.meters(),
+        CoordinateSystem::Wgs84 => 1.0,
+    };
+
+    let mut predictions: Vec<Prediction> = ssids.into_iter()
+        .map(|ssid| {
+            let interpolated = interpolate::estimate(scan_map, &InterpolateOptions{
+                target: Target::Ssid(ssid.to_string()),
+                floor: opts.floor.clone(),
+                spacing: 1.0,
+                power: opts.power,
+                radius: opts.radius,
+                unseen: None,
+            }, opts.position.x, opts.position.y).ok().flatten();
+
+            let nearest = located.iter()
+                .filter(|(_, l)| l.ssid == ssid && (opts.floor.is_none() || l.floor == opts.floor))
+                .map(|(mac, l)| (mac, l.position.distance(&opts.position, scan_map.coordinate_system)))
+                .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal));
+            let modeled = nearest.map(|(_, distance)| opts.locate.path_loss.strength(distance * meters));
+
+            let node_distance = nodes.iter()
+                .filter(|n| n.networks.iter().any(|w| w.ssid == ssid && w.strength.is_some()))
+                .map(|n| n.position.distance(&opts.position, scan_map.coordinate_system))
+                .fold(f64::INFINITY, f64::min);
+
+            let expected = match (interpolated, modeled) {
+                (Some(a), Some(b)) => Some(a.min(b)),
+                (a, b) => a.or(b),
+            };
+
+            Prediction{
+                ssid: ssid.to_string(),
+                expected,
+                interpolated,
+                modeled,
+                access_point: nearest.map(|(mac, _)| mac.to_string()),
+                access_point_distance: nearest.map(|(_, d)| d),
+                node_distance,
+                usable: expected.is_some_and(|e| e >= opts.usable),
+            }
+        })
+        .collect();
+
+    predictions.sort_by(|a, b| b.expected.unwrap_or(f64::NEG_INFINITY).partial_cmp(&a.expected.unwrap_or(f64::NEG_INFINITY)).unwrap_or(std::cmp::Ordering::Equal));
+    predictions
+}
+
+/// Print a table of predictions.
+pub fn print_predictions(scan_map: &ScanMap, predictions: &[Prediction]) {
+    let units = scan_map.units.abbreviation();
+    let dbm = |v: Option<f64>| match v {
+        Some(v) => format!("{:.0} dBm", v),
+        None => String::from("-"),
+    };
+
+    let rows: Vec<Vec<String>> = predictions.iter()
+        .map(|p| vec![
+            p.ssid.clone(),
+            dbm(p.expected),
+            dbm(p.interpolated),
+            dbm(p.modeled),
+            match (&p.access_point, p.access_point_distance) {
+                (Some(mac), Some(d)) => format!("{} ({:.1} {})", mac, d, units),
+                _ => String::from("-"),
+            },
+            format!("{:.1} {}", p.node_distance, units),
+            match p.usable {
+                true => String::from("yes"),
+                false => String::from("no"),
+            },
+        ])
+        .collect();
+
+    crate::table::print_table(&["SSID", "Expected", "Interpolated", "Path loss", "Nearest AP", "Nearest node", "Usable"], &rows);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::locate::PathLoss;
+    use crate::tests::scan_map;
+
+    /// CorpWiFi measured at -50 and -70 on either side of (2, 0), with its access point saved at the origin. OldCafe was
+    /// only seen by the second node and was never located.
+    fn survey() -> ScanMap {
+        scan_map(serde_json::json!({
+            "nodes": [
+                {"position": {"x": 0.0, "y": 0.0, "z": 0.0}, "networks": [
+                    {"mac": "84:d4:7e:69:2f:e2", "ssid": "CorpWiFi", "strength": -50.0},
+                ]},
+                {"position": {"x": 4.0, "y": 0.0, "z": 0.0}, "networks": [
+                    {"mac": "84:d4:7e:69:2f:e2", "ssid": "CorpWiFi", "strength": -70.0},
+                    {"mac": "00:11:22:33:44:55", "ssid": "OldCafe", "strength": -85.0},
+                ]},
+            ],
+            "access_points": {
+                "84:d4:7e:69:2f:e2": {"ssid": "CorpWiFi", "position": {"x": 0.0, "y": 0.0, "z": 0.0}, "floor": null, "radius": 1.0, "nodes": 2},
+            },
+        }))
+    }
+
+    fn opts(x: f64, ssid: Option<&str>) -> PredictOptions {
+        PredictOptions{
+            position: Coordinate{ x, y: 0.0, z: 0.0 },
+            floor: None,
+            ssid: ssid.map(String::from),
+            power: 2.0,
+            radius: None,
+            locate: LocateOptions{
+                path_loss: PathLoss{
+                    reference: -40.0,
+                    exponent: 2.0,
+                    ..PathLoss::default()
+                },
+                min_nodes: 1,
+                floor: None,
+            },
+            usable: -67.0,
+        }
+    }
+
+    #[test]
+    fn parse_position_defaults_z_to_zero() {
+        let position = parse_position("1.5, -2").unwrap();
+        assert_eq!((position.x, position.y, position.z), (1.5, -2.0, 0.0));
+        assert_eq!(parse_position("1,2,3").unwrap().z, 3.0);
+        assert!(parse_position("1").is_err());
+        assert!(parse_position("1,two").is_err());
+    }
+
+    #[test]
+    fn predict_expects_the_weaker_of_interpolation_and_path_loss() {
+        let predictions = predict(&survey(), &opts(2.0, None));
+        let ssids: Vec<&str> = predictions.iter().map(|p| p.ssid.as_str()).collect();
+        assert_eq!(ssids, vec!["CorpWiFi", "OldCafe"]);
+
+        let corp = &predictions[0];
+        assert!((corp.interpolated.unwrap() + 60.0).abs() < 1e-9);
+        assert!((corp.modeled.unwrap() + 46.02).abs() < 0.01);
+        assert_eq!(corp.expected, corp.interpolated);
+        assert_eq!(corp.access_point.as_deref(), Some("84:d4:7e:69:2f:e2"));
+        assert_eq!(corp.access_point_distance, Some(2.0));
+        assert_eq!(corp.node_distance, 2.0);
+        assert!(corp.usable);
+
+        let cafe = &predictions[1];
+        assert_eq!(cafe.expected, Some(-85.0));
+        assert_eq!(cafe.modeled, None);
+        assert!(!cafe.usable);
+    }
+
+    #[test]
+    fn predict_only_the_chosen_ssid() {
+        let predictions = predict(&survey(), &opts(2.0, Some("OldCafe")));
+        assert_eq!(predictions.len(), 1);
+        assert_eq!(predictions[0].ssid, "OldCafe");
+        assert!(predict(&survey(), &opts(2.0, Some("Nobody"))).is_empty());
+    }
+}
@@ -0,0 +1,49 @@
+//! Shows that a slow operation is still running, e.g. scans which take several seconds on some drivers.
+
+use std::io::{stdout, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Characters the spinner cycles through.
+const FRAMES: [char; 4] = ['|', '/', '-', '\\'];
+
+/// Time between spinner updates.
+const FRAME_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Run f while label, a spinner, and the elapsed time are shown on the current line. The line is cleared when f returns. If enabled is false f is run without output.
+pub fn spin<T, F: FnOnce() -> T>(label: &str, enabled: bool, f: F) -> T {
+    if !enabled {
+        return f();
+    }
+
+    let done = AtomicBool::new(false);
+
+    thread::scope(|scope| {
+        scope.spawn(|| {
+            let started = Instant::now();
+            let mut width = 0;
+
+            for frame in FRAMES.iter().cycle() {
+                if done.load(Ordering::SeqCst) {
+                    break;
+                }
+
+                let line = format!("{} {} {}s", label, frame, started.elapsed().as_secs());
+                width = width.max(line.chars().count());
+                print!("\r{}", line);
+                stdout().flush().expect("failed to flush stdout");
+
+                thread::sleep(FRAME_INTERVAL);
+            }
+
+            print!("\r{}\r", " ".repeat(width));
+            stdout().flush().expect("failed to flush stdout");
+        });
+
+        let result = f();
+        done.store(true, Ordering::SeqCst);
+
+        result
+    })
+}
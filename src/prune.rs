@@ -0,0 +1,108 @@
+//! Removes networks and nodes which are not relevant to a survey, e.g. neighbors' hotspots.
+
+use crate::query::glob_match;
+use crate::ScanMap;
+
+/// What to remove. Options which are None or empty remove nothing.
+pub struct PruneOptions {
+    /// Networks weaker than this strength in dBm are removed. Networks whose strength could not be parsed are kept.
+    pub min_strength: Option<f32>,
+
+    /// Networks whose SSID matches any of these globs are removed.
+    pub ssids: Vec<String>,
+
+    /// Nodes measured before this time, in milliseconds since the unix epoch, are removed.
+    pub before: Option<u128>,
+}
+
+/// Number of networks and nodes which were removed.
+pub struct PruneCounts {
+    pub networks: usize,
+    pub nodes: usize,
+}
+
+/// Remove networks and nodes from a map.
+pub fn prune(scan_map: &mut ScanMap, opts: &PruneOptions) -> PruneCounts {
+    let nodes_before = scan_map.nodes.len();
+    let mut counts = PruneCounts{
+        networks: 0,
+        nodes: 0,
+    };
+
+    // Node age is judged before networks are removed, otherwise a node could lose the networks which date it
+    if let Some(before) = opts.before {
+        scan_map.nodes.retain(|n| n.measured().is_none_or(|t| t >= before));
+    }
+    counts.nodes = nodes_before - scan_map.nodes.len();
+
+    for node in &mut scan_map.nodes {
+        let networks_before = node.networks.len();
+
+        node.networks.retain(|n| {
+            let too_weak = match (opts.min_strength, n.strength) {
+                (Some(min), Some(s)) => s < min,
+                _ => false,
+            };
+            let excluded = opts.ssids.iter().any(|pattern| glob_match(pattern, n.display_ssid()));
+
+            !too_weak && !excluded
+        });
+
+        counts.networks += networks_before - node.networks.len();
+    }
+
+    counts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::scan_map;
+    use serde_json::json;
+
+    fn survey() -> ScanMap {
+        scan_map(json!({
+            "nodes": [
+                {"position": {"x": 0.0, "y": 0.0, "z": 0.0}, "networks": [
+                    {"mac": "84:d4:7e:69:2f:e2", "ssid": "CorpWiFi", "strength": -60.0, "time_scanned": 1000},
+                    {"mac": "aa:bb:cc:00:11:22", "ssid": "DIRECT-printer", "strength": -50.0, "time_scanned": 1000},
+                    {"mac": "00:11:22:33:44:55", "ssid": "Neighbor", "strength": -90.0, "time_scanned": 1000},
+                    {"mac": "66:77:88:99:aa:bb", "ssid": "Unparsed", "strength": null, "time_scanned": 1000},
+                ]},
+                {"position": {"x": 5.0, "y": 0.0, "z": 0.0}, "networks": [
+                    {"mac": "84:d4:7e:69:2f:e2", "ssid": "CorpWiFi", "strength": -70.0, "time_scanned": 5000},
+                ]},
+                {"position": {"x": 9.0, "y": 0.0, "z": 0.0}},
+            ],
+        }))
+    }
+
+    #[test]
+    fn prune_removes_weak_and_excluded_networks() {
+        let mut scan_map = survey();
+        let counts = prune(&mut scan_map, &PruneOptions{
+            min_strength: Some(-80.0),
+            ssids: vec![String::from("DIRECT-*")],
+            before: None,
+        });
+
+        assert_eq!((counts.networks, counts.nodes), (2, 0));
+        let ssids: Vec<&str> = scan_map.nodes[0].networks.iter().map(|n| n.ssid.as_str()).collect();
+        assert_eq!(ssids, vec!["CorpWiFi", "Unparsed"]);
+    }
+
+    #[test]
+    fn prune_removes_nodes_measured_before_a_time() {
+        let mut scan_map = survey();
+        let counts = prune(&mut scan_map, &PruneOptions{
+            min_strength: None,
+            ssids: Vec::new(),
+            before: Some(2000),
+        });
+
+        // Nodes without networks have no time and are kept
+        assert_eq!((counts.networks, counts.nodes), (0, 1));
+        let positions: Vec<f64> = scan_map.nodes.iter().map(|n| n.position.x).collect();
+        assert_eq!(positions, vec![5.0, 9.0]);
+    }
+}
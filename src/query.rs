@@ -0,0 +1,387 @@
+//! Finds the networks measured at nodes which match a set of filters.
+
+use std::error::Error;
+use std::io::Write;
+
+use serde::Serialize;
+
+use crate::stream;
+use crate::table;
+use crate::timestamp::{self, TimeRange};
+use crate::{Coordinate, Network, Node, ScanMap};
+
+/// Filters which networks must match. Filters which are None match everything.
+pub struct Filters {
+    /// Glob which the SSID must match, * matches any characters and ? one character.
+    pub ssid: Option<String>,
+
+    /// Prefix of the BSSID, case insensitive.
+    pub bssid: Option<String>,
+
+    /// Area the node must be in: min x, min y, max x, max y.
+    pub bbox: Option<(f64, f64, f64, f64)>,
+
+    /// Floor the node must be on.
+    pub floor: Option<String>,
+
+    /// Tags the node must all have.
+    pub tags: Vec<String>,
+
+    /// Weakest signal strength in dBm.
+    pub min_strength: Option<f32>,
+
+    /// Strongest signal strength in dBm.
+    pub max_strength: Option<f32>,
+
+    /// Times the network must have been scanned within.
+    pub times: TimeRange,
+}
+
+/// A network measured at a node which matched the filters.
+#[derive(Serialize)]
+pub struct Match<'a> {
+    /// Index of the node in the map.
+    pub node: usize,
+
+    pub floor: &'a Option<String>,
+    pub tags: &'a [String],
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+    pub network: &'a Network,
+
+    /// Time the network was scanned, serialized as an RFC 3339 timestamp. network.time_scanned holds the number.
+    #[serde(serialize_with = "timestamp::serialize")]
+    pub scanned: u128,
+}
+
+/// True if text matches a glob pattern, where * matches any characters and ? matches one character.
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+
+    let (mut p, mut t) = (0, 0);
+
+    // Position to retry from after the last *, so the * can absorb one more character
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, t));
+                p += 1;
+            },
+            Some(c) if *c == '?' || *c == text[t] => {
+                p += 1;
+                t += 1;
+            },
+            _ => match backtrack {
+                Some((bp, bt)) => {
+                    backtrack = Some((bp, bt + 1));
+                    p = bp + 1;
+                    t = bt + 1;
+                },
+                None => return false,
+            },
+        };
+    }
+
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// Parse a bounding box in the form "min_x,min_y,max_x,max_y".
+pub fn parse_bbox(s: &str) -> Result<(f64, f64, f64, f64), String> {
+    let parts: Result<Vec<f64>, _> = s.split(',').map(|p| p.trim().parse::<f64>()).collect();
+
+    match parts {
+        Ok(p) if p.len() == 4 => Ok((p[0].min(p[2]), p[1].min(p[3]), p[0].max(p[2]), p[1].max(p[3]))),
+        Ok(_) => Err(format!("invalid bounding box \"{}\", must be in format \"min_x,min_y,max_x,max_y\"", s)),
+        Err(e) => Err(format!("invalid bounding box \"{}\": {}", s, e)),
+    }
+}
+
+impl Filters {
+    /// True if the node passes the position and floor filters.
+    fn matches_node(&self, node: &Node) -> bool {
+        if self.floor.is_some() && node.floor != self.floor {
+            return false;
+        }
+        if !node.has_tags(&self.tags) {
+            return false;
+        }
+
+        match self.bbox {
+            Some((min_x, min_y, max_x, max_y)) => {
+                let p = &node.position;
+                p.x >= min_x && p.x <= max_x && p.y >= min_y && p.y <= max_y
+            },
+            None => true,
+        }
+    }
+
+    /// True if the network passes the network filters. Networks without a parsed strength never match a strength filter.
+    fn matches_network(&self, network: &Network) -> bool {
+        if let Some(pattern) = &self.ssid {
+            if !glob_match(pattern, network.display_ssid()) {
+                return false;
+            }
+        }
+
+        if let Some(prefix) = &self.bssid {
+            if !network.mac.to_lowercase().starts_with(&prefix.to_lowercase()) {
+                return false;
+            }
+        }
+
+        if self.min_strength.is_some() || self.max_strength.is_some() {
+            let strength = match network.strength {
+                Some(s) => s,
+                None => return false,
+            };
+
+            if self.min_strength.is_some_and(|min| strength < min) || self.max_strength.is_some_and(|max| strength > max) {
+                return false;
+            }
+        }
+
+        self.times.contains(network.time_scanned)
+    }
+}
+
+/// Networks of node, the node at index i of its map, which pass the filters.
+fn node_matches<'a>(i: usize, node: &'a Node, filters: &'a Filters) -> impl Iterator<Item = Match<'a>> + 'a {
+    let networks = match filters.matches_node(node) {
+        true => node.networks.as_slice(),
+        false => &[],
+    };
+
+    networks.iter()
+        .filter(move |n| filters.matches_network(n))
+        .map(move |network| Match{
+            node: i,
+            floor: &node.floor,
+            tags: &node.tags,
+            x: node.position.x,
+            y: node.position.y,
+            z: node.position.z,
+            network,
+            scanned: network.time_scanned,
+        })
+}
+
+/// Networks in the map which pass the filters.
+pub fn query<'a>(scan_map: &'a ScanMap, filters: &'a Filters) -> Vec<Match<'a>> {
+    scan_map.nodes.iter()
+        .enumerate()
+        .flat_map(|(i, node)| node_matches(i, node, filters))
+        .collect()
+}
+
+/// Call f with each network of the map which passes the filters. Nodes of streamed maps are read one at a time.
+pub fn for_each_match<F>(scan_map: &ScanMap, filters: &Filters, mut f: F) -> Result<(), Box<dyn Error>>
+where F: FnMut(Match) -> Result<(), Box<dyn Error>> {
+    stream::visit_nodes(scan_map, |i, node| {
+        for m in node_matches(i, node, filters) {
+            f(m)?;
+        }
+        Ok(())
+    })
+}
+
+const MATCH_HEADERS: &[&str] = &["Node", "Floor", "Position", "SSID", "BSSID", "Vendor", "dBm", "Channel", "Scanned", "Note"];
+
+/// Values of a match in a table row.
+fn match_row(scan_map: &ScanMap, m: &Match) -> Vec<String> {
+    vec![
+        m.node.to_string(),
+        m.floor.clone().unwrap_or_default(),
+        Coordinate{ x: m.x, y: m.y, z: m.z }.format(scan_map.coordinate_system, scan_map.units, scan_map.units),
+        m.network.display_ssid().to_string(),
+        m.network.mac.clone(),
+        m.network.vendor.clone().unwrap_or_default(),
+        m.network.strength_str(),
+        m.network.channel.clone(),
+        timestamp::format(m.scanned),
+        scan_map.annotations.get(&m.network.mac).cloned().unwrap_or_default(),
+    ]
+}
+
+/// Print matches as a table.
+pub fn print_matches(scan_map: &ScanMap, matches: &[Match]) {
+    if matches.is_empty() {
+        println!("No matches");
+        return;
+    }
+
+    let rows: Vec<Vec<String>> = matches.iter().map(|m| match_row(scan_map, m)).collect();
+    table::print_table(MATCH_HEADERS, &rows);
+}
+
+/// Write the matches of a streamed map to out as they are read, as a JSON array or a table. Tables read the nodes twice,
+/// once to measure the columns and once to write the rows.
+pub fn write_streamed_matches(scan_map: &ScanMap, filters: &Filters, json: bool, mut out: impl Write) -> Result<(), Box<dyn Error>> {
+    if json {
+        let mut count = 0;
+        out.write_all(b"[")?;
+        for_each_match(scan_map, filters, |m| {
+            out.write_all(match count {
+                0 => b"\n",
+                _ => b",\n",
+            })?;
+            serde_json::to_writer_pretty(&mut out, &m)?;
+            count += 1;
+            Ok(())
+        })?;
+        out.write_all(match count {
+            0 => b"]\n",
+            _ => b"\n]\n",
+        })?;
+        return Ok(out.flush()?);
+    }
+
+    let mut widths = table::header_widths(MATCH_HEADERS);
+    let mut count = 0;
+    for_each_match(scan_map, filters, |m| {
+        table::widen(&mut widths, &match_row(scan_map, &m));
+        count += 1;
+        Ok(())
+    })?;
+    if count == 0 {
+        writeln!(out, "No matches")?;
+        return Ok(out.flush()?);
+    }
+
+    writeln!(out, "{}", table::format_row(&widths, MATCH_HEADERS))?;
+    for_each_match(scan_map, filters, |m| {
+        let row = match_row(scan_map, &m);
+        writeln!(out, "{}", table::format_row(&widths, &row.iter().map(String::as_str).collect::<Vec<&str>>()))?;
+        Ok(())
+    })?;
+
+    Ok(out.flush()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::scan_map;
+    use serde_json::json;
+
+    /// Filters which match everything.
+    fn all() -> Filters {
+        Filters{
+            ssid: None,
+            bssid: None,
+            bbox: None,
+            floor: None,
+            tags: Vec::new(),
+            min_strength: None,
+            max_strength: None,
+            times: TimeRange::default(),
+        }
+    }
+
+    fn survey() -> ScanMap {
+        scan_map(json!({
+            "nodes": [
+                {"position": {"x": 0.0, "y": 0.0, "z": 0.0}, "networks": [
+                    {"mac": "84:D4:7E:69:2F:E2", "ssid": "CorpWiFi", "strength": -60.0, "time_scanned": 1000},
+                    {"mac": "aa:bb:cc:00:11:22", "ssid": "CorpGuest", "strength": -75.0, "time_scanned": 1000},
+                    {"mac": "00:11:22:33:44:55", "ssid": "", "strength": null, "time_scanned": 1000},
+                ]},
+                {"position": {"x": 10.0, "y": 5.0, "z": 0.0}, "floor": "F1", "tags": ["hallway", "east"], "networks": [
+                    {"mac": "84:d4:7e:69:2f:e3", "ssid": "CorpWiFi", "strength": -82.0, "time_scanned": 5000},
+                ]},
+            ],
+        }))
+    }
+
+    /// Node index and SSID of each match.
+    fn found(scan_map: &ScanMap, filters: &Filters) -> Vec<(usize, String)> {
+        query(scan_map, filters).iter().map(|m| (m.node, m.network.display_ssid().to_string())).collect()
+    }
+
+    #[test]
+    fn glob_match_supports_stars_and_question_marks() {
+        assert!(glob_match("CorpWiFi", "CorpWiFi"));
+        assert!(glob_match("Corp*", "CorpGuest"));
+        assert!(glob_match("*Fi", "CorpWiFi"));
+        assert!(glob_match("C?rp*", "CorpWiFi"));
+        assert!(glob_match("a*b*c", "aXbYbZc"));
+        assert!(glob_match("*", ""));
+        assert!(!glob_match("Corp", "CorpWiFi"));
+        assert!(!glob_match("?", ""));
+        assert!(!glob_match("a*b*c", "aXbYbZ"));
+    }
+
+    #[test]
+    fn parse_bbox_orders_the_corners() {
+        assert_eq!(parse_bbox("0,0,10,5"), Ok((0.0, 0.0, 10.0, 5.0)));
+        assert_eq!(parse_bbox("10, 5, 0, 0"), Ok((0.0, 0.0, 10.0, 5.0)));
+        assert!(parse_bbox("0,0,10").is_err());
+        assert!(parse_bbox("0,0,10,north").is_err());
+    }
+
+    #[test]
+    fn query_filters_networks() {
+        let scan_map = survey();
+
+        assert_eq!(found(&scan_map, &Filters{ ssid: Some(String::from("Corp*")), ..all() }).len(), 3);
+        assert_eq!(found(&scan_map, &Filters{ bssid: Some(String::from("84:d4:7E")), ..all() }), vec![
+            (0, String::from("CorpWiFi")),
+            (1, String::from("CorpWiFi")),
+        ]);
+        assert_eq!(found(&scan_map, &Filters{ ssid: Some(String::from("<hidden>")), ..all() }), vec![(0, String::from("<hidden>"))]);
+
+        // Networks without a strength never match a strength filter
+        assert_eq!(found(&scan_map, &Filters{ min_strength: Some(-80.0), ..all() }), vec![
+            (0, String::from("CorpWiFi")),
+            (0, String::from("CorpGuest")),
+        ]);
+        assert_eq!(found(&scan_map, &Filters{ max_strength: Some(-70.0), ..all() }), vec![
+            (0, String::from("CorpGuest")),
+            (1, String::from("CorpWiFi")),
+        ]);
+        assert_eq!(found(&scan_map, &Filters{ times: TimeRange{ since: Some(2000), until: None }, ..all() }), vec![(1, String::from("CorpWiFi"))]);
+        assert_eq!(found(&scan_map, &Filters{ times: TimeRange{ since: None, until: Some(2000) }, ..all() }).len(), 3);
+    }
+
+    #[test]
+    fn query_filters_nodes_by_area_floor_and_tags() {
+        let scan_map = survey();
+
+        let inside = Filters{ bbox: Some((5.0, 0.0, 10.0, 5.0)), ..all() };
+        assert_eq!(found(&scan_map, &inside), vec![(1, String::from("CorpWiFi"))]);
+        assert!(found(&scan_map, &Filters{ bbox: Some((1.0, 1.0, 2.0, 2.0)), ..all() }).is_empty());
+        assert_eq!(found(&scan_map, &Filters{ floor: Some(String::from("F1")), ..all() }).len(), 1);
+        assert!(found(&scan_map, &Filters{ floor: Some(String::from("F2")), ..all() }).is_empty());
+
+        // Nodes need every tag
+        let tagged = |tags: &[&str]| Filters{ tags: tags.iter().map(|t| t.to_string()).collect(), ..all() };
+        assert_eq!(found(&scan_map, &tagged(&["east", "hallway"])), vec![(1, String::from("CorpWiFi"))]);
+        assert!(found(&scan_map, &tagged(&["east", "kitchen"])).is_empty());
+    }
+
+    #[test]
+    fn write_streamed_matches_prints_like_a_loaded_map() {
+        let full = survey();
+        let p = crate::tests::test_dir("query-stream").join("map.json");
+        std::fs::write(&p, serde_json::to_string(&full).unwrap()).unwrap();
+        let streamed = stream::read_header(&p).unwrap();
+        let filters = Filters{ ssid: Some(String::from("Corp*")), ..all() };
+
+        let mut json = Vec::new();
+        write_streamed_matches(&streamed, &filters, true, &mut json).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&json).unwrap();
+        assert_eq!(json, serde_json::to_value(query(&full, &filters)).unwrap());
+
+        let mut text = Vec::new();
+        write_streamed_matches(&streamed, &filters, false, &mut text).unwrap();
+        let rows: Vec<Vec<String>> = query(&full, &filters).iter().map(|m| match_row(&full, m)).collect();
+        assert_eq!(String::from_utf8(text).unwrap().lines().collect::<Vec<&str>>(), table::format_table(MATCH_HEADERS, &rows));
+
+        let mut none = Vec::new();
+        write_streamed_matches(&streamed, &Filters{ ssid: Some(String::from("Nope")), ..all() }, true, &mut none).unwrap();
+        assert_eq!(none, b"[]\n");
+    }
+}
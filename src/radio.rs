@@ -0,0 +1,101 @@
+//! Wireless spectrum helpers: channel numbers, frequencies, and bands.
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+/// Frequency band a network is broadcast in.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Band {
+    #[serde(rename = "2.4GHz")]
+    TwoPointFour,
+
+    #[serde(rename = "5GHz")]
+    Five,
+
+    #[serde(rename = "6GHz")]
+    Six,
+}
+
+impl fmt::Display for Band {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Band::TwoPointFour => write!(f, "2.4 GHz"),
+            Band::Five => write!(f, "5 GHz"),
+            Band::Six => write!(f, "6 GHz"),
+        }
+    }
+}
+
+impl Band {
+    /// Determine the band of a center frequency in MHz. None if the frequency is outside of the wifi bands.
+    pub fn from_frequency(mhz: u32) -> Option<Band> {
+        match mhz {
+            2400..=2500 => Some(Band::TwoPointFour),
+            5150..=5895 => Some(Band::Five),
+            5925..=7125 => Some(Band::Six),
+            _ => None,
+        }
+    }
+}
+
+/// 802.11 amendment generation an access point supports.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Standard {
+    /// 802.11a/b/g, no high throughput capabilities.
+    #[serde(rename = "a/b/g")]
+    Legacy,
+
+    /// 802.11n, high throughput (Wi-Fi 4).
+    #[serde(rename = "n")]
+    N,
+
+    /// 802.11ac, very high throughput (Wi-Fi 5).
+    #[serde(rename = "ac")]
+    Ac,
+
+    /// 802.11ax, high efficiency (Wi-Fi 6).
+    #[serde(rename = "ax")]
+    Ax,
+
+    /// 802.11be, extremely high throughput (Wi-Fi 7).
+    #[serde(rename = "be")]
+    Be,
+}
+
+impl fmt::Display for Standard {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Standard::Legacy => write!(f, "802.11a/b/g"),
+            Standard::N => write!(f, "802.11n"),
+            Standard::Ac => write!(f, "802.11ac"),
+            Standard::Ax => write!(f, "802.11ax"),
+            Standard::Be => write!(f, "802.11be"),
+        }
+    }
+}
+
+/// Parse the primary channel number out of a backend's channel string. Backends report
+/// channels like "6", "36,+1", or "149,80", only the leading number is the primary channel.
+pub fn parse_channel(channel: &str) -> Option<u32> {
+    let digits: String = channel.trim().chars().take_while(|c| c.is_ascii_digit()).collect();
+
+    digits.parse::<u32>().ok()
+}
+
+/// Signal strength in dBm estimated from a signal quality percentage, as reported by NetworkManager and Windows.
+/// Both map 0% to -100 dBm and 100% to -50 dBm linearly.
+pub fn quality_to_dbm(quality: u32) -> f32 {
+    quality.min(100) as f32 / 2.0 - 100.0
+}
+
+/// Center frequency in MHz of a channel. Channel numbers alone cannot distinguish 6 GHz channels
+/// from 2.4 and 5 GHz ones, so they are assumed to be in the 2.4 or 5 GHz band.
+pub fn channel_to_frequency(channel: &str) -> Option<u32> {
+    match parse_channel(channel)? {
+        c @ 1..=13 => Some(2407 + 5 * c),
+        14 => Some(2484),
+        c @ 32..=177 => Some(5000 + 5 * c),
+        _ => None,
+    }
+}
@@ -0,0 +1,260 @@
+//! Sends recorded nodes to a map hosted by the serve command, so several people can survey
+//! into one shared map at the same time.
+
+use std::cell::Cell;
+use std::error::Error;
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::logging;
+use crate::{CoordinateSystem, Node, ScanMap, Session, Units};
+
+/// Nodes submitted to a server, with the sessions they refer to. Session ids are the sender's,
+/// the server gives each session an id of its own.
+#[derive(Serialize)]
+pub struct Upload<'a> {
+    pub coordinate_system: CoordinateSystem,
+
+    pub units: Units,
+
+    pub sessions: Vec<&'a Session>,
+
+    pub nodes: &'a [Node],
+
+    /// Upload id of each node, see upload_id.
+    pub ids: Vec<String>,
+}
+
+/// Nodes received by a server, the owned form of an Upload.
+#[derive(Deserialize)]
+pub struct Submission {
+    pub coordinate_system: CoordinateSystem,
+
+    pub units: Units,
+
+    pub sessions: Vec<Session>,
+
+    pub nodes: Vec<Node>,
+
+    /// Upload id of each node, see upload_id.
+    pub ids: Vec<String>,
+}
+
+/// Identifier which node is sent with, the start of its session and its index in the sender's map. A node gets the same
+/// id each time it is sent, so the server can skip nodes sent again after their response was lost.
+pub fn upload_id(scan_map: &ScanMap, index: usize, node: &Node) -> String {
+    let started = node.session
+        .and_then(|id| scan_map.sessions.iter().find(|s| s.id == id))
+        .map(|s| s.started)
+        .unwrap_or(0);
+
+    format!("{}-{}", started, index)
+}
+
+/// Parts of the served map which nodes must agree with.
+#[derive(Deserialize)]
+struct RemoteMap {
+    name: String,
+
+    coordinate_system: CoordinateSystem,
+
+    units: Units,
+
+    #[serde(default)]
+    floors: Vec<RemoteFloor>,
+}
+
+#[derive(Deserialize)]
+struct RemoteFloor {
+    name: String,
+}
+
+/// Error which occurs when a server cannot accept nodes.
+#[derive(Debug)]
+pub struct RemoteError {
+    /// Reason the server cannot be used.
+    reason: String,
+}
+
+impl fmt::Display for RemoteError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.reason)
+    }
+}
+
+impl Error for RemoteError {}
+
+/// Server which new nodes are sent to whenever the local map is saved.
+pub struct Remote {
+    /// Base URL of the server, without a trailing slash.
+    url: String,
+
+    /// Token the server requires, if any.
+    token: Option<String>,
+
+    /// Number of the local map's nodes which were sent, or existed before recording started.
+    sent: Cell<usize>,
+}
+
+impl Remote {
+    /// Check that the map served at url can hold the nodes of scan_map. Only nodes recorded after this are sent.
+    pub fn connect(url: &str, token: Option<String>, scan_map: &ScanMap) -> Result<Remote, Box<dyn Error>> {
+        let remote = Remote{
+            url: url.trim_end_matches('/').to_string(),
+            token,
+            sent: Cell::new(scan_map.nodes.len()),
+        };
+
+        let served: RemoteMap = remote.request(ureq::get(&format!("{}/map.json", remote.url)))
+            .call()
+            .map_err(|e| RemoteError{
+                reason: format!("failed to reach {}: {}", remote.url, e),
+            })?
+            .into_json()?;
+
+        if served.coordinate_system != scan_map.coordinate_system || served.units != scan_map.units {
+            return Err(Box::new(RemoteError{
+                reason: format!("\"{}\" uses {} coordinates in {} but this map uses {} coordinates in {}", served.name,
+                                served.coordinate_system, served.units, scan_map.coordinate_system, scan_map.units),
+            }));
+        }
+        if let Some(floor) = scan_map.floors.iter().find(|f| !served.floors.iter().any(|s| s.name == f.name)) {
+            return Err(Box::new(RemoteError{
+                reason: format!("\"{}\" has no floor named \"{}\"", served.name, floor.name),
+            }));
+        }
+
+        println!("Sending new nodes to \"{}\" at {}", served.name, remote.url);
+
+        Ok(remote)
+    }
+
+    /// Add the token to a request.
+    fn request(&self, request: ureq::Request) -> ureq::Request {
+        match &self.token {
+            Some(token) => request.set("Authorization", &format!("Bearer {}", token)),
+            None => request,
+        }
+    }
+
+    /// Send the nodes of scan_map which were not sent yet. Nodes which cannot reach the server are tried again the next
+    /// time, nodes the server refuses are only kept in the local map.
+    pub fn push(&self, scan_map: &ScanMap) {
+        // Undone nodes which were already sent stay on the server
+        let sent = self.sent.get().min(scan_map.nodes.len());
+        let nodes = &scan_map.nodes[sent..];
+        if nodes.is_empty() {
+            self.sent.set(sent);
+            return;
+        }
+
+        let upload = Upload{
+            coordinate_system: scan_map.coordinate_system,
+            units: scan_map.units,
+            sessions: scan_map.sessions.iter()
+                .filter(|s| nodes.iter().any(|n| n.session == Some(s.id)))
+                .collect(),
+            nodes,
+            ids: nodes.iter().enumerate().map(|(i, node)| upload_id(scan_map, sent + i, node)).collect(),
+        };
+
+        let result = self.request(ureq::post(&format!("{}/nodes", self.url)))
+            .send_json(&upload);
+        let plural = match nodes.len() != 1 {
+            true => "s",
+            false => "",
+        };
+        match result {
+            Ok(_) => self.sent.set(scan_map.nodes.len()),
+            // Sending the same nodes again would be refused again
+            Err(ureq::Error::Status(code, response)) => {
+                self.sent.set(scan_map.nodes.len());
+                logging::warn(&format!("{} refused {} node{} with status {}, they are only saved in the local map: {}", self.url,
+                                       nodes.len(), plural, code, response.into_string().unwrap_or_default()));
+            },
+            Err(e) => logging::warn(&format!("failed to send {} node{} to {}, they are sent with the next save: {}", nodes.len(),
+                                             plural, self.url, e)),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+
+    fn survey() -> ScanMap {
+        crate::tests::scan_map(serde_json::json!({
+            "nodes": [
+                {"position": {"x": 0.0, "y": 0.0, "z": 0.0}, "networks": []},
+                {"position": {"x": 1.0, "y": 0.0, "z": 0.0}, "networks": []},
+            ],
+        }))
+    }
+
+    /// URL of a server which answers one request with status.
+    fn respond_once(status: &'static str) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+
+        std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if let Some(value) = line.to_lowercase().strip_prefix("content-length:") {
+                    length = value.trim().parse().unwrap();
+                }
+                if line == "\r\n" {
+                    break;
+                }
+            }
+            reader.read_exact(&mut vec![0; length]).unwrap();
+            write!(reader.get_mut(), "HTTP/1.1 {}\r\nContent-Length: 4\r\nConnection: close\r\n\r\nnope", status).unwrap();
+        });
+
+        url
+    }
+
+    #[test]
+    fn upload_ids_name_the_session_and_index() {
+        let scan_map = crate::tests::scan_map(serde_json::json!({
+            "sessions": [{"id": 1, "started": 1700000000000u64, "operator": null, "device": null}],
+            "nodes": [
+                {"position": {"x": 0.0, "y": 0.0, "z": 0.0}, "networks": []},
+                {"position": {"x": 1.0, "y": 0.0, "z": 0.0}, "session": 1, "networks": []},
+            ],
+        }));
+
+        assert_eq!(upload_id(&scan_map, 0, &scan_map.nodes[0]), "0-0");
+        assert_eq!(upload_id(&scan_map, 1, &scan_map.nodes[1]), "1700000000000-1");
+    }
+
+    #[test]
+    fn push_drops_nodes_the_server_refuses() {
+        let remote = Remote{
+            url: respond_once("409 Conflict"),
+            token: None,
+            sent: Cell::new(0),
+        };
+        remote.push(&survey());
+        assert_eq!(remote.sent.get(), 2);
+    }
+
+    #[test]
+    fn push_retries_nodes_which_did_not_reach_the_server() {
+        // Nothing listens on the port once the listener is dropped
+        let url = format!("http://{}", TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap());
+        let remote = Remote{
+            url,
+            token: None,
+            sent: Cell::new(1),
+        };
+        remote.push(&survey());
+        assert_eq!(remote.sent.get(), 1);
+    }
+}
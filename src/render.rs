@@ -0,0 +1,439 @@
+//! Draws the nodes of a scan map and a heatmap of the signal between them onto an image, optionally on top of a floor plan.
+
+use std::collections::HashSet;
+use std::error::Error;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use plotters::coord::Shift;
+use plotters::prelude::*;
+
+use crate::gaps::{self, Gap};
+use crate::interpolate::{self, Grid, InterpolateOptions, Target};
+use crate::{CoordinateSystem, FloorPlan, Node, ScanMap, EARTH_RADIUS};
+
+/// Signal strength drawn in the weakest color.
+pub const WEAK_DBM: f64 = -90.0;
+
+/// Signal strength drawn in the strongest color.
+pub const STRONG_DBM: f64 = -30.0;
+
+/// Size in pixels of the longest side of images rendered without a floor plan.
+const DEFAULT_SIZE: u32 = 800;
+
+/// Space in pixels left around nodes when rendering without a floor plan.
+const MARGIN: f64 = 40.0;
+
+/// Radius in pixels of node markers.
+const NODE_RADIUS: i32 = 8;
+
+/// Size in pixels of heatmap cells when no spacing is chosen.
+const HEATMAP_CELL: f64 = 4.0;
+
+/// Size in pixels of heatmap cells in SVGs when no spacing is chosen, larger so the file does not get huge.
+const SVG_HEATMAP_CELL: f64 = 10.0;
+
+/// Opacity of the heatmap, so the floor plan shows through.
+const HEATMAP_OPACITY: f64 = 0.6;
+
+/// Half the size in pixels of the markers of estimated access point positions.
+const ACCESS_POINT_SIZE: i32 = 6;
+
+/// Image format of renderings.
+#[derive(Clone, Copy)]
+pub enum RenderFormat {
+    Png,
+
+    /// Scalable vector image, nodes are labeled with their coordinates.
+    Svg,
+}
+
+impl std::str::FromStr for RenderFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<RenderFormat, String> {
+        match s.trim().to_lowercase().as_str() {
+            "png" => Ok(RenderFormat::Png),
+            "svg" => Ok(RenderFormat::Svg),
+            _ => Err(format!("unknown render format \"{}\", must be png or svg", s.trim())),
+        }
+    }
+}
+
+/// Options which control how the heatmap is interpolated.
+#[derive(Clone)]
+pub struct HeatmapOptions {
+    /// Distance between grid points in the map's units, meters for wgs84 maps. None picks a spacing of a few pixels.
+    pub spacing: Option<f64>,
+
+    /// Inverse distance weighting exponent.
+    pub power: f64,
+
+    /// Areas farther than this from every node are left blank.
+    pub radius: Option<f64>,
+
+    /// Strength in dBm used for nodes which did not see the network.
+    pub unseen: Option<f64>,
+}
+
+/// Options which control what is rendered.
+pub struct RenderOptions {
+    /// Nodes are colored by the strongest signal of networks with this SSID.
+    pub ssid: String,
+
+    /// Only render nodes on this floor.
+    pub floor: Option<String>,
+
+    /// File to save the image to.
+    pub output: PathBuf,
+
+    pub format: RenderFormat,
+
+    /// Heatmap drawn under the nodes, None to only draw the nodes.
+    pub heatmap: Option<HeatmapOptions>,
+
+    /// Outline the regions of the heatmap weaker than this many dBm.
+    pub gaps: Option<f64>,
+}
+
+/// Error which occurs when nothing can be rendered.
+#[derive(Debug)]
+pub struct RenderError {
+    /// Reason rendering failed.
+    reason: String,
+}
+
+impl fmt::Display for RenderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.reason)
+    }
+}
+
+impl Error for RenderError {}
+
+/// Converts map coordinates to pixel coordinates. Map y coordinates increase upwards while pixel y coordinates increase downwards.
+pub struct Viewport {
+    /// Pixels per map unit.
+    pub scale: f64,
+
+    /// Pixel position of the map's origin.
+    pub origin: (f64, f64),
+}
+
+impl Viewport {
+    /// Viewport of a floor plan image.
+    pub fn of_plan(plan: &FloorPlan) -> Viewport {
+        Viewport{
+            scale: plan.scale,
+            origin: (plan.origin_x, plan.origin_y),
+        }
+    }
+
+    /// Viewport which fits all nodes into an image whose longest side is size pixels. Returns the viewport and the image dimensions.
+    pub fn fit(nodes: &[&Node], size: u32) -> (Viewport, (u32, u32)) {
+        let min_x = nodes.iter().map(|n| n.position.x).fold(f64::INFINITY, f64::min);
+        let max_x = nodes.iter().map(|n| n.position.x).fold(f64::NEG_INFINITY, f64::max);
+        let min_y = nodes.iter().map(|n| n.position.y).fold(f64::INFINITY, f64::min);
+        let max_y = nodes.iter().map(|n| n.position.y).fold(f64::NEG_INFINITY, f64::max);
+
+        // A single node or a line of nodes has no extent in one direction
+        let span_x = (max_x - min_x).max(1e-9);
+        let span_y = (max_y - min_y).max(1e-9);
+        let usable = size as f64 - 2.0 * MARGIN;
+        let scale = (usable / span_x).min(usable / span_y);
+
+        let width = (span_x * scale + 2.0 * MARGIN).min(size as f64).max(2.0 * MARGIN) as u32;
+        let height = (span_y * scale + 2.0 * MARGIN).min(size as f64).max(2.0 * MARGIN) as u32;
+
+        (Viewport{
+            scale,
+            origin: (MARGIN - min_x * scale, MARGIN + max_y * scale),
+        }, (width, height))
+    }
+
+    /// Pixel position of a map coordinate.
+    pub fn to_pixel(&self, x: f64, y: f64) -> (i32, i32) {
+        ((self.origin.0 + x * self.scale).round() as i32, (self.origin.1 - y * self.scale).round() as i32)
+    }
+}
+
+/// Color of a signal strength, from red for weak signals to green for strong ones. Gray if the network was not seen.
+pub fn strength_color(dbm: Option<f64>) -> RGBColor {
+    let dbm = match dbm {
+        Some(v) => v,
+        None => return RGBColor(160, 160, 160),
+    };
+
+    let t = ((dbm - WEAK_DBM) / (STRONG_DBM - WEAK_DBM)).clamp(0.0, 1.0);
+    let hsl = HSLColor(t * 120.0 / 360.0, 0.9, 0.45);
+    let (r, g, b) = hsl.rgb();
+
+    RGBColor(r, g, b)
+}
+
+/// Strongest signal of networks named ssid seen at a node.
+pub fn strongest(node: &Node, ssid: &str) -> Option<f64> {
+    node.networks.iter()
+        .filter(|n| n.ssid == ssid)
+        .filter_map(|n| n.strength)
+        .map(|s| s as f64)
+        .fold(None, |best: Option<f64>, s| Some(best.map_or(s, |b| b.max(s))))
+}
+
+/// Draw a color scale from the weakest to the strongest signal in the top left corner.
+pub fn draw_legend<DB: DrawingBackend>(root: &DrawingArea<DB, Shift>) -> Result<(), Box<dyn Error>>
+where DB::ErrorType: 'static {
+    let (left, top, width, height) = (10, 10, 150, 12);
+
+    root.draw(&Rectangle::new([(left - 4, top - 4), (left + width + 4, top + height + 22)], WHITE.mix(0.8).filled()))?;
+
+    for i in 0..width {
+        let dbm = WEAK_DBM + (STRONG_DBM - WEAK_DBM) * (i as f64 / width as f64);
+        root.draw(&Rectangle::new([(left + i, top), (left + i + 1, top + height)], strength_color(Some(dbm)).filled()))?;
+    }
+
+    let font = ("sans-serif", 12).into_font();
+    root.draw(&Text::new(format!("{} dBm", WEAK_DBM), (left, top + height + 4), font.clone()))?;
+    root.draw(&Text::new(format!("{} dBm", STRONG_DBM), (left + width - 45, top + height + 4), font))?;
+
+    Ok(())
+}
+
+/// Draw a marker for each node, labeled with its signal strength and optionally its coordinates.
+fn draw_nodes<DB: DrawingBackend>(root: &DrawingArea<DB, Shift>, viewport: &Viewport, scan_map: &ScanMap, nodes: &[&Node], ssid: &str, label_coordinates: bool) -> Result<(), Box<dyn Error>>
+where DB::ErrorType: 'static {
+    let font = ("sans-serif", 12).into_font();
+    let small_font = ("sans-serif", 10).into_font().color(&RGBColor(80, 80, 80));
+
+    for node in nodes {
+        let pos = viewport.to_pixel(node.position.x, node.position.y);
+        let dbm = strongest(node, ssid);
+
+        root.draw(&Circle::new(pos, NODE_RADIUS, strength_color(dbm).filled()))?;
+        root.draw(&Circle::new(pos, NODE_RADIUS, BLACK.stroke_width(1)))?;
+
+        let label = match dbm {
+            Some(v) => format!("{:.0}", v),
+            None => String::from("-"),
+        };
+        root.draw(&Text::new(label, (pos.0 + NODE_RADIUS + 2, pos.1 - 6), font.clone()))?;
+
+        if label_coordinates {
+            let coordinates = match scan_map.coordinate_system {
+                CoordinateSystem::Local => format!("{:.1}, {:.1}", node.position.x, node.position.y),
+                CoordinateSystem::Wgs84 => format!("{:.6}, {:.6}", node.position.x, node.position.y),
+            };
+            root.draw(&Text::new(coordinates, (pos.0 + NODE_RADIUS + 2, pos.1 + 7), small_font.clone()))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Interpolated strengths of networks broadcasting ssid, and the spacing they were interpolated with.
+fn heatmap_grid(viewport: &Viewport, scan_map: &ScanMap, opts: &RenderOptions, heatmap: &HeatmapOptions) -> Result<(Grid, f64), Box<dyn Error>> {
+    // A few pixels per cell, wgs84 spacings are in meters while their positions are in degrees
+    let spacing = heatmap.spacing.unwrap_or_else(|| {
+        let cell = match opts.format {
+            RenderFormat::Png => HEATMAP_CELL,
+            RenderFormat::Svg => SVG_HEATMAP_CELL,
+        } / viewport.scale;
+        match scan_map.coordinate_system {
+            CoordinateSystem::Local => cell,
+            CoordinateSystem::Wgs84 => cell.to_radians() * EARTH_RADIUS,
+        }
+    });
+
+    let grid = interpolate::interpolate(scan_map, &InterpolateOptions{
+        target: Target::Ssid(opts.ssid.clone()),
+        floor: opts.floor.clone(),
+        spacing,
+        power: heatmap.power,
+        radius: heatmap.radius,
+        unseen: heatmap.unseen,
+    })?;
+
+    Ok((grid, spacing))
+}
+
+/// Draw the strength of networks broadcasting ssid interpolated between the nodes.
+fn draw_heatmap<DB: DrawingBackend>(root: &DrawingArea<DB, Shift>, viewport: &Viewport, scan_map: &ScanMap, opts: &RenderOptions, heatmap: &HeatmapOptions) -> Result<(), Box<dyn Error>>
+where DB::ErrorType: 'static {
+    let (grid, spacing) = heatmap_grid(viewport, scan_map, opts, heatmap)?;
+
+    for row in 0..grid.rows {
+        for column in 0..grid.columns {
+            let dbm = match grid.value(column, row) {
+                Some(v) => v,
+                None => continue,
+            };
+
+            let (x, y) = grid.position(column, row);
+            let top_left = viewport.to_pixel(x - grid.spacing.0 / 2.0, y + grid.spacing.1 / 2.0);
+            let bottom_right = viewport.to_pixel(x + grid.spacing.0 / 2.0, y - grid.spacing.1 / 2.0);
+
+            root.draw(&Rectangle::new([top_left, bottom_right], strength_color(Some(dbm)).mix(HEATMAP_OPACITY).filled()))?;
+        }
+    }
+
+    if let Some(threshold) = opts.gaps {
+        draw_gaps(root, viewport, &grid, &gaps::find_gaps(&grid, spacing, threshold, 0.0))?;
+    }
+
+    Ok(())
+}
+
+/// Outline each gap and number it like the analyze gaps command does.
+fn draw_gaps<DB: DrawingBackend>(root: &DrawingArea<DB, Shift>, viewport: &Viewport, grid: &Grid, gaps: &[Gap]) -> Result<(), Box<dyn Error>>
+where DB::ErrorType: 'static {
+    let font = ("sans-serif", 14).into_font().style(FontStyle::Bold);
+    let (half_x, half_y) = (grid.spacing.0 / 2.0, grid.spacing.1 / 2.0);
+
+    for (i, gap) in gaps.iter().enumerate() {
+        // Only edges between a cell of the gap and one outside it are drawn
+        let cells: HashSet<&(usize, usize)> = gap.cells.iter().collect();
+        let inside = |c: usize, r: usize| cells.contains(&(c, r));
+        for &(c, r) in &gap.cells {
+            let (x, y) = grid.position(c, r);
+            let edges = [
+                (c == 0 || !inside(c - 1, r), (x - half_x, y - half_y), (x - half_x, y + half_y)),
+                (!inside(c + 1, r), (x + half_x, y - half_y), (x + half_x, y + half_y)),
+                (r == 0 || !inside(c, r - 1), (x - half_x, y - half_y), (x + half_x, y - half_y)),
+                (!inside(c, r + 1), (x - half_x, y + half_y), (x + half_x, y + half_y)),
+            ];
+            for (outside, from, to) in edges {
+                if outside {
+                    root.draw(&PathElement::new(vec![viewport.to_pixel(from.0, from.1), viewport.to_pixel(to.0, to.1)], BLACK.stroke_width(2)))?;
+                }
+            }
+        }
+
+        root.draw(&Text::new(format!("Gap {}", i + 1), viewport.to_pixel(gap.center.0, gap.center.1), font.clone()))?;
+    }
+
+    Ok(())
+}
+
+/// Draw the positions the locate command estimated for access points broadcasting ssid, surrounded by their confidence radius.
+fn draw_access_points<DB: DrawingBackend>(root: &DrawingArea<DB, Shift>, viewport: &Viewport, scan_map: &ScanMap, opts: &RenderOptions) -> Result<(), Box<dyn Error>>
+where DB::ErrorType: 'static {
+    let font = ("sans-serif", 12).into_font();
+
+    for (mac, location) in &scan_map.access_points {
+        if location.ssid != opts.ssid || (opts.floor.is_some() && location.floor != opts.floor) {
+            continue;
+        }
+
+        let pos = viewport.to_pixel(location.position.x, location.position.y);
+        // Radii of wgs84 maps are in meters while their positions are in degrees
+        let radius = match scan_map.coordinate_system {
+            CoordinateSystem::Local => location.radius,
+            CoordinateSystem::Wgs84 => (location.radius / EARTH_RADIUS).to_degrees(),
+        } * viewport.scale;
+
+        root.draw(&Circle::new(pos, radius.round() as i32, BLUE.mix(0.15).filled()))?;
+        root.draw(&Circle::new(pos, radius.round() as i32, BLUE.stroke_width(1)))?;
+        root.draw(&Rectangle::new([(pos.0 - ACCESS_POINT_SIZE, pos.1 - ACCESS_POINT_SIZE), (pos.0 + ACCESS_POINT_SIZE, pos.1 + ACCESS_POINT_SIZE)], BLUE.filled()))?;
+        root.draw(&Text::new(mac.clone(), (pos.0 + ACCESS_POINT_SIZE + 2, pos.1 - 6), font.clone()))?;
+    }
+
+    Ok(())
+}
+
+/// Draw the heatmap, access points, nodes, and legend.
+fn draw<DB: DrawingBackend>(root: &DrawingArea<DB, Shift>, viewport: &Viewport, scan_map: &ScanMap, nodes: &[&Node], opts: &RenderOptions) -> Result<(), Box<dyn Error>>
+where DB::ErrorType: 'static {
+    if let Some(heatmap) = &opts.heatmap {
+        // Networks nobody saw have no heatmap, the nodes are still drawn gray
+        if nodes.iter().any(|n| strongest(n, &opts.ssid).is_some()) || heatmap.unseen.is_some() {
+            draw_heatmap(root, viewport, scan_map, opts, heatmap)?;
+        }
+    }
+    draw_access_points(root, viewport, scan_map, opts)?;
+    draw_nodes(root, viewport, scan_map, nodes, &opts.ssid, matches!(opts.format, RenderFormat::Svg))?;
+    draw_legend(root)?;
+
+    Ok(())
+}
+
+/// Nodes to draw, the viewport, the size of the image, and the floor plan to draw them on. Relative floor plan paths are resolved relative to map_dir.
+type Layout<'a> = (Vec<&'a Node>, Viewport, (u32, u32), Option<image::DynamicImage>);
+
+/// Choose the nodes and viewport of a rendering.
+fn layout<'a>(scan_map: &'a ScanMap, map_dir: &Path, opts: &RenderOptions) -> Result<Layout<'a>, Box<dyn Error>> {
+    let nodes: Vec<&Node> = scan_map.nodes.iter()
+        .filter(|n| opts.floor.is_none() || n.floor == opts.floor)
+        .collect();
+    if nodes.is_empty() {
+        return Err(Box::new(RenderError{
+            reason: String::from("no nodes to render"),
+        }));
+    }
+
+    let plan = scan_map.floor_plan(opts.floor.as_deref());
+
+    Ok(match plan {
+        Some(plan) => {
+            let image = image::open(map_dir.join(&plan.image))?;
+            let size = (image.width(), image.height());
+            (nodes, Viewport::of_plan(plan), size, Some(image))
+        },
+        None => {
+            let (viewport, size) = Viewport::fit(&nodes, DEFAULT_SIZE);
+            (nodes, viewport, size, None)
+        },
+    })
+}
+
+/// Draw everything onto a white background or the floor plan. SVGs embed floor plans as PNG data.
+fn paint<DB: DrawingBackend>(root: DrawingArea<DB, Shift>, layout: Layout, scan_map: &ScanMap, opts: &RenderOptions) -> Result<(), Box<dyn Error>>
+where DB::ErrorType: 'static {
+    let (nodes, viewport, _, plan_image) = layout;
+
+    root.fill(&WHITE)?;
+
+    if let Some(image) = plan_image {
+        let element: BitMapElement<(i32, i32)> = ((0, 0), image).into();
+        root.draw(&element)?;
+    }
+
+    draw(&root, &viewport, scan_map, &nodes, opts)?;
+    root.present()?;
+
+    Ok(())
+}
+
+/// Render the nodes of a scan map to opts.output. Relative floor plan paths are resolved relative to map_dir.
+pub fn render(scan_map: &ScanMap, map_dir: &Path, opts: &RenderOptions) -> Result<(), Box<dyn Error>> {
+    let layout = layout(scan_map, map_dir, opts)?;
+    let size = layout.2;
+
+    match opts.format {
+        RenderFormat::Png => paint(BitMapBackend::new(&opts.output, size).into_drawing_area(), layout, scan_map, opts),
+        RenderFormat::Svg => paint(SVGBackend::new(&opts.output, size).into_drawing_area(), layout, scan_map, opts),
+    }
+}
+
+/// Render the nodes of a scan map as an SVG document instead of saving it to opts.output.
+pub fn render_svg(scan_map: &ScanMap, map_dir: &Path, opts: &RenderOptions) -> Result<String, Box<dyn Error>> {
+    let layout = layout(scan_map, map_dir, opts)?;
+    let size = layout.2;
+
+    let mut svg = String::new();
+    paint(SVGBackend::with_string(&mut svg, size).into_drawing_area(), layout, scan_map, opts)?;
+
+    Ok(svg)
+}
+
+/// Gaps a rendering with these options outlines, numbered in the same order. Empty if opts has no heatmap or gap threshold.
+pub fn find_gaps(scan_map: &ScanMap, map_dir: &Path, opts: &RenderOptions) -> Result<Vec<Gap>, Box<dyn Error>> {
+    let (heatmap, threshold) = match (&opts.heatmap, opts.gaps) {
+        (Some(heatmap), Some(threshold)) => (heatmap, threshold),
+        _ => return Ok(Vec::new()),
+    };
+
+    let (_, viewport, _, _) = layout(scan_map, map_dir, opts)?;
+    let (grid, spacing) = heatmap_grid(&viewport, scan_map, opts, heatmap)?;
+
+    Ok(gaps::find_gaps(&grid, spacing, threshold, 0.0))
+}
@@ -0,0 +1,214 @@
+//! Writes a survey report: a single HTML file with the map's summary, statistics, signal
+//! tables, and heatmaps, which can be handed over without any other files.
+
+use std::error::Error;
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+
+use crate::render::{self, HeatmapOptions, RenderFormat, RenderOptions};
+use crate::gaps;
+use crate::stats;
+use crate::timestamp;
+use crate::ScanMap;
+
+/// Styles of the report.
+const STYLE: &str = "body { font-family: sans-serif; margin: 2em auto; max-width: 1100px; color: #222; }
+table { border-collapse: collapse; margin: 1em 0; }
+th, td { border: 1px solid #ccc; padding: 0.25em 0.6em; text-align: left; }
+th { background: #f0f0f0; }
+figure { margin: 1em 0; }
+figure svg { max-width: 100%; height: auto; border: 1px solid #ccc; }";
+
+/// Options which control what the report contains.
+pub struct ReportOptions {
+    /// Number of SSIDs, best covered first, which get a heatmap and a column in the signal table.
+    pub heatmaps: usize,
+
+    pub heatmap: HeatmapOptions,
+
+    /// Outline and list the regions of each heatmap weaker than this many dBm.
+    pub gaps: Option<f64>,
+}
+
+/// Escape text for use in HTML.
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Append a table with a header row. Values are escaped.
+fn table(html: &mut String, headers: &[&str], rows: &[Vec<String>]) {
+    html.push_str("<table>\n<tr>");
+    for header in headers {
+        let _ = write!(html, "<th>{}</th>", escape(header));
+    }
+    html.push_str("</tr>\n");
+
+    for row in rows {
+        html.push_str("<tr>");
+        for value in row {
+            let _ = write!(html, "<td>{}</td>", escape(value));
+        }
+        html.push_str("</tr>\n");
+    }
+    html.push_str("</table>\n");
+}
+
+/// HTML report of a scan map. Relative floor plan paths are resolved relative to map_dir.
+pub fn report(scan_map: &ScanMap, map_dir: &Path, opts: &ReportOptions) -> Result<String, Box<dyn Error>> {
+    let summary = stats::summarize(scan_map, None, None);
+    let units = scan_map.units;
+    let mut html = String::new();
+
+    let _ = write!(html, "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{name}</title>\n<style>\n{style}\n</style>\n</head>\n<body>\n<h1>{name}</h1>\n",
+                   name=escape(&scan_map.name), style=STYLE);
+    if !scan_map.notes.is_empty() {
+        let _ = writeln!(html, "<p>{}</p>", escape(&scan_map.notes));
+    }
+
+    html.push_str("<h2>Summary</h2>\n");
+    table(&mut html, &["", ""], &[
+        vec![String::from("Nodes"), summary.nodes.to_string()],
+        vec![String::from("BSSIDs"), summary.bssids.to_string()],
+        vec![String::from("SSIDs"), summary.ssids.len().to_string()],
+        vec![String::from("Nodes without networks"), summary.empty.len().to_string()],
+        vec![String::from("Floors"), match scan_map.floors.is_empty() {
+            true => String::from("-"),
+            false => scan_map.floors.iter().map(|f| f.name.as_str()).collect::<Vec<&str>>().join(", "),
+        }],
+        vec![String::from("Coordinate system"), format!("{} ({})", scan_map.coordinate_system, units)],
+        vec![String::from("Sessions"), scan_map.sessions.len().to_string()],
+    ]);
+
+    if !scan_map.sessions.is_empty() {
+        table(&mut html, &["Session", "Started", "Operator", "Device", "Nodes"], &scan_map.sessions.iter()
+              .map(|s| vec![
+                  s.id.to_string(),
+                  timestamp::format(s.started),
+                  s.operator.clone().unwrap_or_default(),
+                  s.device.clone().unwrap_or_default(),
+                  scan_map.nodes.iter().filter(|n| n.session == Some(s.id)).count().to_string(),
+              ])
+              .collect::<Vec<Vec<String>>>());
+    }
+
+    html.push_str("<h2>Networks</h2>\n");
+    table(&mut html, &["SSID", "BSSIDs", "Nodes", "Min dBm", "Avg dBm", "Max dBm"], &summary.ssids.iter()
+          .map(|s| {
+              let [min, avg, max] = s.strength_columns();
+              vec![s.ssid.clone(), s.bssids.to_string(), format!("{}/{}", s.nodes, summary.nodes), min, avg, max]
+          })
+          .collect::<Vec<Vec<String>>>());
+
+    html.push_str("<h2>Channels</h2>\n");
+    table(&mut html, &["Channel", "BSSIDs"], &summary.channels.iter()
+          .map(|(c, count)| vec![c.clone(), count.to_string()])
+          .collect::<Vec<Vec<String>>>());
+
+    // Hidden networks have no SSID to draw a heatmap of
+    let ssids: Vec<&str> = summary.ssids.iter()
+        .map(|s| s.ssid.as_str())
+        .filter(|s| *s != crate::HIDDEN_SSID_LABEL)
+        .take(opts.heatmaps)
+        .collect();
+
+    if !ssids.is_empty() {
+        html.push_str("<h2>Heatmaps</h2>\n");
+
+        let floors: Vec<Option<String>> = match scan_map.floors.is_empty() {
+            true => vec![None],
+            false => scan_map.floors.iter().map(|f| Some(f.name.clone())).collect(),
+        };
+
+        for ssid in &ssids {
+            for floor in &floors {
+                if !scan_map.nodes.iter().any(|n| &n.floor == floor) {
+                    continue;
+                }
+
+                let render_opts = RenderOptions{
+                    ssid: ssid.to_string(),
+                    floor: floor.clone(),
+                    output: PathBuf::new(),
+                    format: RenderFormat::Svg,
+                    heatmap: Some(opts.heatmap.clone()),
+                    gaps: opts.gaps,
+                };
+                let svg = render::render_svg(scan_map, map_dir, &render_opts)?;
+
+                let caption = match floor {
+                    Some(name) => format!("{} on floor {}", ssid, name),
+                    None => ssid.to_string(),
+                };
+                let _ = write!(html, "<figure>\n{}\n<figcaption>{}</figcaption>\n</figure>\n", svg, escape(&caption));
+
+                // Numbered like the outlines in the heatmap
+                if let Some(threshold) = opts.gaps {
+                    let found = render::find_gaps(scan_map, map_dir, &render_opts)?;
+                    match found.is_empty() {
+                        true => {
+                            let _ = writeln!(html, "<p>No areas weaker than {} dBm.</p>", threshold);
+                        },
+                        false => table(&mut html, &["Gap", "Center", "Area", "Weakest", "Average"], &found.iter()
+                                       .enumerate()
+                                       .map(|(i, g)| vec![
+                                           (i + 1).to_string(),
+                                           format!("{:.2} {:.2}", g.center.0, g.center.1),
+                                           gaps::format_area(g.area, units),
+                                           format!("{:.0} dBm", g.weakest),
+                                           format!("{:.0} dBm", g.average),
+                                       ])
+                                       .collect::<Vec<Vec<String>>>()),
+                    };
+                }
+            }
+        }
+    }
+
+    html.push_str("<h2>Signal strength by node</h2>\n");
+    let mut headers = vec!["Node", "Position", "Floor", "Networks"];
+    headers.extend(ssids.iter());
+    table(&mut html, &headers, &scan_map.nodes.iter()
+          .enumerate()
+          .map(|(i, node)| {
+              let mut row = vec![
+                  i.to_string(),
+                  node.position.format(scan_map.coordinate_system, units, units),
+                  node.floor.clone().unwrap_or_default(),
+                  node.networks.len().to_string(),
+              ];
+              row.extend(ssids.iter().map(|ssid| match render::strongest(node, ssid) {
+                  Some(dbm) => format!("{:.0} dBm", dbm),
+                  None => String::from("-"),
+              }));
+              row
+          })
+          .collect::<Vec<Vec<String>>>());
+
+    if !scan_map.access_points.is_empty() {
+        html.push_str("<h2>Estimated access point positions</h2>\n");
+        table(&mut html, &["BSSID", "SSID", "Floor", "Position", "Radius", "Nodes"], &scan_map.access_points.iter()
+              .map(|(mac, l)| vec![
+                  mac.clone(),
+                  l.ssid.clone(),
+                  l.floor.clone().unwrap_or_default(),
+                  l.position.format(scan_map.coordinate_system, units, units),
+                  format!("{:.1} {}", l.radius, units.abbreviation()),
+                  l.nodes.to_string(),
+              ])
+              .collect::<Vec<Vec<String>>>());
+    }
+
+    if !scan_map.annotations.is_empty() {
+        html.push_str("<h2>Access point notes</h2>\n");
+        table(&mut html, &["BSSID", "SSID", "Note"], &scan_map.annotations.iter()
+              .map(|(mac, note)| vec![mac.clone(), scan_map.ssid_of(mac).unwrap_or("").to_string(), note.clone()])
+              .collect::<Vec<Vec<String>>>());
+    }
+
+    html.push_str("</body>\n</html>\n");
+
+    Ok(html)
+}
@@ -0,0 +1,287 @@
+//! Analyzes how clients roam between the access points of one SSID: which BSSID each node
+//! would connect to, where two BSSIDs are too close in strength for clients to settle, and
+//! where the dominant BSSID changes without an overlap to hand off in.
+
+use std::collections::{BTreeMap, HashSet};
+
+use serde::Serialize;
+
+use crate::{Coordinate, ScanMap};
+
+/// Options which control what counts as contested or abrupt.
+pub struct RoamingOptions {
+    pub ssid: String,
+
+    /// Only analyze nodes on this floor.
+    pub floor: Option<String>,
+
+    /// Two BSSIDs closer than this many dB at a node contest it.
+    pub margin: f64,
+
+    /// Weakest strength in dBm a client can use.
+    pub usable: f64,
+
+    /// Nodes closer than this are compared for hand offs, in the map's units.
+    pub neighbor: f64,
+}
+
+/// BSSID and its strength in dBm.
+#[derive(Serialize, Clone)]
+pub struct Signal {
+    pub bssid: String,
+
+    pub strength: f64,
+}
+
+/// BSSIDs of the SSID at one node.
+#[derive(Serialize)]
+pub struct NodeRoaming {
+    /// Index of the node in the map.
+    pub node: usize,
+
+    pub position: Coordinate,
+
+    pub floor: Option<String>,
+
+    /// Strongest BSSID.
+    pub dominant: Signal,
+
+    /// Second strongest BSSID, None if only one was seen.
+    pub runner_up: Option<Signal>,
+
+    /// True if the runner up is usable and within the margin of the dominant BSSID, so clients may stick to either or flip between them.
+    pub contested: bool,
+
+    /// True if even the dominant BSSID is not usable.
+    pub weak: bool,
+}
+
+/// Change of dominant BSSID between two neighboring nodes.
+#[derive(Serialize)]
+pub struct Handoff {
+    /// Index of the nodes in the map.
+    pub from: usize,
+
+    pub to: usize,
+
+    pub from_bssid: String,
+
+    pub to_bssid: String,
+
+    /// Distance between the nodes in the map's units.
+    pub distance: f64,
+
+    /// True if neither node hears the other's dominant BSSID well enough to use, so clients have nowhere to roam in between.
+    pub abrupt: bool,
+}
+
+/// Roaming analysis of an SSID.
+#[derive(Serialize)]
+pub struct Roaming {
+    /// BSSIDs of the SSID and the number of nodes each dominates, most first.
+    pub bssids: Vec<(String, usize)>,
+
+    pub nodes: Vec<NodeRoaming>,
+
+    pub handoffs: Vec<Handoff>,
+}
+
+/// Compare the BSSIDs of opts.ssid at every node and between neighboring nodes.
+pub fn analyze_roaming(scan_map: &ScanMap, opts: &RoamingOptions) -> Roaming {
+    // Strength of each BSSID of the SSID at each node, strongest first
+    let mut signals = Vec::<(usize, Vec<Signal>)>::new();
+    let mut bssids = BTreeMap::<String, usize>::new();
+    for (i, node) in scan_map.nodes.iter().enumerate() {
+        if opts.floor.is_some() && node.floor != opts.floor {
+            continue;
+        }
+
+        let mut seen: Vec<Signal> = node.networks.iter()
+            .filter(|n| n.ssid == opts.ssid)
+            .filter_map(|n| n.strength.map(|s| Signal{ bssid: n.mac.to_lowercase(), strength: s as f64 }))
+            .collect();
+        for signal in &seen {
+            bssids.entry(signal.bssid.clone()).or_insert(0);
+        }
+        if seen.is_empty() {
+            continue;
+        }
+
+        seen.sort_by(|a, b| b.strength.partial_cmp(&a.strength).unwrap_or(std::cmp::Ordering::Equal));
+        // A BSSID seen by several interfaces only counts with its strongest signal
+        let mut kept = HashSet::<String>::new();
+        seen.retain(|s| kept.insert(s.bssid.clone()));
+        *bssids.entry(seen[0].bssid.clone()).or_insert(0) += 1;
+        signals.push((i, seen));
+    }
+
+    let nodes: Vec<NodeRoaming> = signals.iter()
+        .map(|(i, seen)| {
+            let node = &scan_map.nodes[*i];
+            let dominant = seen[0].clone();
+            let runner_up = seen.get(1).cloned();
+
+            NodeRoaming{
+                node: *i,
+                position: Coordinate{ x: node.position.x, y: node.position.y, z: node.position.z },
+                floor: node.floor.clone(),
+                contested: runner_up.as_ref().is_some_and(|r| r.strength >= opts.usable && dominant.strength - r.strength < opts.margin),
+                weak: dominant.strength < opts.usable,
+                dominant,
+                runner_up,
+            }
+        })
+        .collect();
+
+    let usable_at = |seen: &[Signal], bssid: &str| seen.iter().any(|s| s.bssid == bssid && s.strength >= opts.usable);
+
+    let mut handoffs = Vec::<Handoff>::new();
+    for (a, (i, seen_i)) in signals.iter().enumerate() {
+        for (j, seen_j) in &signals[a + 1..] {
+            let (node_i, node_j) = (&scan_map.nodes[*i], &scan_map.nodes[*j]);
+            if node_i.floor != node_j.floor || seen_i[0].bssid == seen_j[0].bssid {
+                continue;
+            }
+
+            let distance = node_i.position.distance(&node_j.position, scan_map.coordinate_system);
+            if distance > opts.neighbor {
+                continue;
+            }
+
+            handoffs.push(Handoff{
+                from: *i,
+                to: *j,
+                from_bssid: seen_i[0].bssid.clone(),
+                to_bssid: seen_j[0].bssid.clone(),
+                distance,
+                abrupt: !usable_at(seen_i, &seen_j[0].bssid) && !usable_at(seen_j, &seen_i[0].bssid),
+            });
+        }
+    }
+
+    let mut bssids: Vec<(String, usize)> = bssids.into_iter().collect();
+    bssids.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+
+    Roaming{
+        bssids,
+        nodes,
+        handoffs,
+    }
+}
+
+/// Print tables of the BSSIDs, each node, and the hand offs between nodes.
+pub fn print_roaming(scan_map: &ScanMap, roaming: &Roaming) {
+    let signal = |s: &Signal| format!("{} ({:.0} dBm)", s.bssid, s.strength);
+
+    crate::table::print_table(&["BSSID", "Dominant at"], &roaming.bssids.iter()
+                              .map(|(bssid, count)| vec![bssid.clone(), format!("{} node{}", count, match *count != 1 {
+                                  true => "s",
+                                  false => "",
+                              })])
+                              .collect::<Vec<Vec<String>>>());
+    println!();
+
+    crate::table::print_table(&["Node", "Position", "Dominant", "Runner up", "Margin", "Flags"], &roaming.nodes.iter()
+                              .map(|n| {
+                                  let mut flags = Vec::<&str>::new();
+                                  if n.contested {
+                                      flags.push("contested");
+                                  }
+                                  if n.weak {
+                                      flags.push("weak");
+                                  }
+
+                                  vec![
+                                      n.node.to_string(),
+                                      n.position.format(scan_map.coordinate_system, scan_map.units, scan_map.units),
+                                      signal(&n.dominant),
+                                      n.runner_up.as_ref().map(signal).unwrap_or_else(|| String::from("-")),
+                                      n.runner_up.as_ref().map(|r| format!("{:.0} dB", n.dominant.strength - r.strength)).unwrap_or_else(|| String::from("-")),
+                                      flags.join(","),
+                                  ]
+                              })
+                              .collect::<Vec<Vec<String>>>());
+
+    if roaming.handoffs.is_empty() {
+        return;
+    }
+    println!();
+
+    let units = scan_map.units.abbreviation();
+    crate::table::print_table(&["From", "To", "Hand off", "Distance", "Flags"], &roaming.handoffs.iter()
+                              .map(|h| vec![
+                                  h.from.to_string(),
+                                  h.to.to_string(),
+                                  format!("{} to {}", h.from_bssid, h.to_bssid),
+                                  format!("{:.1} {}", h.distance, units),
+                                  match h.abrupt {
+                                      true => String::from("abrupt"),
+                                      false => String::new(),
+                                  },
+                              ])
+                              .collect::<Vec<Vec<String>>>());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::scan_map;
+
+    /// Three nodes along a hallway: one near the first access point, one between the first and second, and one which
+    /// only faintly hears a third. A last node far away does not hear the SSID at all.
+    fn survey() -> ScanMap {
+        let network = |mac: &str, strength: f64| serde_json::json!({"mac": mac, "ssid": "CorpWiFi", "strength": strength});
+        scan_map(serde_json::json!({
+            "nodes": [
+                {"position": {"x": 0.0, "y": 0.0, "z": 0.0}, "networks": [network("84:d4:7e:00:00:0a", -50.0), network("84:d4:7e:00:00:0b", -60.0)]},
+                {"position": {"x": 3.0, "y": 0.0, "z": 0.0}, "networks": [network("84:d4:7e:00:00:0a", -62.0), network("84:D4:7E:00:00:0B", -60.0)]},
+                {"position": {"x": 6.0, "y": 0.0, "z": 0.0}, "networks": [network("84:d4:7e:00:00:0c", -75.0)]},
+                {"position": {"x": 20.0, "y": 0.0, "z": 0.0}, "networks": [{"mac": "00:11:22:33:44:55", "ssid": "OldCafe", "strength": -40.0}]},
+            ],
+        }))
+    }
+
+    #[test]
+    fn analyze_roaming_flags_contested_and_weak_nodes() {
+        let roaming = analyze_roaming(&survey(), &RoamingOptions{
+            ssid: String::from("CorpWiFi"),
+            floor: None,
+            margin: 5.0,
+            usable: -70.0,
+            neighbor: 4.0,
+        });
+
+        let nodes: Vec<(usize, &str, bool, bool)> = roaming.nodes.iter()
+            .map(|n| (n.node, n.dominant.bssid.as_str(), n.contested, n.weak))
+            .collect();
+        assert_eq!(nodes, vec![
+            (0, "84:d4:7e:00:00:0a", false, false),
+            (1, "84:d4:7e:00:00:0b", true, false),
+            (2, "84:d4:7e:00:00:0c", false, true),
+        ]);
+        assert_eq!(roaming.nodes[1].runner_up.as_ref().map(|r| r.strength), Some(-62.0));
+        assert!(roaming.nodes[2].runner_up.is_none());
+
+        assert_eq!(roaming.bssids, vec![
+            (String::from("84:d4:7e:00:00:0a"), 1),
+            (String::from("84:d4:7e:00:00:0b"), 1),
+            (String::from("84:d4:7e:00:00:0c"), 1),
+        ]);
+    }
+
+    #[test]
+    fn analyze_roaming_finds_abrupt_handoffs_between_neighbors() {
+        let roaming = analyze_roaming(&survey(), &RoamingOptions{
+            ssid: String::from("CorpWiFi"),
+            floor: None,
+            margin: 5.0,
+            usable: -70.0,
+            neighbor: 4.0,
+        });
+
+        // The first and last nodes are too far apart to be compared
+        let handoffs: Vec<(usize, usize, bool)> = roaming.handoffs.iter().map(|h| (h.from, h.to, h.abrupt)).collect();
+        assert_eq!(handoffs, vec![(0, 1, false), (1, 2, true)]);
+        assert_eq!(roaming.handoffs[1].distance, 3.0);
+    }
+}
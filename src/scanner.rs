@@ -0,0 +1,478 @@
+//! Backends which scan for wireless networks. Platforms expose very different amounts of detail, so the backend can be
+//! chosen with --backend.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::airport;
+use crate::capture;
+use crate::iw;
+use crate::mock;
+use crate::netsh;
+use crate::nmcli;
+use crate::radio::{self, Band, Standard};
+use crate::security::Security;
+use crate::{is_hidden_ssid, parse_strength, Network, ScanError, ScanFailure, ScanOptions};
+
+/// Scans for wireless networks.
+pub trait Scanner: Sync {
+    /// Names of the wireless interfaces the backend can scan with. Empty if the backend cannot choose an interface.
+    fn interfaces(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// Interface which is scanned with if none is chosen, None if it is not known.
+    fn default_interface(&self) -> Option<String> {
+        None
+    }
+
+    /// Scan once with interface, or the default interface if None. Networks are measured at scan_time.
+    fn scan(&self, interface: Option<&str>, opts: &ScanOptions, scan_time: u128) -> Result<Vec<Network>, ScanError>;
+}
+
+/// Available scan backends.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Backend {
+    /// The wifiscanner crate, which works on Linux, macOS, and Windows. On Linux details it drops are read from iw.
+    Wifiscanner,
+
+    /// The Linux iw tool, which reports the most detail: beacon intervals, capabilities, and information elements.
+    Iw,
+
+    /// NetworkManager's nmcli tool, which can scan without root. Signal strengths are estimated from a quality percentage.
+    Nmcli,
+
+    /// The Windows netsh tool. Unlike wifiscanner it lists every BSSID of a network. Signal strengths are estimated from a quality percentage.
+    Netsh,
+
+    /// The macOS airport tool. The noise floor is read for the channel the computer is associated on.
+    Airport,
+
+    /// Beacon frames captured with a monitor mode interface, which give a strength sample per beacon. Needs the capture feature.
+    Capture,
+
+    /// Synthetic results from the fixture file named by the WIFI_MAPPER_MOCK environment variable.
+    Mock,
+}
+
+impl Backend {
+    /// Every backend, in the order they are listed to users.
+    const ALL: [Backend; 7] = [Backend::Wifiscanner, Backend::Iw, Backend::Nmcli, Backend::Netsh, Backend::Airport, Backend::Capture, Backend::Mock];
+
+    /// The implementation of the backend.
+    pub fn scanner(self) -> Box<dyn Scanner> {
+        match self {
+            Backend::Wifiscanner => Box::new(WifiscannerScanner),
+            Backend::Iw => Box::new(IwScanner),
+            Backend::Nmcli => Box::new(NmcliScanner),
+            Backend::Netsh => Box::new(NetshScanner),
+            Backend::Airport => Box::new(AirportScanner),
+            Backend::Capture => Box::new(CaptureScanner),
+            Backend::Mock => Box::new(MockScanner),
+        }
+    }
+}
+
+impl fmt::Display for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Backend::Wifiscanner => write!(f, "wifiscanner"),
+            Backend::Iw => write!(f, "iw"),
+            Backend::Nmcli => write!(f, "nmcli"),
+            Backend::Netsh => write!(f, "netsh"),
+            Backend::Airport => write!(f, "airport"),
+            Backend::Capture => write!(f, "capture"),
+            Backend::Mock => write!(f, "mock"),
+        }
+    }
+}
+
+impl FromStr for Backend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Backend, String> {
+        match Backend::ALL.iter().find(|b| b.to_string() == s.to_lowercase()) {
+            Some(b) => Ok(*b),
+            None => Err(format!("unknown backend, must be one of: {}", Backend::ALL.iter()
+                .map(|b| b.to_string())
+                .collect::<Vec<String>>()
+                .join(", "))),
+        }
+    }
+}
+
+/// Set the noise of networks to the noise floor iw measured on their channel.
+fn add_survey_noise(networks: &mut [Network], survey: &[iw::Survey]) {
+    for network in networks {
+        network.noise = survey.iter()
+            .find(|s| Some(s.freq) == network.frequency)
+            .map(|s| s.noise);
+    }
+}
+
+/// ScanError for a scan command which failed.
+fn command_error(e: Box<dyn Error>) -> ScanError {
+    ScanError{
+        reason: ScanFailure::Backend(e.to_string()),
+        attempts: 1,
+    }
+}
+
+/// Scans with the wifiscanner crate.
+struct WifiscannerScanner;
+
+impl Scanner for WifiscannerScanner {
+    /// wifiscanner uses the first interface iw lists on Linux.
+    fn default_interface(&self) -> Option<String> {
+        match cfg!(target_os = "linux") {
+            true => iw::interfaces().ok().and_then(|i| i.into_iter().next()),
+            false => None,
+        }
+    }
+
+    fn scan(&self, interface: Option<&str>, opts: &ScanOptions, scan_time: u128) -> Result<Vec<Network>, ScanError> {
+        if interface.is_some() {
+            return Err(ScanError{
+                reason: ScanFailure::Backend(String::from("the wifiscanner backend cannot choose an interface")),
+                attempts: 1,
+            });
+        }
+
+        let scan = wifiscanner::scan().map_err(ScanError::from)?;
+
+        // wifiscanner drops most of iw's output on Linux, read the details it missed from iw's scan cache
+        let details = match cfg!(target_os = "linux") {
+            true => iw::scan_dump_default().unwrap_or_default(),
+            false => Vec::new(),
+        };
+        let details_by_mac: HashMap<String, &iw::Bss> = details.iter()
+            .map(|bss| (bss.mac.clone(), bss))
+            .collect();
+
+        let mut networks = Vec::<Network>::new();
+
+        for network in scan {
+            // Notes about access points are looked up by lower case BSSIDs, like iw prints them
+            let mac = network.mac.to_lowercase();
+            let detail = details_by_mac.get(&mac);
+
+            let frequency = detail.and_then(|d| d.freq)
+                .or_else(|| radio::channel_to_frequency(&network.channel));
+            let security = Security::parse(&network.security)
+                .or_else(|| detail.map(|d| d.security()));
+
+            let strength = parse_strength(&network.signal_level);
+            let strength_raw = match strength {
+                Some(_) => None,
+                None => Some(network.signal_level),
+            };
+
+            networks.push(Network{
+                vendor: opts.vendors.lookup(&mac),
+                mac,
+                is_hidden: is_hidden_ssid(&network.ssid),
+                ssid: network.ssid,
+                channel: network.channel,
+                frequency,
+                band: frequency.and_then(Band::from_frequency),
+                channel_width: detail.map(|d| d.channel_width()),
+                standard: detail.map(|d| d.standard()),
+                security,
+                strength,
+                strength_raw,
+                samples: 1,
+                strength_stddev: None,
+                noise: None,
+                snr: None,
+                interface: None,
+                beacon_interval: detail.and_then(|d| d.beacon_interval),
+                capabilities: detail.map(|d| d.capabilities.clone()).unwrap_or_default(),
+                information_elements: detail.map(|d| d.elements.clone()).unwrap_or_default(),
+                time_scanned: scan_time,
+            });
+        }
+
+        // wifiscanner skips networks without an SSID, add the hidden networks iw saw
+        for bss in &details {
+            let hidden = bss.ssid.as_ref().map(|s| is_hidden_ssid(s)).unwrap_or(true);
+            if hidden && !networks.iter().any(|n| n.mac == bss.mac) {
+                networks.push(Network::from_bss(bss, scan_time, opts));
+            }
+        }
+
+        if let Some(interface) = self.default_interface() {
+            add_survey_noise(&mut networks, &iw::survey(&interface).unwrap_or_default());
+        }
+
+        Ok(networks)
+    }
+}
+
+/// Scans with the Linux iw tool.
+struct IwScanner;
+
+impl Scanner for IwScanner {
+    fn interfaces(&self) -> Vec<String> {
+        iw::interfaces().unwrap_or_default()
+    }
+
+    fn default_interface(&self) -> Option<String> {
+        self.interfaces().into_iter().next()
+    }
+
+    fn scan(&self, interface: Option<&str>, opts: &ScanOptions, scan_time: u128) -> Result<Vec<Network>, ScanError> {
+        let interface = match interface.map(|i| i.to_string()).or_else(|| self.default_interface()) {
+            Some(i) => i,
+            None => return Err(ScanError{
+                reason: ScanFailure::Backend(String::from("iw: no wireless interfaces found")),
+                attempts: 1,
+            }),
+        };
+
+        let mut networks: Vec<Network> = iw::scan(&interface).map_err(command_error)?
+            .iter()
+            .map(|bss| Network::from_bss(bss, scan_time, opts))
+            .collect();
+        add_survey_noise(&mut networks, &iw::survey(&interface).unwrap_or_default());
+
+        Ok(networks)
+    }
+}
+
+/// Scans with NetworkManager's nmcli tool.
+struct NmcliScanner;
+
+impl Scanner for NmcliScanner {
+    fn interfaces(&self) -> Vec<String> {
+        nmcli::interfaces().unwrap_or_default()
+    }
+
+    /// NetworkManager scans with every wireless interface it manages.
+    fn scan(&self, interface: Option<&str>, opts: &ScanOptions, scan_time: u128) -> Result<Vec<Network>, ScanError> {
+        Ok(nmcli::scan(interface).map_err(command_error)?
+            .into_iter()
+            .map(|ap| {
+                let frequency = ap.freq.or_else(|| radio::channel_to_frequency(&ap.channel));
+
+                Network{
+                    vendor: opts.vendors.lookup(&ap.mac),
+                    is_hidden: is_hidden_ssid(&ap.ssid),
+                    security: match ap.security.is_empty() {
+                        true => Some(Security::Open),
+                        false => Security::parse(&ap.security),
+                    },
+                    strength: ap.quality.map(radio::quality_to_dbm),
+                    mac: ap.mac,
+                    ssid: ap.ssid,
+                    channel: ap.channel,
+                    frequency,
+                    band: frequency.and_then(Band::from_frequency),
+                    channel_width: None,
+                    standard: None,
+                    strength_raw: None,
+                    samples: 1,
+                    strength_stddev: None,
+                    noise: None,
+                    snr: None,
+                    interface: None,
+                    beacon_interval: None,
+                    capabilities: Vec::new(),
+                    information_elements: Vec::new(),
+                    time_scanned: scan_time,
+                }
+            })
+            .collect())
+    }
+}
+
+/// Scans with the Windows netsh tool.
+struct NetshScanner;
+
+impl Scanner for NetshScanner {
+    fn interfaces(&self) -> Vec<String> {
+        netsh::interfaces().unwrap_or_default()
+    }
+
+    fn scan(&self, interface: Option<&str>, opts: &ScanOptions, scan_time: u128) -> Result<Vec<Network>, ScanError> {
+        Ok(netsh::scan(interface).map_err(command_error)?
+            .into_iter()
+            .map(|ap| {
+                // 6 GHz channel numbers overlap the 2.4 and 5 GHz ones
+                let frequency = match ap.band {
+                    Some(Band::Six) => radio::parse_channel(&ap.channel).map(|c| 5950 + 5 * c),
+                    _ => radio::channel_to_frequency(&ap.channel),
+                };
+
+                Network{
+                    vendor: opts.vendors.lookup(&ap.mac),
+                    is_hidden: is_hidden_ssid(&ap.ssid),
+                    security: Security::parse(&ap.authentication),
+                    strength: ap.quality.map(radio::quality_to_dbm),
+                    mac: ap.mac,
+                    ssid: ap.ssid,
+                    channel: ap.channel,
+                    frequency,
+                    band: ap.band.or_else(|| frequency.and_then(Band::from_frequency)),
+                    channel_width: None,
+                    standard: ap.standard,
+                    strength_raw: None,
+                    samples: 1,
+                    strength_stddev: None,
+                    noise: None,
+                    snr: None,
+                    interface: None,
+                    beacon_interval: None,
+                    capabilities: Vec::new(),
+                    information_elements: Vec::new(),
+                    time_scanned: scan_time,
+                }
+            })
+            .collect())
+    }
+}
+
+/// Scans with the macOS airport tool.
+struct AirportScanner;
+
+impl Scanner for AirportScanner {
+    fn scan(&self, interface: Option<&str>, opts: &ScanOptions, scan_time: u128) -> Result<Vec<Network>, ScanError> {
+        if interface.is_some() {
+            return Err(ScanError{
+                reason: ScanFailure::Backend(String::from("the airport backend cannot choose an interface")),
+                attempts: 1,
+            });
+        }
+
+        let access_points = airport::scan().map_err(command_error)?;
+        // The noise floor is only measured on the associated channel
+        let noise = airport::noise().unwrap_or(None);
+
+        Ok(access_points.into_iter()
+            .map(|ap| {
+                let frequency = radio::channel_to_frequency(&ap.channel);
+
+                Network{
+                    vendor: opts.vendors.lookup(&ap.mac),
+                    is_hidden: is_hidden_ssid(&ap.ssid),
+                    security: Security::parse(&ap.security),
+                    strength: ap.rssi,
+                    noise: noise.as_ref().filter(|n| n.channel == ap.channel).map(|n| n.noise),
+                    snr: None,
+                    mac: ap.mac,
+                    ssid: ap.ssid,
+                    channel: ap.channel,
+                    frequency,
+                    band: frequency.and_then(Band::from_frequency),
+                    channel_width: Some(ap.width),
+                    // 80 and 160 MHz channels were added by 802.11ac
+                    standard: match (ap.width >= 80, ap.ht) {
+                        (true, _) => Some(Standard::Ac),
+                        (false, true) => Some(Standard::N),
+                        (false, false) => None,
+                    },
+                    strength_raw: None,
+                    samples: 1,
+                    strength_stddev: None,
+                    interface: None,
+                    beacon_interval: None,
+                    capabilities: Vec::new(),
+                    information_elements: Vec::new(),
+                    time_scanned: scan_time,
+                }
+            })
+            .collect())
+    }
+}
+
+/// Captures beacons with a monitor mode interface.
+struct CaptureScanner;
+
+impl Scanner for CaptureScanner {
+    fn interfaces(&self) -> Vec<String> {
+        iw::interfaces().unwrap_or_default()
+    }
+
+    fn default_interface(&self) -> Option<String> {
+        self.interfaces().into_iter().next()
+    }
+
+    /// Each beacon is a measurement, the beacons of a BSSID are averaged into one network.
+    fn scan(&self, interface: Option<&str>, opts: &ScanOptions, scan_time: u128) -> Result<Vec<Network>, ScanError> {
+        let interface = match interface.map(|i| i.to_string()).or_else(|| self.default_interface()) {
+            Some(i) => i,
+            None => return Err(ScanError{
+                reason: ScanFailure::Backend(String::from("capture: no wireless interfaces found")),
+                attempts: 1,
+            }),
+        };
+
+        let mut by_mac = Vec::<(String, Vec<Network>)>::new();
+        for beacon in capture::capture(&interface, opts.dwell).map_err(command_error)? {
+            let mut network = Network::from_bss(&beacon.bss, scan_time, opts);
+            network.noise = beacon.noise;
+
+            match by_mac.iter_mut().find(|(mac, _)| *mac == network.mac) {
+                Some((_, measurements)) => measurements.push(network),
+                None => by_mac.push((network.mac.clone(), vec![network])),
+            };
+        }
+
+        Ok(by_mac.into_iter()
+            .map(|(_, measurements)| Network::average(measurements))
+            .collect())
+    }
+}
+
+/// Index of the next fixture scan the mock backend returns.
+static NEXT_MOCK_SCAN: AtomicUsize = AtomicUsize::new(0);
+
+/// Returns the scans of a fixture file in turn, starting over after the last one.
+struct MockScanner;
+
+impl Scanner for MockScanner {
+    fn interfaces(&self) -> Vec<String> {
+        vec![String::from("mock0"), String::from("mock1")]
+    }
+
+    fn default_interface(&self) -> Option<String> {
+        Some(String::from("mock0"))
+    }
+
+    fn scan(&self, _interface: Option<&str>, opts: &ScanOptions, scan_time: u128) -> Result<Vec<Network>, ScanError> {
+        let scans = mock::load().map_err(command_error)?;
+        let scan = &scans[NEXT_MOCK_SCAN.fetch_add(1, Ordering::SeqCst) % scans.len()];
+
+        Ok(scan.iter()
+            .map(|mock| {
+                let frequency = radio::channel_to_frequency(&mock.channel);
+
+                Network{
+                    vendor: opts.vendors.lookup(&mock.mac),
+                    mac: mock.mac.to_lowercase(),
+                    is_hidden: is_hidden_ssid(&mock.ssid),
+                    ssid: mock.ssid.clone(),
+                    channel: mock.channel.clone(),
+                    frequency,
+                    band: frequency.and_then(Band::from_frequency),
+                    channel_width: None,
+                    standard: None,
+                    security: mock.security,
+                    strength: mock.strength,
+                    strength_raw: None,
+                    samples: 1,
+                    strength_stddev: None,
+                    noise: mock.noise,
+                    snr: None,
+                    interface: None,
+                    beacon_interval: None,
+                    capabilities: Vec::new(),
+                    information_elements: Vec::new(),
+                    time_scanned: scan_time,
+                }
+            })
+            .collect())
+    }
+}
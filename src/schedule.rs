@@ -0,0 +1,73 @@
+//! Records repeated measurements at one position, e.g. to monitor how signals change overnight,
+//! or to run a fixed sensor which publishes its measurements over MQTT.
+
+use std::error::Error;
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::logging;
+use crate::mqtt::Publisher;
+use crate::{Coordinate, Node, RecordOptions, ScanMap, ScanOptions, WriteOptions};
+
+/// When measurements are taken.
+pub struct ScheduleOptions {
+    /// Time from the start of one measurement to the start of the next.
+    pub every: Duration,
+
+    /// Stop after this many measurements.
+    pub count: Option<u32>,
+
+    /// Stop once this much time has passed since the first measurement.
+    pub duration: Option<Duration>,
+
+    /// Publish every node here.
+    pub publisher: Option<Publisher>,
+
+    /// If true nodes are added to the map and saved. Otherwise they are only published.
+    pub save: bool,
+}
+
+/// Ask for a position once, then record a node there on a fixed schedule. Runs until the count or duration is reached, or forever if neither is set. The map is saved after every node unless opts.save is false.
+pub fn repeat(scan_map: &mut ScanMap, record_opts: &RecordOptions, scan_opts: &ScanOptions, opts: &mut ScheduleOptions, map_path: &Path, write_opts: &WriteOptions) -> Result<(), Box<dyn Error>> {
+    println!("Measurement properties:");
+    let floor = record_opts.determine_floor(scan_map);
+    let position = record_opts.determine_position(scan_map);
+
+    let started = Instant::now();
+    let mut taken: u32 = 0;
+
+    loop {
+        let node = Node::measure(scan_map, record_opts, scan_opts, floor.clone(), Coordinate{ x: position.x, y: position.y, z: position.z }, String::new())?;
+        taken += 1;
+
+        println!("Recorded measurement {}{} with {} network{}", taken, match opts.count {
+            Some(count) => format!(" of {}", count),
+            None => String::new(),
+        }, node.networks.len(), match node.networks.len() != 1 {
+            true => "s",
+            false => "",
+        });
+
+        // A broker which is down should not stop a sensor, the next node is published once it is back
+        if let Some(publisher) = &mut opts.publisher {
+            if let Err(e) = publisher.publish(&scan_map.name, &node) {
+                logging::warn(&format!("failed to publish node: {}", e));
+            }
+        }
+
+        if opts.save {
+            scan_map.nodes.push(node);
+            scan_map.write(map_path, write_opts)?;
+        }
+
+        // Measurements are scheduled from the start so slow scans do not make the schedule drift
+        let next = opts.every * taken;
+        if opts.count.is_some_and(|count| taken >= count) || opts.duration.is_some_and(|d| next > d) {
+            return Ok(());
+        }
+
+        println!();
+        thread::sleep(next.saturating_sub(started.elapsed()));
+    }
+}
@@ -0,0 +1,75 @@
+//! Network security and encryption types.
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+/// Security or encryption scheme a network uses to authenticate clients.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Security {
+    /// No encryption.
+    Open,
+
+    /// Legacy WEP encryption.
+    #[serde(rename = "WEP")]
+    Wep,
+
+    /// Original WPA (TKIP).
+    #[serde(rename = "WPA")]
+    Wpa,
+
+    /// WPA2 with a pre-shared key.
+    #[serde(rename = "WPA2")]
+    Wpa2,
+
+    /// WPA3 with SAE, including WPA2/WPA3 transition mode networks.
+    #[serde(rename = "WPA3")]
+    Wpa3,
+
+    /// 802.1X authentication against a RADIUS server.
+    Enterprise,
+}
+
+impl fmt::Display for Security {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Security::Open => write!(f, "Open"),
+            Security::Wep => write!(f, "WEP"),
+            Security::Wpa => write!(f, "WPA"),
+            Security::Wpa2 => write!(f, "WPA2"),
+            Security::Wpa3 => write!(f, "WPA3"),
+            Security::Enterprise => write!(f, "Enterprise"),
+        }
+    }
+}
+
+impl Security {
+    /// Parse a security description as reported by airport ("WPA2(PSK/AES/AES)", "NONE") or
+    /// netsh ("WPA2-Personal", "Open"). None if the description is empty or not recognized.
+    pub fn parse(description: &str) -> Option<Security> {
+        let d = description.trim().to_lowercase();
+
+        if d.is_empty() {
+            None
+        } else if d.contains("802.1x") || d.contains("enterprise") || d.contains("eap") {
+            Some(Security::Enterprise)
+        } else if d.contains("wpa3") || d.contains("sae") {
+            Some(Security::Wpa3)
+        } else if d.contains("wpa2") || d.contains("rsn") {
+            Some(Security::Wpa2)
+        } else if d.contains("wpa") {
+            Some(Security::Wpa)
+        } else if d.contains("wep") {
+            Some(Security::Wep)
+        } else if d.contains("none") || d.contains("open") {
+            Some(Security::Open)
+        } else {
+            None
+        }
+    }
+
+    /// True if the network is unencrypted or uses an encryption scheme which is considered broken.
+    pub fn is_legacy(&self) -> bool {
+        matches!(self, Security::Open | Security::Wep | Security::Wpa)
+    }
+}
@@ -0,0 +1,284 @@
+//! Hosts a web page which shows the scan map and follows the map file as nodes are recorded,
+//! so someone else can watch a survey, e.g. on a tablet. Optionally accepts nodes recorded
+//! elsewhere with `record --remote`, so several people can survey into one map.
+//!
+//! Endpoints:
+//!
+//! - `GET /`: the viewer.
+//! - `GET /map.json`: the scan map.
+//! - `POST /nodes`: add nodes to the map, the body is a remote::Submission. Responds with the number of nodes in the map.
+
+use std::collections::HashSet;
+use std::error::Error;
+use std::fmt;
+use std::io::Read;
+use std::path::Path;
+
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use crate::logging;
+use crate::remote::Submission;
+use crate::{ScanMap, WriteOptions};
+
+/// Largest submission accepted, in bytes.
+const MAX_SUBMISSION: u64 = 64 * 1024 * 1024;
+
+/// Page of the viewer, which polls the map and draws it.
+const VIEWER_HTML: &str = include_str!("viewer.html");
+
+/// Options which control where the server listens.
+pub struct ServeOptions {
+    /// Address to listen on, e.g. "127.0.0.1" for this computer only or "0.0.0.0" for every interface.
+    pub bind: String,
+
+    pub port: u16,
+
+    /// If true nodes can be added with POST /nodes.
+    pub accept_nodes: bool,
+
+    /// Token requests must send as "Authorization: Bearer TOKEN". None accepts every request.
+    pub token: Option<String>,
+}
+
+/// Error which occurs when the server cannot start.
+#[derive(Debug)]
+pub struct ServeError {
+    /// Reason the server failed.
+    reason: String,
+}
+
+impl fmt::Display for ServeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.reason)
+    }
+}
+
+impl Error for ServeError {}
+
+/// Header with a content type.
+fn content_type(value: &str) -> Header {
+    Header::from_bytes("Content-Type", value).expect("content type is a valid header")
+}
+
+/// Add submitted nodes to the map at map_path. Returns the number of nodes in the map, or the status code and reason the nodes were refused.
+fn add_nodes(body: &str, map_path: &Path, write_opts: &WriteOptions) -> Result<usize, (u16, String)> {
+    let submission: Submission = serde_json::from_str(body).map_err(|e| (400, format!("invalid submission: {}", e)))?;
+    let mut scan_map = ScanMap::read(map_path).map_err(|e| (500, format!("failed to load scan map: {}", e)))?;
+
+    if submission.coordinate_system != scan_map.coordinate_system || submission.units != scan_map.units {
+        return Err((409, format!("map uses {} coordinates in {} but the nodes use {} coordinates in {}",
+                                 scan_map.coordinate_system, scan_map.units, submission.coordinate_system, submission.units)));
+    }
+    if submission.ids.len() != submission.nodes.len() {
+        return Err((400, format!("{} ids given for {} nodes", submission.ids.len(), submission.nodes.len())));
+    }
+    if let Some(floor) = submission.nodes.iter().filter_map(|n| n.floor.as_ref()).find(|f| scan_map.floor(f).is_none()) {
+        return Err((409, format!("map has no floor named \"{}\"", floor)));
+    }
+
+    // Sessions are matched by when and by whom they were started, since every sender numbers its own
+    let mut session_ids = Vec::<(u32, u32)>::new();
+    for session in submission.sessions {
+        let existing = scan_map.sessions.iter()
+            .find(|s| s.started == session.started && s.operator == session.operator && s.device == session.device)
+            .map(|s| s.id);
+        let id = match existing {
+            Some(id) => id,
+            None => {
+                let id = scan_map.sessions.iter().map(|s| s.id).max().unwrap_or(0) + 1;
+                scan_map.sessions.push(crate::Session{ id, ..session });
+                id
+            },
+        };
+        session_ids.push((session.id, id));
+    }
+
+    // Nodes are sent again if the sender did not get the response, those are only added once
+    let added: HashSet<String> = scan_map.nodes.iter().filter_map(|n| n.upload_id.clone()).collect();
+    let mut count = 0;
+    for (mut node, id) in submission.nodes.into_iter().zip(submission.ids) {
+        if added.contains(&id) {
+            continue;
+        }
+        node.session = node.session.and_then(|id| session_ids.iter().find(|(from, _)| *from == id).map(|(_, to)| *to));
+        node.upload_id = Some(id);
+        scan_map.nodes.push(node);
+        count += 1;
+    }
+    if count == 0 {
+        return Ok(scan_map.nodes.len());
+    }
+
+    scan_map.write(map_path, write_opts).map_err(|e| (500, format!("failed to save scan map: {}", e)))?;
+    println!("Added {} node{}, the map has {}", count, match count != 1 {
+        true => "s",
+        false => "",
+    }, scan_map.nodes.len());
+
+    Ok(scan_map.nodes.len())
+}
+
+/// True if a and b are equal. Takes as long for every a of the same length, so the token cannot be guessed a byte at a
+/// time from how long requests take.
+fn tokens_match(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// Respond to one request. The map file is read again for every request so recordings show up live.
+fn handle(mut request: Request, map_path: &Path, opts: &ServeOptions, write_opts: &WriteOptions) -> Result<(), Box<dyn Error>> {
+    let (path, query) = match request.url().split_once('?') {
+        Some((path, query)) => (path.to_string(), query.to_string()),
+        None => (request.url().to_string(), String::new()),
+    };
+
+    // Browsers cannot send headers when opening a page, so the viewer passes the token in the query
+    if let Some(token) = &opts.token {
+        let authorized = request.headers().iter()
+            .filter(|h| h.field.equiv("Authorization"))
+            .filter_map(|h| h.value.as_str().strip_prefix("Bearer "))
+            .chain(query.split('&').filter_map(|p| p.strip_prefix("token=")))
+            .fold(false, |authorized, sent| tokens_match(sent.as_bytes(), token.as_bytes()) | authorized);
+        if !authorized {
+            request.respond(Response::from_string("missing or wrong token").with_status_code(401))?;
+            return Ok(());
+        }
+    }
+
+    match (request.method(), path.as_str()) {
+        (Method::Get, "/") | (Method::Get, "/index.html") => {
+            request.respond(Response::from_string(VIEWER_HTML).with_header(content_type("text/html; charset=utf-8")))?;
+        },
+        (Method::Get, "/map.json") => match ScanMap::read(map_path) {
+            Ok(scan_map) => {
+                let body = serde_json::to_string(&scan_map)?;
+                request.respond(Response::from_string(body)
+                                .with_header(content_type("application/json"))
+                                .with_header(Header::from_bytes("Cache-Control", "no-store").expect("valid header")))?;
+            },
+            Err(e) => {
+                request.respond(Response::from_string(format!("failed to load scan map: {}", e)).with_status_code(500))?;
+            },
+        },
+        (Method::Post, "/nodes") if !opts.accept_nodes => {
+            request.respond(Response::from_string("this server does not accept nodes, start it with --accept-nodes").with_status_code(403))?;
+        },
+        (Method::Post, "/nodes") => {
+            let mut body = String::new();
+            request.as_reader().take(MAX_SUBMISSION).read_to_string(&mut body)?;
+
+            match add_nodes(&body, map_path, write_opts) {
+                Ok(total) => request.respond(Response::from_string(serde_json::json!({ "nodes": total }).to_string())
+                                             .with_header(content_type("application/json"))
+                                             .with_status_code(201))?,
+                Err((status, reason)) => {
+                    logging::warn(&format!("refused nodes: {}", reason));
+                    request.respond(Response::from_string(reason).with_status_code(status))?;
+                },
+            };
+        },
+        _ => request.respond(Response::from_string("not found").with_status_code(404))?,
+    };
+
+    Ok(())
+}
+
+/// Serve the viewer of the map at map_path until the process is stopped. Submitted nodes are saved with write_opts.
+pub fn serve(map_path: &Path, opts: &ServeOptions, write_opts: &WriteOptions) -> Result<(), Box<dyn Error>> {
+    let address = format!("{}:{}", opts.bind, opts.port);
+    let server = Server::http(&address).map_err(|e| ServeError{
+        reason: format!("failed to listen on {}: {}", address, e),
+    })?;
+
+    println!("Serving \"{}\" at http://{}/, press Ctrl-C to stop", map_path.display(), address);
+
+    for request in server.incoming_requests() {
+        if let Err(e) = handle(request, map_path, opts, write_opts) {
+            logging::warn(&format!("failed to respond: {}", e));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{scan_map, test_dir};
+    use serde_json::json;
+
+    fn write_opts() -> WriteOptions {
+        WriteOptions{
+            backups: 0,
+            pretty: false,
+            remote: None,
+        }
+    }
+
+    /// Map file of an empty map with one floor.
+    fn saved(name: &str) -> std::path::PathBuf {
+        let p = test_dir(name).join("map.json");
+        scan_map(json!({"floors": [{"name": "F1", "elevation": 0.0}]})).write(&p, &write_opts()).unwrap();
+        p
+    }
+
+    /// Body of a submission of nodes in local meters, with ids numbered from first.
+    fn submission(sessions: serde_json::Value, nodes: serde_json::Value, first: usize) -> String {
+        let nodes = scan_map(json!({"nodes": nodes})).nodes;
+        let ids: Vec<String> = (first..first + nodes.len()).map(|i| format!("1000-{}", i)).collect();
+        json!({
+            "coordinate_system": "local",
+            "units": "meters",
+            "sessions": sessions,
+            "nodes": nodes,
+            "ids": ids,
+        }).to_string()
+    }
+
+    #[test]
+    fn tokens_match_only_equal_tokens() {
+        assert!(tokens_match(b"secret", b"secret"));
+        assert!(!tokens_match(b"secreT", b"secret"));
+        assert!(!tokens_match(b"secret!", b"secret"));
+        assert!(!tokens_match(b"", b"secret"));
+    }
+
+    #[test]
+    fn add_nodes_renumbers_sessions_of_the_sender() {
+        let p = saved("serve-add");
+        let sessions = json!([{"id": 7, "started": 1000, "operator": "sam", "device": null}]);
+        let node = json!([{"position": {"x": 1.0, "y": 2.0, "z": 0.0}, "session": 7, "networks": []}]);
+
+        assert_eq!(add_nodes(&submission(sessions.clone(), node.clone(), 0), &p, &write_opts()), Ok(1));
+        assert_eq!(add_nodes(&submission(sessions, node, 1), &p, &write_opts()), Ok(2));
+
+        let saved = ScanMap::read(&p).unwrap();
+        assert_eq!(saved.sessions.len(), 1);
+        assert_eq!(saved.sessions[0].id, 1);
+        assert_eq!(saved.nodes[0].session, Some(1));
+        assert_eq!(saved.nodes[1].upload_id.as_deref(), Some("1000-1"));
+    }
+
+    #[test]
+    fn add_nodes_skips_nodes_it_already_added() {
+        let p = saved("serve-again");
+        let node = json!({"position": {"x": 1.0, "y": 2.0, "z": 0.0}, "networks": []});
+
+        assert_eq!(add_nodes(&submission(json!([]), json!([node.clone()]), 0), &p, &write_opts()), Ok(1));
+        assert_eq!(add_nodes(&submission(json!([]), json!([node.clone()]), 0), &p, &write_opts()), Ok(1));
+        assert_eq!(add_nodes(&submission(json!([]), json!([node.clone(), node]), 0), &p, &write_opts()), Ok(2));
+    }
+
+    #[test]
+    fn add_nodes_refuses_nodes_which_do_not_fit_the_map() {
+        let p = saved("serve-refuse");
+
+        let feet = submission(json!([]), json!([]), 0).replace("meters", "feet");
+        assert_eq!(add_nodes(&feet, &p, &write_opts()).unwrap_err().0, 409);
+
+        let floor = submission(json!([]), json!([{"position": {"x": 0.0, "y": 0.0, "z": 0.0}, "floor": "F9", "networks": []}]), 0);
+        assert_eq!(add_nodes(&floor, &p, &write_opts()).unwrap_err().0, 409);
+
+        assert_eq!(add_nodes("{", &p, &write_opts()).unwrap_err().0, 400);
+        assert!(ScanMap::read(&p).unwrap().nodes.is_empty());
+    }
+}
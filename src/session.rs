@@ -0,0 +1,76 @@
+//! Lists the survey sessions of a scan map.
+
+use serde::Serialize;
+
+use crate::table;
+use crate::timestamp;
+use crate::ScanMap;
+
+/// Session and the number of nodes recorded in it.
+#[derive(Serialize)]
+pub struct SessionSummary {
+    pub id: u32,
+
+    /// Time the session was started in milliseconds since the unix epoch, serialized as an RFC 3339 timestamp.
+    #[serde(serialize_with = "timestamp::serialize")]
+    pub started: u128,
+
+    pub operator: Option<String>,
+
+    pub device: Option<String>,
+
+    pub nodes: usize,
+}
+
+/// Sessions of a map.
+#[derive(Serialize)]
+pub struct Sessions {
+    pub sessions: Vec<SessionSummary>,
+
+    /// Number of nodes recorded before sessions were added.
+    pub unassigned: usize,
+}
+
+/// Summarize the sessions of a map with the number of nodes recorded in each.
+pub fn sessions(scan_map: &ScanMap) -> Sessions {
+    Sessions{
+        sessions: scan_map.sessions.iter()
+            .map(|s| SessionSummary{
+                id: s.id,
+                started: s.started,
+                operator: s.operator.clone(),
+                device: s.device.clone(),
+                nodes: scan_map.nodes.iter().filter(|n| n.session == Some(s.id)).count(),
+            })
+            .collect(),
+        unassigned: scan_map.nodes.iter().filter(|n| n.session.is_none()).count(),
+    }
+}
+
+/// Print a table of sessions.
+pub fn print_sessions(sessions: &Sessions) {
+    if sessions.sessions.is_empty() {
+        println!("No sessions");
+        return;
+    }
+
+    let rows: Vec<Vec<String>> = sessions.sessions.iter()
+        .map(|s| vec![
+            s.id.to_string(),
+            timestamp::format(s.started),
+            s.operator.clone().unwrap_or_default(),
+            s.device.clone().unwrap_or_default(),
+            s.nodes.to_string(),
+        ])
+        .collect();
+
+    table::print_table(&["ID", "Started", "Operator", "Device", "Nodes"], &rows);
+
+    if sessions.unassigned > 0 {
+        println!();
+        println!("{} node{} recorded before sessions were added", sessions.unassigned, match sessions.unassigned != 1 {
+            true => "s",
+            false => "",
+        });
+    }
+}
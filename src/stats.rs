@@ -0,0 +1,186 @@
+//! Summarizes the networks seen across a scan map.
+
+use std::collections::{HashMap, HashSet};
+
+use serde::Serialize;
+
+use crate::table;
+use crate::{Network, Node, ScanMap};
+
+/// Signal strengths of one SSID across the map.
+#[derive(Default)]
+struct SsidStats {
+    /// BSSIDs broadcasting the SSID.
+    macs: HashSet<String>,
+
+    /// Number of nodes which saw the SSID.
+    nodes: usize,
+
+    /// Every measured strength in dBm.
+    strengths: Vec<f32>,
+}
+
+/// Min, average, and max of strengths, None if there were no measurements.
+fn strength_range(strengths: &[f32]) -> Option<(f32, f32, f32)> {
+    if strengths.is_empty() {
+        return None;
+    }
+
+    let min = strengths.iter().cloned().fold(f32::INFINITY, f32::min);
+    let max = strengths.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+    let avg = strengths.iter().sum::<f32>() / strengths.len() as f32;
+
+    Some((min, avg, max))
+}
+
+/// Channel a network is on, with its band if known.
+fn channel_label(network: &Network) -> String {
+    match network.band {
+        Some(band) => format!("{} ({})", network.channel, band),
+        None => network.channel.clone(),
+    }
+}
+
+/// Summary of an SSID across the nodes of a map.
+#[derive(Serialize)]
+pub struct SsidSummary {
+    pub ssid: String,
+
+    /// Number of BSSIDs broadcasting the SSID.
+    pub bssids: usize,
+
+    /// Number of nodes which saw the SSID.
+    pub nodes: usize,
+
+    /// Minimum, average, and maximum strength in dBm, None if there were no measurements.
+    pub min_strength: Option<f32>,
+
+    pub average_strength: Option<f32>,
+
+    pub max_strength: Option<f32>,
+}
+
+impl SsidSummary {
+    /// Minimum, average, and maximum strength formatted for display, dashes if there were no measurements.
+    pub fn strength_columns(&self) -> [String; 3] {
+        [self.min_strength, self.average_strength, self.max_strength].map(|s| match s {
+            Some(s) => format!("{:.1}", s),
+            None => String::from("-"),
+        })
+    }
+}
+
+/// Statistics about the networks in a map.
+#[derive(Serialize)]
+pub struct Summary {
+    pub nodes: usize,
+
+    pub bssids: usize,
+
+    /// SSIDs, best covered first.
+    pub ssids: Vec<SsidSummary>,
+
+    /// Channels and the number of BSSIDs on them, busiest first.
+    pub channels: Vec<(String, usize)>,
+
+    /// Indices of nodes which saw no networks.
+    pub empty: Vec<usize>,
+}
+
+/// Summarize the networks in a map. If floor or session are set only nodes on that floor or recorded in that session are included.
+pub fn summarize(scan_map: &ScanMap, floor: Option<&str>, session: Option<u32>) -> Summary {
+    let nodes: Vec<(usize, &Node)> = scan_map.nodes.iter()
+        .enumerate()
+        .filter(|(_, n)| floor.is_none() || n.floor.as_deref() == floor)
+        .filter(|(_, n)| session.is_none() || n.session == session)
+        .collect();
+
+    let mut macs = HashSet::<&str>::new();
+    let mut ssids = HashMap::<&str, SsidStats>::new();
+    let mut channels = HashMap::<String, HashSet<&str>>::new();
+    let mut empty = Vec::<usize>::new();
+
+    for (i, node) in &nodes {
+        if node.networks.is_empty() {
+            empty.push(*i);
+        }
+
+        let mut seen = HashSet::<&str>::new();
+        for network in &node.networks {
+            macs.insert(&network.mac);
+            channels.entry(channel_label(network)).or_default().insert(&network.mac);
+
+            let entry = ssids.entry(network.display_ssid()).or_default();
+            entry.macs.insert(network.mac.clone());
+            if let Some(s) = network.strength {
+                entry.strengths.push(s);
+            }
+            if seen.insert(network.display_ssid()) {
+                entry.nodes += 1;
+            }
+        }
+    }
+
+    // Best covered SSIDs first
+    let mut ssid_rows: Vec<(&str, SsidStats)> = ssids.into_iter().collect();
+    ssid_rows.sort_by(|a, b| b.1.nodes.cmp(&a.1.nodes).then(a.0.cmp(b.0)));
+
+    // Busiest channels first
+    let mut channel_rows: Vec<(String, usize)> = channels.into_iter().map(|(c, m)| (c, m.len())).collect();
+    channel_rows.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+
+    Summary{
+        nodes: nodes.len(),
+        bssids: macs.len(),
+        ssids: ssid_rows.into_iter()
+            .map(|(ssid, s)| {
+                let range = strength_range(&s.strengths);
+                SsidSummary{
+                    ssid: ssid.to_string(),
+                    bssids: s.macs.len(),
+                    nodes: s.nodes,
+                    min_strength: range.map(|r| r.0),
+                    average_strength: range.map(|r| r.1),
+                    max_strength: range.map(|r| r.2),
+                }
+            })
+            .collect(),
+        channels: channel_rows,
+        empty,
+    }
+}
+
+/// Print statistics about the networks in a map.
+pub fn print_summary(summary: &Summary) {
+    println!("Nodes:          {}", summary.nodes);
+    println!("BSSIDs:         {}", summary.bssids);
+    println!("SSIDs:          {}", summary.ssids.len());
+    println!("Empty nodes:    {}", match summary.empty.is_empty() {
+        true => String::from("0"),
+        false => format!("{} (index {})", summary.empty.len(), summary.empty.iter().map(|i| i.to_string()).collect::<Vec<String>>().join(", ")),
+    });
+
+    if summary.ssids.is_empty() {
+        return;
+    }
+
+    println!();
+    table::print_table(&["SSID", "BSSIDs", "Nodes", "Min dBm", "Avg dBm", "Max dBm"], &summary.ssids.iter()
+        .map(|s| {
+            let [min, avg, max] = s.strength_columns();
+            vec![
+                s.ssid.clone(),
+                s.bssids.to_string(),
+                format!("{}/{}", s.nodes, summary.nodes),
+                min,
+                avg,
+                max,
+            ]
+        })
+        .collect::<Vec<Vec<String>>>());
+
+    println!();
+    table::print_table(&["Channel", "BSSIDs"], &summary.channels.iter()
+        .map(|(c, count)| vec![c.clone(), count.to_string()])
+        .collect::<Vec<Vec<String>>>());
+}
@@ -0,0 +1,555 @@
+//! Reads maps one node at a time, so maps larger than memory can be listed, queried, exported,
+//! and recorded to. A streamed map is read twice: once for everything but its nodes, which are
+//! skipped, since files sorted by key have fields after the nodes, and once more for the nodes.
+//! Streamed maps are written with their nodes first, so nodes recorded later can be appended to
+//! the file in place of the fields which follow them.
+
+use std::cell::{Cell, RefCell};
+use std::error::Error;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::Instant;
+
+use serde::de::{self, DeserializeSeed, Deserializer, IgnoredAny, MapAccess, SeqAccess, Visitor};
+use serde_json::{Map, Value};
+
+use crate::logging;
+use crate::migrate;
+use crate::{Node, ScanMap};
+
+/// Nodes of a map file which were not loaded, see read_header and read_last.
+#[derive(Default)]
+pub struct Streamed {
+    /// Map file the nodes are read from.
+    pub path: PathBuf,
+
+    /// Number of nodes at the start of the file which were not loaded.
+    pub nodes: usize,
+
+    /// Schema version of the file, its nodes are upgraded as they are read.
+    pub version: Cell<u64>,
+
+    /// Number of the loaded nodes which the file already holds after those which were not loaded.
+    pub written: Cell<usize>,
+
+    /// Byte offset of the ] which ends the nodes in the file, None if it is not known.
+    pub nodes_end: Cell<Option<u64>>,
+
+    /// Fields which come before the nodes in the file. Appending nodes cannot change them.
+    pub before_nodes: RefCell<Map<String, Value>>,
+}
+
+/// Called with the index and value of each node of a map.
+type Visit<'v> = dyn FnMut(usize, Value) -> Result<(), Box<dyn Error>> + 'v;
+
+/// How the nodes of a map are read.
+struct Reading<'a, 'v> {
+    /// Called with the nodes which are deserialized, the others are skipped.
+    visit: Option<&'a mut Visit<'v>>,
+
+    /// Only deserialize the node at this index.
+    only: Option<usize>,
+
+    /// Number of bytes of the file read so far.
+    offset: Rc<Cell<u64>>,
+}
+
+/// Fields of a map other than its nodes, and where its nodes are.
+struct Layout {
+    /// Fields other than the nodes, including those in before_nodes.
+    fields: Map<String, Value>,
+
+    before_nodes: Map<String, Value>,
+
+    /// Number of nodes.
+    nodes: usize,
+
+    /// Byte offset of the ] which ends the nodes, None if the map has no nodes field.
+    nodes_end: Option<u64>,
+}
+
+impl<'de> Visitor<'de> for Reading<'_, '_> {
+    type Value = Layout;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a scan map object")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(mut self, mut access: A) -> Result<Self::Value, A::Error> {
+        let mut layout = Layout{
+            fields: Map::new(),
+            before_nodes: Map::new(),
+            nodes: 0,
+            nodes_end: None,
+        };
+
+        while let Some(key) = access.next_key::<String>()? {
+            match key == "nodes" {
+                true => {
+                    layout.nodes = access.next_value_seed(Nodes{
+                        visit: self.visit.as_deref_mut(),
+                        only: self.only,
+                    })?;
+                    // The reader is read one byte at a time, so the last byte read is the ]
+                    layout.nodes_end = Some(self.offset.get() - 1);
+                },
+                false => {
+                    let value: Value = access.next_value()?;
+                    if layout.nodes_end.is_none() {
+                        layout.before_nodes.insert(key.clone(), value.clone());
+                    }
+                    layout.fields.insert(key, value);
+                },
+            };
+        }
+
+        Ok(layout)
+    }
+}
+
+/// Nodes of a map, each is passed to visit or skipped if there is none or it is not the only one.
+struct Nodes<'a, 'v> {
+    visit: Option<&'a mut Visit<'v>>,
+    only: Option<usize>,
+}
+
+impl<'de> DeserializeSeed<'de> for Nodes<'_, '_> {
+    type Value = usize;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<usize, D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de> Visitor<'de> for Nodes<'_, '_> {
+    type Value = usize;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a list of nodes")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<usize, A::Error> {
+        let mut count = 0;
+
+        match self.visit {
+            Some(visit) => loop {
+                let more = match self.only.is_none_or(|only| only == count) {
+                    true => match seq.next_element::<Value>()? {
+                        Some(node) => {
+                            visit(count, node).map_err(|e| de::Error::custom(format!("node {}: {}", count, e)))?;
+                            true
+                        },
+                        None => false,
+                    },
+                    false => seq.next_element::<IgnoredAny>()?.is_some(),
+                };
+                if !more {
+                    break;
+                }
+                count += 1;
+            },
+            None => while seq.next_element::<IgnoredAny>()?.is_some() {
+                count += 1;
+            },
+        };
+
+        Ok(count)
+    }
+}
+
+/// Reader which counts the bytes read through it.
+struct Counted<R> {
+    inner: R,
+    count: Rc<Cell<u64>>,
+}
+
+impl<R: Read> Read for Counted<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.count.set(self.count.get() + n as u64);
+        Ok(n)
+    }
+}
+
+/// Writer which counts the bytes written through it.
+struct CountedWriter<W> {
+    inner: W,
+    count: u64,
+}
+
+impl<W: Write> Write for CountedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.count += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Read the map at p, passing its nodes to visit if there is one. Only the node at index only is passed if it is given.
+fn scan(p: &Path, visit: Option<&mut Visit>, only: Option<usize>) -> Result<Layout, Box<dyn Error>> {
+    let offset = Rc::new(Cell::new(0));
+    let reader = Counted{
+        inner: BufReader::new(File::open(p)?),
+        count: offset.clone(),
+    };
+    let mut deserializer = serde_json::Deserializer::from_reader(reader);
+    let layout = (&mut deserializer).deserialize_map(Reading{
+        visit,
+        only,
+        offset,
+    })?;
+    deserializer.end()?;
+
+    Ok(layout)
+}
+
+/// Read the map at p without its nodes, which are read by for_each_node.
+pub fn read_header(p: &Path) -> Result<ScanMap, Box<dyn Error>> {
+    let started = Instant::now();
+    let layout = scan(p, None, None)?;
+    let mut fields = layout.fields;
+    fields.insert(String::from("nodes"), Value::Array(Vec::new()));
+
+    let mut value = Value::Object(fields);
+    let from_version = migrate::migrate(&mut value)?;
+    if from_version != migrate::SCHEMA_VERSION {
+        logging::info(&format!("Upgraded scan map from schema version {} to {}", from_version, migrate::SCHEMA_VERSION));
+    }
+
+    let mut scan_map: ScanMap = serde_json::from_value(value)?;
+    scan_map.streamed = Streamed{
+        path: p.to_path_buf(),
+        nodes: layout.nodes,
+        version: Cell::new(from_version),
+        written: Cell::new(0),
+        nodes_end: Cell::new(layout.nodes_end),
+        before_nodes: RefCell::new(layout.before_nodes),
+    };
+    logging::info(&format!("Read \"{}\" without its {} nodes in {:.2}s", p.display(), layout.nodes, started.elapsed().as_secs_f64()));
+
+    Ok(scan_map)
+}
+
+/// Read the map at p with only its last node loaded, so nodes can be recorded after it without loading the others. The
+/// other nodes are skipped without deserializing them.
+pub fn read_last(p: &Path) -> Result<ScanMap, Box<dyn Error>> {
+    let mut scan_map = read_header(p)?;
+    let count = scan_map.streamed.nodes;
+    if count == 0 {
+        return Ok(scan_map);
+    }
+
+    let version = scan_map.streamed.version.get();
+    let mut last = None;
+    let mut visit = |_: usize, value: Value| -> Result<(), Box<dyn Error>> {
+        last = Some(serde_json::from_value::<Node>(migrate::migrate_node(version, value)?)?);
+        Ok(())
+    };
+    scan(p, Some(&mut visit), Some(count - 1))?;
+
+    scan_map.nodes.extend(last);
+    scan_map.streamed.nodes = count - scan_map.nodes.len();
+    scan_map.streamed.written.set(scan_map.nodes.len());
+
+    Ok(scan_map)
+}
+
+/// Call visit with the index and contents of each node of the streamed map's file, one at a time.
+pub fn for_each_node<F>(streamed: &Streamed, mut visit: F) -> Result<(), Box<dyn Error>>
+where F: FnMut(usize, Node) -> Result<(), Box<dyn Error>> {
+    let version = streamed.version.get();
+    let mut visit_value = |i: usize, value: Value| -> Result<(), Box<dyn Error>> {
+        let node: Node = serde_json::from_value(migrate::migrate_node(version, value)?)?;
+        visit(i, node)
+    };
+
+    scan(&streamed.path, Some(&mut visit_value), None)?;
+
+    Ok(())
+}
+
+/// Call visit with the index and contents of every node of scan_map, reading those which were not loaded from its file.
+pub fn visit_nodes<F>(scan_map: &ScanMap, mut visit: F) -> Result<(), Box<dyn Error>>
+where F: FnMut(usize, &Node) -> Result<(), Box<dyn Error>> {
+    let streamed = &scan_map.streamed;
+    if streamed.nodes > 0 {
+        for_each_node(streamed, |i, node| match i < streamed.nodes {
+            true => visit(i, &node),
+            false => Ok(()),
+        })?;
+    }
+
+    for (i, node) in scan_map.nodes.iter().enumerate() {
+        visit(streamed.nodes + i, node)?;
+    }
+
+    Ok(())
+}
+
+/// Fields of scan_map other than its nodes, as they are serialized.
+fn fields(scan_map: &ScanMap) -> Result<Map<String, Value>, Box<dyn Error>> {
+    let mut value = serde_json::to_value(scan_map)?;
+    let fields = value.as_object_mut().ok_or(migrate::MigrationError::NotAnObject)?;
+    fields.remove("nodes");
+
+    Ok(std::mem::take(fields))
+}
+
+/// Write fields as the members of an object after its nodes, each preceded by a comma, and close the object.
+fn write_fields<'a>(mut writer: impl Write, fields: impl Iterator<Item = (&'a String, &'a Value)>) -> Result<(), Box<dyn Error>> {
+    for (key, value) in fields {
+        writer.write_all(b",")?;
+        serde_json::to_writer(&mut writer, key)?;
+        writer.write_all(b":")?;
+        serde_json::to_writer(&mut writer, value)?;
+    }
+    writer.write_all(b"}")?;
+
+    Ok(())
+}
+
+/// Write scan_map to writer like ScanMap::write, with the nodes which were not loaded copied from its file before the
+/// loaded ones. Nodes are written first and without indentation even if the map is pretty printed, since that needs the
+/// whole map. Returns the byte offset of the ] which ends the nodes.
+pub fn write(scan_map: &ScanMap, writer: impl Write) -> Result<u64, Box<dyn Error>> {
+    let fields = fields(scan_map)?;
+    let mut writer = CountedWriter{
+        inner: writer,
+        count: 0,
+    };
+
+    writer.write_all(b"{\"nodes\":[")?;
+    visit_nodes(scan_map, |i, node| {
+        if i > 0 {
+            writer.write_all(b",")?;
+        }
+        serde_json::to_writer(&mut writer, node)?;
+        Ok(())
+    })?;
+    writer.write_all(b"]")?;
+    let nodes_end = writer.count - 1;
+    write_fields(&mut writer, fields.iter())?;
+
+    Ok(nodes_end)
+}
+
+/// The file of scan_map was replaced by one which write wrote, with the ] ending its nodes at nodes_end.
+pub fn rewritten(scan_map: &ScanMap, nodes_end: u64) {
+    let streamed = &scan_map.streamed;
+    streamed.version.set(migrate::SCHEMA_VERSION);
+    streamed.written.set(scan_map.nodes.len());
+    streamed.nodes_end.set(Some(nodes_end));
+    streamed.before_nodes.borrow_mut().clear();
+}
+
+/// Append the loaded nodes which the file of scan_map does not hold yet to it in place, followed by the fields after its
+/// nodes, so the nodes which are already in the file are not copied. Returns false without changing the file if it must
+/// be replaced instead: when nodes in it were undone, a field before its nodes changed, or its nodes need upgrading.
+pub fn append(scan_map: &ScanMap) -> Result<bool, Box<dyn Error>> {
+    let streamed = &scan_map.streamed;
+    let written = streamed.written.get();
+    let nodes_end = match streamed.nodes_end.get() {
+        Some(end) if written <= scan_map.nodes.len() && streamed.version.get() == migrate::SCHEMA_VERSION => end,
+        _ => return Ok(false),
+    };
+
+    let fields = fields(scan_map)?;
+    let before_nodes = streamed.before_nodes.borrow();
+    if before_nodes.iter().any(|(key, value)| fields.get(key) != Some(value)) {
+        return Ok(false);
+    }
+
+    // Make sure the file still ends its nodes where it did when it was read
+    let mut file = OpenOptions::new().read(true).write(true).open(&streamed.path)?;
+    let mut end = [0];
+    file.seek(SeekFrom::Start(nodes_end))?;
+    if file.read_exact(&mut end).is_err() || end != *b"]" {
+        return Ok(false);
+    }
+
+    file.seek(SeekFrom::Start(nodes_end))?;
+    let mut writer = CountedWriter{
+        inner: BufWriter::new(&file),
+        count: 0,
+    };
+    for (i, node) in scan_map.nodes.iter().enumerate().skip(written) {
+        if streamed.nodes + i > 0 {
+            writer.write_all(b",")?;
+        }
+        serde_json::to_writer(&mut writer, node)?;
+    }
+    writer.write_all(b"]")?;
+    let new_end = nodes_end + writer.count - 1;
+    write_fields(&mut writer, fields.iter().filter(|(key, _)| !before_nodes.contains_key(*key)))?;
+    writer.flush()?;
+
+    let len = nodes_end + writer.count;
+    drop(writer);
+    file.set_len(len)?;
+    file.sync_all()?;
+
+    streamed.written.set(scan_map.nodes.len());
+    streamed.nodes_end.set(Some(new_end));
+
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{scan_map, test_dir};
+
+    fn saved(name: &str) -> (ScanMap, PathBuf) {
+        let scan_map = scan_map(serde_json::json!({
+            "name": "Office",
+            "nodes": [
+                {"position": {"x": 0.0, "y": 0.0, "z": 0.0}, "networks": [{"mac": "aa:bb:cc:00:11:22", "ssid": "Corp", "strength": -50.0}]},
+                {"position": {"x": 1.0, "y": 0.0, "z": 0.0}, "networks": [{"mac": "aa:bb:cc:00:11:22", "ssid": "Corp", "strength": -60.0}]},
+                {"position": {"x": 2.0, "y": 0.0, "z": 0.0}, "networks": [{"mac": "aa:bb:cc:00:11:22", "ssid": "Corp", "strength": -70.0}]},
+            ],
+        }));
+        let p = test_dir(name).join("map.json");
+        std::fs::write(&p, serde_json::to_string(&scan_map).unwrap()).unwrap();
+        (scan_map, p)
+    }
+
+    #[test]
+    fn read_header_counts_nodes_without_loading_them() {
+        let (_, p) = saved("stream-header");
+        let header = read_header(&p).unwrap();
+
+        assert_eq!(header.name, "Office");
+        assert!(header.nodes.is_empty());
+        assert_eq!(header.streamed.nodes, 3);
+    }
+
+    #[test]
+    fn read_last_loads_only_the_last_node() {
+        let (_, p) = saved("stream-last");
+        let last = read_last(&p).unwrap();
+
+        assert_eq!(last.nodes.len(), 1);
+        assert_eq!(last.nodes[0].position.x, 2.0);
+        assert_eq!(last.streamed.nodes, 2);
+        assert_eq!(last.streamed.written.get(), 1);
+    }
+
+    #[test]
+    fn read_last_skips_the_other_nodes() {
+        let (full, p) = saved("stream-skip");
+        let mut value = serde_json::to_value(&full).unwrap();
+        value["nodes"][0] = serde_json::json!({"not": "a node"});
+        std::fs::write(&p, value.to_string()).unwrap();
+
+        assert_eq!(read_last(&p).unwrap().nodes[0].position.x, 2.0);
+        assert!(for_each_node(&read_header(&p).unwrap().streamed, |_, _| Ok(())).is_err());
+    }
+
+    #[test]
+    fn visit_nodes_reads_streamed_nodes_before_loaded_ones() {
+        let (_, p) = saved("stream-visit");
+        let mut last = read_last(&p).unwrap();
+        let mut node: Node = serde_json::from_value(serde_json::to_value(&last.nodes[0]).unwrap()).unwrap();
+        node.position.x = 3.0;
+        last.nodes.push(node);
+
+        let mut seen = Vec::new();
+        visit_nodes(&last, |i, node| {
+            seen.push((i, node.position.x));
+            Ok(())
+        }).unwrap();
+
+        assert_eq!(seen, vec![(0, 0.0), (1, 1.0), (2, 2.0), (3, 3.0)]);
+    }
+
+    #[test]
+    fn write_copies_streamed_nodes_into_the_map() {
+        let (full, p) = saved("stream-write");
+        let last = read_last(&p).unwrap();
+
+        let mut written = Vec::new();
+        write(&last, &mut written).unwrap();
+
+        let written: Value = serde_json::from_slice(&written).unwrap();
+        assert_eq!(written, serde_json::to_value(&full).unwrap());
+    }
+
+    fn write_opts(backups: usize) -> crate::WriteOptions {
+        crate::WriteOptions{
+            backups,
+            pretty: false,
+            remote: None,
+        }
+    }
+
+    /// Node at x.
+    fn node(x: f64) -> Node {
+        scan_map(serde_json::json!({
+            "nodes": [{"position": {"x": x, "y": 0.0, "z": 0.0}, "networks": []}],
+        })).nodes.remove(0)
+    }
+
+    /// Nodes of the map at p by their x.
+    fn xs(p: &Path) -> Vec<f64> {
+        ScanMap::read(p).unwrap().nodes.iter().map(|n| n.position.x).collect()
+    }
+
+    #[test]
+    fn write_round_trips_through_scan_map_write() {
+        let (full, p) = saved("stream-round-trip");
+        let last = read_last(&p).unwrap();
+
+        let copy = p.with_file_name("copy.json");
+        last.write(&copy, &write_opts(0)).unwrap();
+        assert_eq!(serde_json::to_value(ScanMap::read(&copy).unwrap()).unwrap(), serde_json::to_value(&full).unwrap());
+
+        // Keeping a backup replaces the file instead of appending to it
+        last.write(&p, &write_opts(1)).unwrap();
+        assert!(crate::backup_path(&p, 1).exists());
+        assert_eq!(serde_json::to_value(ScanMap::read(&p).unwrap()).unwrap(), serde_json::to_value(&full).unwrap());
+    }
+
+    #[test]
+    fn write_appends_new_nodes_in_place() {
+        let (_, p) = saved("stream-append");
+        let original = std::fs::read(&p).unwrap();
+        let mut last = read_last(&p).unwrap();
+        let nodes_end = last.streamed.nodes_end.get().unwrap() as usize;
+
+        last.nodes.push(node(3.0));
+        last.write(&p, &write_opts(0)).unwrap();
+        last.nodes.push(node(4.0));
+        last.write(&p, &write_opts(0)).unwrap();
+
+        assert_eq!(std::fs::read(&p).unwrap()[..nodes_end], original[..nodes_end]);
+        assert_eq!(xs(&p), vec![0.0, 1.0, 2.0, 3.0, 4.0]);
+        assert_eq!(read_header(&p).unwrap().name, "Office");
+    }
+
+    #[test]
+    fn write_replaces_the_file_when_appending_cannot_undo() {
+        let (_, p) = saved("stream-undo");
+        let mut last = read_last(&p).unwrap();
+
+        last.nodes.push(node(3.0));
+        last.write(&p, &write_opts(0)).unwrap();
+        last.nodes.truncate(0);
+        last.write(&p, &write_opts(0)).unwrap();
+        assert_eq!(xs(&p), vec![0.0, 1.0]);
+
+        // The replaced file lists its nodes first, so it is appended to from then on
+        last.nodes.push(node(5.0));
+        last.name = String::from("Renamed");
+        last.write(&p, &write_opts(0)).unwrap();
+        assert_eq!(xs(&p), vec![0.0, 1.0, 5.0]);
+        assert_eq!(ScanMap::read(&p).unwrap().name, "Renamed");
+        assert!(std::fs::read_to_string(&p).unwrap().starts_with("{\"nodes\":["));
+    }
+}
@@ -0,0 +1,43 @@
+//! Prints rows of values as aligned text columns.
+
+/// Print a table with a header row. Each column is padded to its widest value.
+pub fn print_table(headers: &[&str], rows: &[Vec<String>]) {
+    for line in format_table(headers, rows) {
+        println!("{}", line);
+    }
+}
+
+/// Widths of columns which only hold their headers.
+pub fn header_widths(headers: &[&str]) -> Vec<usize> {
+    headers.iter().map(|h| h.chars().count()).collect()
+}
+
+/// Widen widths so each column fits its value in row. Values past the last column are ignored.
+pub fn widen(widths: &mut [usize], row: &[String]) {
+    for (width, value) in widths.iter_mut().zip(row) {
+        *width = (*width).max(value.chars().count());
+    }
+}
+
+/// Line of a table with each value padded to the width of its column.
+pub fn format_row(widths: &[usize], values: &[&str]) -> String {
+    values.iter()
+        .enumerate()
+        .map(|(i, v)| format!("{:width$}", v, width = widths[i]))
+        .collect::<Vec<String>>()
+        .join("  ")
+        .trim_end()
+        .to_string()
+}
+
+/// Lines of a table with a header row, the header first. Each column is padded to its widest value.
+pub fn format_table(headers: &[&str], rows: &[Vec<String>]) -> Vec<String> {
+    let mut widths = header_widths(headers);
+    for row in rows {
+        widen(&mut widths, row);
+    }
+
+    std::iter::once(format_row(&widths, headers))
+        .chain(rows.iter().map(|row| format_row(&widths, &row.iter().map(|v| v.as_str()).collect::<Vec<&str>>())))
+        .collect()
+}
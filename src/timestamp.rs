@@ -0,0 +1,198 @@
+//! Shows the times maps store, milliseconds since the unix epoch, as RFC 3339 timestamps in the
+//! local time zone, and parses the times given to --since and --until. Map files keep the numbers.
+
+use std::convert::TryFrom;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use chrono::{DateTime, Local, LocalResult, NaiveDate, NaiveDateTime, SecondsFormat, TimeZone};
+use serde::Serializer;
+
+use crate::DurationArg;
+
+/// RFC 3339 timestamp of a time in milliseconds since the unix epoch, in the local time zone and to the second.
+pub fn format(ms: u128) -> String {
+    let local = i64::try_from(ms).ok().and_then(|ms| Local.timestamp_millis_opt(ms).earliest());
+    match local {
+        Some(t) => t.to_rfc3339_opts(SecondsFormat::Secs, false),
+        None => ms.to_string(),
+    }
+}
+
+/// Serialize a time in milliseconds since the unix epoch as an RFC 3339 timestamp, for serde's serialize_with.
+pub fn serialize<S: Serializer>(ms: &u128, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&format(*ms))
+}
+
+/// Serialize an optional time like serialize, None as null.
+pub fn serialize_option<S: Serializer>(ms: &Option<u128>, serializer: S) -> Result<S::Ok, S::Error> {
+    match ms {
+        Some(ms) => serialize(ms, serializer),
+        None => serializer.serialize_none(),
+    }
+}
+
+/// Time given on the command line, the first and last millisecond it names since the unix epoch. A date names the
+/// whole day and a time without seconds the whole minute, so e.g. --until 2024-05-01 includes that day.
+pub struct TimeArg {
+    pub earliest: u128,
+    pub latest: u128,
+}
+
+impl TimeArg {
+    fn span(earliest: i64, length: i64) -> TimeArg {
+        let earliest = earliest.max(0) as u128;
+        TimeArg{
+            earliest,
+            latest: earliest + length.max(1) as u128 - 1,
+        }
+    }
+}
+
+/// Formats of local times without an offset, and the length of time each names.
+const LOCAL_FORMATS: [(&str, i64); 4] = [
+    ("%Y-%m-%dT%H:%M:%S", 1000),
+    ("%Y-%m-%d %H:%M:%S", 1000),
+    ("%Y-%m-%dT%H:%M", 60 * 1000),
+    ("%Y-%m-%d %H:%M", 60 * 1000),
+];
+
+/// Time in the local time zone as milliseconds since the unix epoch, the earlier one if the clock was turned back.
+fn local_millis(t: NaiveDateTime) -> Result<i64, String> {
+    match Local.from_local_datetime(&t) {
+        LocalResult::Single(t) | LocalResult::Ambiguous(t, _) => Ok(t.timestamp_millis()),
+        LocalResult::None => Err(format!("{} does not exist in the local time zone", t)),
+    }
+}
+
+impl std::str::FromStr for TimeArg {
+    type Err = String;
+
+    /// Parse an RFC 3339 timestamp, a local date or time like "2024-05-01" or "2024-05-01 14:30", a unix time in
+    /// seconds, or a duration before now like "2h".
+    fn from_str(s: &str) -> Result<TimeArg, String> {
+        let s = s.trim();
+
+        if let Ok(t) = DateTime::parse_from_rfc3339(s) {
+            return Ok(TimeArg::span(t.timestamp_millis(), match t.timestamp_subsec_millis() {
+                0 => 1000,
+                _ => 1,
+            }));
+        }
+        if !s.is_empty() && s.chars().all(|c| c.is_ascii_digit()) {
+            let secs: i64 = s.parse().map_err(|_| String::from("unix time is too large"))?;
+            return Ok(TimeArg::span(secs.saturating_mul(1000), 1000));
+        }
+        if let Ok(date) = NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+            let start = local_millis(date.and_hms_opt(0, 0, 0).expect("midnight is a valid time"))?;
+            let end = match date.succ_opt() {
+                Some(next) => local_millis(next.and_hms_opt(0, 0, 0).expect("midnight is a valid time"))?,
+                None => start + 24 * 3600 * 1000,
+            };
+            return Ok(TimeArg::span(start, end - start));
+        }
+        for (format, length) in LOCAL_FORMATS {
+            if let Ok(t) = NaiveDateTime::parse_from_str(s, format) {
+                return Ok(TimeArg::span(local_millis(t)?, length));
+            }
+        }
+        if s.ends_with(|c: char| c.is_ascii_alphabetic()) {
+            if let Ok(DurationArg(ago)) = s.parse::<DurationArg>() {
+                let now = SystemTime::now().duration_since(UNIX_EPOCH).expect("system time is before the unix epoch");
+                let t = now.saturating_sub(ago).as_millis();
+                return Ok(TimeArg{
+                    earliest: t,
+                    latest: t,
+                });
+            }
+        }
+
+        Err(String::from("must be an RFC 3339 timestamp, a local date or time like \"2024-05-01\" or \"2024-05-01 14:30\", a unix time in seconds, or a duration before now like \"2h\""))
+    }
+}
+
+/// Times which --since and --until let through, in milliseconds since the unix epoch. Ends which are None are open.
+#[derive(Clone, Copy, Default)]
+pub struct TimeRange {
+    pub since: Option<u128>,
+    pub until: Option<u128>,
+}
+
+impl TimeRange {
+    /// True if the range filters nothing.
+    pub fn is_open(&self) -> bool {
+        self.since.is_none() && self.until.is_none()
+    }
+
+    pub fn contains(&self, ms: u128) -> bool {
+        self.since.is_none_or(|since| ms >= since) && self.until.is_none_or(|until| ms <= until)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(s: &str) -> TimeArg {
+        s.parse().unwrap_or_else(|e| panic!("{}: {}", s, e))
+    }
+
+    #[test]
+    fn rfc3339_names_its_second_unless_it_has_milliseconds() {
+        let t = parse("2024-05-01T12:00:00Z");
+        assert_eq!((t.earliest, t.latest), (1714564800000, 1714564800999));
+
+        let t = parse("2024-05-01T14:00:00.250+02:00");
+        assert_eq!((t.earliest, t.latest), (1714564800250, 1714564800250));
+    }
+
+    #[test]
+    fn unix_seconds_name_their_second() {
+        let t = parse("1714564800");
+        assert_eq!((t.earliest, t.latest), (1714564800000, 1714564800999));
+        assert!("99999999999999999999999".parse::<TimeArg>().is_err());
+    }
+
+    #[test]
+    fn local_dates_and_times_name_their_day_minute_or_second() {
+        let day = parse("2024-05-01");
+        let start = local_millis(NaiveDate::from_ymd_opt(2024, 5, 1).unwrap().and_hms_opt(0, 0, 0).unwrap()).unwrap();
+        assert_eq!(day.earliest, start as u128);
+        assert_eq!(day.latest - day.earliest + 1, 24 * 3600 * 1000);
+
+        let minute = parse("2024-05-01 14:30");
+        assert_eq!(minute.earliest, start as u128 + (14 * 60 + 30) * 60 * 1000);
+        assert_eq!(minute.latest - minute.earliest + 1, 60 * 1000);
+        assert_eq!(parse("2024-05-01T14:30").earliest, minute.earliest);
+
+        let second = parse("2024-05-01 14:30:15");
+        assert_eq!(second.earliest, minute.earliest + 15 * 1000);
+        assert_eq!(second.latest - second.earliest + 1, 1000);
+    }
+
+    #[test]
+    fn durations_are_before_now() {
+        let t = parse("2h");
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis();
+        assert_eq!(t.earliest, t.latest);
+        assert!(t.earliest <= now - 2 * 3600 * 1000);
+        assert!(t.earliest > now - 2 * 3600 * 1000 - 60 * 1000);
+    }
+
+    #[test]
+    fn other_text_is_an_error() {
+        for s in ["", "yesterday", "2024-13-01", "2024-05-01 25:00", "-5"] {
+            assert!(s.parse::<TimeArg>().is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn ranges_include_their_ends() {
+        let range = TimeRange{
+            since: Some(10),
+            until: Some(20),
+        };
+        assert!(range.contains(10) && range.contains(20));
+        assert!(!range.contains(9) && !range.contains(21));
+        assert!(TimeRange::default().is_open());
+    }
+}
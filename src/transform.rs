@@ -0,0 +1,153 @@
+//! Moves, rotates, and scales the positions of a scan map, e.g. to fix a survey which was
+//! started with the origin in the wrong place.
+
+use crate::{Coordinate, FloorPlan, ScanMap};
+
+/// Affine transform which is applied in order: scale about the origin, rotate about the z axis, then translate.
+pub struct Transform {
+    /// Distance to move positions along the x, y, and z axes, in the map's units.
+    pub translate: (f64, f64, f64),
+
+    /// Counterclockwise rotation about the z axis in degrees.
+    pub rotate: f64,
+
+    /// Factor all lengths are multiplied by.
+    pub scale: f64,
+}
+
+impl Transform {
+    /// Horizontal part of the transform, applied to an x y position.
+    fn apply_xy(&self, x: f64, y: f64) -> (f64, f64) {
+        let (sin, cos) = self.rotate.to_radians().sin_cos();
+        let (x, y) = (x * self.scale, y * self.scale);
+
+        (x * cos - y * sin + self.translate.0, x * sin + y * cos + self.translate.1)
+    }
+
+    /// Transformed position. translate_z is false for positions whose z coordinate is relative to a floor, since the floor's elevation is moved instead.
+    fn apply(&self, position: &Coordinate, translate_z: bool) -> Coordinate {
+        let (x, y) = self.apply_xy(position.x, position.y);
+        let z = position.z * self.scale + match translate_z {
+            true => self.translate.2,
+            false => 0.0,
+        };
+
+        Coordinate{ x, y, z }
+    }
+
+    /// Update a floor plan so it stays aligned with transformed positions. Rotations cannot be represented by a floor plan, false is returned if the plan no longer lines up.
+    fn apply_plan(&self, plan: &mut FloorPlan) -> bool {
+        // pixel = origin + position * scale, solved for the pixel of the transformed position
+        plan.scale /= self.scale;
+        plan.origin_x -= self.translate.0 * plan.scale;
+        plan.origin_y += self.translate.1 * plan.scale;
+
+        self.rotate % 360.0 == 0.0
+    }
+}
+
+/// Apply a transform to every position and floor elevation in a scan map. Returns the number of floor plans which no longer line up with the nodes.
+pub fn transform(scan_map: &mut ScanMap, t: &Transform) -> usize {
+    for node in &mut scan_map.nodes {
+        let on_floor = match &node.floor {
+            Some(name) => scan_map.floors.iter().any(|f| &f.name == name),
+            None => false,
+        };
+
+        node.position = t.apply(&node.position, !on_floor);
+    }
+
+    for location in scan_map.access_points.values_mut() {
+        let on_floor = match &location.floor {
+            Some(name) => scan_map.floors.iter().any(|f| &f.name == name),
+            None => false,
+        };
+
+        location.position = t.apply(&location.position, !on_floor);
+        location.radius *= t.scale;
+    }
+
+    let mut misaligned = 0;
+
+    for floor in &mut scan_map.floors {
+        floor.elevation = floor.elevation * t.scale + t.translate.2;
+
+        if let Some(plan) = &mut floor.floor_plan {
+            if !t.apply_plan(plan) {
+                misaligned += 1;
+            }
+        }
+    }
+
+    if let Some(plan) = &mut scan_map.floor_plan {
+        if !t.apply_plan(plan) {
+            misaligned += 1;
+        }
+    }
+
+    misaligned
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::scan_map;
+    use serde_json::json;
+
+    fn assert_near(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-9, "{} is not {}", actual, expected);
+    }
+
+    fn survey() -> ScanMap {
+        scan_map(json!({
+            "floors": [{"name": "F1", "elevation": 3.0, "floor_plan": {"image": "f1.png", "scale": 10.0, "origin_x": 100.0, "origin_y": 200.0}}],
+            "nodes": [
+                {"position": {"x": 1.0, "y": 0.0, "z": 2.0}},
+                {"position": {"x": 1.0, "y": 0.0, "z": 2.0}, "floor": "F1"},
+            ],
+        }))
+    }
+
+    #[test]
+    fn transform_scales_then_rotates_then_translates() {
+        let mut scan_map = survey();
+        transform(&mut scan_map, &Transform{
+            translate: (10.0, 0.0, 1.0),
+            rotate: 90.0,
+            scale: 2.0,
+        });
+
+        let p = &scan_map.nodes[0].position;
+        assert_near(p.x, 10.0);
+        assert_near(p.y, 2.0);
+        assert_near(p.z, 5.0);
+
+        // Heights on a floor are relative to it, the floor moves instead
+        assert_near(scan_map.nodes[1].position.z, 4.0);
+        assert_near(scan_map.floors[0].elevation, 7.0);
+    }
+
+    #[test]
+    fn transform_keeps_floor_plans_aligned_unless_rotated() {
+        let mut scan_map = survey();
+        let misaligned = transform(&mut scan_map, &Transform{
+            translate: (1.0, 1.0, 0.0),
+            rotate: 0.0,
+            scale: 2.0,
+        });
+        assert_eq!(misaligned, 0);
+
+        // The pixel of the node stays the same
+        let plan = scan_map.floors[0].floor_plan.as_ref().unwrap();
+        let p = &scan_map.nodes[1].position;
+        assert_near(plan.origin_x + p.x * plan.scale, 100.0 + 1.0 * 10.0);
+        assert_near(plan.origin_y - p.y * plan.scale, 200.0 - 0.0 * 10.0);
+
+        let misaligned = transform(&mut survey(), &Transform{
+            translate: (0.0, 0.0, 0.0),
+            rotate: 45.0,
+            scale: 1.0,
+        });
+        assert_eq!(misaligned, 1);
+    }
+}
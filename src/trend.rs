@@ -0,0 +1,395 @@
+//! Follows the strength of each access point across repeated surveys of the same checkpoints,
+//! e.g. a monthly walk, so access points which are degrading stand out.
+//!
+//! Every session of every map is a visit. Nodes of different visits within the tolerance of
+//! each other are the same checkpoint, and a BSSID's change is how much weaker or stronger it
+//! got at the checkpoints it was measured at more than once.
+
+use std::collections::{BTreeMap, HashSet};
+use std::error::Error;
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::timestamp;
+use crate::transform::{self, Transform};
+use crate::{Coordinate, Node, ScanMap};
+
+/// Milliseconds in the 30 days changes are given per.
+const MONTH_MS: f64 = 30.0 * 24.0 * 60.0 * 60.0 * 1000.0;
+
+/// Error which occurs when surveys cannot be compared.
+#[derive(Debug)]
+pub struct TrendError {
+    /// Reason the surveys could not be compared.
+    reason: String,
+}
+
+impl fmt::Display for TrendError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.reason)
+    }
+}
+
+impl Error for TrendError {}
+
+/// Options which control how nodes are matched and which changes are flagged.
+pub struct TrendOptions {
+    /// Largest distance between nodes of the same checkpoint, in the units of the first map.
+    pub tolerance: f64,
+
+    /// BSSIDs which weakened by at least this many dB are flagged as degrading.
+    pub threshold: f64,
+
+    /// Only follow BSSIDs of this SSID.
+    pub ssid: Option<String>,
+}
+
+/// One walk of the checkpoints.
+#[derive(Serialize)]
+pub struct Visit {
+    /// Map and session the visit was recorded in.
+    pub label: String,
+
+    /// Time the first node of the visit was measured in milliseconds since the unix epoch, serialized as an RFC 3339 timestamp.
+    #[serde(serialize_with = "timestamp::serialize")]
+    pub time: u128,
+}
+
+/// Strength of a BSSID at a checkpoint in each visit it was measured in.
+#[derive(Serialize)]
+pub struct CheckpointTrend {
+    pub position: Coordinate,
+
+    pub floor: Option<String>,
+
+    /// Index of the visit and the average strength in dBm, oldest first.
+    pub strengths: Vec<(usize, f64)>,
+
+    /// True if the last visit of the checkpoint did not hear the BSSID.
+    pub missing: bool,
+}
+
+/// How one BSSID changed across the visits.
+#[derive(Serialize)]
+pub struct BssidTrend {
+    pub bssid: String,
+
+    pub ssid: String,
+
+    /// Average difference between the last and first strength at each checkpoint measured more than once, in dB.
+    pub change: Option<f64>,
+
+    /// Least squares change per 30 days across the checkpoints, in dB.
+    pub per_month: Option<f64>,
+
+    /// True if the change is at least the threshold weaker, or the BSSID went missing at a checkpoint.
+    pub degrading: bool,
+
+    pub checkpoints: Vec<CheckpointTrend>,
+}
+
+/// Trends of every BSSID, the most degraded first.
+#[derive(Serialize)]
+pub struct TrendReport {
+    pub visits: Vec<Visit>,
+
+    /// Number of checkpoints the nodes were matched to.
+    pub checkpoints: usize,
+
+    pub bssids: Vec<BssidTrend>,
+}
+
+/// Position of a checkpoint and what was measured there.
+struct Checkpoint {
+    position: Coordinate,
+
+    floor: Option<String>,
+
+    /// Visits of the checkpoint.
+    visits: HashSet<usize>,
+
+    /// Strengths measured at the checkpoint by BSSID and visit.
+    strengths: BTreeMap<String, BTreeMap<usize, Vec<f64>>>,
+}
+
+/// Follow every BSSID across the sessions of scan_map and others, which are converted to the units of scan_map.
+/// others are paired with the name they are labeled with.
+pub fn trend(scan_map: &ScanMap, others: Vec<(String, ScanMap)>, opts: &TrendOptions) -> Result<TrendReport, TrendError> {
+    let crs = scan_map.coordinate_system;
+    let mut converted = Vec::<(String, ScanMap)>::new();
+    for (name, mut other) in others {
+        if other.coordinate_system != crs {
+            return Err(TrendError{
+                reason: format!("\"{}\" uses {} coordinates but the map uses {}", name, other.coordinate_system, crs),
+            });
+        }
+        if other.units != scan_map.units {
+            let scale = other.units.convert(1.0, scan_map.units);
+            transform::transform(&mut other, &Transform{
+                translate: (0.0, 0.0, 0.0),
+                rotate: 0.0,
+                scale,
+            });
+        }
+        converted.push((name, other));
+    }
+
+    // Measured nodes of each visit
+    let mut visits = Vec::<Visit>::new();
+    let mut measured = Vec::<(usize, u128, &Node)>::new();
+    let maps = std::iter::once((None, scan_map)).chain(converted.iter().map(|(name, m)| (Some(name.as_str()), m)));
+    for (name, map) in maps {
+        let mut sessions: Vec<Option<u32>> = map.nodes.iter().map(|n| n.session).collect();
+        sessions.sort();
+        sessions.dedup();
+
+        for session in sessions {
+            let nodes: Vec<(u128, &Node)> = map.nodes.iter()
+                .filter(|n| n.session == session)
+                .filter_map(|n| n.networks.iter().map(|w| w.time_scanned).min().map(|t| (t, n)))
+                .collect();
+            let time = match nodes.iter().map(|(t, _)| *t).min() {
+                Some(t) => t,
+                None => continue,
+            };
+
+            let session_label = match session {
+                Some(id) => format!("session {}", id),
+                None => String::from("no session"),
+            };
+            visits.push(Visit{
+                label: match name {
+                    Some(name) => format!("{} {}", name, session_label),
+                    None => session_label,
+                },
+                time,
+            });
+            measured.extend(nodes.into_iter().map(|(t, n)| (visits.len() - 1, t, n)));
+        }
+    }
+
+    // Visits are numbered oldest first
+    let mut order: Vec<usize> = (0..visits.len()).collect();
+    order.sort_by_key(|i| visits[*i].time);
+    let mut rank = vec![0; visits.len()];
+    for (r, i) in order.iter().enumerate() {
+        rank[*i] = r;
+    }
+    for m in &mut measured {
+        m.0 = rank[m.0];
+    }
+    visits.sort_by_key(|v| v.time);
+    measured.sort_by_key(|(visit, time, _)| (*visit, *time));
+
+    // Nodes join the nearest checkpoint within the tolerance, or start a new one
+    let mut checkpoints = Vec::<Checkpoint>::new();
+    let mut ssids = BTreeMap::<String, String>::new();
+    for (visit, _, node) in &measured {
+        let nearest = checkpoints.iter()
+            .enumerate()
+            .filter(|(_, c)| c.floor == node.floor)
+            .map(|(i, c)| (i, c.position.distance(&node.position, crs)))
+            .filter(|(_, d)| *d <= opts.tolerance)
+            .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal));
+        let i = match nearest {
+            Some((i, _)) => i,
+            None => {
+                checkpoints.push(Checkpoint{
+                    position: Coordinate{ x: node.position.x, y: node.position.y, z: node.position.z },
+                    floor: node.floor.clone(),
+                    visits: HashSet::new(),
+                    strengths: BTreeMap::new(),
+                });
+                checkpoints.len() - 1
+            },
+        };
+
+        let checkpoint = &mut checkpoints[i];
+        checkpoint.visits.insert(*visit);
+        for network in &node.networks {
+            if opts.ssid.as_ref().is_some_and(|s| *s != network.ssid) {
+                continue;
+            }
+            if let Some(strength) = network.strength {
+                let mac = network.mac.to_lowercase();
+                ssids.entry(mac.clone()).or_insert_with(|| network.display_ssid().to_string());
+                checkpoint.strengths.entry(mac).or_default().entry(*visit).or_default().push(strength as f64);
+            }
+        }
+    }
+
+    let mut bssids = Vec::<BssidTrend>::new();
+    for (mac, ssid) in ssids {
+        let mut trends = Vec::<CheckpointTrend>::new();
+        for checkpoint in &checkpoints {
+            let by_visit = match checkpoint.strengths.get(&mac) {
+                Some(s) => s,
+                None => continue,
+            };
+            let strengths: Vec<(usize, f64)> = by_visit.iter()
+                .map(|(visit, s)| (*visit, s.iter().sum::<f64>() / s.len() as f64))
+                .collect();
+            let last_seen = strengths.last().map(|(v, _)| *v).unwrap_or(0);
+
+            trends.push(CheckpointTrend{
+                position: Coordinate{ x: checkpoint.position.x, y: checkpoint.position.y, z: checkpoint.position.z },
+                floor: checkpoint.floor.clone(),
+                strengths,
+                missing: checkpoint.visits.iter().any(|v| *v > last_seen),
+            });
+        }
+
+        // Only checkpoints measured more than once say anything about change
+        let repeated: Vec<&CheckpointTrend> = trends.iter().filter(|t| t.strengths.len() > 1).collect();
+        let change = match repeated.is_empty() {
+            true => None,
+            false => Some(repeated.iter()
+                          .map(|t| t.strengths[t.strengths.len() - 1].1 - t.strengths[0].1)
+                          .sum::<f64>() / repeated.len() as f64),
+        };
+
+        // Slope of the strengths against time, each checkpoint around its own average so stronger checkpoints do not skew it
+        let (mut sxy, mut sxx) = (0.0, 0.0);
+        for t in &repeated {
+            let mean_time = t.strengths.iter().map(|(v, _)| visits[*v].time as f64).sum::<f64>() / t.strengths.len() as f64;
+            let mean_strength = t.strengths.iter().map(|(_, s)| *s).sum::<f64>() / t.strengths.len() as f64;
+            for (v, s) in &t.strengths {
+                let dt = (visits[*v].time as f64 - mean_time) / MONTH_MS;
+                sxy += dt * (s - mean_strength);
+                sxx += dt * dt;
+            }
+        }
+        let per_month = match sxx > 0.0 {
+            true => Some(sxy / sxx),
+            false => None,
+        };
+
+        bssids.push(BssidTrend{
+            bssid: mac,
+            ssid,
+            change,
+            per_month,
+            degrading: change.is_some_and(|c| c <= -opts.threshold) || trends.iter().any(|t| t.missing),
+            checkpoints: trends,
+        });
+    }
+
+    bssids.sort_by(|a, b| b.degrading.cmp(&a.degrading)
+                   .then(a.change.unwrap_or(f64::INFINITY).partial_cmp(&b.change.unwrap_or(f64::INFINITY)).unwrap_or(std::cmp::Ordering::Equal)));
+
+    Ok(TrendReport{
+        visits,
+        checkpoints: checkpoints.len(),
+        bssids,
+    })
+}
+
+/// Print the visits and a table of BSSIDs.
+pub fn print_trend(report: &TrendReport) {
+    println!("{} visit{} of {} checkpoint{}", report.visits.len(), match report.visits.len() != 1 {
+        true => "s",
+        false => "",
+    }, report.checkpoints, match report.checkpoints != 1 {
+        true => "s",
+        false => "",
+    });
+    for (i, visit) in report.visits.iter().enumerate() {
+        println!("    {}: {} at {}", i + 1, visit.label, timestamp::format(visit.time));
+    }
+    println!();
+
+    let db = |v: Option<f64>| match v {
+        Some(v) => format!("{:+.1} dB", v),
+        None => String::from("-"),
+    };
+    let rows: Vec<Vec<String>> = report.bssids.iter()
+        .map(|b| vec![
+            b.bssid.clone(),
+            b.ssid.clone(),
+            b.checkpoints.iter().filter(|c| c.strengths.len() > 1).count().to_string(),
+            db(b.change),
+            db(b.per_month),
+            b.checkpoints.iter().filter(|c| c.missing).count().to_string(),
+            match b.degrading {
+                true => String::from("degrading"),
+                false => String::new(),
+            },
+        ])
+        .collect();
+
+    crate::table::print_table(&["BSSID", "SSID", "Repeated", "Change", "Per 30 days", "Missing", "Flags"], &rows);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::scan_map;
+
+    /// Node of a session at (x, 0) which measured strengths of BSSIDs at time.
+    fn node(session: u32, x: f64, time: f64, strengths: &[(&str, f64)]) -> serde_json::Value {
+        let networks: Vec<serde_json::Value> = strengths.iter()
+            .map(|(mac, strength)| serde_json::json!({"mac": mac, "ssid": "CorpWiFi", "strength": strength, "time_scanned": time as u128}))
+            .collect();
+        serde_json::json!({"position": {"x": x, "y": 0.0, "z": 0.0}, "session": session, "networks": networks})
+    }
+
+    /// Two walks a month apart of checkpoints at 0 and 10 meters. The first access point weakened at the first
+    /// checkpoint, and the second was not heard on the second walk.
+    fn survey() -> ScanMap {
+        scan_map(serde_json::json!({
+            "nodes": [
+                node(1, 0.0, 0.0, &[("84:d4:7e:00:00:0a", -50.0), ("84:d4:7e:00:00:0b", -60.0)]),
+                node(1, 10.0, 1000.0, &[("84:d4:7e:00:00:0a", -70.0)]),
+                node(2, 0.5, MONTH_MS, &[("84:d4:7e:00:00:0a", -56.0)]),
+                node(2, 10.0, MONTH_MS + 1000.0, &[("84:d4:7e:00:00:0a", -70.0)]),
+            ],
+        }))
+    }
+
+    fn opts() -> TrendOptions {
+        TrendOptions{
+            tolerance: 1.0,
+            threshold: 5.0,
+            ssid: None,
+        }
+    }
+
+    #[test]
+    fn trend_follows_bssids_across_visits_of_the_checkpoints() {
+        let report = trend(&survey(), Vec::new(), &opts()).unwrap();
+        assert_eq!(report.visits.iter().map(|v| v.label.as_str()).collect::<Vec<&str>>(), vec!["session 1", "session 2"]);
+        assert_eq!(report.checkpoints, 2);
+
+        // The missing access point is degrading, the weakened one changed less than the threshold
+        let missing = &report.bssids[0];
+        assert_eq!(missing.bssid, "84:d4:7e:00:00:0b");
+        assert!(missing.degrading && missing.checkpoints[0].missing);
+        assert_eq!(missing.change, None);
+
+        let weakened = &report.bssids[1];
+        assert_eq!(weakened.change, Some(-3.0));
+        assert!((weakened.per_month.unwrap() + 3.0).abs() < 1e-9);
+        assert!(!weakened.degrading);
+        assert_eq!(weakened.checkpoints[0].strengths, vec![(0, -50.0), (1, -56.0)]);
+    }
+
+    #[test]
+    fn trend_converts_the_other_maps_to_the_units_of_the_first() {
+        let later = scan_map(serde_json::json!({
+            "units": "feet",
+            "nodes": [node(1, 32.8084, 2.0 * MONTH_MS, &[("84:d4:7e:00:00:0a", -82.0)])],
+        }));
+        let report = trend(&survey(), vec![(String::from("later.json"), later)], &opts()).unwrap();
+        assert_eq!(report.visits[2].label, "later.json session 1");
+        assert_eq!(report.checkpoints, 2);
+
+        let weakened = report.bssids.iter().find(|b| b.bssid == "84:d4:7e:00:00:0a").unwrap();
+        assert_eq!(weakened.checkpoints[1].strengths, vec![(0, -70.0), (1, -70.0), (2, -82.0)]);
+        assert_eq!(weakened.change, Some(-9.0));
+        assert!(weakened.degrading);
+
+        let gps = scan_map(serde_json::json!({"coordinate_system": "wgs84"}));
+        assert!(trend(&survey(), vec![(String::from("gps.json"), gps)], &opts()).is_err());
+    }
+}
@@ -0,0 +1,532 @@
+//! Full screen interface for long surveys. The networks of the latest scan are shown in a live
+//! table next to the most recent nodes and a summary of the map, and single keys record, move,
+//! and undo nodes instead of answering prompts.
+//!
+//! The screen is drawn with ratatui on crossterm, so it works in Windows consoles as well as in
+//! unix terminals.
+
+use std::error::Error;
+use std::io::{self, stdin, stdout, IsTerminal};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Style, Stylize};
+use ratatui::widgets::{Paragraph, Row, Table};
+use ratatui::Frame;
+
+use crate::walk::{self, Scan};
+use crate::{Coordinate, Network, Node, RecordOptions, ScanMap, ScanOptions, WriteOptions};
+
+/// Number of recorded nodes listed below the scan table.
+const RECENT_NODES: usize = 5;
+
+/// Longest time to wait for a key, so new scans and the age of the last one are drawn while the user does nothing.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Spaces between table columns, like table::format_table.
+const COLUMN_SPACING: u16 = 2;
+
+/// Options which control the interface.
+pub struct TuiOptions {
+    /// Time between the scans shown in the table.
+    pub interval: Duration,
+}
+
+/// Column the scan table is sorted by.
+#[derive(Clone, Copy, PartialEq)]
+enum Sort {
+    Strength,
+    Ssid,
+    Bssid,
+    Channel,
+}
+
+impl Sort {
+    /// Column which s switches to.
+    fn next(self) -> Sort {
+        match self {
+            Sort::Strength => Sort::Ssid,
+            Sort::Ssid => Sort::Bssid,
+            Sort::Bssid => Sort::Channel,
+            Sort::Channel => Sort::Strength,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Sort::Strength => "strength",
+            Sort::Ssid => "SSID",
+            Sort::Bssid => "BSSID",
+            Sort::Channel => "channel",
+        }
+    }
+}
+
+/// Key pressed by the user.
+enum Key {
+    Char(char),
+    Enter,
+    Escape,
+    Backspace,
+    Up,
+    Down,
+
+    /// Ctrl-C, which does not raise SIGINT while the screen is shown.
+    Interrupt,
+}
+
+/// Key which a key event presses, None for releases, repeats on terminals which report them, and unused keys.
+fn key(event: KeyEvent) -> Option<Key> {
+    if event.kind != KeyEventKind::Press {
+        return None;
+    }
+
+    match event.code {
+        KeyCode::Char('c') if event.modifiers.contains(KeyModifiers::CONTROL) => Some(Key::Interrupt),
+        KeyCode::Char(_) if event.modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) => None,
+        KeyCode::Char(c) => Some(Key::Char(c)),
+        KeyCode::Enter => Some(Key::Enter),
+        KeyCode::Esc => Some(Key::Escape),
+        KeyCode::Backspace => Some(Key::Backspace),
+        KeyCode::Up => Some(Key::Up),
+        KeyCode::Down => Some(Key::Down),
+        _ => None,
+    }
+}
+
+/// Value being typed in at the bottom of the screen.
+enum Field {
+    Coordinates,
+    Notes,
+}
+
+/// Line the user is editing.
+struct Input {
+    field: Field,
+
+    text: String,
+}
+
+/// Puts the terminal in raw mode on the alternate screen, restores it when dropped.
+struct Screen {
+    terminal: ratatui::DefaultTerminal,
+}
+
+impl Screen {
+    fn enter() -> io::Result<Screen> {
+        Ok(Screen{
+            terminal: ratatui::try_init()?,
+        })
+    }
+}
+
+impl Drop for Screen {
+    fn drop(&mut self) {
+        ratatui::restore();
+    }
+}
+
+/// Everything shown on the screen besides the map.
+struct State {
+    /// Position the next node is recorded at, None until the user enters one in an empty map.
+    position: Option<Coordinate>,
+
+    floor: Option<String>,
+
+    notes: String,
+
+    /// Networks of the latest successful scan.
+    networks: Vec<Network>,
+
+    /// Number of scans received and when the last one arrived.
+    scans: usize,
+    scanned: Option<Instant>,
+
+    /// Why the latest scan failed.
+    error: Option<String>,
+
+    sort: Sort,
+    reverse: bool,
+
+    /// Number of scan table rows scrolled past.
+    scroll: usize,
+
+    input: Option<Input>,
+
+    /// Time record was pressed in milliseconds since the unix epoch, the first scan started after it is recorded.
+    recording: Option<u128>,
+
+    /// Result of the last action.
+    message: String,
+}
+
+impl State {
+    /// Networks of the latest scan in the order of the table.
+    fn sorted(&self) -> Vec<&Network> {
+        let mut networks: Vec<&Network> = self.networks.iter().collect();
+        match self.sort {
+            Sort::Strength => networks.sort_by(|a, b| b.strength.partial_cmp(&a.strength).unwrap_or(std::cmp::Ordering::Equal)),
+            Sort::Ssid => networks.sort_by_key(|n| n.display_ssid().to_lowercase()),
+            Sort::Bssid => networks.sort_by_key(|n| n.mac.to_lowercase()),
+            Sort::Channel => networks.sort_by_key(|n| n.channel.parse::<u32>().unwrap_or(u32::MAX)),
+        };
+        if self.reverse {
+            networks.reverse();
+        }
+        networks
+    }
+
+    /// Show a scan, and record it if record was pressed before it started.
+    fn receive(&mut self, scan: Scan, scan_map: &mut ScanMap, record_opts: &RecordOptions, map_path: &Path, write_opts: &WriteOptions) -> Result<(), Box<dyn Error>> {
+        self.scans += 1;
+        self.scanned = Some(Instant::now());
+        let waiting = self.recording.is_some_and(|pressed| scan.time >= pressed);
+
+        match scan.found {
+            Ok((networks, _)) => {
+                self.networks = networks;
+                self.error = None;
+            },
+            Err(e) => {
+                self.error = Some(e);
+                if waiting {
+                    self.recording = None;
+                    self.message = String::from("The scan failed, nothing was recorded");
+                }
+                return Ok(());
+            },
+        };
+
+        if !waiting {
+            return Ok(());
+        }
+        self.recording = None;
+
+        let position = self.position.as_ref().expect("recording without a position");
+        let mut networks = self.networks.clone();
+        networks.sort_by_key(|n| n.mac.clone());
+        scan_map.nodes.push(Node{
+            position: Coordinate{ x: position.x, y: position.y, z: position.z },
+            floor: self.floor.clone(),
+            notes: self.notes.clone(),
+            tags: record_opts.tags.clone(),
+            session: record_opts.session,
+            upload_id: None,
+            networks,
+            clients: Vec::new(),
+            ble_devices: Vec::new(),
+        });
+        scan_map.write(map_path, write_opts)?;
+
+        self.message = format!("Recorded node {} with {} network{}", scan_map.node_count() - 1, self.networks.len(), match self.networks.len() != 1 {
+            true => "s",
+            false => "",
+        });
+        Ok(())
+    }
+
+    /// Act on a key. Returns false once the user quits.
+    fn press(&mut self, key: Key, scan_map: &mut ScanMap, map_path: &Path, write_opts: &WriteOptions) -> Result<bool, Box<dyn Error>> {
+        if let Key::Interrupt = key {
+            return Ok(false);
+        }
+
+        if let Some(input) = &mut self.input {
+            match key {
+                Key::Char(c) => input.text.push(c),
+                Key::Backspace => {
+                    input.text.pop();
+                },
+                Key::Escape => self.input = None,
+                Key::Enter => self.submit(scan_map),
+                _ => (),
+            };
+            return Ok(true);
+        }
+
+        match key {
+            Key::Enter | Key::Char(' ') => match (&self.position, &self.floor) {
+                (None, _) => self.message = String::from("Enter coordinates with c before recording"),
+                (_, None) if !scan_map.floors.is_empty() => self.message = String::from("Choose a floor with f before recording"),
+                _ => {
+                    self.recording = Some(walk::now());
+                    self.message = String::from("Recording the next scan");
+                },
+            },
+            Key::Char('c') => self.input = Some(Input{
+                field: Field::Coordinates,
+                text: String::new(),
+            }),
+            Key::Char('n') => self.input = Some(Input{
+                field: Field::Notes,
+                text: self.notes.clone(),
+            }),
+            Key::Char('f') => match scan_map.floors.is_empty() {
+                true => self.message = String::from("The map has no floors, add them with the floor add sub-command"),
+                false => {
+                    let names: Vec<&String> = scan_map.floors.iter().map(|f| &f.name).collect();
+                    let next = match names.iter().position(|n| Some(*n) == self.floor.as_ref()) {
+                        Some(i) => (i + 1) % names.len(),
+                        None => 0,
+                    };
+                    self.floor = Some(names[next].clone());
+                    self.message = format!("Recording on floor {}", names[next]);
+                },
+            },
+            Key::Char('u') => match scan_map.nodes.pop() {
+                Some(n) => {
+                    scan_map.write(map_path, write_opts)?;
+                    self.message = format!("Removed node {} at {}", scan_map.node_count(), n.position.format(scan_map.coordinate_system, scan_map.units, scan_map.units));
+                },
+                None => self.message = String::from("No nodes to undo"),
+            },
+            Key::Char('s') => {
+                self.sort = self.sort.next();
+                self.scroll = 0;
+            },
+            Key::Char('r') => self.reverse = !self.reverse,
+            Key::Up | Key::Char('k') => self.scroll = self.scroll.saturating_sub(1),
+            Key::Down | Key::Char('j') => self.scroll = (self.scroll + 1).min(self.networks.len().saturating_sub(1)),
+            Key::Char('q') => return Ok(false),
+            _ => (),
+        };
+
+        Ok(true)
+    }
+
+    /// Apply the line being edited. Invalid coordinates keep it open so they can be fixed.
+    fn submit(&mut self, scan_map: &ScanMap) {
+        let input = match self.input.take() {
+            Some(i) => i,
+            None => return,
+        };
+
+        match input.field {
+            Field::Coordinates => {
+                let previous = self.position.as_ref().or_else(|| scan_map.nodes.last().map(|n| &n.position));
+                match Coordinate::parse(input.text.trim(), scan_map.coordinate_system, previous) {
+                    Ok(p) => {
+                        self.message = format!("Recording at {}", p.format(scan_map.coordinate_system, scan_map.units, scan_map.units));
+                        self.position = Some(p);
+                    },
+                    Err(e) => {
+                        self.message = format!("Error: {}", e);
+                        self.input = Some(input);
+                    },
+                };
+            },
+            Field::Notes => {
+                self.notes = input.text.trim().to_string();
+                self.message = match self.notes.is_empty() {
+                    true => String::from("Cleared the notes"),
+                    false => format!("Notes of new nodes are \"{}\"", self.notes),
+                };
+            },
+        };
+    }
+}
+
+/// Line in reverse video which fills the width of area.
+fn bar(frame: &mut Frame, area: Rect, s: &str) {
+    frame.render_widget(Paragraph::new(s.to_string()).style(Style::new().reversed()), area);
+}
+
+/// Table of rows under headers, with columns as wide as their widest value.
+fn table<'a>(headers: &[&'a str], rows: Vec<Vec<String>>) -> Table<'a> {
+    let mut widths: Vec<usize> = headers.iter().map(|h| h.chars().count()).collect();
+    for row in &rows {
+        for (width, value) in widths.iter_mut().zip(row) {
+            *width = (*width).max(value.chars().count());
+        }
+    }
+
+    Table::new(rows.into_iter().map(Row::new), widths.into_iter().map(|w| Constraint::Length(w as u16)))
+        .header(Row::new(headers.to_vec()))
+        .column_spacing(COLUMN_SPACING)
+}
+
+/// Draw the whole screen.
+fn draw(frame: &mut Frame, scan_map: &ScanMap, state: &State, opts: &TuiOptions) {
+    let crs = scan_map.coordinate_system;
+    let units = scan_map.units;
+
+    // Recent nodes
+    let recent: Vec<Vec<String>> = scan_map.nodes.iter()
+        .enumerate()
+        .rev()
+        .take(RECENT_NODES)
+        .map(|(i, n)| {
+            let strongest = n.networks.iter()
+                .filter(|w| w.strength.is_some())
+                .max_by(|a, b| a.strength.partial_cmp(&b.strength).unwrap_or(std::cmp::Ordering::Equal));
+            vec![
+                i.to_string(),
+                n.position.format(crs, units, units),
+                n.floor.clone().unwrap_or_else(|| String::from("-")),
+                n.networks.len().to_string(),
+                strongest.map(|w| format!("{} ({} dBm)", w.display_ssid(), w.strength_str())).unwrap_or_else(|| String::from("-")),
+                n.notes.clone(),
+            ]
+        })
+        .collect();
+
+    // The scan table gets whatever the other panes leave
+    let [summary_bar, summary, _, scans_bar, scans, recent_bar, recent_area, status, keys] = Layout::vertical([
+        Constraint::Length(1),
+        Constraint::Length(3),
+        Constraint::Length(1),
+        Constraint::Length(1),
+        Constraint::Min(1),
+        Constraint::Length(1),
+        Constraint::Length(recent.len() as u16 + 1),
+        Constraint::Length(1),
+        Constraint::Length(1),
+    ]).areas(frame.area());
+
+    // Map summary
+    bar(frame, summary_bar, &format!(" wifi-mapper  {}", scan_map));
+    frame.render_widget(Paragraph::new(vec![
+        format!(" Position: {}   Floor: {}   Session: {}",
+                state.position.as_ref().map(|p| p.format(crs, units, units)).unwrap_or_else(|| String::from("not set")),
+                state.floor.as_deref().unwrap_or("-"),
+                scan_map.sessions.last().map(|s| s.id.to_string()).unwrap_or_else(|| String::from("-"))).into(),
+        format!(" Notes: {}", match state.notes.is_empty() {
+            true => "-",
+            false => &state.notes,
+        }).into(),
+        match (&state.error, state.scanned) {
+            (Some(e), _) => format!(" Scan failed: {}", e),
+            (None, Some(t)) => format!(" Scan {}: {} network{} {}s ago, scanning every {:.1}s", state.scans, state.networks.len(), match state.networks.len() != 1 {
+                true => "s",
+                false => "",
+            }, t.elapsed().as_secs(), opts.interval.as_secs_f64()),
+            (None, None) => String::from(" Waiting for the first scan"),
+        }.into(),
+    ]), summary);
+
+    // Scan results, columns are sized by every row so they do not jump while scrolling
+    let mut rows: Vec<Vec<String>> = state.sorted().iter().map(|n| n.table_row()).collect();
+    bar(frame, scans_bar, &format!(" Scan results, sorted by {}{}", state.sort.name(), match state.reverse {
+        true => " reversed",
+        false => "",
+    }));
+    let mut scan_table = table(&Network::TABLE_HEADERS, Vec::new());
+    if !rows.is_empty() {
+        let widths = table(&Network::TABLE_HEADERS, rows.clone());
+        rows.drain(..state.scroll.min(rows.len()));
+        scan_table = widths.rows(rows.into_iter().map(Row::new));
+    }
+    frame.render_widget(scan_table, scans);
+
+    bar(frame, recent_bar, " Recent nodes");
+    frame.render_widget(table(&["Node", "Position", "Floor", "Networks", "Strongest", "Notes"], recent), recent_area);
+
+    // Status and keys
+    frame.render_widget(Paragraph::new(match &state.input {
+        Some(input) => format!(" {}: {}_", match input.field {
+            Field::Coordinates => {
+                let [x, y, z] = crs.axis_names();
+                format!("Coordinates ({} {} {} in {}, +d{} d{} d{} to move)", x, y, z, units, x, y, z)
+            },
+            Field::Notes => String::from("Notes"),
+        }, input.text),
+        None => format!(" {}", state.message),
+    }), status);
+    bar(frame, keys, match &state.input {
+        Some(_) => " enter save  esc cancel",
+        None => " enter record  c coordinates  n notes  f floor  u undo  s sort  r reverse  j/k scroll  q quit",
+    });
+}
+
+/// Read keys and scans until the user quits.
+fn interact(scan_map: &mut ScanMap, record_opts: &RecordOptions, opts: &TuiOptions, scans: Receiver<Scan>, map_path: &Path, write_opts: &WriteOptions) -> Result<(), Box<dyn Error>> {
+    let mut screen = Screen::enter()?;
+
+    // Continue from the last node like record --loop does
+    let last = scan_map.nodes.last();
+    let mut state = State{
+        position: last.map(|n| Coordinate{ x: n.position.x, y: n.position.y, z: n.position.z }),
+        floor: record_opts.floor.clone()
+            .or_else(|| last.and_then(|n| n.floor.clone()))
+            .or_else(|| scan_map.floors.first().map(|f| f.name.clone())),
+        notes: String::new(),
+        networks: Vec::new(),
+        scans: 0,
+        scanned: None,
+        error: None,
+        sort: Sort::Strength,
+        reverse: false,
+        scroll: 0,
+        input: None,
+        recording: None,
+        message: String::from("Walk to a spot, enter its coordinates with c, and press enter to record it"),
+    };
+
+    loop {
+        for scan in scans.try_iter() {
+            state.receive(scan, scan_map, record_opts, map_path, write_opts)?;
+        }
+
+        // Only cells which changed are written, so drawing after every key and poll is cheap
+        screen.terminal.draw(|frame| draw(frame, scan_map, &state, opts))?;
+
+        let mut timeout = POLL_INTERVAL;
+        while event::poll(timeout)? {
+            timeout = Duration::ZERO;
+            if let Some(key) = match event::read()? {
+                Event::Key(e) => key(e),
+                _ => None,
+            } {
+                if !state.press(key, scan_map, map_path, write_opts)? {
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+/// Show the interface until the user quits, scanning every opts.interval. Nodes are saved to map_path as they are recorded or undone.
+pub fn run(scan_map: &mut ScanMap, record_opts: &RecordOptions, scan_opts: &ScanOptions, opts: &TuiOptions, map_path: &Path, write_opts: &WriteOptions) -> Result<(), Box<dyn Error>> {
+    if !stdin().is_terminal() || !stdout().is_terminal() {
+        return Err(String::from("the tui needs a terminal, use record --loop to record from a script").into());
+    }
+
+    let stop = AtomicBool::new(false);
+    let (sender, receiver) = mpsc::channel();
+
+    thread::scope(|scope| {
+        let stop = &stop;
+        scope.spawn(move || walk::scan_loop(scan_opts, opts.interval, stop, sender));
+
+        let result = interact(scan_map, record_opts, opts, receiver, map_path, write_opts);
+        stop.store(true, Ordering::SeqCst);
+
+        result
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn key_maps_presses_and_ignores_releases_and_shortcuts() {
+        let press = |code, modifiers| key(KeyEvent::new(code, modifiers));
+
+        assert!(matches!(press(KeyCode::Char('s'), KeyModifiers::NONE), Some(Key::Char('s'))));
+        assert!(matches!(press(KeyCode::Char('S'), KeyModifiers::SHIFT), Some(Key::Char('S'))));
+        assert!(matches!(press(KeyCode::Char('c'), KeyModifiers::CONTROL), Some(Key::Interrupt)));
+        assert!(matches!(press(KeyCode::Enter, KeyModifiers::NONE), Some(Key::Enter)));
+        assert!(press(KeyCode::Char('x'), KeyModifiers::ALT).is_none());
+        assert!(press(KeyCode::F(1), KeyModifiers::NONE).is_none());
+
+        let mut release = KeyEvent::new(KeyCode::Char('s'), KeyModifiers::NONE);
+        release.kind = KeyEventKind::Release;
+        assert!(key(release).is_none());
+    }
+}
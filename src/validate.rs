@@ -0,0 +1,372 @@
+//! Checks a map file for corruption which hand edits and merges leave behind, e.g. nodes which
+//! were merged in twice or strengths which are not in dBm. Problems which can be repaired without
+//! guessing are fixed with --fix.
+
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+
+use crate::migrate;
+use crate::table;
+use crate::{CoordinateSystem, Node, ScanMap};
+
+/// Range of signal strengths in dBm which a radio can measure, values outside are not in dBm.
+const MIN_STRENGTH: f32 = -120.0;
+const MAX_STRENGTH: f32 = 0.0;
+
+/// Scans in milliseconds since the unix epoch before this time, the start of 2000, are assumed to be in seconds.
+const EARLIEST_SCAN: u128 = 946_684_800_000;
+
+/// Something wrong with a map.
+#[derive(Serialize)]
+pub struct Problem {
+    /// Index of the node in the file, None for problems of the whole map.
+    pub node: Option<usize>,
+
+    pub problem: String,
+
+    /// What --fix does about the problem, None if it has to be repaired by hand.
+    pub repair: Option<String>,
+
+    /// True if the repair was made.
+    pub fixed: bool,
+}
+
+impl Problem {
+    fn new(node: Option<usize>, problem: String, repair: Option<&str>) -> Problem {
+        Problem{
+            node,
+            problem,
+            repair: repair.map(|r| r.to_string()),
+            fixed: false,
+        }
+    }
+}
+
+/// Read the map at p like ScanMap::read, but describe why a file which does not match the schema cannot be read.
+/// Nodes are checked one at a time so the broken ones can be named.
+pub fn read(p: &Path) -> Result<ScanMap, Vec<Problem>> {
+    let problem = |reason: String| vec![Problem::new(None, reason, None)];
+
+    let file = File::open(p).map_err(|e| problem(format!("cannot be opened: {}", e)))?;
+    let mut value: serde_json::Value = serde_json::from_reader(BufReader::new(file))
+        .map_err(|e| problem(format!("is not valid JSON: {}", e)))?;
+    migrate::migrate(&mut value).map_err(|e| problem(e.to_string()))?;
+
+    match serde_json::from_value::<ScanMap>(value.clone()) {
+        Ok(m) => Ok(m),
+        Err(e) => {
+            let nodes: Vec<Problem> = value.get("nodes")
+                .and_then(|n| n.as_array())
+                .map(|nodes| nodes.iter()
+                    .enumerate()
+                    .filter_map(|(i, n)| serde_json::from_value::<Node>(n.clone()).err().map(|e| Problem::new(Some(i), format!("does not match the schema: {}", e), None)))
+                    .collect())
+                .unwrap_or_default();
+
+            match nodes.is_empty() {
+                true => Err(problem(format!("does not match the schema: {}", e))),
+                false => Err(nodes),
+            }
+        },
+    }
+}
+
+/// MAC address in the lowercase colon separated form, None if it does not have 6 hexadecimal octets.
+fn normalize_mac(mac: &str) -> Option<String> {
+    let digits: String = mac.chars().filter(|c| !":-.".contains(*c)).collect();
+    if digits.len() != 12 || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+
+    let octets: Vec<String> = (0..6).map(|i| digits[i * 2..i * 2 + 2].to_lowercase()).collect();
+    Some(octets.join(":"))
+}
+
+/// True if a MAC address is 6 hexadecimal octets separated by colons, in either case.
+fn is_mac(mac: &str) -> bool {
+    mac.len() == 17 && normalize_mac(mac).is_some_and(|m| m.eq_ignore_ascii_case(mac))
+}
+
+fn in_range(strength: f32) -> bool {
+    (MIN_STRENGTH..=MAX_STRENGTH).contains(&strength)
+}
+
+/// Time a node was measured, the time its last network was scanned. None if the node saw no networks with plausible times.
+fn measured_at(node: &Node) -> Option<u128> {
+    node.networks.iter().map(|n| n.time_scanned).filter(|t| *t >= EARLIEST_SCAN).max()
+}
+
+/// True if b repeats the measurements of a at the same place, e.g. because a map was merged twice.
+fn is_duplicate(a: &Node, b: &Node) -> bool {
+    let measurements = |n: &Node| -> Vec<(String, u128)> {
+        n.networks.iter().map(|net| (net.mac.to_lowercase(), net.time_scanned)).collect()
+    };
+
+    !a.networks.is_empty()
+        && a.floor == b.floor
+        && (a.position.x, a.position.y, a.position.z) == (b.position.x, b.position.y, b.position.z)
+        && measurements(a) == measurements(b)
+}
+
+/// Check a map for problems, repairing those which can be if fix is true.
+pub fn validate(scan_map: &mut ScanMap, fix: bool) -> Vec<Problem> {
+    let mut problems = Vec::<Problem>::new();
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis()).unwrap_or(0);
+
+    for (i, node) in scan_map.nodes.iter_mut().enumerate() {
+        let mut problem = |reason: String, repair: Option<&str>| {
+            problems.push(Problem::new(Some(i), reason, repair));
+            repair.is_some() && fix
+        };
+
+        let p = &node.position;
+        if !(p.x.is_finite() && p.y.is_finite() && p.z.is_finite()) {
+            problem(String::from("position is not a number"), None);
+        } else if scan_map.coordinate_system == CoordinateSystem::Wgs84 && (p.x.abs() > 180.0 || p.y.abs() > 90.0) {
+            problem(format!("longitude {} or latitude {} is out of range", p.x, p.y), None);
+        }
+
+        if let Some(floor) = &node.floor {
+            if !scan_map.floors.iter().any(|f| &f.name == floor) {
+                problem(format!("floor \"{}\" does not exist", floor), None);
+            }
+        }
+        if let Some(id) = node.session {
+            if !scan_map.sessions.iter().any(|s| s.id == id) {
+                problem(format!("session {} does not exist", id), None);
+            }
+        }
+
+        for network in &mut node.networks {
+            if !is_mac(&network.mac) {
+                let normalized = normalize_mac(&network.mac);
+                let repair = normalized.as_ref().map(|m| format!("change it to {}", m));
+                if problem(format!("network has a malformed MAC address \"{}\"", network.mac), repair.as_deref()) {
+                    network.mac = normalized.expect("only well formed addresses are repaired");
+                }
+            }
+
+            if let Some(s) = network.strength.filter(|s| !in_range(*s)) {
+                // A raw strength the scanner already kept must not be overwritten
+                let repair = network.strength_raw.is_none().then_some("move it to the raw strength, which is not mapped");
+                if problem(format!("network {} has a strength of {} dBm", network.mac, s), repair) {
+                    network.strength = None;
+                    network.strength_raw = Some(s.to_string());
+                    network.update_snr();
+                }
+            }
+
+            if network.time_scanned < EARLIEST_SCAN {
+                problem(format!("network {} was scanned at {}, which is not in milliseconds since the unix epoch", network.mac, network.time_scanned), None);
+            } else if network.time_scanned > now {
+                problem(format!("network {} was scanned in the future", network.mac), None);
+            }
+        }
+
+        for client in &mut node.clients {
+            if !is_mac(&client.mac) {
+                let normalized = normalize_mac(&client.mac);
+                let repair = normalized.as_ref().map(|m| format!("change it to {}", m));
+                if problem(format!("client has a malformed MAC address \"{}\"", client.mac), repair.as_deref()) {
+                    client.mac = normalized.expect("only well formed addresses are repaired");
+                }
+            }
+
+            if let Some(s) = client.strength.filter(|s| !in_range(*s)) {
+                if problem(format!("client {} has a strength of {} dBm", client.mac, s), Some("remove the strength")) {
+                    client.strength = None;
+                }
+            }
+        }
+
+        for device in &mut node.ble_devices {
+            if let Some(s) = device.strength.filter(|s| !in_range(*s)) {
+                if problem(format!("bluetooth device {} has a strength of {} dBm", device.address, s), Some("remove the strength")) {
+                    device.strength = None;
+                }
+            }
+        }
+    }
+
+    // Addresses are compared in lowercase, so repaired addresses do not hide duplicates
+    let mut duplicate = vec![false; scan_map.nodes.len()];
+    for j in 0..scan_map.nodes.len() {
+        if let Some(i) = (0..j).find(|i| !duplicate[*i] && is_duplicate(&scan_map.nodes[*i], &scan_map.nodes[j])) {
+            duplicate[j] = true;
+            problems.push(Problem::new(Some(j), format!("duplicates node {}", i), Some("remove the node")));
+        }
+    }
+
+    // Nodes should be in the order they were recorded, undo removes the last one
+    let mut out_of_order = false;
+    let mut latest: Option<(usize, u128)> = None;
+    for (i, node) in scan_map.nodes.iter().enumerate().filter(|(i, _)| !duplicate[*i]) {
+        let time = match measured_at(node) {
+            Some(t) => t,
+            None => continue,
+        };
+
+        match latest {
+            Some((j, t)) if time < t => {
+                out_of_order = true;
+                problems.push(Problem::new(Some(i), format!("was measured before node {}", j), Some("sort the nodes by the time they were measured")));
+            },
+            _ => latest = Some((i, time)),
+        };
+    }
+
+    if fix {
+        for p in problems.iter_mut().filter(|p| p.repair.is_some()) {
+            p.fixed = true;
+        }
+
+        let mut index = 0;
+        scan_map.nodes.retain(|_| {
+            index += 1;
+            !duplicate[index - 1]
+        });
+
+        if out_of_order {
+            // Nodes without networks stay after the node recorded before them
+            let mut time = 0;
+            let mut keyed: Vec<(u128, Node)> = scan_map.nodes.drain(..)
+                .map(|n| {
+                    time = measured_at(&n).unwrap_or(time);
+                    (time, n)
+                })
+                .collect();
+            keyed.sort_by_key(|(t, _)| *t);
+            scan_map.nodes = keyed.into_iter().map(|(_, n)| n).collect();
+        }
+    }
+
+    problems.sort_by_key(|p| p.node);
+    problems
+}
+
+/// Print a table of problems.
+pub fn print_problems(problems: &[Problem]) {
+    if problems.is_empty() {
+        println!("No problems found");
+        return;
+    }
+
+    let rows: Vec<Vec<String>> = problems.iter()
+        .map(|p| vec![
+            p.node.map(|i| i.to_string()).unwrap_or_else(|| String::from("map")),
+            p.problem.clone(),
+            match (&p.repair, p.fixed) {
+                (Some(r), true) => format!("fixed: {}", r),
+                (Some(r), false) => format!("--fix will {}", r),
+                (None, _) => String::from("repair by hand"),
+            },
+        ])
+        .collect();
+
+    table::print_table(&["Node", "Problem", "Repair"], &rows);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{scan_map, test_dir};
+
+    /// A node with malformed addresses, a strength which is not in dBm, and a time in seconds, then a node on a floor
+    /// which does not exist that was measured earlier, and a copy of it.
+    fn broken() -> ScanMap {
+        let earlier = serde_json::json!({"position": {"x": 5.0, "y": 0.0, "z": 0.0}, "floor": "F9", "networks": [
+            {"mac": "aa:bb:cc:00:11:22", "strength": -70.0, "time_scanned": 1_600_000_000_000u64},
+        ]});
+        scan_map(serde_json::json!({
+            "nodes": [
+                {"position": {"x": 0.0, "y": 0.0, "z": 0.0}, "networks": [
+                    {"mac": "84-D4-7E-69-2F-E2", "strength": 20.0, "time_scanned": 1_700_000_000_000u64},
+                    {"mac": "zz", "strength": -60.0, "time_scanned": 1_700_000_000u64},
+                ]},
+                earlier.clone(),
+                earlier,
+            ],
+        }))
+    }
+
+    fn problems(scan_map: &mut ScanMap, fix: bool) -> Vec<(Option<usize>, String, bool)> {
+        validate(scan_map, fix).into_iter().map(|p| (p.node, p.problem, p.fixed)).collect()
+    }
+
+    #[test]
+    fn normalize_mac_accepts_other_separators() {
+        assert_eq!(normalize_mac("84-D4-7E-69-2F-E2").as_deref(), Some("84:d4:7e:69:2f:e2"));
+        assert_eq!(normalize_mac("84d4.7e69.2fe2").as_deref(), Some("84:d4:7e:69:2f:e2"));
+        assert_eq!(normalize_mac("84:d4:7e:69:2f"), None);
+        assert!(is_mac("84:D4:7E:69:2F:E2") && !is_mac("84d47e692fe2"));
+    }
+
+    #[test]
+    fn validate_reports_every_problem() {
+        let mut scan_map = broken();
+        assert_eq!(problems(&mut scan_map, false), vec![
+            (Some(0), String::from("network has a malformed MAC address \"84-D4-7E-69-2F-E2\""), false),
+            (Some(0), String::from("network 84-D4-7E-69-2F-E2 has a strength of 20 dBm"), false),
+            (Some(0), String::from("network has a malformed MAC address \"zz\""), false),
+            (Some(0), String::from("network zz was scanned at 1700000000, which is not in milliseconds since the unix epoch"), false),
+            (Some(1), String::from("floor \"F9\" does not exist"), false),
+            (Some(1), String::from("was measured before node 0"), false),
+            (Some(2), String::from("floor \"F9\" does not exist"), false),
+            (Some(2), String::from("duplicates node 1"), false),
+        ]);
+        assert_eq!(scan_map.nodes.len(), 3);
+    }
+
+    #[test]
+    fn validate_fix_repairs_what_it_can() {
+        let mut scan_map = broken();
+        let fixed: Vec<bool> = problems(&mut scan_map, true).into_iter().map(|(_, _, fixed)| fixed).collect();
+        assert_eq!(fixed, vec![true, true, false, false, false, true, false, true]);
+
+        // The copy is removed and the earlier node moves first
+        assert_eq!(scan_map.nodes.len(), 2);
+        assert_eq!(scan_map.nodes[0].position.x, 5.0);
+        let network = &scan_map.nodes[1].networks[0];
+        assert_eq!(network.mac, "84:d4:7e:69:2f:e2");
+        assert_eq!(network.strength, None);
+        assert_eq!(network.strength_raw.as_deref(), Some("20"));
+    }
+
+    #[test]
+    fn validate_fix_keeps_raw_strengths() {
+        let mut scan_map = scan_map(serde_json::json!({"nodes": [
+            {"position": {"x": 0.0, "y": 0.0, "z": 0.0}, "networks": [
+                {"mac": "84:d4:7e:69:2f:e2", "strength": 20.0, "strength_raw": "80/100", "time_scanned": 1_700_000_000_000u64},
+            ]},
+        ]}));
+        assert_eq!(problems(&mut scan_map, true), vec![
+            (Some(0), String::from("network 84:d4:7e:69:2f:e2 has a strength of 20 dBm"), false),
+        ]);
+        let network = &scan_map.nodes[0].networks[0];
+        assert_eq!(network.strength, Some(20.0));
+        assert_eq!(network.strength_raw.as_deref(), Some("80/100"));
+    }
+
+    #[test]
+    fn read_names_the_nodes_which_do_not_match_the_schema() {
+        let p = test_dir("validate").join("map.json");
+        let mut value = serde_json::to_value(broken()).unwrap();
+        value["nodes"][1]["position"] = serde_json::json!("hallway");
+        std::fs::write(&p, value.to_string()).unwrap();
+
+        let problems = match read(&p) {
+            Ok(_) => panic!("broken map was read"),
+            Err(problems) => problems,
+        };
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].node, Some(1));
+        assert!(problems[0].problem.starts_with("does not match the schema"));
+
+        std::fs::write(&p, "{").unwrap();
+        assert!(read(&p).err().unwrap()[0].problem.starts_with("is not valid JSON"));
+    }
+}
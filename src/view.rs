@@ -0,0 +1,120 @@
+//! Draws a heatmap of a network in the terminal with colored characters, for feedback
+//! during a survey without generating images.
+
+use std::error::Error;
+
+use crate::interpolate::{self, InterpolateOptions, Target};
+use crate::render::{self, HeatmapOptions, STRONG_DBM, WEAK_DBM};
+use crate::{CoordinateSystem, ScanMap, EARTH_RADIUS};
+
+/// Characters from the weakest to the strongest signal, used when colors are off.
+const SHADES: &[char] = &['.', ':', '-', '=', '+', '*', '#', '%', '@'];
+
+/// Character nodes are drawn with.
+const NODE_CHAR: char = 'o';
+
+/// Terminal characters are about twice as tall as they are wide.
+const CHAR_ASPECT: f64 = 2.0;
+
+/// Lines left for the legend below the heatmap.
+const LEGEND_LINES: usize = 3;
+
+/// Options which control what is drawn.
+pub struct ViewOptions {
+    /// Heatmap of networks with this SSID.
+    pub ssid: String,
+
+    /// Only use nodes on this floor.
+    pub floor: Option<String>,
+
+    /// Width and height of the view in characters, including the legend.
+    pub size: (usize, usize),
+
+    /// Color characters with ANSI escape codes.
+    pub color: bool,
+
+    pub heatmap: HeatmapOptions,
+}
+
+/// Character for a strength in dBm.
+fn cell(dbm: f64, color: bool) -> String {
+    let t = ((dbm - WEAK_DBM) / (STRONG_DBM - WEAK_DBM)).clamp(0.0, 1.0);
+
+    match color {
+        true => {
+            let c = render::strength_color(Some(dbm));
+            format!("\x1b[38;2;{};{};{}m\u{2588}\x1b[0m", c.0, c.1, c.2)
+        },
+        false => SHADES[(t * (SHADES.len() - 1) as f64).round() as usize].to_string(),
+    }
+}
+
+/// Print the heatmap of opts.ssid scaled to fit the view.
+pub fn view(scan_map: &ScanMap, opts: &ViewOptions) -> Result<(), Box<dyn Error>> {
+    let nodes: Vec<(f64, f64)> = scan_map.nodes.iter()
+        .filter(|n| opts.floor.is_none() || n.floor == opts.floor)
+        .map(|n| (n.position.x, n.position.y))
+        .collect();
+
+    let (width, height) = opts.size;
+    let height = height.saturating_sub(LEGEND_LINES).max(1);
+
+    // Extent of the nodes in the units interpolation spacings are in, meters for wgs84 maps
+    let min_x = nodes.iter().map(|n| n.0).fold(f64::INFINITY, f64::min);
+    let max_x = nodes.iter().map(|n| n.0).fold(f64::NEG_INFINITY, f64::max);
+    let min_y = nodes.iter().map(|n| n.1).fold(f64::INFINITY, f64::min);
+    let max_y = nodes.iter().map(|n| n.1).fold(f64::NEG_INFINITY, f64::max);
+    let unit = match scan_map.coordinate_system {
+        CoordinateSystem::Local => (1.0, 1.0),
+        CoordinateSystem::Wgs84 => {
+            let lat = ((min_y + max_y) / 2.0).to_radians();
+            (1f64.to_radians() * lat.cos() * EARTH_RADIUS, 1f64.to_radians() * EARTH_RADIUS)
+        },
+    };
+    let span = (((max_x - min_x) * unit.0).max(1e-9), ((max_y - min_y) * unit.1).max(1e-9));
+
+    // Grid points are a character apart horizontally, every CHAR_ASPECT rows of the grid is a line
+    let spacing = opts.heatmap.spacing.unwrap_or_else(|| (span.0 / (width.max(2) - 1) as f64).max(span.1 / ((height.max(2) - 1) as f64 * CHAR_ASPECT)));
+    let grid = interpolate::interpolate(scan_map, &InterpolateOptions{
+        target: Target::Ssid(opts.ssid.clone()),
+        floor: opts.floor.clone(),
+        spacing,
+        power: opts.heatmap.power,
+        radius: opts.heatmap.radius,
+        unseen: opts.heatmap.unseen,
+    })?;
+
+    let step = CHAR_ASPECT.round() as usize;
+    let lines = grid.rows.div_ceil(step);
+    let mut chars = vec![vec![String::from(" "); grid.columns]; lines];
+    for (line, row) in (0..grid.rows).rev().step_by(step).enumerate() {
+        for (column, c) in chars[line].iter_mut().enumerate() {
+            if let Some(dbm) = grid.value(column, row) {
+                *c = cell(dbm, opts.color);
+            }
+        }
+    }
+
+    // Line 0 is the northmost row of the grid
+    let top = grid.origin.1 + (grid.rows - 1) as f64 * grid.spacing.1;
+    for (x, y) in &nodes {
+        let column = ((x - grid.origin.0) / grid.spacing.0).round() as usize;
+        let line = ((top - y) / (grid.spacing.1 * step as f64)).round() as usize;
+        if line < lines && column < grid.columns {
+            chars[line][column] = NODE_CHAR.to_string();
+        }
+    }
+
+    for line in chars {
+        println!("{}", line.concat().trim_end());
+    }
+
+    let scale: String = (0..SHADES.len())
+        .map(|i| cell(WEAK_DBM + (STRONG_DBM - WEAK_DBM) * i as f64 / (SHADES.len() - 1) as f64, opts.color))
+        .collect();
+    println!();
+    // wgs84 maps always measure lengths in meters
+    println!("{} dBm {} {} dBm, {} node, {} {} per character", WEAK_DBM, scale, STRONG_DBM, NODE_CHAR, (spacing * 100.0).round() / 100.0, scan_map.units.abbreviation());
+
+    Ok(())
+}
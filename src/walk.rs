@@ -0,0 +1,186 @@
+//! Records a node per scan while the user walks between waypoints. The positions of the nodes are interpolated
+//! between the waypoints by the time of each scan.
+
+use std::error::Error;
+use std::io::{stdin, stdout, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::ble::{self, BleDevice};
+use crate::logging;
+use crate::{Coordinate, Network, Node, RecordOptions, ScanMap, ScanOptions, WriteOptions};
+
+/// Longest time the scan thread sleeps before checking whether recording has finished.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Position the user was at when they pressed enter.
+struct Waypoint {
+    /// Milliseconds since the unix epoch.
+    time: u128,
+
+    position: Coordinate,
+}
+
+/// Result of one scan made while walking.
+pub struct Scan {
+    /// Time the scan was started in milliseconds since the unix epoch.
+    pub time: u128,
+
+    /// Networks and Bluetooth devices which were found, or why the scan failed.
+    pub found: Result<(Vec<Network>, Vec<BleDevice>), String>,
+}
+
+/// Current time in milliseconds since the unix epoch.
+pub fn now() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("failed to read system time")
+        .as_millis()
+}
+
+/// Position between two waypoints at a time, assuming the user walked at a constant speed.
+fn interpolate(from: &Waypoint, to: &Waypoint, time: u128) -> Coordinate {
+    let fraction = match to.time > from.time {
+        true => (time.saturating_sub(from.time) as f64 / (to.time - from.time) as f64).clamp(0.0, 1.0),
+        false => 0.0,
+    };
+    let (a, b) = (&from.position, &to.position);
+
+    Coordinate{
+        x: a.x + (b.x - a.x) * fraction,
+        y: a.y + (b.y - a.y) * fraction,
+        z: a.z + (b.z - a.z) * fraction,
+    }
+}
+
+/// Scan the radios opts chooses once.
+fn scan(opts: &ScanOptions) -> Result<(Vec<Network>, Vec<BleDevice>), String> {
+    let networks = match opts.protocol.wifi() {
+        true => Network::scan(opts).map_err(|e| e.to_string())?,
+        false => Vec::new(),
+    };
+    let ble_devices = match opts.protocol.ble() {
+        true => ble::scan(opts.ble_duration).map_err(|e| e.to_string())?,
+        false => Vec::new(),
+    };
+
+    Ok((networks, ble_devices))
+}
+
+/// Scan every interval and send the results until stop is set.
+pub fn scan_loop(opts: &ScanOptions, interval: Duration, stop: &AtomicBool, scans: Sender<Scan>) {
+    while !stop.load(Ordering::SeqCst) {
+        let started = Instant::now();
+        let scan = Scan{
+            time: now(),
+            found: scan(opts),
+        };
+
+        if scans.send(scan).is_err() {
+            return;
+        }
+
+        // Sleep in steps so finishing does not have to wait for a whole interval
+        while !stop.load(Ordering::SeqCst) && started.elapsed() < interval {
+            thread::sleep(POLL_INTERVAL.min(interval.saturating_sub(started.elapsed())));
+        }
+    }
+}
+
+/// Ask for waypoints after start until the user quits. After each waypoint the scans made since the previous one are added to the map and it is saved.
+fn record_waypoints(scan_map: &mut ScanMap, record_opts: &RecordOptions, floor: Option<String>, start: Waypoint, scans: mpsc::Receiver<Scan>, map_path: &Path, write_opts: &WriteOptions) -> Result<(), Box<dyn Error>> {
+    let mut last = start;
+    let mut pending = Vec::<Scan>::new();
+
+    loop {
+        print!("Press enter at the next waypoint, or q to finish: ");
+        stdout().flush().expect("failed to flush stdout");
+        let mut answer = String::new();
+        let read = stdin().read_line(&mut answer).expect("failed to read input");
+
+        let time = now();
+        pending.extend(scans.try_iter());
+
+        if read == 0 || answer.trim() == "q" {
+            if !pending.is_empty() {
+                println!("Discarded {} scan{} made after the last waypoint", pending.len(), match pending.len() != 1 {
+                    true => "s",
+                    false => "",
+                });
+            }
+            return Ok(());
+        }
+
+        let next = Waypoint{
+            time,
+            position: record_opts.determine_position(scan_map),
+        };
+
+        // A scan which started after enter was pressed belongs to the next leg
+        let (leg, later): (Vec<Scan>, Vec<Scan>) = pending.into_iter().partition(|s| s.time <= next.time);
+        pending = later;
+
+        let mut recorded = 0;
+        for scan in leg {
+            let (mut networks, ble_devices) = match scan.found {
+                Ok(found) => found,
+                Err(e) => {
+                    logging::warn(&format!("Scan failed: {}", e));
+                    continue;
+                },
+            };
+            networks.sort_by_key(|n| n.mac.clone());
+
+            scan_map.nodes.push(Node{
+                position: interpolate(&last, &next, scan.time),
+                floor: floor.clone(),
+                notes: String::new(),
+                tags: record_opts.tags.clone(),
+                session: record_opts.session,
+                upload_id: None,
+                networks,
+                clients: Vec::new(),
+                ble_devices,
+            });
+            recorded += 1;
+        }
+
+        scan_map.write(map_path, write_opts)?;
+
+        println!("Recorded {} node{} since the last waypoint", recorded, match recorded != 1 {
+            true => "s",
+            false => "",
+        });
+
+        last = next;
+    }
+}
+
+/// Record nodes continuously, scanning every interval while the user walks between waypoints.
+pub fn walk(scan_map: &mut ScanMap, record_opts: &RecordOptions, scan_opts: &ScanOptions, interval: Duration, map_path: &Path, write_opts: &WriteOptions) -> Result<(), Box<dyn Error>> {
+    let floor = record_opts.determine_floor(scan_map);
+
+    println!("Starting waypoint:");
+    let position = record_opts.determine_position(scan_map);
+    let start = Waypoint{
+        time: now(),
+        position,
+    };
+    println!("Scanning every {:.1}s, walk to the next waypoint", interval.as_secs_f64());
+
+    let stop = AtomicBool::new(false);
+    let (sender, receiver) = mpsc::channel();
+
+    thread::scope(|scope| {
+        let stop = &stop;
+        scope.spawn(move || scan_loop(scan_opts, interval, stop, sender));
+
+        let result = record_waypoints(scan_map, record_opts, floor, start, receiver, map_path, write_opts);
+        stop.store(true, Ordering::SeqCst);
+
+        result
+    })
+}
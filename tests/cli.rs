@@ -0,0 +1,132 @@
+//! Runs the binary on scans read from the fixtures in tests/fixtures with the mock backend.
+
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+
+use serde_json::Value;
+
+/// Empty directory for the files of a test.
+fn test_dir(name: &str) -> PathBuf {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join(name);
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).expect("failed to create test directory");
+    dir
+}
+
+/// Run wifi-mapper on the map file map with args, writing input to its stdin. Scans come from the fixture mock.
+fn run(map: &Path, mock: &str, args: &[&str], input: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_wifi-mapper"))
+        .arg("--no-config")
+        .arg("--map-file")
+        .arg(map)
+        .args(args)
+        .env("WIFI_MAPPER_MOCK", Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("fixtures").join(mock))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("failed to run wifi-mapper");
+    child.stdin.take().expect("stdin is piped").write_all(input.as_bytes()).expect("failed to write stdin");
+
+    let output = child.wait_with_output().expect("failed to wait for wifi-mapper");
+    assert!(output.status.success(), "wifi-mapper {:?} failed: {}", args, String::from_utf8_lossy(&output.stderr));
+    output
+}
+
+/// JSON which a run printed.
+fn json(output: Output) -> Value {
+    serde_json::from_slice(&output.stdout).expect("output is JSON")
+}
+
+/// Record a map of the office fixture at the origin and the hallway fixture 10 meters away.
+fn record_survey(dir: &Path) -> PathBuf {
+    let map = dir.join("office.json");
+
+    // Coordinate system and units of the new map, then the position and notes of the node
+    let output = run(&map, "mock-office.json", &["record", "--name", "Office", "--notes", "", "--samples", "2"], "\n\n0 0 0\n\n");
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Recorded a new measurement with 2 networks"));
+    run(&map, "mock-hallway.json", &["record"], "10 0 0\nHallway\n");
+
+    map
+}
+
+#[test]
+fn record_saves_the_averaged_scans() {
+    let dir = test_dir("record");
+    let map = record_survey(&dir);
+
+    let saved: Value = serde_json::from_str(&fs::read_to_string(&map).expect("map was saved")).expect("map is JSON");
+    assert_eq!(saved["name"], "Office");
+    assert_eq!(saved["sessions"].as_array().map(Vec::len), Some(1));
+
+    let nodes = saved["nodes"].as_array().expect("map has nodes");
+    assert_eq!(nodes.len(), 2);
+    assert_eq!(nodes[1]["notes"], "Hallway");
+
+    let corp = &nodes[0]["networks"][0];
+    assert_eq!(corp["mac"], "84:d4:7e:69:2f:e2");
+    assert_eq!(corp["strength"], -62.0);
+    assert_eq!(corp["samples"], 2);
+    assert_eq!(corp["noise"], -95.0);
+    assert_eq!(corp["snr"], 33.0);
+}
+
+#[test]
+fn list_prints_every_node() {
+    let dir = test_dir("list");
+    let map = record_survey(&dir);
+
+    let output = run(&map, "mock-office.json", &["list"], "");
+    let mut rows: Vec<Vec<String>> = String::from_utf8_lossy(&output.stdout).lines()
+        .skip(1)
+        .map(|l| l.split_whitespace().map(String::from).collect())
+        .collect();
+
+    // Nodes were recorded just now, so only check that the time is an RFC 3339 timestamp
+    for row in &mut rows {
+        let time = row.remove(6);
+        assert!(time.len() >= 20 && &time[10..11] == "T", "{} is not an RFC 3339 timestamp", time);
+    }
+    assert_eq!(rows, vec![
+        vec!["0", "0", "0", "0", "m", "2"],
+        vec!["1", "10", "0", "0", "m", "2", "Hallway"],
+    ]);
+}
+
+#[test]
+fn analyze_gaps_finds_the_weak_hallway() {
+    let dir = test_dir("gaps");
+    let map = record_survey(&dir);
+
+    let gaps = json(run(&map, "mock-office.json", &["analyze", "gaps", "--ssid", "CorpWiFi", "--json"], ""));
+    let gaps = gaps.as_array().expect("gaps prints an array");
+    assert_eq!(gaps.len(), 1);
+    assert_eq!(gaps[0]["weakest"], -88.0);
+    assert_eq!(gaps[0]["bounds"], serde_json::json!([5.0, 0.0, 10.0, 0.0]));
+
+    let gaps = json(run(&map, "mock-office.json", &["analyze", "gaps", "--ssid", "CorpWiFi", "--threshold", "-90", "--json"], ""));
+    assert_eq!(gaps, serde_json::json!([]));
+}
+
+#[test]
+fn manpage_writes_a_page_for_every_subcommand() {
+    let dir = test_dir("manpage");
+    run(&dir.join("unused.json"), "mock-office.json", &["manpage", "--dir", dir.to_str().expect("path is utf-8")], "");
+
+    for page in ["wifi-mapper.1", "wifi-mapper-record.1", "wifi-mapper-analyze-gaps.1"] {
+        let text = fs::read_to_string(dir.join(page)).unwrap_or_else(|e| panic!("{}: {}", page, e));
+        assert!(text.starts_with(".ie"), "{} is not a manual page", page);
+    }
+}
+
+#[test]
+fn completions_complete_subcommands() {
+    let dir = test_dir("completions");
+    let output = run(&dir.join("unused.json"), "mock-office.json", &["completions", "bash"], "");
+    let script = String::from_utf8_lossy(&output.stdout);
+
+    assert!(script.contains("wifi__mapper)"));
+    assert!(script.contains("record"));
+}